@@ -1,36 +1,98 @@
+use crate::captures::{CaptureRow, CaptureTable};
+use crate::config::Config;
+use crate::filter::{self, ExtFilter, PathFilter};
+use crate::git::{self, GitInfo};
+use crate::input::{self, Input, Inputs};
+use crate::input_history::InputHistory;
+use crate::line_edit::{self, Edit};
+use crate::media::{self, VideoInfo};
 use crate::path_utils;
+use crate::plan_cache::{self, PlanCache};
+use crate::plan_file::PlanFile;
+use crate::planner::{
+    self, ConfirmThresholds, ConflictPolicy, FileOrder, MovePair, NumberingOrder, PlanStats,
+    SymlinkPolicy,
+};
+use crate::portability::{self, PathLimits};
+use crate::prefetch::Prefetcher;
+use crate::scan::{self, DirScan, FileKind};
+use crate::session::SessionFile;
+use crate::template::{self, Generated, Template, TokenContext};
+use crate::transform::{CaseStyle, NameTransform, Normalization, Sanitizer, Truncation};
+use crate::tutorial::{Goal, Tutorial};
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt::Display,
+    path::{Path, PathBuf},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
 
-use std::{fmt::Display, path::PathBuf, str::FromStr, thread, time::Duration};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use lazy_static::lazy_static;
 use num_derive::{FromPrimitive, ToPrimitive};
 use regex::Regex;
-use termion::{event::Key, input::TermRead};
+use termion::event::{Key, MouseButton, MouseEvent};
 use tui::{
     backend::Backend,
-    layout::{Constraint, Direction, Layout, Margin},
+    layout::{Constraint, Direction, Layout, Margin, Rect},
     style::{Color, Modifier, Style},
     text::{Span, Spans},
-    widgets::{Block, Borders, Paragraph, Wrap},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
     Frame, Terminal,
 };
 use variant_count::VariantCount;
 
 bitflags::bitflags! {
-    struct MatchFlags : u8 {
+    /// flags of a rule written after the last delimiter of a sed-style expression, e.g. `gi`.
+    /// Parsed from and displayed as these characters, also in serde
+    pub struct MatchFlags : u8 {
         const NO_FLAGS = 0;
         const GLOBAL = 1;
         const ICASE = 2;
+        /// `^` and `$` match at line boundaries
+        const MULTILINE = 4;
+        /// `.` matches `\n`
+        const DOTALL = 8;
+        /// swap the meaning of greedy and lazy quantifiers
+        const UNGREEDY = 16;
+        /// ignore whitespace and allow `#` comments in the pattern
+        const EXTENDED = 32;
+    }
+}
+
+/// flags with their characters in the display order
+const FLAG_CHARS: [(MatchFlags, char); 6] = [
+    (MatchFlags::GLOBAL, 'g'),
+    (MatchFlags::ICASE, 'i'),
+    (MatchFlags::MULTILINE, 'm'),
+    (MatchFlags::DOTALL, 's'),
+    (MatchFlags::UNGREEDY, 'U'),
+    (MatchFlags::EXTENDED, 'x'),
+];
+
+impl MatchFlags {
+    /// inline flags of the regex syntax, e.g. `ims`. `g` isn't one of them
+    fn regex_flags(&self) -> String {
+        FLAG_CHARS
+            .iter()
+            .filter(|(flag, _)| *flag != MatchFlags::GLOBAL && self.contains(*flag))
+            .map(|(_, ch)| ch)
+            .collect()
     }
 }
 
 impl Display for MatchFlags {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if self.contains(MatchFlags::GLOBAL) {
-            f.write_str("g")?;
-        }
-        if self.contains(MatchFlags::ICASE) {
-            f.write_str("i")?;
+        for (flag, ch) in FLAG_CHARS {
+            if self.contains(flag) {
+                write!(f, "{}", ch)?;
+            }
         }
 
         Ok(())
@@ -38,7 +100,7 @@ impl Display for MatchFlags {
 }
 
 lazy_static! {
-    static ref FLAGS_REGEX: Regex = Regex::new("[gi]{0,2}").unwrap();
+    static ref FLAGS_REGEX: Regex = Regex::new("^[gimsUx]*$").unwrap();
 }
 
 impl FromStr for MatchFlags {
@@ -47,11 +109,10 @@ impl FromStr for MatchFlags {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         if FLAGS_REGEX.is_match(s) {
             let mut flags = MatchFlags::NO_FLAGS;
-            if s.contains('g') {
-                flags |= MatchFlags::GLOBAL;
-            }
-            if s.contains('i') {
-                flags |= MatchFlags::ICASE;
+            for (flag, ch) in FLAG_CHARS {
+                if s.contains(ch) {
+                    flags |= flag;
+                }
             }
             Ok(flags)
         } else {
@@ -60,10 +121,366 @@ impl FromStr for MatchFlags {
     }
 }
 
+impl Serialize for MatchFlags {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for MatchFlags {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl Default for MatchFlags {
+    fn default() -> Self {
+        MatchFlags::NO_FLAGS
+    }
+}
+
+/// single match replaced instead of the first one or all of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Occurrence {
+    /// 1-based number of the match
+    Nth(usize),
+    Last,
+}
+
+impl FromStr for Occurrence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "last" => Ok(Occurrence::Last),
+            _ => match s.parse() {
+                Ok(n) if n > 0 => Ok(Occurrence::Nth(n)),
+                _ => Err(format!(
+                    "invalid occurrence: '{}', expected a positive number or 'last'",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl Display for Occurrence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Occurrence::Nth(n) => write!(f, "{}", n),
+            Occurrence::Last => write!(f, "last"),
+        }
+    }
+}
+
+impl Occurrence {
+    /// marker in the flags of a sed-style expression: the number or `$` for the last match
+    fn flag(&self) -> String {
+        match self {
+            Occurrence::Nth(n) => n.to_string(),
+            Occurrence::Last => "$".to_owned(),
+        }
+    }
+}
+
+impl Serialize for Occurrence {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Occurrence {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+/// split the flags of a sed-style expression into the regex flags and the occurrence
+fn parse_expression_flags(flags: &str) -> Result<(MatchFlags, Option<Occurrence>), String> {
+    let (marker, letters): (String, String) = flags
+        .chars()
+        .partition(|ch| ch.is_ascii_digit() || *ch == '$');
+    let occurrence = match marker.as_str() {
+        "" => None,
+        "$" => Some(Occurrence::Last),
+        number => Some(
+            number
+                .parse()
+                .map_err(|_| format!("invalid regex flags: '{}'", flags))?,
+        ),
+    };
+
+    Ok((letters.parse()?, occurrence))
+}
+
+/// regex, replacement and flags parsed from a sed-style `s/regex/replacement/flags` expression
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RenameRule {
+    pub regex: String,
+    /// may contain `{token}` placeholders expanded for every file
+    #[serde(default)]
+    pub replacement: String,
+    #[serde(default)]
+    pub flags: MatchFlags,
+    #[serde(default)]
+    pub mode: MatchMode,
+    /// replace only this match, overrides the global flag
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub occurrence: Option<Occurrence>,
+}
+
+impl RenameRule {
+    pub fn new(regex: String, replacement: String, flags: MatchFlags) -> Self {
+        RenameRule {
+            regex,
+            replacement,
+            flags,
+            mode: MatchMode::Regex,
+            occurrence: None,
+        }
+    }
+
+    pub fn with_occurrence(mut self, occurrence: Option<Occurrence>) -> Self {
+        self.occurrence = occurrence;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: MatchMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    fn compile(&self, engine: Engine) -> CompiledRule {
+        CompiledRule {
+            regex: compose_regex(&self.regex, self.flags, self.mode, engine),
+            replacement: Template::parse(&compose_replacement(&self.replacement, self.mode)),
+            targets: self.targets(),
+        }
+    }
+
+    /// matches replaced by the rule: the occurrence if it's set, all of them with `g`,
+    /// or the first one
+    pub fn targets(&self) -> Targets {
+        match self.occurrence {
+            Some(occurrence) => Targets::Occurrence(occurrence),
+            None if self.flags.contains(MatchFlags::GLOBAL) => Targets::All,
+            None => Targets::First,
+        }
+    }
+
+    /// parse a sed-style expression. Any character following `s` is used as a delimiter
+    /// and can be escaped with a backslash inside the regex and the replacement
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let mut chars = expr.chars();
+        if chars.next() != Some('s') {
+            return Err(format!("expression must start with 's': '{}'", expr));
+        }
+        let delimiter = chars
+            .next()
+            .ok_or_else(|| format!("missing delimiter in expression: '{}'", expr))?;
+
+        let mut parts = vec![String::new()];
+        while let Some(ch) = chars.next() {
+            match ch {
+                '\\' => match chars.next() {
+                    Some(escaped) if escaped == delimiter => {
+                        parts.last_mut().unwrap().push(escaped)
+                    }
+                    Some(escaped) => {
+                        parts.last_mut().unwrap().push(ch);
+                        parts.last_mut().unwrap().push(escaped);
+                    }
+                    None => parts.last_mut().unwrap().push(ch),
+                },
+                ch if ch == delimiter => parts.push(String::new()),
+                ch => parts.last_mut().unwrap().push(ch),
+            }
+        }
+
+        match parts.len() {
+            2 | 3 => {
+                let (flags, occurrence) =
+                    parse_expression_flags(parts.get(2).map(String::as_str).unwrap_or_default())?;
+                let mut parts = parts.into_iter();
+                Ok(RenameRule {
+                    regex: parts.next().unwrap(),
+                    replacement: parts.next().unwrap(),
+                    flags,
+                    mode: MatchMode::Regex,
+                    occurrence,
+                })
+            }
+            _ => Err(format!(
+                "expected s/regex/replacement/flags, got '{}'",
+                expr
+            )),
+        }
+    }
+
+    /// parse a sed script: one expression per line, applied in order.
+    /// Empty lines and lines starting with `#` are skipped
+    pub fn parse_script(script: &str) -> Result<Vec<Self>, String> {
+        script
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| (idx, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(idx, line)| {
+                RenameRule::parse(line).map_err(|err| format!("line {}: {}", idx + 1, err))
+            })
+            .collect()
+    }
+}
+
+impl FromStr for RenameRule {
+    type Err = String;
+
+    fn from_str(expr: &str) -> Result<Self, Self::Err> {
+        RenameRule::parse(expr)
+    }
+}
+
+impl Display for RenameRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "s/{}/{}/{}{}",
+            self.regex.replace('/', "\\/"),
+            self.replacement.replace('/', "\\/"),
+            self.flags,
+            self.occurrence
+                .as_ref()
+                .map(Occurrence::flag)
+                .unwrap_or_default()
+        )
+    }
+}
+
+/// syntax of the pattern typed into the pattern input
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    /// regular expression
+    #[default]
+    Regex,
+    /// shell-like glob where wildcards become capture groups
+    Glob,
+    /// plain text without any metacharacters
+    Literal,
+}
+
+impl MatchMode {
+    fn title(&self) -> &'static str {
+        match self {
+            MatchMode::Regex => "Regex",
+            MatchMode::Glob => "Glob",
+            MatchMode::Literal => "Literal",
+        }
+    }
+
+    /// short marker shown in the flags area
+    fn flag(&self) -> &'static str {
+        match self {
+            MatchMode::Literal => "l",
+            _ => "",
+        }
+    }
+
+    fn toggled(self, mode: MatchMode) -> Self {
+        if self == mode {
+            MatchMode::Regex
+        } else {
+            mode
+        }
+    }
+}
+
+/// translate a glob pattern into an anchored regex.
+/// Every wildcard (`*`, `?`, `[...]`, `{a,b}`) becomes a capture group
+fn glob_to_regex(glob: &str) -> String {
+    let mut regex = String::from("^");
+    let mut chars = glob.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '*' => regex.push_str("(.*)"),
+            '?' => regex.push_str("(.)"),
+            '[' => {
+                regex.push_str("([");
+                let mut class = chars.clone();
+                if let Some('!') = class.next() {
+                    regex.push('^');
+                    chars.next();
+                }
+                for ch in chars.by_ref() {
+                    if ch == ']' {
+                        break;
+                    }
+                    if ch == '\\' || ch == '[' {
+                        regex.push('\\');
+                    }
+                    regex.push(ch);
+                }
+                regex.push_str("])");
+            }
+            '{' => {
+                regex.push('(');
+                for ch in chars.by_ref() {
+                    match ch {
+                        '}' => break,
+                        ',' => regex.push('|'),
+                        ch => regex.push_str(&regex::escape(&ch.to_string())),
+                    }
+                }
+                regex.push(')');
+            }
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    regex.push_str(&regex::escape(&escaped.to_string()));
+                }
+            }
+            ch => regex.push_str(&regex::escape(&ch.to_string())),
+        }
+    }
+
+    regex.push('$');
+    regex
+}
+
+/// replace `*` and `?` in a glob replacement with the successive capture groups
+fn glob_replacement(replacement: &str) -> String {
+    let mut result = String::new();
+    let mut group = 0;
+    let mut chars = replacement.chars();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '*' | '?' => {
+                group += 1;
+                result.push_str(&format!("${{{}}}", group));
+            }
+            '\\' => {
+                if let Some(escaped) = chars.next() {
+                    result.push(escaped);
+                }
+            }
+            ch => result.push(ch),
+        }
+    }
+
+    result
+}
+
 #[derive(Debug, PartialEq, FromPrimitive, ToPrimitive, VariantCount)]
 enum EditableArea {
     Regex,
     Replace,
+    /// the files list, for dropping entries from the renaming
+    Files,
 }
 
 impl EditableArea {
@@ -76,342 +493,5522 @@ impl EditableArea {
 
     fn prev(&self) -> Self {
         let num_value = num::ToPrimitive::to_usize(self).unwrap();
-        let prev_value = (num_value.overflowing_sub(1).0) % EditableArea::VARIANT_COUNT;
+        let prev_value =
+            (num_value + EditableArea::VARIANT_COUNT - 1) % EditableArea::VARIANT_COUNT;
 
         num::FromPrimitive::from_usize(prev_value).unwrap()
     }
 }
 
+/// modal window drawn on top of the editor
 #[derive(Debug, PartialEq)]
+enum Popup {
+    /// list of renames which would be executed
+    Plan,
+    /// typed confirmation of a huge or destructive plan
+    Confirm {
+        stats: PlanStats,
+        /// what the user has typed so far
+        typed: String,
+    },
+    /// list of saved presets to pick from
+    Presets { selected: usize },
+    /// name input for saving the edited rule as a preset
+    SavePreset { name: String, error: Option<String> },
+    /// input of the single match to replace
+    Occurrence {
+        typed: String,
+        error: Option<String>,
+    },
+    /// confirmation of exiting with edited rules
+    Quit,
+    /// choice between replacing and keeping the existing destinations
+    Conflicts { existing: usize },
+    /// menu of case styles, the first item turns the conversion off
+    CaseStyles { selected: usize },
+    /// path input for exporting the plan as JSON
+    ExportPlan { path: String, error: Option<String> },
+    /// path or glob input for adding files to the list
+    AddFiles { path: String, error: Option<String> },
+}
+
+/// where the inputs and the files list were drawn, to find what is clicked
+#[derive(Debug, Default, Clone, Copy)]
+struct ClickAreas {
+    regex: Rect,
+    replacement: Rect,
+    expression: Rect,
+    files: Rect,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 enum ReplacementResult {
     InvalidRegex,
     NoMatch,
     Unchanged,
     Replaced(String),
+    /// a post-processing step failed for this name
+    Failed(String),
 }
 
-fn compose_regex(regex_str: &str, flags: MatchFlags) -> Option<Regex> {
-    let flags_str = if flags.contains(MatchFlags::ICASE) {
-        "i"
-    } else {
-        ""
-    };
-    let composed_str = format!("(?{}:{})", flags_str, regex_str);
+/// regex implementation used to match the names
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    /// `regex` crate: linear time, no look-around and backreferences
+    #[default]
+    Std,
+    /// `fancy-regex` crate: supports look-around and backreferences
+    Fancy,
+}
+
+impl Engine {
+    fn compile(&self, pattern: &str) -> Option<Box<dyn RegexBackend>> {
+        match self {
+            Engine::Std => Regex::new(pattern)
+                .ok()
+                .map(|re| Box::new(re) as Box<dyn RegexBackend>),
+            Engine::Fancy => fancy_regex::Regex::new(pattern)
+                .ok()
+                .map(|re| Box::new(re) as Box<dyn RegexBackend>),
+        }
+    }
 
-    Regex::new(&composed_str).ok()
+    fn toggled(self) -> Self {
+        match self {
+            Engine::Std => Engine::Fancy,
+            Engine::Fancy => Engine::Std,
+        }
+    }
 }
 
-fn try_replace(
-    text: &str,
-    regex: &Option<Regex>,
-    replacement: &str,
-    global: bool,
-) -> ReplacementResult {
-    if let Some(regex) = regex.as_ref() {
-        if !regex.is_match(text) {
-            ReplacementResult::NoMatch
-        } else {
-            let replaced = if global {
-                regex.replace_all(text, replacement)
-            } else {
-                regex.replace(text, replacement)
-            };
+/// order of the files in the list
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ListOrder {
+    /// order of the input
+    #[default]
+    Input,
+    /// the largest changes of the names first, to review the riskiest renames
+    MostChanged,
+}
 
-            if replaced == text {
-                ReplacementResult::Unchanged
-            } else {
-                ReplacementResult::Replaced(replaced.into())
-            }
+impl ListOrder {
+    fn toggled(self) -> Self {
+        match self {
+            ListOrder::Input => ListOrder::MostChanged,
+            ListOrder::MostChanged => ListOrder::Input,
         }
-    } else {
-        ReplacementResult::InvalidRegex
     }
 }
 
-pub enum AppResult {
-    MoveFiles(Vec<(PathBuf, PathBuf)>),
-    Exit,
+/// matches replaced by a rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Targets {
+    First,
+    All,
+    /// only the Nth or the last match
+    Occurrence(Occurrence),
 }
 
-pub struct App {
-    /// current value of the regex input box
-    regex: String,
-    /// current value of the replacement string box
-    replacement: String,
-    /// match flags
-    flags: MatchFlags,
-    /// active editing area where the cursor is
-    active_area: EditableArea,
-    /// source files to rename
-    source_files: Vec<PathBuf>,
+/// compiled regex of one of the engines. Errors are runtime failures of the `fancy` engine,
+/// e.g. exceeded backtracking limits
+pub trait RegexBackend {
+    fn is_match(&self, text: &str) -> Result<bool, String>;
+    /// replace the `targets` matches. `replacement` refers to the groups as `$1` or `${name}`
+    /// and may contain `${group:+present:absent}` conditionals
+    fn replace(&self, text: &str, replacement: &str, targets: Targets) -> Result<String, String>;
+    /// names of the capture groups, numbers for the unnamed ones. The whole match isn't included
+    fn group_names(&self) -> Vec<String>;
+    /// capture groups of the first match, `None` if the text doesn't match
+    fn captures(&self, text: &str) -> Result<Option<Vec<Option<String>>>, String>;
 }
 
-impl Default for App {
-    fn default() -> App {
-        App {
-            regex: String::new(),
-            replacement: String::new(),
-            flags: MatchFlags::NO_FLAGS,
-            active_area: EditableArea::Regex,
-            source_files: Vec::new(),
-        }
+fn group_names<'a>(names: impl Iterator<Item = Option<&'a str>>) -> Vec<String> {
+    names
+        .enumerate()
+        .skip(1)
+        .map(|(idx, name)| name.map_or_else(|| idx.to_string(), str::to_owned))
+        .collect()
+}
+
+impl RegexBackend for Regex {
+    fn is_match(&self, text: &str) -> Result<bool, String> {
+        Ok(Regex::is_match(self, text))
+    }
+
+    fn replace(&self, text: &str, replacement: &str, targets: Targets) -> Result<String, String> {
+        let conditional = template::has_conditionals(replacement);
+        let nth = match targets {
+            Targets::First if !conditional => {
+                return Ok(self.replacen(text, 1, replacement).into_owned())
+            }
+            Targets::All if !conditional => {
+                return Ok(self.replace_all(text, replacement).into_owned())
+            }
+            Targets::First => Some(1),
+            Targets::All => None,
+            Targets::Occurrence(Occurrence::Nth(n)) => Some(n),
+            Targets::Occurrence(Occurrence::Last) => Some(self.find_iter(text).count()),
+        };
+
+        let mut idx = 0;
+        let replaced = self.replace_all(text, |caps: &regex::Captures| {
+            idx += 1;
+            let mut dst = String::new();
+            if nth.is_none_or(|nth| nth == idx) {
+                let is_set = |group: &str| {
+                    let found = match group.parse() {
+                        Ok(idx) => caps.get(idx),
+                        Err(_) => caps.name(group),
+                    };
+                    found.is_some_and(|found| !found.as_str().is_empty())
+                };
+                let replacement = template::resolve_conditionals(replacement, &is_set);
+                caps.expand(&replacement, &mut dst);
+            } else {
+                dst.push_str(&caps[0]);
+            }
+            dst
+        });
+        Ok(replaced.into_owned())
+    }
+
+    fn group_names(&self) -> Vec<String> {
+        group_names(self.capture_names())
+    }
+
+    fn captures(&self, text: &str) -> Result<Option<Vec<Option<String>>>, String> {
+        Ok(Regex::captures(self, text).map(|caps| {
+            caps.iter()
+                .skip(1)
+                .map(|group| group.map(|group| group.as_str().to_owned()))
+                .collect()
+        }))
     }
 }
 
-impl App {
-    pub fn with_files(mut self, files: Vec<PathBuf>) -> Self {
-        self.source_files = files
-            .into_iter()
-            .map(|path| path_utils::normalize_path(&path))
-            .collect();
-        self
+/// backtracking can fail at runtime, e.g. when exceeding the backtrack limit
+impl RegexBackend for fancy_regex::Regex {
+    fn is_match(&self, text: &str) -> Result<bool, String> {
+        fancy_regex::Regex::is_match(self, text).map_err(|err| err.to_string())
     }
 
-    pub fn with_regex(mut self, regex: String) -> Self {
-        self.regex = regex;
-        self
+    fn replace(&self, text: &str, replacement: &str, targets: Targets) -> Result<String, String> {
+        let conditional = template::has_conditionals(replacement);
+        let nth = match targets {
+            Targets::First | Targets::All if !conditional => {
+                let limit = if targets == Targets::All { 0 } else { 1 };
+                return self
+                    .try_replacen(text, limit, replacement)
+                    .map(|replaced| replaced.into_owned())
+                    .map_err(|err| err.to_string());
+            }
+            Targets::First => Some(1),
+            Targets::All => None,
+            Targets::Occurrence(Occurrence::Nth(n)) => Some(n),
+            Targets::Occurrence(Occurrence::Last) => Some(
+                self.find_iter(text)
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|err| err.to_string())?
+                    .len(),
+            ),
+        };
+
+        let mut idx = 0;
+        self.try_replacen(text, 0, |caps: &fancy_regex::Captures<str>| {
+            idx += 1;
+            let mut dst = String::new();
+            if nth.is_none_or(|nth| nth == idx) {
+                let is_set = |group: &str| {
+                    let found = match group.parse() {
+                        Ok(idx) => caps.get(idx),
+                        Err(_) => caps.name(group),
+                    };
+                    found.is_some_and(|found| !found.as_str().is_empty())
+                };
+                let replacement = template::resolve_conditionals(replacement, &is_set);
+                caps.expand(&replacement, &mut dst);
+            } else {
+                dst.push_str(&caps[0]);
+            }
+            dst
+        })
+        .map(|replaced| replaced.into_owned())
+        .map_err(|err| err.to_string())
     }
 
-    pub fn with_replacement(mut self, replacement: String) -> Self {
-        self.replacement = replacement;
-        self
+    fn group_names(&self) -> Vec<String> {
+        group_names(self.capture_names())
     }
 
-    fn is_global(&self) -> bool {
-        self.flags.contains(MatchFlags::GLOBAL)
+    fn captures(&self, text: &str) -> Result<Option<Vec<Option<String>>>, String> {
+        let caps = fancy_regex::Regex::captures(self, text).map_err(|err| err.to_string())?;
+        Ok(caps.map(|caps| {
+            (1..caps.len())
+                .map(|idx| caps.get(idx).map(|group| group.as_str().to_owned()))
+                .collect()
+        }))
     }
+}
 
-    pub fn run<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> std::io::Result<AppResult> {
-        let mut keys_iter = termion::async_stdin().keys();
+/// compile the pattern of a rule like the app does: `mode` defines its syntax,
+/// and `flags` except `g` become inline flags of the regex. `None` if the pattern is invalid
+pub fn compose_regex(
+    pattern: &str,
+    flags: MatchFlags,
+    mode: MatchMode,
+    engine: Engine,
+) -> Option<Box<dyn RegexBackend>> {
+    let regex_str = match mode {
+        MatchMode::Regex => pattern.to_owned(),
+        MatchMode::Glob => glob_to_regex(pattern),
+        MatchMode::Literal => regex::escape(pattern),
+    };
+    let composed_str = if flags.contains(MatchFlags::EXTENDED) {
+        // a `#` comment would hide the closing parenthesis otherwise
+        format!("(?{}:{}\n)", flags.regex_flags(), regex_str)
+    } else {
+        format!("(?{}:{})", flags.regex_flags(), regex_str)
+    };
 
-        loop {
-            terminal.draw(|f| self.ui(f))?;
+    engine.compile(&composed_str)
+}
 
-            let edited_string = match self.active_area {
-                EditableArea::Regex => &mut self.regex,
-                EditableArea::Replace => &mut self.replacement,
-            };
+fn compose_replacement(replacement: &str, mode: MatchMode) -> String {
+    match mode {
+        MatchMode::Regex => replacement.to_owned(),
+        MatchMode::Glob => glob_replacement(replacement),
+        MatchMode::Literal => replacement.replace('$', "$$"),
+    }
+}
 
-            if let Some(Ok(key)) = keys_iter.next() {
-                match key {
-                    Key::Ctrl('c') => {
-                        return Ok(AppResult::Exit);
-                    }
-                    Key::Char('\t') => {
-                        self.active_area = self.active_area.next();
-                    }
-                    Key::BackTab => {
-                        self.active_area = self.active_area.prev();
-                    }
-                    Key::Backspace => {
-                        edited_string.pop();
-                    }
-                    Key::Ctrl('g') => {
-                        self.flags ^= MatchFlags::GLOBAL;
-                    }
-                    Key::Ctrl('r') => {
-                        self.flags ^= MatchFlags::ICASE;
-                    }
-                    Key::Char('\n') => {
-                        let re = Regex::new(&self.regex).ok();
+fn try_replace(
+    text: &str,
+    regex: Option<&dyn RegexBackend>,
+    replacement: &str,
+    targets: Targets,
+) -> ReplacementResult {
+    let Some(regex) = regex else {
+        return ReplacementResult::InvalidRegex;
+    };
 
-                        let move_pairs: Vec<(PathBuf, PathBuf)> = self
-                            .source_files
-                            .clone()
-                            .into_iter()
-                            .filter_map(path_utils::split_path)
-                            .filter_map(|(parent, name)| {
-                                match try_replace(&name, &re, &self.replacement, self.is_global()) {
-                                    ReplacementResult::Replaced(dst_name) => {
-                                        let src_path = parent.join(name);
-                                        let dst_path = parent.join(dst_name);
-
-                                        Some((src_path, dst_path))
-                                    }
-                                    _ => None,
-                                }
-                            })
-                            .collect();
+    let replaced = match regex.is_match(text) {
+        Ok(false) => return ReplacementResult::NoMatch,
+        Ok(true) => regex.replace(text, replacement, targets),
+        Err(err) => Err(err),
+    };
+    match replaced {
+        Ok(replaced) if replaced == text => ReplacementResult::Unchanged,
+        Ok(replaced) => ReplacementResult::Replaced(replaced),
+        Err(err) => ReplacementResult::Failed(err),
+    }
+}
 
-                        return Ok(AppResult::MoveFiles(move_pairs));
-                    }
-                    Key::Char(ch) => {
-                        edited_string.push(ch);
-                    }
-                    _ => {}
-                }
+/// rule ready to be applied to file names
+struct CompiledRule {
+    regex: Option<Box<dyn RegexBackend>>,
+    replacement: Template,
+    targets: Targets,
+}
+
+/// apply rules one after another, each to the result of the previous one.
+/// The text is considered matched if at least one rule matches it
+fn try_replace_chain(text: &str, rules: &[CompiledRule], ctx: &TokenContext) -> ReplacementResult {
+    let mut current = text.to_owned();
+    let mut matched = false;
+
+    for rule in rules {
+        let replacement = match rule.replacement.try_expand(ctx) {
+            Ok(replacement) => replacement,
+            // a missing value matters only for the matched names
+            Err(err) => match rule.regex.as_deref().map(|regex| regex.is_match(&current)) {
+                None => return ReplacementResult::InvalidRegex,
+                Some(Ok(false)) => continue,
+                Some(_) => return ReplacementResult::Failed(err),
+            },
+        };
+        match try_replace(&current, rule.regex.as_deref(), &replacement, rule.targets) {
+            ReplacementResult::InvalidRegex => return ReplacementResult::InvalidRegex,
+            ReplacementResult::Failed(err) => return ReplacementResult::Failed(err),
+            ReplacementResult::NoMatch => {}
+            ReplacementResult::Unchanged => matched = true,
+            ReplacementResult::Replaced(replaced) => {
+                matched = true;
+                current = replaced;
             }
-            thread::sleep(Duration::from_millis(20));
         }
     }
 
-    fn ui<B: Backend>(&self, frame: &mut Frame<B>) {
-        let re = compose_regex(&self.regex, self.flags);
+    if !matched {
+        ReplacementResult::NoMatch
+    } else if current == text {
+        ReplacementResult::Unchanged
+    } else {
+        ReplacementResult::Replaced(current)
+    }
+}
 
-        // editor and help areas
-        let main_layout = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([Constraint::Min(50), Constraint::Max(25)])
-            .split(frame.size());
+/// edit the input at the cursor, `None` is its end before and after the edit
+fn edit_at_cursor(text: &mut String, cursor: Option<usize>, edit: Edit) -> Option<usize> {
+    let cursor = line_edit::apply(text, cursor.unwrap_or(text.len()), edit);
+    (cursor < text.len()).then_some(cursor)
+}
 
-        // editor area: regex, replacement, flags, files list
-        let editor_layout = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Length(3), Constraint::Percentage(100)])
-            .split(main_layout[0].inner(&Margin {
-                vertical: 0,
-                horizontal: 0,
-            }));
+/// run the post-processing steps on the name produced by the rules
+fn apply_transforms(
+    name: &str,
+    result: ReplacementResult,
+    transforms: &[&dyn NameTransform],
+) -> ReplacementResult {
+    if transforms.is_empty() {
+        return result;
+    }
+
+    let mut current = match result {
+        ReplacementResult::Replaced(replaced) => replaced,
+        ReplacementResult::Unchanged => name.to_owned(),
+        other => return other,
+    };
+    for transform in transforms {
+        match transform.apply(&current) {
+            Ok(transformed) => current = transformed,
+            Err(err) => return ReplacementResult::Failed(err),
+        }
+    }
+
+    if current == name {
+        ReplacementResult::Unchanged
+    } else {
+        ReplacementResult::Replaced(current)
+    }
+}
+
+/// shorten the new names which are too long. Only the last component of a path is changed
+fn truncate_entries(entries: &mut [PlanEntry], truncation: &Truncation) {
+    for entry in entries {
+        let ReplacementResult::Replaced(dst_name) = &entry.result else {
+            continue;
+        };
+        let (dir, file_name) = match dst_name.rfind('/') {
+            Some(slash) => dst_name.split_at(slash + 1),
+            None => ("", dst_name.as_str()),
+        };
+        if let Some(truncated) = truncation.truncate(file_name) {
+            entry.result = ReplacementResult::Replaced(format!("{}{}", dir, truncated));
+            entry.truncated = true;
+        }
+    }
+}
+
+/// append conflict numbers to the new names taken by earlier files of the plan
+/// or by existing files which aren't renamed. Files are numbered in the plan order,
+/// so the same files always get the same names
+fn suffix_conflicts(entries: &mut [PlanEntry], compound: bool) {
+    let is_renamed = |entry: &PlanEntry| matches!(entry.result, ReplacementResult::Replaced(_));
+    let sources: HashSet<PathBuf> = entries
+        .iter()
+        .filter(|entry| is_renamed(entry))
+        .map(|entry| path_utils::join_name(&entry.parent, &entry.name))
+        .collect();
+    // files which aren't renamed keep their paths
+    let mut taken: HashSet<PathBuf> = entries
+        .iter()
+        .filter(|entry| !is_renamed(entry))
+        .map(|entry| path_utils::join_name(&entry.parent, &entry.name))
+        .collect();
+
+    for entry in entries {
+        let ReplacementResult::Replaced(dst_name) = &entry.result else {
+            continue;
+        };
+        let src = path_utils::join_name(&entry.parent, &entry.name);
+        let (dir, file_name) = match dst_name.rfind('/') {
+            Some(slash) => dst_name.split_at(slash + 1),
+            None => ("", dst_name.as_str()),
+        };
+
+        let mut candidate = dst_name.clone();
+        let mut n = 0;
+        loop {
+            let dst = path_utils::normalize_path(&path_utils::join_name(&entry.parent, &candidate));
+            // sources of the plan are moved away, so their paths aren't conflicts
+            let is_free = !taken.contains(&dst)
+                && (sources.contains(&dst) || !planner::is_overwritten(&src, &dst));
+            if is_free {
+                taken.insert(dst);
+                break;
+            }
+            n += 1;
+            candidate = format!(
+                "{}{}",
+                dir,
+                planner::conflict_suffixed(file_name, n, compound)
+            );
+        }
+
+        if n > 0 {
+            entry.result = ReplacementResult::Replaced(candidate);
+            entry.suffixed = true;
+        }
+    }
+}
+
+/// why the new name of a renamed entry can't be used on Windows
+fn portability_problem(entry: &PlanEntry) -> Option<String> {
+    let ReplacementResult::Replaced(dst_name) = &entry.result else {
+        return None;
+    };
+    portability::windows_problem(dst_name.rsplit('/').next().unwrap_or_default())
+}
+
+/// attempts to generate values of `{uuid}`, `{rand}`, etc. without collisions
+const MAX_REGENERATIONS: usize = 16;
+
+/// files whose metadata is read at once by default
+pub const DEFAULT_METADATA_JOBS: usize = 4;
+
+/// path offered for the exported plan without `--plan-out`
+const DEFAULT_PLAN_FILE: &str = "irename-plan.json";
+
+/// rows skipped by PageUp and PageDown in the files list
+const LIST_PAGE: usize = 10;
+
+/// rows of the files list scrolled by a turn of the mouse wheel
+const WHEEL_ROWS: usize = 3;
+
+/// indices of the renamed entries whose destinations are taken by other entries
+fn destination_collisions(entries: &[PlanEntry]) -> Vec<usize> {
+    let destination = |entry: &PlanEntry| match &entry.result {
+        ReplacementResult::Replaced(dst_name) => Some(path_utils::normalize_path(
+            &path_utils::join_name(&entry.parent, dst_name),
+        )),
+        _ => None,
+    };
+
+    // files which aren't renamed keep their paths
+    let mut taken: HashSet<PathBuf> = entries
+        .iter()
+        .filter(|entry| destination(entry).is_none())
+        .map(|entry| path_utils::join_name(&entry.parent, &entry.name))
+        .collect();
+
+    entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| destination(entry).is_some_and(|dst| !taken.insert(dst)))
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+/// settings which can be changed in the app
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// the rules chain with the edited rule last
+    rules: Vec<RenameRule>,
+    expression: bool,
+    engine: Engine,
+    match_path: bool,
+    sanitize: bool,
+    case_style: Option<CaseStyle>,
+    excluded_root: Option<PathBuf>,
+    filter_by_ext: bool,
+    list_order: ListOrder,
+    #[serde(default)]
+    file_order: FileOrder,
+}
+
+impl Session {
+    /// change the paths of the settings, e.g. when the session is opened on another machine
+    pub fn map_paths(&mut self, f: impl Fn(&Path) -> PathBuf) {
+        self.excluded_root = self.excluded_root.as_deref().map(f);
+    }
+}
+
+/// a source file and what the rules make of its name
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PlanEntry {
+    parent: PathBuf,
+    name: String,
+    result: ReplacementResult,
+    /// the new name was shortened to `max_name_bytes`
+    #[serde(default)]
+    truncated: bool,
+    /// the new name got a conflict number with `ConflictPolicy::Suffix`
+    #[serde(default)]
+    suffixed: bool,
+    /// git tokens of the new name are empty because the file isn't committed
+    #[serde(default)]
+    not_in_git: bool,
+    /// the file is unchecked in the list, the rules aren't applied to it
+    #[serde(default)]
+    unchecked: bool,
+}
+
+/// renamed files on a mount point
+#[derive(Debug, PartialEq, Eq)]
+struct RootSummary {
+    root: PathBuf,
+    files: usize,
+    renamed: usize,
+    excluded: bool,
+}
+
+impl Display for RootSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.excluded {
+            write!(f, "{}: excluded", self.root.display())
+        } else {
+            write!(
+                f,
+                "{}: {}/{}",
+                self.root.display(),
+                self.renamed,
+                self.files
+            )
+        }
+    }
+}
+
+pub enum AppResult {
+    MoveFiles(Vec<(PathBuf, PathBuf)>),
+    Exit,
+}
+
+pub struct App {
+    /// current value of the regex input box
+    regex: String,
+    /// current value of the replacement string box
+    replacement: String,
+    /// match flags
+    flags: MatchFlags,
+    /// syntax of the pattern
+    mode: MatchMode,
+    /// single match replaced by the edited rule
+    occurrence: Option<Occurrence>,
+    /// rules applied before the edited one
+    rules: Vec<RenameRule>,
+    /// settings when the app was started. Exiting with them needs no confirmation
+    launch: Option<Session>,
+    /// settings before the last reset, so it can be undone
+    before_reset: Option<Session>,
+    /// active editing area where the cursor is
+    active_area: EditableArea,
+    /// cursor in the edited input as a byte offset, `None` at the end
+    cursor: Option<usize>,
+    /// selected row of the files list when it's the active area
+    list_cursor: usize,
+    /// first shown row of the files list
+    list_scroll: Cell<usize>,
+    /// areas of the last drawn screen
+    click_areas: Cell<ClickAreas>,
+    /// files unchecked in the list, they are shown but not renamed
+    unchecked_files: BTreeSet<PathBuf>,
+    /// source files to rename
+    source_files: Vec<PathBuf>,
+    /// input paths dropped because they are the same as other ones after normalization
+    duplicate_files: usize,
+    /// why source files can't be renamed: they are missing or can't be accessed
+    source_problems: HashMap<PathBuf, String>,
+    /// missing and inaccessible input paths dropped from the list
+    dropped_sources: usize,
+    /// drop the files added later too if they are missing or inaccessible
+    drop_missing: bool,
+    /// how the symlinks among the source files are treated, shown next to them
+    symlink_markers: HashMap<PathBuf, String>,
+    /// symlinks left out of the source files
+    skipped_symlinks: usize,
+    /// applied to the files added by the directory walk too
+    symlink_policy: SymlinkPolicy,
+    /// input files selected by globs, also the ones added by the directory walk
+    path_filter: PathFilter,
+    /// input files which don't pass the path filter
+    filtered_out: usize,
+    /// directory walk still adding files
+    scan: Option<DirScan>,
+    /// paths skipped by the walk because of `.gitignore` and `.ignore` files
+    ignored_by_rules: usize,
+    /// kinds of the input files selected by `--type`, shown in a column of the list if any
+    file_types: Vec<FileKind>,
+    /// input files of other kinds
+    other_types: usize,
+    /// input files outside of the size and modification time limits
+    out_of_limits: usize,
+    /// kinds of the input files for the column, read in the background
+    kinds: Prefetcher<Option<FileKind>>,
+    /// never execute the renaming, only show the plan
+    preview_only: bool,
+    /// sed-style expression edited instead of the separate regex and replacement inputs
+    expression: Option<String>,
+    /// currently shown popup
+    popup: Option<Popup>,
+    /// plans exceeding these limits must be confirmed
+    confirm_thresholds: ConfirmThresholds,
+    /// value of the `{n}` token for the first renamed file
+    counter_start: u64,
+    /// start the counter after the largest number already present in destination directories
+    continue_numbering: bool,
+    /// order of assigning counter values
+    numbering_order: NumberingOrder,
+    /// restart the counter in every source directory
+    counter_per_dir: bool,
+    /// `{ext}` of `a.tar.gz` is `.tar.gz` instead of `.gz`
+    compound_ext: bool,
+    /// regex implementation used by all the rules
+    engine: Engine,
+    /// order of the files list, doesn't affect the renaming
+    list_order: ListOrder,
+    /// order of the source files, also the order of the counters
+    file_order: FileOrder,
+    /// positions of the files in the input, to restore the input order and break ties
+    input_positions: HashMap<PathBuf, usize>,
+    /// apply the rules to full paths instead of names
+    match_path: bool,
+    /// re-sequence numbers already present in the resulting names
+    renumber: bool,
+    /// digits of the re-sequenced numbers, `None` to keep the original width
+    renumber_width: Option<usize>,
+    /// names of files in directories scanned for `continue_numbering`
+    dir_listings: RefCell<HashMap<PathBuf, Vec<String>>>,
+    /// metadata of the destinations fetched in the background
+    prefetcher: Prefetcher,
+    /// mount points of the directories of the source files
+    mount_roots: RefCell<HashMap<PathBuf, PathBuf>>,
+    /// files on this mount point aren't renamed
+    excluded_root: Option<PathBuf>,
+    /// extensions of the renamed files
+    ext_filter: ExtFilter,
+    /// apply `ext_filter`, it can be turned off in the app
+    filter_by_ext: bool,
+    /// plans of previous sessions
+    plan_cache: Option<PlanCache>,
+    /// last computed plan with its key
+    cached_plan: RefCell<Option<(u64, Vec<PlanEntry>)>>,
+    /// time of the session used by the `{now}` token
+    started: DateTime<Local>,
+    /// generator token values of the source files, stable while the app is running
+    generated: RefCell<HashMap<PathBuf, Generated>>,
+    /// creation times of the source files fetched for the `{btime}` token
+    btimes: RefCell<HashMap<PathBuf, Result<DateTime<Local>, String>>>,
+    /// metadata of the source files fetched in the background for the video tokens
+    videos: Prefetcher<Result<VideoInfo, String>>,
+    /// last commits of the source files fetched in the background for the git tokens
+    git_infos: Prefetcher<Option<GitInfo>>,
+    /// how long a plan waits for the metadata before showing the files as pending.
+    /// `None` to wait for all of it
+    metadata_budget: Option<Duration>,
+    /// post-processing steps applied after the rules
+    transforms: Vec<Box<dyn NameTransform>>,
+    /// case convention applied right after the rules
+    case_style: Option<CaseStyle>,
+    /// shorten the new names after all the other post-processing steps
+    truncation: Option<Truncation>,
+    /// how destinations taken by other files are handled
+    on_conflict: ConflictPolicy,
+    /// replaced files are moved to the trash
+    use_trash: bool,
+    /// missing destination directories are created
+    mkdir: bool,
+    /// destination directories known to exist or not, for showing the new ones
+    existing_dirs: RefCell<HashMap<PathBuf, bool>>,
+    /// refuse to rename files to names which can't be used on Windows
+    strict_portability: bool,
+    /// limits of the new paths highlighted in the list
+    path_limits: PathLimits,
+    /// apply the sanitizer after the other post-processing steps
+    sanitize: bool,
+    sanitizer: Sanitizer,
+    /// unicode form of the new names, applied after the sanitizer
+    normalization: Option<Normalization>,
+    /// compact ui drawn inline in the terminal
+    inline: bool,
+    /// walkthrough shown instead of the help
+    tutorial: Option<Tutorial>,
+    /// user settings and where to save them
+    config: Config,
+    config_path: PathBuf,
+    /// default path of the exported plan
+    plan_out: Option<PathBuf>,
+    /// inputs of the previous sessions and where to save them. Nothing is saved without a path
+    input_history: InputHistory,
+    input_history_path: Option<PathBuf>,
+    /// position in the history of the focused input while it's browsed with Up and Down
+    browsing: Option<HistoryBrowsing>,
+}
+
+/// position in the input history and the value typed before browsing it
+struct HistoryBrowsing {
+    idx: usize,
+    typed: String,
+}
+
+impl Default for App {
+    fn default() -> App {
+        App {
+            regex: String::new(),
+            replacement: String::new(),
+            flags: MatchFlags::NO_FLAGS,
+            mode: MatchMode::Regex,
+            occurrence: None,
+            rules: Vec::new(),
+            launch: None,
+            before_reset: None,
+            active_area: EditableArea::Regex,
+            cursor: None,
+            list_cursor: 0,
+            list_scroll: Cell::new(0),
+            click_areas: Cell::new(ClickAreas::default()),
+            unchecked_files: BTreeSet::new(),
+            source_files: Vec::new(),
+            duplicate_files: 0,
+            source_problems: HashMap::new(),
+            dropped_sources: 0,
+            drop_missing: false,
+            symlink_markers: HashMap::new(),
+            skipped_symlinks: 0,
+            symlink_policy: SymlinkPolicy::default(),
+            path_filter: PathFilter::default(),
+            filtered_out: 0,
+            scan: None,
+            ignored_by_rules: 0,
+            file_types: Vec::new(),
+            other_types: 0,
+            out_of_limits: 0,
+            kinds: Prefetcher::new(DEFAULT_METADATA_JOBS, FileKind::of),
+            preview_only: false,
+            expression: None,
+            popup: None,
+            confirm_thresholds: ConfirmThresholds::default(),
+            counter_start: 1,
+            continue_numbering: false,
+            numbering_order: NumberingOrder::Forward,
+            counter_per_dir: false,
+            compound_ext: false,
+            engine: Engine::Std,
+            list_order: ListOrder::Input,
+            file_order: FileOrder::Input,
+            input_positions: HashMap::new(),
+            match_path: false,
+            renumber: false,
+            renumber_width: None,
+            dir_listings: RefCell::new(HashMap::new()),
+            prefetcher: Prefetcher::default(),
+            mount_roots: RefCell::new(HashMap::new()),
+            excluded_root: None,
+            ext_filter: ExtFilter::default(),
+            filter_by_ext: false,
+            plan_cache: None,
+            cached_plan: RefCell::new(None),
+            started: Local::now(),
+            generated: RefCell::new(HashMap::new()),
+            btimes: RefCell::new(HashMap::new()),
+            videos: Prefetcher::new(DEFAULT_METADATA_JOBS, VideoInfo::probe),
+            git_infos: Prefetcher::new(DEFAULT_METADATA_JOBS, GitInfo::of),
+            metadata_budget: None,
+            transforms: Vec::new(),
+            case_style: None,
+            truncation: None,
+            on_conflict: ConflictPolicy::default(),
+            use_trash: false,
+            mkdir: false,
+            existing_dirs: RefCell::new(HashMap::new()),
+            strict_portability: false,
+            path_limits: PathLimits::native(),
+            sanitize: false,
+            sanitizer: Sanitizer::default(),
+            normalization: None,
+            inline: false,
+            tutorial: None,
+            config: Config::default(),
+            config_path: PathBuf::new(),
+            plan_out: None,
+            input_history: InputHistory::default(),
+            input_history_path: None,
+            browsing: None,
+        }
+    }
+}
+
+impl App {
+    pub fn with_files(mut self, files: Vec<PathBuf>) -> Self {
+        let files = files
+            .into_iter()
+            .map(|path| path_utils::normalize_input_path(&path))
+            .collect();
+        (self.source_files, self.duplicate_files) = planner::dedup_paths(files);
+        self.input_positions = self
+            .source_files
+            .iter()
+            .enumerate()
+            .map(|(idx, path)| (path.clone(), idx))
+            .collect();
+        self.sort_sources();
+        // stale paths are common in lists from stdin, they are shown before renaming fails
+        self.source_problems = self
+            .source_files
+            .iter()
+            .filter_map(|path| Some((path.clone(), planner::source_problem(path)?)))
+            .collect();
+        self
+    }
+
+    /// rename symlinks among the files, their targets instead or leave them out
+    pub fn with_symlink_policy(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlink_policy = policy;
+        let files = std::mem::take(&mut self.source_files)
+            .into_iter()
+            .filter_map(|path| self.apply_symlink_policy(path))
+            .collect();
+
+        // a target can be among the files already
+        let duplicates;
+        (self.source_files, duplicates) = planner::dedup_paths(files);
+        self.duplicate_files += duplicates;
+        self.sort_sources();
+        self
+    }
+
+    /// show the kinds of the input files, which are selected by the scan, in the list
+    pub fn with_file_types(mut self, types: Vec<FileKind>) -> Self {
+        self.file_types = types;
+        self
+    }
+
+    /// leave out the input files which don't match the globs
+    pub fn with_path_filter(mut self, path_filter: PathFilter) -> Self {
+        let before = self.source_files.len();
+        self.source_files.retain(|path| path_filter.matches(path));
+        self.filtered_out += before - self.source_files.len();
+        self.path_filter = path_filter;
+        self
+    }
+
+    /// the file to rename instead of `path` if it's a symlink, `None` if it's skipped
+    fn apply_symlink_policy(&mut self, path: PathBuf) -> Option<PathBuf> {
+        let is_symlink =
+            std::fs::symlink_metadata(&path).is_ok_and(|meta| meta.file_type().is_symlink());
+        if !is_symlink {
+            return Some(path);
+        }
+
+        match self.symlink_policy {
+            SymlinkPolicy::Rename => {
+                self.symlink_markers
+                    .insert(path.clone(), "symlink".to_owned());
+                Some(path)
+            }
+            SymlinkPolicy::Follow => match std::fs::canonicalize(&path) {
+                Ok(target) => {
+                    // the target takes the place of the symlink in the input order
+                    if let Some(&position) = self.input_positions.get(&path) {
+                        self.input_positions
+                            .entry(target.clone())
+                            .or_insert(position);
+                    }
+                    let marker = format!("target of {}", path.display());
+                    self.symlink_markers.insert(target.clone(), marker);
+                    Some(target)
+                }
+                // there is nothing to follow
+                Err(_) => {
+                    let marker = "broken symlink, renamed itself".to_owned();
+                    self.symlink_markers.insert(path.clone(), marker);
+                    Some(path)
+                }
+            },
+            SymlinkPolicy::Skip => {
+                self.skipped_symlinks += 1;
+                None
+            }
+        }
+    }
+
+    /// add the files of a directory walk to the list while the app is running
+    pub fn with_scan(mut self, scan: DirScan) -> Self {
+        self.scan = Some(scan);
+        self
+    }
+
+    /// add the files found by the walk since the last call
+    fn poll_scan(&mut self) {
+        let Some(scan) = &self.scan else {
+            return;
+        };
+        let (files, finished) = scan.poll();
+        self.ignored_by_rules = scan.ignored();
+        self.other_types = scan.other_types();
+        self.out_of_limits = scan.out_of_limits();
+        if finished {
+            self.scan = None;
+        }
+        self.add_files(files);
+    }
+
+    /// wait for the walk to finish and add all its files
+    pub fn finish_scan(&mut self) {
+        if let Some(scan) = self.scan.take() {
+            let files = scan.finish();
+            self.ignored_by_rules = scan.ignored();
+            self.other_types = scan.other_types();
+            self.out_of_limits = scan.out_of_limits();
+            self.add_files(files);
+        }
+    }
+
+    /// rename other files with the same rules, the filters and the symlink policy apply to them
+    pub fn replace_files(&mut self, files: Vec<PathBuf>) {
+        self.source_files.clear();
+        self.source_problems.clear();
+        self.input_positions.clear();
+        self.unchecked_files.clear();
+        self.list_cursor = 0;
+        self.list_scroll.set(0);
+        self.add_files(files);
+    }
+
+    /// add files found after the app is built, the symlink policy applies to them too
+    fn add_files(&mut self, files: Vec<PathBuf>) {
+        if files.is_empty() {
+            return;
+        }
+
+        let (files, filtered_out): (Vec<PathBuf>, Vec<PathBuf>) = files
+            .into_iter()
+            .map(|path| path_utils::normalize_input_path(&path))
+            .partition(|path| self.path_filter.matches(path));
+        self.filtered_out += filtered_out.len();
+        let files: Vec<PathBuf> = files
+            .into_iter()
+            .filter_map(|path| self.apply_symlink_policy(path))
+            .collect();
+        let known: HashSet<&PathBuf> = self.source_files.iter().collect();
+        let (files, duplicates) = planner::dedup_paths(
+            files
+                .into_iter()
+                .filter(|path| !known.contains(path))
+                .collect(),
+        );
+        self.duplicate_files += duplicates;
+        // stale paths are common in lists from stdin, they are shown before renaming fails
+        let mut files = files;
+        for path in &files {
+            if let Some(problem) = planner::source_problem(path) {
+                self.source_problems.insert(path.clone(), problem);
+            }
+        }
+        if self.drop_missing {
+            let count = files.len();
+            files.retain(|path| self.source_problems.remove(path).is_none());
+            self.dropped_sources += count - files.len();
+        }
+        let next_position = self.input_positions.len();
+        for (idx, path) in files.iter().enumerate() {
+            self.input_positions
+                .entry(path.clone())
+                .or_insert(next_position + idx);
+        }
+        self.source_files.extend(files);
+        self.sort_sources();
+    }
+
+    /// add the files typed in the app, a glob pattern adds all its matches
+    fn add_typed_files(&mut self, typed: &str) -> Result<(), String> {
+        let paths = if filter::is_glob(typed) {
+            scan::expand_glob(typed)
+        } else {
+            vec![PathBuf::from(typed)]
+        };
+        if typed.is_empty() || paths.iter().all(|path| path.symlink_metadata().is_err()) {
+            return Err(format!("nothing matches {}", typed));
+        }
+
+        let count = self.source_files.len();
+        self.add_files(paths);
+        if self.source_files.len() == count {
+            return Err("the files are in the list already or filtered out".to_owned());
+        }
+        Ok(())
+    }
+
+    /// sort the files in the file order, ties are broken by the input order
+    fn sort_sources(&mut self) {
+        let positions = &self.input_positions;
+        self.source_files
+            .sort_by_key(|path| positions.get(path).copied().unwrap_or(usize::MAX));
+        planner::sort_paths(&mut self.source_files, self.file_order);
+    }
+
+    /// drop the missing and inaccessible files from the list
+    pub fn with_missing_dropped(mut self) -> Self {
+        let problems = std::mem::take(&mut self.source_problems);
+        self.source_files
+            .retain(|path| !problems.contains_key(path));
+        self.dropped_sources += problems.len();
+        self.drop_missing = true;
+        self
+    }
+
+    pub fn source_files(&self) -> &[PathBuf] {
+        &self.source_files
+    }
+
+    pub fn with_file_order(mut self, file_order: FileOrder) -> Self {
+        self.file_order = file_order;
+        self.sort_sources();
+        self
+    }
+
+    pub fn with_regex(mut self, regex: String) -> Self {
+        self.regex = regex;
+        self
+    }
+
+    pub fn with_replacement(mut self, replacement: String) -> Self {
+        self.replacement = replacement;
+        self
+    }
+
+    pub fn with_rule(mut self, rule: RenameRule) -> Self {
+        self.load_rule(rule);
+        self
+    }
+
+    /// rules applied one after another before the edited one
+    pub fn with_rules(mut self, rules: Vec<RenameRule>) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: MatchMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_preview_only(mut self, preview_only: bool) -> Self {
+        self.preview_only = preview_only;
+        self
+    }
+
+    pub fn with_confirm_thresholds(mut self, thresholds: ConfirmThresholds) -> Self {
+        self.confirm_thresholds = thresholds;
+        self
+    }
+
+    pub fn with_counter_start(mut self, start: u64) -> Self {
+        self.counter_start = start;
+        self
+    }
+
+    pub fn with_continue_numbering(mut self, continue_numbering: bool) -> Self {
+        self.continue_numbering = continue_numbering;
+        self
+    }
+
+    /// add a post-processing step applied to the names produced by the rules
+    pub fn with_transform(mut self, transform: Box<dyn NameTransform>) -> Self {
+        self.transforms.push(transform);
+        self
+    }
+
+    pub fn with_engine(mut self, engine: Engine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    pub fn with_match_path(mut self, match_path: bool) -> Self {
+        self.match_path = match_path;
+        self
+    }
+
+    pub fn with_renumber(mut self, renumber: bool) -> Self {
+        self.renumber = renumber;
+        self
+    }
+
+    pub fn with_renumber_width(mut self, width: Option<usize>) -> Self {
+        self.renumber_width = width;
+        self
+    }
+
+    pub fn with_sanitize(mut self, sanitize: bool) -> Self {
+        self.sanitize = sanitize;
+        self
+    }
+
+    pub fn with_sanitizer(mut self, sanitizer: Sanitizer) -> Self {
+        self.sanitizer = sanitizer;
+        self
+    }
+
+    pub fn with_normalization(mut self, normalization: Option<Normalization>) -> Self {
+        self.normalization = normalization;
+        self
+    }
+
+    pub fn with_counter_per_dir(mut self, counter_per_dir: bool) -> Self {
+        self.counter_per_dir = counter_per_dir;
+        self
+    }
+
+    pub fn with_case_style(mut self, case_style: Option<CaseStyle>) -> Self {
+        self.case_style = case_style;
+        self
+    }
+
+    /// read the metadata of at most `jobs` files at once
+    pub fn with_metadata_jobs(mut self, jobs: usize) -> Self {
+        self.videos = Prefetcher::new(jobs, VideoInfo::probe);
+        self.git_infos = Prefetcher::new(jobs, GitInfo::of);
+        self
+    }
+
+    pub fn with_metadata_budget(mut self, budget: Option<Duration>) -> Self {
+        self.metadata_budget = budget;
+        self
+    }
+
+    pub fn with_truncation(mut self, truncation: Option<Truncation>) -> Self {
+        self.truncation = truncation;
+        self
+    }
+
+    pub fn with_on_conflict(mut self, on_conflict: ConflictPolicy) -> Self {
+        self.on_conflict = on_conflict;
+        self
+    }
+
+    pub fn with_use_trash(mut self, use_trash: bool) -> Self {
+        self.use_trash = use_trash;
+        self
+    }
+
+    pub fn with_mkdir(mut self, mkdir: bool) -> Self {
+        self.mkdir = mkdir;
+        self
+    }
+
+    pub fn with_strict_portability(mut self, strict_portability: bool) -> Self {
+        self.strict_portability = strict_portability;
+        self
+    }
+
+    pub fn with_list_order(mut self, list_order: ListOrder) -> Self {
+        self.list_order = list_order;
+        self
+    }
+
+    pub fn with_compound_ext(mut self, compound_ext: bool) -> Self {
+        self.compound_ext = compound_ext;
+        self
+    }
+
+    pub fn with_ext_filter(mut self, ext_filter: ExtFilter) -> Self {
+        self.filter_by_ext = !ext_filter.is_empty();
+        self.ext_filter = ext_filter;
+        self
+    }
+
+    pub fn with_plan_cache(mut self, plan_cache: PlanCache) -> Self {
+        self.plan_cache = Some(plan_cache);
+        self
+    }
+
+    pub fn with_numbering_order(mut self, order: NumberingOrder) -> Self {
+        self.numbering_order = order;
+        self
+    }
+
+    pub fn with_inline(mut self, inline: bool) -> Self {
+        self.inline = inline;
+        self
+    }
+
+    pub fn with_tutorial(mut self, tutorial: Tutorial) -> Self {
+        self.tutorial = Some(tutorial);
+        self
+    }
+
+    pub fn with_config(mut self, config: Config, path: PathBuf) -> Self {
+        self.config = config;
+        self.config_path = path;
+        self
+    }
+
+    pub fn with_plan_out(mut self, plan_out: Option<PathBuf>) -> Self {
+        self.plan_out = plan_out;
+        self
+    }
+
+    /// the previewed renames with their details and the settings they are computed with
+    pub fn plan_file(&self) -> PlanFile {
+        let move_pairs = self.move_pairs();
+        let conflicts = planner::conflicting_destinations(&move_pairs, &self.source_files);
+        PlanFile::new(&move_pairs, &conflicts, self.session())
+    }
+
+    pub fn with_input_history(mut self, history: InputHistory, path: PathBuf) -> Self {
+        self.input_history = history;
+        self.input_history_path = Some(path);
+        self
+    }
+
+    /// add the current inputs to the history and save it
+    pub fn remember_inputs(&mut self) -> anyhow::Result<()> {
+        let Some(path) = &self.input_history_path else {
+            return Ok(());
+        };
+        self.input_history.remember(&self.regex, &self.replacement);
+        self.input_history.save(path)
+    }
+
+    /// replace the focused input with an older or a newer value from the history.
+    /// Going past the latest one brings back the typed value
+    fn browse_history(&mut self, older: bool) {
+        // the expression input has no history
+        if self.expression.is_some() {
+            return;
+        }
+        let (edited, values) = match self.active_area {
+            EditableArea::Regex => (&mut self.regex, &self.input_history.regexes),
+            EditableArea::Replace => (&mut self.replacement, &self.input_history.replacements),
+            EditableArea::Files => return,
+        };
+
+        let next = match (&self.browsing, older) {
+            (None, true) => values.len().checked_sub(1),
+            (None, false) => return,
+            (Some(browsing), true) => Some(browsing.idx.saturating_sub(1)),
+            (Some(browsing), false) => Some(browsing.idx + 1).filter(|idx| *idx < values.len()),
+        };
+
+        match next {
+            Some(idx) => {
+                let typed = match self.browsing.take() {
+                    Some(browsing) => browsing.typed,
+                    None => edited.clone(),
+                };
+                *edited = values[idx].clone();
+                self.browsing = Some(HistoryBrowsing { idx, typed });
+            }
+            None => {
+                if let Some(browsing) = self.browsing.take() {
+                    *edited = browsing.typed;
+                }
+            }
+        }
+    }
+
+    fn rule(&self) -> RenameRule {
+        RenameRule {
+            regex: self.regex.clone(),
+            replacement: self.replacement.clone(),
+            flags: self.flags,
+            mode: self.mode,
+            occurrence: self.occurrence,
+        }
+    }
+
+    /// move the edited rule to the end of the rules chain and start a new one
+    fn push_rule(&mut self) {
+        self.rules.push(self.rule());
+        self.regex.clear();
+        self.replacement.clear();
+        self.flags = MatchFlags::NO_FLAGS;
+        self.occurrence = None;
+        self.refresh_expression();
+    }
+
+    /// move the last rule of the chain back to the editor
+    fn pop_rule(&mut self) {
+        if let Some(rule) = self.rules.pop() {
+            self.load_rule(rule);
+        }
+    }
+
+    /// replace the edited rule
+    fn load_rule(&mut self, rule: RenameRule) {
+        self.regex = rule.regex;
+        self.replacement = rule.replacement;
+        self.flags = rule.flags;
+        self.mode = rule.mode;
+        self.occurrence = rule.occurrence;
+        self.refresh_expression();
+    }
+
+    /// note of the renames replacing existing files
+    pub fn overwrite_marker(&self) -> &'static str {
+        if self.use_trash {
+            " (overwrites, old file to trash)"
+        } else {
+            " (overwrites)"
+        }
+    }
+
+    /// limits above which renaming is confirmed by typing the number of files
+    pub fn confirm_thresholds(&self) -> &ConfirmThresholds {
+        &self.confirm_thresholds
+    }
+
+    /// what to do with the new names taken by existing files
+    pub fn on_conflict(&self) -> ConflictPolicy {
+        self.on_conflict
+    }
+
+    /// pattern of the edited rule
+    pub fn regex(&self) -> &str {
+        &self.regex
+    }
+
+    /// replacement of the edited rule
+    pub fn replacement(&self) -> &str {
+        &self.replacement
+    }
+
+    /// change the pattern of the edited rule
+    pub fn set_regex(&mut self, regex: String) {
+        self.regex = regex;
+        self.refresh_expression();
+    }
+
+    /// change the replacement of the edited rule
+    pub fn set_replacement(&mut self, replacement: String) {
+        self.replacement = replacement;
+        self.refresh_expression();
+    }
+
+    /// save the edited rule as a named preset in the config file
+    fn save_preset(&mut self, name: &str) -> anyhow::Result<()> {
+        let mut config = self.config.clone();
+        config.insert_preset(name.to_owned(), self.rule());
+        config.save(&self.config_path)?;
+        self.config = config;
+
+        Ok(())
+    }
+
+    /// regenerate the expression after the rule was changed outside of it
+    fn refresh_expression(&mut self) {
+        if self.expression.is_some() {
+            self.expression = Some(self.rule().to_string());
+        }
+    }
+
+    /// the rules chain followed by the edited rule
+    fn compiled_rules(&self) -> Vec<CompiledRule> {
+        self.rules
+            .iter()
+            .chain(std::iter::once(&self.rule()))
+            .map(|rule| rule.compile(self.engine))
+            .collect()
+    }
+
+    /// switch between the single expression input and separate regex/replacement inputs
+    fn toggle_expression(&mut self) {
+        self.expression = match self.expression {
+            Some(_) => None,
+            None => Some(self.rule().to_string()),
+        }
+    }
+
+    /// update regex, replacement and flags from the expression if it's valid
+    fn apply_expression(&mut self) {
+        if let Some(Ok(rule)) = self.expression.as_deref().map(RenameRule::parse) {
+            self.regex = rule.regex;
+            self.replacement = rule.replacement;
+            self.flags = rule.flags;
+            self.occurrence = rule.occurrence;
+        }
+    }
+
+    pub fn run<B: Backend>(&mut self, terminal: &mut Terminal<B>) -> std::io::Result<AppResult> {
+        let mut inputs = Inputs::new(termion::async_stdin());
+        self.launch = Some(self.session());
+
+        loop {
+            terminal.draw(|f| {
+                if self.inline {
+                    self.ui_inline(f)
+                } else {
+                    self.ui(f)
+                }
+            })?;
+
+            self.poll_scan();
+            for input in inputs.poll() {
+                let result = match input {
+                    Input::Key(key) if self.popup.is_some() => self.handle_popup_key(key),
+                    Input::Key(key) => self.handle_key(key),
+                    Input::Paste(text) => {
+                        self.paste(&text);
+                        None
+                    }
+                    Input::Mouse(mouse) => {
+                        if self.popup.is_none() {
+                            self.handle_mouse(mouse);
+                        }
+                        None
+                    }
+                };
+
+                if let Some(result) = result {
+                    return Ok(result);
+                }
+                self.advance_tutorial();
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    /// go to the next tutorial steps if the user did what they ask for
+    fn advance_tutorial(&mut self) {
+        let Some(mut tutorial) = self.tutorial.take() else {
+            return;
+        };
+
+        tutorial.advance(|goal| match goal {
+            Goal::Match => {
+                !self.regex.is_empty()
+                    && self.active_area == EditableArea::Replace
+                    && self.plan_entries().iter().any(|entry| {
+                        matches!(
+                            entry.result,
+                            ReplacementResult::Replaced(_) | ReplacementResult::Unchanged
+                        )
+                    })
+            }
+            Goal::Replace => {
+                !self.replacement.is_empty()
+                    && self
+                        .plan_entries()
+                        .iter()
+                        .any(|entry| matches!(entry.result, ReplacementResult::Replaced(_)))
+            }
+            Goal::IgnoreCase => self.flags.contains(MatchFlags::ICASE),
+            Goal::Execute => false,
+        });
+        self.tutorial = Some(tutorial);
+    }
+
+    /// handle a key pressed in the main editor. Returns a result if the app should exit
+    fn handle_key(&mut self, key: Key) -> Option<AppResult> {
+        if !matches!(key, Key::Up | Key::Down) {
+            self.browsing = None;
+        }
+        if self.active_area == EditableArea::Files && self.handle_list_key(key) {
+            return None;
+        }
+        // another input or value is edited from its end
+        if matches!(
+            key,
+            Key::Char('\t') | Key::BackTab | Key::Up | Key::Down | Key::Ctrl('e')
+        ) {
+            self.cursor = None;
+        }
+        let edited_string = match (&mut self.expression, &self.active_area) {
+            (Some(expression), _) => expression,
+            (None, EditableArea::Regex | EditableArea::Files) => &mut self.regex,
+            (None, EditableArea::Replace) => &mut self.replacement,
+        };
+
+        match key {
+            Key::Ctrl('c') => {
+                return self.quit();
+            }
+            Key::Char('\t') => {
+                self.active_area = self.active_area.next();
+            }
+            Key::BackTab => {
+                self.active_area = self.active_area.prev();
+            }
+            Key::Up | Key::Down => {
+                self.browse_history(key == Key::Up);
+            }
+            Key::Backspace => {
+                self.cursor = edit_at_cursor(edited_string, self.cursor, Edit::Backspace);
+                self.apply_expression();
+            }
+            Key::Delete => {
+                self.cursor = edit_at_cursor(edited_string, self.cursor, Edit::Delete);
+                self.apply_expression();
+            }
+            Key::Left | Key::Right | Key::Home | Key::End | Key::Alt('b') | Key::Alt('f') => {
+                let edit = match key {
+                    Key::Left => Edit::Left,
+                    Key::Right => Edit::Right,
+                    Key::Home => Edit::Home,
+                    Key::Alt('b') => Edit::WordLeft,
+                    Key::Alt('f') => Edit::WordRight,
+                    _ => Edit::End,
+                };
+                self.cursor = edit_at_cursor(edited_string, self.cursor, edit);
+            }
+            Key::Ctrl('w') | Key::Ctrl('u') | Key::Ctrl('k') => {
+                let edit = match key {
+                    Key::Ctrl('w') => Edit::DeleteWordBack,
+                    Key::Ctrl('u') => Edit::DeleteToStart,
+                    _ => Edit::DeleteToEnd,
+                };
+                self.cursor = edit_at_cursor(edited_string, self.cursor, edit);
+                self.apply_expression();
+            }
+            Key::Ctrl('e') => {
+                self.toggle_expression();
+            }
+            Key::Ctrl('v') => {
+                if let Some(text) = input::clipboard_text() {
+                    self.paste(&text);
+                }
+            }
+            Key::Ctrl('g') => {
+                self.flags ^= MatchFlags::GLOBAL;
+                self.refresh_expression();
+            }
+            Key::Ctrl('r') => {
+                self.flags ^= MatchFlags::ICASE;
+                self.refresh_expression();
+            }
+            Key::Alt('m') => {
+                self.flags ^= MatchFlags::MULTILINE;
+                self.refresh_expression();
+            }
+            Key::Alt('d') => {
+                self.flags ^= MatchFlags::DOTALL;
+                self.refresh_expression();
+            }
+            Key::Alt('u') => {
+                self.flags ^= MatchFlags::UNGREEDY;
+                self.refresh_expression();
+            }
+            Key::Alt('x') => {
+                self.flags ^= MatchFlags::EXTENDED;
+                self.refresh_expression();
+            }
+            Key::Ctrl('n') => {
+                self.push_rule();
+            }
+            Key::Ctrl('t') => {
+                self.popup = Some(Popup::Presets { selected: 0 });
+            }
+            Key::Ctrl('s') => {
+                self.popup = Some(Popup::SavePreset {
+                    name: String::new(),
+                    error: None,
+                });
+            }
+            Key::Ctrl('p') => {
+                self.pop_rule();
+            }
+            Key::Ctrl('o') => {
+                self.mode = self.mode.toggled(MatchMode::Glob);
+            }
+            Key::Ctrl('l') => {
+                self.mode = self.mode.toggled(MatchMode::Literal);
+            }
+            Key::Alt('s') => {
+                self.sanitize = !self.sanitize;
+            }
+            Key::Alt('p') => {
+                self.match_path = !self.match_path;
+            }
+            Key::Alt('r') => {
+                self.engine = self.engine.toggled();
+            }
+            Key::Alt('e') => {
+                self.toggle_excluded_root();
+            }
+            Key::Alt('c') => {
+                self.list_order = self.list_order.toggled();
+            }
+            Key::Alt('o') => {
+                self.file_order = self.file_order.toggled();
+                self.sort_sources();
+            }
+            Key::Ctrl('x') => {
+                self.reset();
+            }
+            Key::Alt('k') => {
+                let selected = CaseStyle::ALL
+                    .iter()
+                    .position(|style| Some(*style) == self.case_style)
+                    .map_or(0, |idx| idx + 1);
+                self.popup = Some(Popup::CaseStyles { selected });
+            }
+            Key::Alt('t') => {
+                self.filter_by_ext = !self.filter_by_ext && !self.ext_filter.is_empty();
+            }
+            Key::Alt('a') => {
+                self.popup = Some(Popup::AddFiles {
+                    path: String::new(),
+                    error: None,
+                });
+            }
+            Key::Alt('w') => {
+                self.popup = Some(Popup::ExportPlan {
+                    path: self
+                        .plan_out
+                        .as_deref()
+                        .unwrap_or(Path::new(DEFAULT_PLAN_FILE))
+                        .to_string_lossy()
+                        .into_owned(),
+                    error: None,
+                });
+            }
+            Key::Alt('n') => {
+                self.popup = Some(Popup::Occurrence {
+                    typed: self
+                        .occurrence
+                        .map(|occurrence| occurrence.to_string())
+                        .unwrap_or_default(),
+                    error: None,
+                });
+            }
+            Key::Char('\n') => {
+                if self.preview_only {
+                    self.popup = Some(Popup::Plan);
+                } else {
+                    let move_pairs = self.move_pairs();
+                    // conflicts are highlighted in the files list, the rules need fixing first.
+                    // Pending files would be silently skipped
+                    if self.conflicting_files(&move_pairs) > 0
+                        || self.pending_entries() > 0
+                        || self.scan.is_some()
+                    {
+                        return None;
+                    }
+                    if self.strict_portability && self.non_portable_entries() > 0 {
+                        return None;
+                    }
+                    match self.confirm_thresholds.check(&move_pairs, self.on_conflict) {
+                        Some(stats) => {
+                            self.popup = Some(Popup::Confirm {
+                                stats,
+                                typed: String::new(),
+                            });
+                        }
+                        None => return self.confirm_conflicts(move_pairs),
+                    }
+                }
+            }
+            Key::Char(ch) => {
+                self.cursor = edit_at_cursor(edited_string, self.cursor, Edit::Insert(ch));
+                self.apply_expression();
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    /// focus the clicked input or select the clicked row of the files list,
+    /// the wheel scrolls the files list
+    fn handle_mouse(&mut self, mouse: MouseEvent) {
+        let MouseEvent::Press(button, x, y) = mouse else {
+            return;
+        };
+        // the terminal counts from 1
+        let (column, row) = (x.saturating_sub(1), y.saturating_sub(1));
+        let areas = self.click_areas.get();
+        let contains = |area: Rect| {
+            (area.left()..area.right()).contains(&column)
+                && (area.top()..area.bottom()).contains(&row)
+        };
+        let list_height = areas.files.height.saturating_sub(2) as usize;
+        let last = self.listed_entries().len().saturating_sub(1);
+
+        match button {
+            MouseButton::WheelUp | MouseButton::WheelDown => {
+                let scroll = self.list_scroll.get();
+                let scroll = if button == MouseButton::WheelDown {
+                    (scroll + WHEEL_ROWS).min(last)
+                } else {
+                    scroll.saturating_sub(WHEEL_ROWS)
+                };
+                self.list_scroll.set(scroll);
+                // the selected row stays on the screen
+                self.list_cursor = self
+                    .list_cursor
+                    .clamp(scroll, scroll + list_height.saturating_sub(1))
+                    .min(last);
+            }
+            MouseButton::Left => {
+                let (area, input_area) = if self.expression.is_some() {
+                    (areas.expression, EditableArea::Regex)
+                } else if contains(areas.replacement) {
+                    (areas.replacement, EditableArea::Replace)
+                } else {
+                    (areas.regex, EditableArea::Regex)
+                };
+                if contains(area) {
+                    self.browsing = None;
+                    self.active_area = input_area;
+                    let text = match (&self.expression, &self.active_area) {
+                        (Some(expression), _) => expression,
+                        (None, EditableArea::Replace) => &self.replacement,
+                        _ => &self.regex,
+                    };
+                    // inside the borders
+                    let clicked = column.saturating_sub(area.x + 1) as usize;
+                    self.cursor = text.char_indices().nth(clicked).map(|(idx, _)| idx);
+                } else if contains(areas.files) && row > areas.files.y {
+                    let clicked = self.list_scroll.get() + (row - areas.files.y - 1) as usize;
+                    if clicked <= last && !self.source_files.is_empty() {
+                        self.active_area = EditableArea::Files;
+                        self.list_cursor = clicked;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// insert pasted text at the cursor of the focused input. Line breaks and the other
+    /// control chars are left out, so they don't act as keys or break the one line inputs
+    fn paste(&mut self, text: &str) {
+        let chars = text.chars().filter(|ch| !ch.is_control());
+        match &self.popup {
+            Some(
+                Popup::SavePreset { .. }
+                | Popup::ExportPlan { .. }
+                | Popup::AddFiles { .. }
+                | Popup::Occurrence { .. }
+                | Popup::Confirm { .. },
+            ) => {
+                for ch in chars {
+                    self.handle_popup_key(Key::Char(ch));
+                }
+                return;
+            }
+            Some(_) => return,
+            None => {}
+        }
+
+        self.browsing = None;
+        let edited_string = match (&mut self.expression, &self.active_area) {
+            (_, EditableArea::Files) => return,
+            (Some(expression), _) => expression,
+            (None, EditableArea::Regex) => &mut self.regex,
+            (None, EditableArea::Replace) => &mut self.replacement,
+        };
+        for ch in chars {
+            self.cursor = edit_at_cursor(edited_string, self.cursor, Edit::Insert(ch));
+        }
+        self.apply_expression();
+    }
+
+    /// the edited input up to the cursor
+    fn text_before_cursor<'a>(&self, text: &'a str) -> &'a str {
+        match self.cursor {
+            Some(cursor) => text.get(..cursor).unwrap_or(text),
+            None => text,
+        }
+    }
+
+    /// move through the files list and check the files to rename.
+    /// Returns whether the key is handled, the other keys work as in the inputs
+    fn handle_list_key(&mut self, key: Key) -> bool {
+        let last = self.listed_entries().len().saturating_sub(1);
+        match key {
+            Key::Up | Key::Char('k') => self.list_cursor = self.list_cursor.saturating_sub(1),
+            Key::Down | Key::Char('j') => self.list_cursor = (self.list_cursor + 1).min(last),
+            Key::PageUp => self.list_cursor = self.list_cursor.saturating_sub(LIST_PAGE),
+            Key::PageDown => self.list_cursor = (self.list_cursor + LIST_PAGE).min(last),
+            Key::Home => self.list_cursor = 0,
+            Key::End => self.list_cursor = last,
+            Key::Char(' ') | Key::Char('d') | Key::Delete => self.toggle_listed_file(),
+            Key::Char('a') => self.unchecked_files.clear(),
+            Key::Char('n') => self.unchecked_files = self.listed_files().collect(),
+            Key::Char('i') => {
+                self.unchecked_files = self
+                    .listed_files()
+                    .filter(|path| !self.unchecked_files.contains(path))
+                    .collect()
+            }
+            Key::Char('\n') | Key::Char('\t') => return false,
+            // the inputs aren't edited from the list
+            Key::Char(_) | Key::Backspace | Key::Left | Key::Right => {}
+            Key::Ctrl('w' | 'u' | 'k' | 'v') | Key::Alt('b' | 'f') => {}
+            _ => return false,
+        }
+        true
+    }
+
+    /// check or uncheck the file under the list cursor
+    fn toggle_listed_file(&mut self) {
+        let Some(entry) = self.listed_entries().into_iter().nth(self.list_cursor) else {
+            return;
+        };
+        let path = path_utils::join_name(&entry.parent, &entry.name);
+        if !self.unchecked_files.remove(&path) {
+            self.unchecked_files.insert(path);
+        }
+    }
+
+    /// source files shown in the list, checked or not
+    fn listed_files(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.source_files
+            .iter()
+            .filter(|path| self.is_listed(path))
+            .cloned()
+    }
+
+    /// handle a key pressed while a popup is shown. Returns a result if the app should exit
+    fn handle_popup_key(&mut self, key: Key) -> Option<AppResult> {
+        if let Key::Ctrl('c') = key {
+            return match self.popup {
+                Some(Popup::Quit) => Some(AppResult::Exit),
+                _ => self.quit(),
+            };
+        }
+
+        match self.popup.as_mut() {
+            Some(Popup::Plan) => {
+                if let Key::Esc | Key::Char('\n') | Key::Char('q') = key {
+                    self.popup = None;
+                }
+            }
+            Some(Popup::Confirm { stats, typed }) => match key {
+                Key::Esc => {
+                    self.popup = None;
+                }
+                Key::Backspace => {
+                    typed.pop();
+                }
+                Key::Char('\n') => {
+                    if *typed == stats.files.to_string() {
+                        self.popup = None;
+                        return self.confirm_conflicts(self.move_pairs());
+                    }
+                    typed.clear();
+                }
+                Key::Char(ch) if ch.is_ascii_digit() => {
+                    typed.push(ch);
+                }
+                _ => {}
+            },
+            Some(Popup::Presets { selected }) => match key {
+                Key::Esc => {
+                    self.popup = None;
+                }
+                Key::Up => {
+                    *selected = selected.saturating_sub(1);
+                }
+                Key::Down => {
+                    *selected = (*selected + 1).min(self.config.presets().len().saturating_sub(1));
+                }
+                Key::Char('\n') => {
+                    let preset = self.config.presets().values().nth(*selected).cloned();
+                    self.popup = None;
+                    if let Some(rule) = preset {
+                        self.load_rule(rule.clone());
+                    }
+                }
+                _ => {}
+            },
+            Some(Popup::SavePreset { name, .. }) => match key {
+                Key::Esc => {
+                    self.popup = None;
+                }
+                Key::Backspace => {
+                    name.pop();
+                }
+                Key::Char('\n') => {
+                    let name = name.clone();
+                    match self.save_preset(&name) {
+                        Ok(()) => self.popup = None,
+                        Err(err) => {
+                            if let Some(Popup::SavePreset { error, .. }) = self.popup.as_mut() {
+                                *error = Some(format!("{:#}", err));
+                            }
+                        }
+                    }
+                }
+                Key::Char(ch) => {
+                    name.push(ch);
+                }
+                _ => {}
+            },
+            Some(Popup::ExportPlan { path, .. }) => match key {
+                Key::Esc => {
+                    self.popup = None;
+                }
+                Key::Backspace => {
+                    path.pop();
+                }
+                Key::Char('\n') => {
+                    let path = PathBuf::from(path.as_str());
+                    match self.plan_file().save(&path) {
+                        Ok(()) => self.popup = None,
+                        Err(err) => {
+                            if let Some(Popup::ExportPlan { error, .. }) = self.popup.as_mut() {
+                                *error = Some(format!("{:#}", err));
+                            }
+                        }
+                    }
+                }
+                Key::Char(ch) => {
+                    path.push(ch);
+                }
+                _ => {}
+            },
+            Some(Popup::AddFiles { path, error }) => match key {
+                Key::Esc => {
+                    self.popup = None;
+                }
+                Key::Backspace => {
+                    path.pop();
+                }
+                Key::Char('\t') => {
+                    *path = path_utils::complete_path(path);
+                    *error = None;
+                }
+                Key::Char('\n') => {
+                    let typed = std::mem::take(path);
+                    match self.add_typed_files(&typed) {
+                        Ok(()) => self.popup = None,
+                        Err(err) => {
+                            if let Some(Popup::AddFiles { path, error }) = self.popup.as_mut() {
+                                *path = typed;
+                                *error = Some(err);
+                            }
+                        }
+                    }
+                }
+                Key::Char(ch) => {
+                    path.push(ch);
+                }
+                _ => {}
+            },
+            Some(Popup::Occurrence { typed, error }) => match key {
+                Key::Esc => {
+                    self.popup = None;
+                }
+                Key::Backspace => {
+                    typed.pop();
+                }
+                Key::Char('\n') if typed.is_empty() => {
+                    self.occurrence = None;
+                    self.popup = None;
+                    self.refresh_expression();
+                }
+                Key::Char('\n') => match typed.parse() {
+                    Ok(occurrence) => {
+                        self.occurrence = Some(occurrence);
+                        self.popup = None;
+                        self.refresh_expression();
+                    }
+                    Err(err) => *error = Some(err),
+                },
+                Key::Char(ch) => {
+                    typed.push(ch);
+                }
+                _ => {}
+            },
+            Some(Popup::CaseStyles { selected }) => match key {
+                Key::Esc => {
+                    self.popup = None;
+                }
+                Key::Up => {
+                    *selected = selected.saturating_sub(1);
+                }
+                Key::Down => {
+                    *selected = (*selected + 1).min(CaseStyle::ALL.len());
+                }
+                Key::Char('\n') => {
+                    self.case_style = selected.checked_sub(1).map(|idx| CaseStyle::ALL[idx]);
+                    self.popup = None;
+                }
+                _ => {}
+            },
+            Some(Popup::Quit) => match key {
+                Key::Char('y') => return Some(AppResult::Exit),
+                Key::Char('s') => {
+                    self.popup = Some(Popup::SavePreset {
+                        name: String::new(),
+                        error: None,
+                    });
+                }
+                Key::Esc | Key::Char('n') => {
+                    self.popup = None;
+                }
+                _ => {}
+            },
+            Some(Popup::Conflicts { .. }) => match key {
+                Key::Char('o') => {
+                    self.popup = None;
+                    return Some(AppResult::MoveFiles(self.move_pairs()));
+                }
+                Key::Char('s') => {
+                    self.popup = None;
+                    let move_pairs = self.move_pairs();
+                    let existing = planner::existing_destinations(&move_pairs);
+                    return Some(AppResult::MoveFiles(
+                        move_pairs
+                            .into_iter()
+                            .filter(|(_, dst)| !existing.contains(dst))
+                            .collect(),
+                    ));
+                }
+                Key::Esc => {
+                    self.popup = None;
+                }
+                _ => {}
+            },
+            None => {}
+        }
+
+        None
+    }
+
+    /// rename, or ask about the existing destinations first with `ConflictPolicy::Prompt`
+    fn confirm_conflicts(&mut self, move_pairs: Vec<MovePair>) -> Option<AppResult> {
+        let existing = planner::existing_destinations(&move_pairs).len();
+        if self.on_conflict == ConflictPolicy::Prompt && existing > 0 {
+            self.popup = Some(Popup::Conflicts { existing });
+            None
+        } else {
+            Some(AppResult::MoveFiles(move_pairs))
+        }
+    }
+
+    /// current values of the settings changed from the app
+    fn session(&self) -> Session {
+        let mut rules = self.rules.clone();
+        rules.push(self.rule());
+
+        Session {
+            rules,
+            expression: self.expression.is_some(),
+            engine: self.engine,
+            match_path: self.match_path,
+            sanitize: self.sanitize,
+            case_style: self.case_style,
+            excluded_root: self.excluded_root.clone(),
+            filter_by_ext: self.filter_by_ext,
+            list_order: self.list_order,
+            file_order: self.file_order,
+        }
+    }
+
+    fn restore_session(&mut self, session: Session) {
+        let Session {
+            mut rules,
+            expression,
+            engine,
+            match_path,
+            sanitize,
+            case_style,
+            excluded_root,
+            filter_by_ext,
+            list_order,
+            file_order,
+        } = session;
+
+        let rule = rules.pop().expect("session has the edited rule");
+        self.rules = rules;
+        self.expression = expression.then(String::new);
+        self.load_rule(rule);
+        self.engine = engine;
+        self.match_path = match_path;
+        self.sanitize = sanitize;
+        self.case_style = case_style;
+        self.excluded_root = excluded_root;
+        self.filter_by_ext = filter_by_ext;
+        self.list_order = list_order;
+        self.file_order = file_order;
+        self.sort_sources();
+    }
+
+    /// the files and the current settings to be reopened by `with_session`
+    pub fn export_session(&self) -> SessionFile {
+        SessionFile::new(
+            self.source_files.clone(),
+            self.session(),
+            self.ext_filter.clone(),
+        )
+    }
+
+    /// open the files of a session with its settings
+    pub fn with_session(self, session: SessionFile) -> Self {
+        let mut app = self
+            .with_files(session.files)
+            .with_ext_filter(session.ext_filter);
+        app.restore_session(session.settings);
+        app
+    }
+
+    fn launch_session(&self) -> Session {
+        self.launch
+            .clone()
+            .unwrap_or_else(|| App::default().session())
+    }
+
+    /// go back to the settings the app was started with.
+    /// Resetting again right after that brings the reset settings back
+    fn reset(&mut self) {
+        let launch = self.launch_session();
+        let current = self.session();
+        if current != launch {
+            self.before_reset = Some(current);
+            self.restore_session(launch);
+        } else if let Some(before_reset) = self.before_reset.take() {
+            self.restore_session(before_reset);
+        }
+    }
+
+    /// exit, or ask for a confirmation if the rules were edited
+    fn quit(&mut self) -> Option<AppResult> {
+        let edited =
+            !self.rules.is_empty() || !self.regex.is_empty() || !self.replacement.is_empty();
+        if edited && self.session().rules != self.launch_session().rules {
+            self.popup = Some(Popup::Quit);
+            None
+        } else {
+            Some(AppResult::Exit)
+        }
+    }
+
+    /// flags of the edited rule with the occurrence and the mode markers
+    fn flags_text(&self) -> String {
+        format!(
+            "{}{}{}",
+            self.flags,
+            self.occurrence
+                .as_ref()
+                .map(Occurrence::flag)
+                .unwrap_or_default(),
+            self.mode.flag()
+        )
+    }
+
+    /// title of the pattern input with the engine if it's not the default one
+    fn pattern_title(&self) -> String {
+        match self.engine {
+            Engine::Std => self.mode.title().to_owned(),
+            Engine::Fancy => format!("{} (fancy)", self.mode.title()),
+        }
+    }
+
+    fn compose_regex(&self) -> Option<Box<dyn RegexBackend>> {
+        compose_regex(&self.regex, self.flags, self.mode, self.engine)
+    }
+
+    /// (source, destination) pairs for all the files affected by the rules
+    fn move_pairs(&self) -> Vec<MovePair> {
+        Self::entries_move_pairs(self.plan_entries())
+    }
+
+    fn entries_move_pairs(entries: Vec<PlanEntry>) -> Vec<MovePair> {
+        let move_pairs = entries
+            .into_iter()
+            .filter_map(|entry| match entry.result {
+                ReplacementResult::Replaced(dst_name) => {
+                    let src_path = path_utils::join_name(&entry.parent, &entry.name);
+                    // the replacement can move the file, e.g. `../{parent}_$0`
+                    let dst_path = path_utils::normalize_path(&path_utils::join_name(
+                        &entry.parent,
+                        &dst_name,
+                    ));
+
+                    Some((src_path, dst_path))
+                }
+                _ => None,
+            })
+            .collect();
+        planner::order_nested(move_pairs)
+    }
+
+    /// (source, destination) pairs like the app shows them, or an error
+    /// if some rule is invalid or some name can't be processed
+    pub fn plan(&self) -> Result<Vec<MovePair>, String> {
+        if self
+            .compiled_rules()
+            .iter()
+            .any(|rule| rule.regex.is_none())
+        {
+            return Err("invalid regex".to_owned());
+        }
+
+        // unlike the preview, the plan needs all the metadata
+        if self.pending_entries() > 0 {
+            self.videos.wait(None);
+            self.git_infos.wait(None);
+            for transform in &self.transforms {
+                transform.wait();
+            }
+        }
+        let entries = self.plan_entries();
+        for entry in &entries {
+            if let ReplacementResult::Failed(err) = &entry.result {
+                return Err(format!(
+                    "{}: {}",
+                    path_utils::join_name(&entry.parent, &entry.name).display(),
+                    err
+                ));
+            }
+            if let Some(problem) = portability_problem(entry).filter(|_| self.strict_portability) {
+                return Err(format!(
+                    "{}: {}",
+                    path_utils::join_name(&entry.parent, &entry.name).display(),
+                    problem
+                ));
+            }
+        }
+
+        Ok(Self::entries_move_pairs(entries))
+    }
+
+    /// capture groups of the edited regex for every source file.
+    /// Applied to full paths in `match_path` mode
+    pub fn capture_table(&self) -> Result<CaptureTable, String> {
+        let regex = self
+            .compose_regex()
+            .ok_or_else(|| "invalid regex".to_owned())?;
+
+        let rows = self
+            .source_files
+            .iter()
+            .filter(|path| self.is_selected(path))
+            .map(|path| {
+                let text = if self.match_path {
+                    path_utils::name_to_string(path.as_os_str())
+                } else {
+                    path_utils::name_to_string(path.file_name().unwrap_or_default())
+                };
+                let captures = regex
+                    .captures(&text)
+                    .map_err(|err| format!("{}: {}", path.display(), err))?;
+
+                Ok(CaptureRow {
+                    path: path.clone(),
+                    captures,
+                })
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(CaptureTable {
+            groups: regex.group_names(),
+            rows,
+        })
+    }
+
+    /// replace the source files with their new paths after renaming
+    pub fn rename_sources(&mut self, applied: &[MovePair]) {
+        for (src, dst) in applied {
+            for file in &mut self.source_files {
+                // files inside a renamed directory are moved with it
+                if let Ok(rest) = file.strip_prefix(src) {
+                    let renamed = if rest.as_os_str().is_empty() {
+                        dst.clone()
+                    } else {
+                        dst.join(rest)
+                    };
+                    if let Some(position) = self.input_positions.remove(file) {
+                        self.input_positions.insert(renamed.clone(), position);
+                    }
+                    *file = renamed;
+                }
+            }
+            // files stay unchecked when their directory is renamed
+            self.unchecked_files = std::mem::take(&mut self.unchecked_files)
+                .into_iter()
+                .map(|file| match file.strip_prefix(src) {
+                    Ok(rest) if !rest.as_os_str().is_empty() => dst.join(rest),
+                    _ => file,
+                })
+                .collect();
+        }
+    }
+
+    /// apply the rules to every source file
+    fn plan_entries(&self) -> Vec<PlanEntry> {
+        let key = self.plan_key();
+        if let Some((cached_key, entries)) = &*self.cached_plan.borrow() {
+            if *cached_key == key {
+                return entries.clone();
+            }
+        }
+
+        let entries = self
+            .restore_plan(key)
+            .unwrap_or_else(|| self.compute_plan_entries());
+        *self.cached_plan.borrow_mut() = Some((key, entries.clone()));
+        entries
+    }
+
+    /// key of the plan depending on the files and all the settings affecting it
+    fn plan_key(&self) -> u64 {
+        let rules = (&self.rules, self.rule(), self.engine, self.match_path);
+        let counter = (
+            self.counter_start,
+            self.continue_numbering,
+            self.numbering_order,
+            self.counter_per_dir,
+            self.compound_ext,
+            self.renumber,
+            self.renumber_width,
+        );
+        let rest = (
+            self.sanitize,
+            &self.sanitizer,
+            self.normalization,
+            &self.excluded_root,
+            &self.unchecked_files,
+            self.filter_by_ext,
+            self.truncation,
+            self.case_style,
+            self.on_conflict,
+        );
+        // the plan is computed again when more metadata is read or more names are filtered
+        let fetched = (
+            self.videos.progress().0,
+            self.git_infos.progress().0,
+            self.transforms
+                .iter()
+                .map(|transform| transform.progress())
+                .collect::<Vec<_>>(),
+        );
+        plan_cache::plan_key(
+            &self.source_files,
+            &format!("{:?}", (rules, counter, rest, fetched)),
+        )
+    }
+
+    /// plans are saved only if the same settings produce the same plan in another session
+    fn is_plan_reproducible(&self) -> bool {
+        // commits don't change the files, and pending metadata isn't worth saving.
+        // Conflict numbers depend on the files existing at the moment
+        self.transforms.is_empty()
+            && self.on_conflict != ConflictPolicy::Suffix
+            && !self.uses_git()
+            && !self.uses_video()
+            && self
+                .compiled_rules()
+                .iter()
+                .all(|rule| !rule.replacement.has_generators())
+    }
+
+    fn restore_plan(&self, key: u64) -> Option<Vec<PlanEntry>> {
+        let cache = self.plan_cache.as_ref()?;
+        if !self.is_plan_reproducible() {
+            return None;
+        }
+        cache.load(key)
+    }
+
+    /// save the current plan, so it's restored instantly when the same files
+    /// are opened with the same rules. Paths which aren't UTF-8 can't be saved as JSON
+    pub fn save_plan(&self) -> anyhow::Result<()> {
+        let is_utf8 = self.source_files.iter().all(|path| path.to_str().is_some());
+        match &self.plan_cache {
+            Some(cache) if self.is_plan_reproducible() && is_utf8 => {
+                cache.save(self.plan_key(), &self.source_files, &self.plan_entries())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn compute_plan_entries(&self) -> Vec<PlanEntry> {
+        let rules = self.compiled_rules();
+        let files: Vec<(PathBuf, String)> = self
+            .source_files
+            .iter()
+            .filter(|path| self.is_selected(path))
+            .cloned()
+            .filter_map(path_utils::split_path)
+            .collect();
+        self.fetch_metadata(
+            files
+                .iter()
+                .map(|(parent, name)| path_utils::join_name(parent, name))
+                .collect(),
+        );
+
+        let mut transforms: Vec<&dyn NameTransform> = Vec::new();
+        if let Some(case_style) = &self.case_style {
+            transforms.push(case_style);
+        }
+        transforms.extend(self.transforms.iter().map(Box::as_ref));
+        if self.sanitize {
+            transforms.push(&self.sanitizer);
+        }
+        if let Some(normalization) = &self.normalization {
+            transforms.push(normalization);
+        }
+
+        let counters = if rules.iter().any(|rule| rule.replacement.has_counter()) {
+            self.assign_counters(&files, &rules)
+        } else {
+            vec![None; files.len()]
+        };
+
+        let plan_entry = |parent: PathBuf, name: String, counter: Option<u64>| {
+            let ctx = TokenContext {
+                counter: Some(counter.unwrap_or_default()),
+                ..self.token_context(&path_utils::join_name(&parent, &name))
+            };
+            let result = self.replace(&parent, &name, &rules, &ctx);
+            let result = apply_transforms(&name, result, &transforms);
+            let not_in_git =
+                matches!(ctx.git, Some(None)) && matches!(result, ReplacementResult::Replaced(_));
+
+            PlanEntry {
+                parent,
+                name,
+                result,
+                truncated: false,
+                suffixed: false,
+                not_in_git,
+                unchecked: false,
+            }
+        };
+
+        let mut entries: Vec<PlanEntry> = files
+            .into_iter()
+            .zip(&counters)
+            .map(|((parent, name), counter)| plan_entry(parent, name, *counter))
+            .collect();
+
+        if rules.iter().any(|rule| rule.replacement.has_generators()) {
+            // values are generated again for the colliding files, and they are kept,
+            // so the executed renaming is the previewed one
+            for _ in 0..MAX_REGENERATIONS {
+                let collisions = destination_collisions(&entries);
+                if collisions.is_empty() {
+                    break;
+                }
+                for idx in collisions {
+                    let PlanEntry { parent, name, .. } = &entries[idx];
+                    self.generated
+                        .borrow_mut()
+                        .insert(path_utils::join_name(parent, name), Generated::random());
+                    entries[idx] = plan_entry(parent.clone(), name.clone(), counters[idx]);
+                }
+            }
+        }
+
+        for transform in &self.transforms {
+            transform.plan_done();
+        }
+
+        if self.renumber {
+            self.renumber_entries(&mut entries);
+        }
+        if let Some(truncation) = &self.truncation {
+            truncate_entries(&mut entries, truncation);
+        }
+        if self.on_conflict == ConflictPolicy::Suffix {
+            suffix_conflicts(&mut entries, self.compound_ext);
+        }
+
+        if self.unchecked_files.is_empty() {
+            entries
+        } else {
+            self.with_unchecked_entries(entries)
+        }
+    }
+
+    /// the unchecked files put among the computed entries in the list order
+    fn with_unchecked_entries(&self, entries: Vec<PlanEntry>) -> Vec<PlanEntry> {
+        let mut computed = entries.into_iter();
+        self.listed_files()
+            .filter_map(|path| {
+                let unchecked = self.unchecked_files.contains(&path);
+                let (parent, name) = path_utils::split_path(path)?;
+                if !unchecked {
+                    return computed.next();
+                }
+                Some(PlanEntry {
+                    parent,
+                    name,
+                    result: ReplacementResult::NoMatch,
+                    truncated: false,
+                    suffixed: false,
+                    not_in_git: false,
+                    unchecked: true,
+                })
+            })
+            .collect()
+    }
+
+    /// re-sequence numbers in the resulting names contiguously in the list order
+    fn renumber_entries(&self, entries: &mut [PlanEntry]) {
+        let names: Vec<Option<&str>> = entries
+            .iter()
+            .map(|entry| match &entry.result {
+                ReplacementResult::Replaced(dst_name) => Some(dst_name.as_str()),
+                ReplacementResult::Unchanged => Some(entry.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        let renumbered = planner::renumber(&names, self.counter_start, self.renumber_width);
+
+        for (entry, renumbered) in entries.iter_mut().zip(renumbered) {
+            if let Some(dst_name) = renumbered {
+                entry.result = if dst_name == entry.name {
+                    ReplacementResult::Unchanged
+                } else {
+                    ReplacementResult::Replaced(dst_name)
+                };
+            }
+        }
+    }
+
+    /// apply the rules to the name or to the whole path in `match_path` mode.
+    /// Only the final component of the result is used as the new name
+    fn replace(
+        &self,
+        parent: &Path,
+        name: &str,
+        rules: &[CompiledRule],
+        ctx: &TokenContext,
+    ) -> ReplacementResult {
+        if !self.match_path {
+            return try_replace_chain(name, rules, ctx);
+        }
+
+        let path = path_utils::name_to_string(path_utils::join_name(parent, name).as_os_str());
+        match try_replace_chain(&path, rules, ctx) {
+            ReplacementResult::Replaced(path) => match Path::new(&path).file_name() {
+                Some(dst_name) if dst_name == name => ReplacementResult::Unchanged,
+                Some(dst_name) => ReplacementResult::Replaced(dst_name.to_string_lossy().into()),
+                None => ReplacementResult::Failed("no file name in the resulting path".into()),
+            },
+            other => other,
+        }
+    }
+
+    fn uses_token(&self, token: &str) -> bool {
+        self.rules
+            .iter()
+            .map(|rule| rule.replacement.as_str())
+            .chain(std::iter::once(self.replacement.as_str()))
+            .any(|replacement| Template::parse(replacement).has_token(token))
+    }
+
+    /// creation time of the file, fetched once
+    fn btime(&self, path: &Path) -> Result<DateTime<Local>, String> {
+        self.btimes
+            .borrow_mut()
+            .entry(path.to_owned())
+            .or_insert_with(|| {
+                std::fs::metadata(path)
+                    .and_then(|meta| meta.created())
+                    .map(DateTime::from)
+                    .map_err(|err| err.to_string())
+            })
+            .clone()
+    }
+
+    fn uses_video(&self) -> bool {
+        media::VIDEO_TOKENS
+            .iter()
+            .any(|token| self.uses_token(token))
+    }
+
+    fn uses_git(&self) -> bool {
+        git::GIT_TOKENS.iter().any(|token| self.uses_token(token))
+    }
+
+    /// start reading the metadata used by the tokens and wait for it within the budget
+    fn fetch_metadata(&self, paths: Vec<PathBuf>) {
+        let started = Instant::now();
+        let budget_left = || {
+            self.metadata_budget
+                .map(|budget| budget.saturating_sub(started.elapsed()))
+        };
+
+        // the budget is spent only on new files, the rest arrives while the app is redrawn
+        if self.uses_video() && self.videos.request(paths.clone()) {
+            self.videos.wait(budget_left());
+        }
+        if self.uses_git() && self.git_infos.request(paths) {
+            self.git_infos.wait(budget_left());
+        }
+    }
+
+    /// files whose metadata is being read
+    fn pending_entries(&self) -> usize {
+        self.plan_entries()
+            .iter()
+            .filter(|entry| matches!(&entry.result, ReplacementResult::Failed(err) if err == template::PENDING))
+            .count()
+    }
+
+    /// token values of the file which don't depend on other files
+    fn token_context(&self, path: &Path) -> TokenContext {
+        let generated = *self
+            .generated
+            .borrow_mut()
+            .entry(path.to_owned())
+            .or_insert_with(Generated::random);
+
+        TokenContext {
+            counter: None,
+            dir: path.parent().map(Path::to_owned).unwrap_or_default(),
+            name: path
+                .file_name()
+                .map(path_utils::name_to_string)
+                .unwrap_or_default(),
+            compound_ext: self.compound_ext,
+            now: self.started,
+            btime: self.uses_token("btime").then(|| self.btime(path)),
+            video: self.uses_video().then(|| self.videos.get(path)).flatten(),
+            git: self.uses_git().then(|| self.git_infos.get(path)).flatten(),
+            generated,
+        }
+    }
+
+    /// counter values of the files matched by the rules
+    fn assign_counters(
+        &self,
+        files: &[(PathBuf, String)],
+        rules: &[CompiledRule],
+    ) -> Vec<Option<u64>> {
+        let results: Vec<ReplacementResult> = files
+            .iter()
+            .map(|(parent, name)| {
+                let ctx = self.token_context(&path_utils::join_name(parent, name));
+                self.replace(parent, name, rules, &ctx)
+            })
+            .collect();
+
+        if !self.counter_per_dir {
+            return self.assign_group_counters(files, &results);
+        }
+
+        let mut counters = vec![None; files.len()];
+        for group in planner::group_indices(files.iter().map(|(parent, _)| parent)) {
+            let group_files: Vec<(PathBuf, String)> =
+                group.iter().map(|idx| files[*idx].clone()).collect();
+            let group_results: Vec<ReplacementResult> =
+                group.iter().map(|idx| results[*idx].clone()).collect();
+
+            let group_counters = self.assign_group_counters(&group_files, &group_results);
+            for (idx, counter) in group.into_iter().zip(group_counters) {
+                counters[idx] = counter;
+            }
+        }
+        counters
+    }
+
+    /// counter values of files numbered together.
+    /// `results` are produced with `COUNTER_MARKER` in place of the counter
+    fn assign_group_counters(
+        &self,
+        files: &[(PathBuf, String)],
+        results: &[ReplacementResult],
+    ) -> Vec<Option<u64>> {
+        let mut start = self.counter_start;
+        if self.continue_numbering {
+            start = start.max(self.next_free_number(files, results));
+        }
+
+        let matched: Vec<Option<&str>> = files
+            .iter()
+            .zip(results)
+            .map(|((_, name), result)| match result {
+                ReplacementResult::Replaced(_) | ReplacementResult::Unchanged => {
+                    Some(name.as_str())
+                }
+                _ => None,
+            })
+            .collect();
+
+        planner::assign_counters(&matched, start, self.numbering_order)
+    }
+
+    /// mount point containing the file
+    fn mount_root(&self, path: &Path) -> PathBuf {
+        let dir = path.parent().unwrap_or(path);
+        self.mount_roots
+            .borrow_mut()
+            .entry(dir.to_owned())
+            .or_insert_with(|| path_utils::mount_root(dir))
+            .clone()
+    }
+
+    /// distinct mount points of the source files in the order of the files
+    fn roots(&self) -> Vec<PathBuf> {
+        let mut roots: Vec<PathBuf> = Vec::new();
+        for path in &self.source_files {
+            let root = self.mount_root(path);
+            if !roots.contains(&root) {
+                roots.push(root);
+            }
+        }
+        roots
+    }
+
+    fn is_excluded(&self, path: &Path) -> bool {
+        self.excluded_root
+            .as_ref()
+            .is_some_and(|root| self.mount_root(path) == *root)
+    }
+
+    /// whether the file is shown in the list: it's not excluded and passes the filters
+    fn is_listed(&self, path: &Path) -> bool {
+        !self.is_excluded(path) && (!self.filter_by_ext || self.ext_filter.matches(path))
+    }
+
+    /// whether the file is renamed: it's listed and checked
+    fn is_selected(&self, path: &Path) -> bool {
+        self.is_listed(path) && !self.unchecked_files.contains(path)
+    }
+
+    /// exclude the next mount point, or none after the last one
+    fn toggle_excluded_root(&mut self) {
+        let roots = self.roots();
+        self.excluded_root = match &self.excluded_root {
+            _ if roots.len() < 2 => None,
+            None => roots.first().cloned(),
+            Some(excluded) => roots
+                .iter()
+                .skip_while(|root| *root != excluded)
+                .nth(1)
+                .cloned(),
+        };
+    }
+
+    /// number of renamed and of all the source files on every mount point.
+    /// Empty if all the files are on the same one
+    fn root_summary(&self, move_pairs: &[MovePair]) -> Vec<RootSummary> {
+        let roots = self.roots();
+        if roots.len() < 2 {
+            return Vec::new();
+        }
+
+        let renamed: Vec<PathBuf> = move_pairs
+            .iter()
+            .map(|(src, _)| self.mount_root(src))
+            .collect();
+
+        roots
+            .into_iter()
+            .map(|root| RootSummary {
+                files: self
+                    .source_files
+                    .iter()
+                    .filter(|path| self.mount_root(path) == root)
+                    .count(),
+                renamed: renamed.iter().filter(|src_root| **src_root == root).count(),
+                excluded: self.excluded_root.as_ref() == Some(&root),
+                root,
+            })
+            .collect()
+    }
+
+    /// stat the destinations in the background, cancelling the checks of the previous plan
+    fn prefetch_destinations(&self, move_pairs: &[MovePair]) {
+        self.prefetcher
+            .request(move_pairs.iter().map(|(_, dst)| dst.clone()).collect());
+    }
+
+    /// destinations known to exist which aren't renamed themselves
+    fn known_overwrites(&self, move_pairs: &[MovePair]) -> HashSet<PathBuf> {
+        let sources: HashSet<&PathBuf> = move_pairs.iter().map(|(src, _)| src).collect();
+        move_pairs
+            .iter()
+            .filter(|(src, dst)| {
+                !sources.contains(dst)
+                    && matches!(self.prefetcher.get(dst), Some(Some(_)))
+                    && !path_utils::is_same_file(src, dst)
+            })
+            .map(|(_, dst)| dst.clone())
+            .collect()
+    }
+
+    /// number following the largest one already used by the files in destination directories.
+    /// `results` are produced with `COUNTER_MARKER` in place of the counter
+    fn next_free_number(&self, files: &[(PathBuf, String)], results: &[ReplacementResult]) -> u64 {
+        let sources: HashSet<&PathBuf> = self.source_files.iter().collect();
+        let mut listings = self.dir_listings.borrow_mut();
+
+        files
+            .iter()
+            .zip(results)
+            .filter_map(|((parent, _), result)| match result {
+                ReplacementResult::Replaced(dst_name) => {
+                    let (dst_dir, dst_name) =
+                        path_utils::split_path(path_utils::join_name(parent, dst_name))?;
+                    let existing = listings
+                        .entry(dst_dir.clone())
+                        .or_insert_with(|| list_dir(&dst_dir));
+                    planner::max_existing_number(
+                        &dst_name,
+                        existing
+                            .iter()
+                            .filter(|name| {
+                                !sources.contains(&path_utils::join_name(&dst_dir, name))
+                            })
+                            .map(String::as_str),
+                    )
+                }
+                _ => None,
+            })
+            .max()
+            .map_or(0, |max| max + 1)
+    }
+
+    /// title of the files panel with the enabled name processing modes
+    fn files_title(&self) -> String {
+        let mut title = String::from("Files");
+        if self.match_path {
+            title.push_str(" [full path]");
+        }
+        if self.sanitize {
+            title.push_str(" [sanitized]");
+        }
+        if let Some(case_style) = &self.case_style {
+            title.push_str(&format!(" [{}]", case_style.example()));
+        }
+        // natural is the default order of the command line
+        match self.file_order {
+            FileOrder::Natural => {}
+            FileOrder::Input => title.push_str(" [input order]"),
+            order => title.push_str(&format!(" [sorted by {}]", order.name())),
+        }
+        if self.list_order == ListOrder::MostChanged {
+            title.push_str(" [most changed first]");
+        }
+
+        if !self.ext_filter.is_empty() {
+            if self.filter_by_ext {
+                let skipped = self
+                    .source_files
+                    .iter()
+                    .filter(|path| !self.ext_filter.matches(path))
+                    .count();
+                title.push_str(&format!(" [skipped by extension: {}]", skipped));
+            } else {
+                title.push_str(" [extension filter off]");
+            }
+        }
+        let truncated = self
+            .plan_entries()
+            .iter()
+            .filter(|entry| entry.truncated)
+            .count();
+        if truncated > 0 {
+            title.push_str(&format!(" [truncated: {}]", truncated));
+        }
+        let suffixed = self
+            .plan_entries()
+            .iter()
+            .filter(|entry| entry.suffixed)
+            .count();
+        if suffixed > 0 {
+            title.push_str(&format!(" [numbered on conflict: {}]", suffixed));
+        }
+        let not_in_git = self
+            .plan_entries()
+            .iter()
+            .filter(|entry| entry.not_in_git)
+            .count();
+        if not_in_git > 0 {
+            title.push_str(&format!(" [not in git: {}]", not_in_git));
+        }
+        let non_portable = self.non_portable_entries();
+        if non_portable > 0 {
+            title.push_str(&format!(" [invalid on Windows: {}]", non_portable));
+        }
+        let too_long = self.too_long_entries();
+        if too_long > 0 {
+            title.push_str(&format!(" [too long: {}]", too_long));
+        }
+        let missing = self
+            .source_files
+            .iter()
+            .filter(|path| self.source_problems.contains_key(*path))
+            .count();
+        if missing > 0 {
+            title.push_str(&format!(" [missing or unreadable: {}]", missing));
+        }
+        if self.dropped_sources > 0 {
+            title.push_str(&format!(
+                " [missing or unreadable dropped: {}]",
+                self.dropped_sources
+            ));
+        }
+        if self.skipped_symlinks > 0 {
+            title.push_str(&format!(" [symlinks skipped: {}]", self.skipped_symlinks));
+        }
+        if !self.unchecked_files.is_empty() {
+            title.push_str(&format!(" [unchecked: {}]", self.unchecked_files.len()));
+        }
+        let include: Vec<&str> = self.path_filter.include().collect();
+        if !include.is_empty() {
+            title.push_str(&format!(" [include: {}]", include.join(" ")));
+        }
+        let exclude: Vec<&str> = self.path_filter.exclude().collect();
+        if !exclude.is_empty() {
+            title.push_str(&format!(" [exclude: {}]", exclude.join(" ")));
+        }
+        if self.filtered_out > 0 {
+            title.push_str(&format!(" [filtered out: {}]", self.filtered_out));
+        }
+        if self.ignored_by_rules > 0 {
+            title.push_str(&format!(" [gitignored: {}]", self.ignored_by_rules));
+        }
+        if !self.file_types.is_empty() {
+            let types: Vec<&str> = self.file_types.iter().map(|kind| kind.name()).collect();
+            title.push_str(&format!(" [type: {}]", types.join(" ")));
+        }
+        if self.other_types > 0 {
+            title.push_str(&format!(" [other types: {}]", self.other_types));
+        }
+        if self.out_of_limits > 0 {
+            title.push_str(&format!(
+                " [outside size/time limits: {}]",
+                self.out_of_limits
+            ));
+        }
+        if self.duplicate_files > 0 {
+            title.push_str(&format!(" [duplicates ignored: {}]", self.duplicate_files));
+        }
+        let pending = self.pending_entries();
+        if pending > 0 {
+            title.push_str(&format!(" [metadata of {} files pending…]", pending));
+        }
+        if let Some(scan) = &self.scan {
+            title.push_str(&format!(
+                " [{}, {} files found…]",
+                scan.activity(),
+                self.source_files.len()
+            ));
+        }
+
+        let move_pairs = self.move_pairs();
+        let conflicts = self.conflicting_files(&move_pairs);
+        if conflicts > 0 {
+            title.push_str(&format!(" [conflicts: {}]", conflicts));
+        }
+        let nested = planner::nested_sources(&move_pairs);
+        if nested > 0 {
+            title.push_str(&format!(" [inside renamed dirs: {}]", nested));
+        }
+        let roots = self.root_summary(&move_pairs);
+        if !roots.is_empty() {
+            let roots: Vec<String> = roots.iter().map(RootSummary::to_string).collect();
+            title.push_str(&format!(" [{}]", roots.join(", ")));
+        }
+
+        self.prefetch_destinations(&move_pairs);
+        let overwrites = self.known_overwrites(&move_pairs).len();
+        if overwrites > 0 && self.on_conflict == ConflictPolicy::Skip {
+            title.push_str(&format!(" [skipped, exist: {}]", overwrites));
+        } else if overwrites > 0 {
+            title.push_str(&format!(" [overwrites existing: {}]", overwrites));
+        }
+        let pending = self.prefetcher.pending();
+        if pending > 0 {
+            title.push_str(&format!(" [{} checks pending…]", pending));
+        }
+        title
+    }
+
+    /// plan entries in the order of the files list
+    fn listed_entries(&self) -> Vec<PlanEntry> {
+        let mut entries = self.plan_entries();
+        if self.list_order == ListOrder::MostChanged {
+            entries.sort_by_cached_key(|entry| {
+                std::cmp::Reverse(match &entry.result {
+                    ReplacementResult::Replaced(dst_name) => {
+                        planner::edit_distance(&entry.name, dst_name)
+                    }
+                    _ => 0,
+                })
+            });
+        }
+        entries
+    }
+
+    /// why the new path of a file is too long for this system
+    fn length_problem(&self, dst_path: &Path) -> Option<String> {
+        let dst_path = path_utils::normalize_path(&planner::absolute(dst_path));
+        portability::length_problem(&dst_path, &self.path_limits)
+    }
+
+    /// number of files whose new paths are too long for this system
+    fn too_long_entries(&self) -> usize {
+        self.plan_entries()
+            .iter()
+            .filter(|entry| match &entry.result {
+                ReplacementResult::Replaced(dst_name) => self
+                    .length_problem(&path_utils::join_name(&entry.parent, dst_name))
+                    .is_some(),
+                _ => false,
+            })
+            .count()
+    }
+
+    /// new name split into its directories which exist, the ones which don't and the file name
+    fn split_new_dirs<'a>(&self, parent: &Path, dst_name: &'a str) -> (&'a str, &'a str, &'a str) {
+        let (dirs, file_name) = match dst_name.rfind('/') {
+            Some(idx) => dst_name.split_at(idx + 1),
+            None => return ("", "", dst_name),
+        };
+
+        let mut existing_dirs = self.existing_dirs.borrow_mut();
+        let mut exists = |dir: &str| {
+            let dir = parent.join(dir);
+            *existing_dirs
+                .entry(dir.clone())
+                .or_insert_with(|| dir.is_dir())
+        };
+        let existing_len = dirs
+            .match_indices('/')
+            .map(|(idx, _)| idx + 1)
+            .take_while(|&len| exists(&dirs[..len]))
+            .last()
+            .unwrap_or(0);
+        (&dirs[..existing_len], &dirs[existing_len..], file_name)
+    }
+
+    /// number of files whose new names can't be used on Windows
+    fn non_portable_entries(&self) -> usize {
+        self.plan_entries()
+            .iter()
+            .filter(|entry| portability_problem(entry).is_some())
+            .count()
+    }
+
+    /// number of files renamed to the same destination as some other file
+    /// or to the path of an input file which isn't renamed
+    fn conflicting_files(&self, move_pairs: &[MovePair]) -> usize {
+        let conflicts = planner::conflicting_destinations(move_pairs, &self.source_files);
+        move_pairs
+            .iter()
+            .filter(|(_, dst)| conflicts.contains(dst))
+            .count()
+    }
+
+    /// source files with their new names
+    fn files_list(&self) -> Vec<Spans<'static>> {
+        let move_pairs = self.move_pairs();
+        let conflicts = planner::conflicting_destinations(&move_pairs, &self.source_files);
+        let overwrites = self.known_overwrites(&move_pairs);
+        let show_kinds = !self.file_types.is_empty();
+        // the checkboxes are shown while they are used
+        let show_checkboxes =
+            self.active_area == EditableArea::Files || !self.unchecked_files.is_empty();
+        if show_kinds {
+            self.kinds.request(self.source_files.clone());
+        }
+
+        self.listed_entries()
+            .into_iter()
+            .map(
+                |PlanEntry {
+                     parent,
+                     name,
+                     result,
+                     truncated,
+                     suffixed,
+                     not_in_git,
+                     unchecked,
+                 }| {
+                    let dir_style = Style::default().add_modifier(Modifier::BOLD);
+                    let src_name_style = Style::default().fg(Color::Red);
+                    let dst_name_style = Style::default().fg(Color::Green);
+
+                    let dir_str = path_utils::display_path(&parent) + "/";
+                    let path = path_utils::join_name(&parent, &name);
+                    let symlink_marker = self.symlink_markers.get(&path).map(|marker| {
+                        Span::styled(format!(" ({})", marker), Style::default().fg(Color::Cyan))
+                    });
+                    let problem_marker = self.source_problems.get(&path).map(|problem| {
+                        Span::styled(format!(" ({})", problem), Style::default().fg(Color::Red))
+                    });
+                    let source_markers: Vec<Span> =
+                        symlink_marker.into_iter().chain(problem_marker).collect();
+                    // `?` while the kind is being read
+                    let kind_column = show_kinds.then(|| {
+                        let marker = match self.kinds.get(&path) {
+                            Some(Some(kind)) => kind.marker(),
+                            Some(None) => ' ',
+                            None => '?',
+                        };
+                        Span::styled(format!("{} ", marker), Style::default().fg(Color::Cyan))
+                    });
+
+                    let mut line = match result {
+                        ReplacementResult::Replaced(dst_name) => {
+                            let dst_path = path_utils::join_name(&parent, &dst_name);
+                            let portability_problem = portability::windows_problem(
+                                dst_name.rsplit('/').next().unwrap_or_default(),
+                            );
+                            let warning_style = Style::default().fg(Color::Yellow);
+                            let mut spans = vec![
+                                Span::styled(dir_str, dir_style),
+                                Span::styled(path_utils::display_name(&name), src_name_style),
+                                Span::raw("->"),
+                            ];
+                            let name_style = if truncated {
+                                warning_style
+                            } else {
+                                dst_name_style
+                            };
+                            let (old_dirs, new_dirs, file_name) =
+                                self.split_new_dirs(&parent, &dst_name);
+                            spans
+                                .push(Span::styled(path_utils::display_name(old_dirs), name_style));
+                            spans.push(Span::styled(
+                                path_utils::display_name(new_dirs),
+                                name_style.add_modifier(Modifier::UNDERLINED),
+                            ));
+                            spans.push(Span::styled(
+                                path_utils::display_name(file_name),
+                                name_style,
+                            ));
+                            if truncated {
+                                spans.push(Span::styled(" (truncated)", warning_style));
+                            }
+                            if !new_dirs.is_empty() && self.mkdir {
+                                spans.push(Span::styled(" (new directories)", dst_name_style));
+                            } else if !new_dirs.is_empty() {
+                                spans.push(Span::styled(
+                                    " (no such directory, see --mkdir)",
+                                    warning_style,
+                                ));
+                            }
+                            if suffixed {
+                                spans.push(Span::styled(" (numbered on conflict)", warning_style));
+                            }
+                            if not_in_git {
+                                spans.push(Span::styled(" (not in git)", warning_style));
+                            }
+                            if let Some(problem) = &portability_problem {
+                                spans.push(Span::styled(format!(" ({})", problem), warning_style));
+                            }
+                            if let Some(problem) = self.length_problem(&dst_path) {
+                                spans.push(Span::styled(format!(" ({})", problem), warning_style));
+                            }
+                            let dst_path = path_utils::normalize_path(&dst_path);
+                            if overwrites.contains(&dst_path) {
+                                spans.push(if self.on_conflict == ConflictPolicy::Skip {
+                                    Span::styled(" (exists, skipped)", warning_style)
+                                } else {
+                                    Span::styled(
+                                        self.overwrite_marker(),
+                                        Style::default().fg(Color::Red),
+                                    )
+                                });
+                            }
+                            spans.extend(source_markers);
+                            if conflicts.contains(&dst_path) {
+                                for span in &mut spans {
+                                    span.style = span.style.bg(Color::Yellow);
+                                }
+                            }
+                            Spans::from(spans)
+                        }
+                        ReplacementResult::Failed(err) => {
+                            let mut spans = vec![
+                                Span::styled(dir_str, dir_style),
+                                Span::from(path_utils::display_name(&name)),
+                                Span::raw(" "),
+                                Span::styled(err, Style::default().fg(Color::Yellow)),
+                            ];
+                            spans.extend(source_markers);
+                            Spans::from(spans)
+                        }
+                        _ => {
+                            let mut spans = vec![
+                                Span::styled(dir_str, dir_style),
+                                Span::from(path_utils::display_name(&name)),
+                            ];
+                            spans.extend(source_markers);
+                            Spans::from(spans)
+                        }
+                    };
+                    if let Some(column) = kind_column {
+                        line.0.insert(0, column);
+                    }
+                    if show_checkboxes {
+                        let checkbox = if unchecked { "[ ] " } else { "[x] " };
+                        line.0.insert(0, Span::raw(checkbox));
+                    }
+                    if unchecked {
+                        for span in &mut line.0 {
+                            span.style = span.style.add_modifier(Modifier::DIM);
+                        }
+                    }
+                    line
+                },
+            )
+            .enumerate()
+            .map(|(idx, mut line)| {
+                if self.active_area == EditableArea::Files && idx == self.list_cursor {
+                    for span in &mut line.0 {
+                        span.style = span.style.add_modifier(Modifier::REVERSED);
+                    }
+                }
+                line
+            })
+            .collect()
+    }
+
+    /// compact ui for `inline` mode: a prompt line and a few preview lines
+    fn ui_inline<B: Backend>(&self, frame: &mut Frame<B>) {
+        let re = self.compose_regex();
+        let layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(1), Constraint::Min(0)])
+            .split(frame.size());
+
+        let label_style = Style::default().add_modifier(Modifier::BOLD);
+        let invalid_style = Style::default().fg(Color::Red);
+
+        let mut prompt = Vec::new();
+        let cursor_offset = match &self.expression {
+            Some(expression) => {
+                prompt.push(Span::styled("expr> ", label_style));
+                prompt.push(Span::styled(
+                    expression.clone(),
+                    match RenameRule::parse(expression) {
+                        Ok(_) if re.is_some() => Style::default(),
+                        _ => invalid_style,
+                    },
+                ));
+                prompt[0].width() + Span::raw(self.text_before_cursor(expression)).width()
+            }
+            None => {
+                prompt.push(Span::styled(
+                    format!("{}> ", self.pattern_title().to_lowercase()),
+                    label_style,
+                ));
+                prompt.push(Span::styled(
+                    self.regex.clone(),
+                    if re.is_some() {
+                        Style::default()
+                    } else {
+                        invalid_style
+                    },
+                ));
+                prompt.push(Span::styled("  replacement> ", label_style));
+                prompt.push(Span::raw(self.replacement.clone()));
+
+                match self.active_area {
+                    EditableArea::Regex | EditableArea::Files => {
+                        prompt[0].width() + Span::raw(self.text_before_cursor(&self.regex)).width()
+                    }
+                    EditableArea::Replace => {
+                        prompt[..3].iter().map(Span::width).sum::<usize>()
+                            + Span::raw(self.text_before_cursor(&self.replacement)).width()
+                    }
+                }
+            }
+        };
+        let flags = self.flags_text();
+        if !flags.is_empty() {
+            prompt.push(Span::raw(format!("  [{}]", flags)));
+        }
+
+        frame.render_widget(Paragraph::new(Spans::from(prompt)), layout[0]);
+        frame.set_cursor(layout[0].x + cursor_offset as u16, layout[0].y);
+        frame.render_widget(Paragraph::new(self.files_list()), layout[1]);
+
+        if let Some(popup) = &self.popup {
+            self.render_popup(frame, popup);
+        }
+    }
+
+    fn ui<B: Backend>(&self, frame: &mut Frame<B>) {
+        let re = self.compose_regex();
+
+        // editor and help areas
+        let main_layout = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Min(50), Constraint::Max(25)])
+            .split(frame.size());
+
+        // editor area: regex, replacement, flags, rules chain, files list
+        let rules_height = if self.rules.is_empty() {
+            0
+        } else {
+            self.rules.len() as u16 + 2
+        };
+        let editor_layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(3),
+                Constraint::Length(rules_height),
+                Constraint::Percentage(100),
+            ])
+            .split(main_layout[0].inner(&Margin {
+                vertical: 0,
+                horizontal: 0,
+            }));
 
         // regex and replacement inputs, flags
         let top_row_layout = Layout::default()
             .direction(Direction::Horizontal)
-            .constraints([Constraint::Min(0), Constraint::Length(7)])
+            .constraints([Constraint::Min(0), Constraint::Length(9)])
             .split(editor_layout[0].inner(&Margin {
                 vertical: 0,
                 horizontal: 0,
             }));
 
-        // regex and replacement inputs
-        let input_layout = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
-            .split(top_row_layout[0].inner(&Margin {
-                vertical: 0,
-                horizontal: 0,
-            }));
+        // regex and replacement inputs
+        let input_layout = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+            .split(top_row_layout[0].inner(&Margin {
+                vertical: 0,
+                horizontal: 0,
+            }));
+
+        if let Some(expression) = &self.expression {
+            let expression_input = Paragraph::new(expression.as_ref())
+                .style(match RenameRule::parse(expression) {
+                    Ok(_) if re.is_some() => Style::default(),
+                    _ => Style::default().fg(Color::Red),
+                })
+                .block(Block::default().title("Expression").borders(Borders::ALL));
+            frame.render_widget(expression_input, top_row_layout[0]);
+        }
+
+        let regex_input = Paragraph::new(self.regex.as_ref())
+            .style(if re.as_ref().is_some() {
+                Style::default()
+            } else {
+                Style::default().fg(Color::Red)
+            })
+            .block(
+                Block::default()
+                    .title(self.pattern_title())
+                    .borders(Borders::ALL),
+            );
+        if self.expression.is_none() {
+            frame.render_widget(regex_input, input_layout[0]);
+        }
+
+        let replace_input = Paragraph::new(self.replacement.as_ref())
+            .block(Block::default().title("Replacement").borders(Borders::ALL));
+        if self.expression.is_none() {
+            frame.render_widget(replace_input, input_layout[1]);
+        }
+
+        let flags_view = Paragraph::new(self.flags_text())
+            .block(Block::default().title("Flags").borders(Borders::ALL));
+        frame.render_widget(flags_view, top_row_layout[1]);
+
+        match (&self.expression, &self.active_area) {
+            // no cursor while moving through the files list
+            (_, EditableArea::Files) => {}
+            (Some(expression), _) => {
+                frame.set_cursor(
+                    top_row_layout[0].x
+                        + Span::raw(self.text_before_cursor(expression)).width() as u16
+                        + 1,
+                    top_row_layout[0].y + 1,
+                );
+            }
+            (None, EditableArea::Regex) => {
+                frame.set_cursor(
+                    // Put cursor after the edited part of the regex
+                    input_layout[0].x
+                        + Span::raw(self.text_before_cursor(&self.regex)).width() as u16
+                        + 1,
+                    // Move one line down, from the border to the regex input
+                    input_layout[0].y + 1,
+                );
+            }
+            (None, EditableArea::Replace) => {
+                frame.set_cursor(
+                    // Put cursor after the edited part of the replacement
+                    input_layout[1].x
+                        + Span::raw(self.text_before_cursor(&self.replacement)).width() as u16
+                        + 1,
+                    // Move one line down, from the border to the replacement input
+                    input_layout[1].y + 1,
+                );
+            }
+        }
+
+        let files_list = self.files_list();
+
+        if !self.rules.is_empty() {
+            let rules_list: Vec<Spans> = self
+                .rules
+                .iter()
+                .enumerate()
+                .map(|(idx, rule)| {
+                    let mode = match rule.mode {
+                        MatchMode::Regex => String::new(),
+                        mode => format!("[{}] ", mode.title()),
+                    };
+                    Spans::from(vec![
+                        Span::styled(
+                            format!("{}. ", idx + 1),
+                            Style::default().add_modifier(Modifier::BOLD),
+                        ),
+                        Span::raw(mode),
+                        Span::raw(rule.to_string()),
+                    ])
+                })
+                .collect();
+
+            let rules_view = Paragraph::new(rules_list)
+                .block(Block::default().title("Rules").borders(Borders::ALL));
+            frame.render_widget(rules_view, editor_layout[1]);
+        }
+
+        // the selected row is kept on the screen, wrapped rows may shift it a bit
+        let list_height = editor_layout[2].height.saturating_sub(2) as usize;
+        let mut list_scroll = self.list_scroll.get();
+        if self.active_area == EditableArea::Files {
+            list_scroll = list_scroll
+                .min(self.list_cursor)
+                .max((self.list_cursor + 1).saturating_sub(list_height));
+        }
+        self.list_scroll.set(list_scroll);
+        self.click_areas.set(ClickAreas {
+            regex: input_layout[0],
+            replacement: input_layout[1],
+            expression: top_row_layout[0],
+            files: editor_layout[2],
+        });
+        let files_view = Paragraph::new(files_list)
+            .wrap(Wrap { trim: false })
+            .scroll((list_scroll as u16, 0))
+            .block(
+                Block::default()
+                    .title(self.files_title())
+                    .borders(Borders::ALL),
+            );
+        frame.render_widget(files_view, editor_layout[2]);
+
+        let help_list: Vec<Spans> = vec![
+            (
+                "Tab",
+                "switch between regex, replacement and the files list",
+            ),
+            (
+                "Space/d",
+                "check the file in the list, a all, n none, i invert",
+            ),
+            ("Up/Down", "previously used values"),
+            (
+                "Enter",
+                if self.preview_only {
+                    "show renaming plan"
+                } else {
+                    "execute renaming"
+                },
+            ),
+            ("Ctrl-w/u/k", "delete a word, to the start, to the end"),
+            ("Ctrl-v", "paste from the clipboard"),
+            ("Alt-b/f", "word back, word forward"),
+            ("Ctrl-g", "'global' flag"),
+            ("Ctrl-r", "'icase' flag"),
+            ("Alt-m", "'multiline' flag"),
+            ("Alt-d", "'dotall' flag"),
+            ("Alt-u", "'ungreedy' flag"),
+            ("Alt-x", "'extended' flag"),
+            ("Ctrl-e", "single sed-style expression input"),
+            ("Ctrl-n", "add the rule to the chain and start a new one"),
+            ("Ctrl-p", "edit the last rule of the chain"),
+            ("Ctrl-s", "save the rule as a preset"),
+            ("Ctrl-t", "load a preset"),
+            ("Ctrl-o", "glob mode"),
+            ("Ctrl-l", "literal mode"),
+            ("Alt-s", "sanitize names"),
+            ("Alt-p", "match full paths"),
+            ("Alt-r", "fancy regex engine"),
+            ("Alt-e", "exclude the next mount point"),
+            ("Alt-t", "filter files by extension"),
+            ("Alt-c", "most changed files first"),
+            (
+                "Alt-o",
+                "sort by input order, natural order, name, mtime or size",
+            ),
+            ("Alt-k", "case style of the names"),
+            ("Alt-n", "replace only the Nth match"),
+            ("Alt-w", "export the plan as JSON"),
+            ("Alt-a", "add files to the list"),
+            ("Ctrl-x", "reset, again to undo"),
+            ("Ctrl-c", "exit"),
+        ]
+        .into_iter()
+        .map(|(key, descr)| {
+            Spans::from(vec![
+                Span::styled(key, Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(" - "),
+                Span::raw(descr),
+            ])
+        })
+        .collect();
+        let help_view = match &self.tutorial {
+            Some(tutorial) => Paragraph::new(tutorial.text())
+                .wrap(Wrap { trim: false })
+                .block(
+                    Block::default()
+                        .title(tutorial.title())
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(Color::Cyan)),
+                ),
+            None => Paragraph::new(help_list)
+                .wrap(Wrap { trim: false })
+                .block(Block::default().title("Help").borders(Borders::ALL)),
+        };
+        frame.render_widget(help_view, main_layout[1]);
+
+        if let Some(popup) = &self.popup {
+            self.render_popup(frame, popup);
+        }
+    }
+
+    fn render_popup<B: Backend>(&self, frame: &mut Frame<B>, popup: &Popup) {
+        let area = centered_rect(80, 80, frame.size());
+
+        let view = match popup {
+            Popup::Plan => {
+                let move_pairs = self.move_pairs();
+                let roots = self.root_summary(&move_pairs);
+                let mut plan: Vec<Spans> = Vec::new();
+                if !roots.is_empty() {
+                    plan.extend(roots.iter().map(|root| {
+                        Spans::from(Span::styled(
+                            root.to_string(),
+                            Style::default().add_modifier(Modifier::BOLD),
+                        ))
+                    }));
+                    plan.push(Spans::default());
+                }
+                plan.extend(self.move_pairs().into_iter().map(|(src, dst)| {
+                    Spans::from(vec![
+                        Span::styled(src.display().to_string(), Style::default().fg(Color::Red)),
+                        Span::raw(" -> "),
+                        Span::styled(dst.display().to_string(), Style::default().fg(Color::Green)),
+                    ])
+                }));
+
+                let title = if self.preview_only {
+                    "Plan (preview only, Esc to close)"
+                } else {
+                    "Plan (Esc to close)"
+                };
+
+                Paragraph::new(plan)
+                    .wrap(Wrap { trim: false })
+                    .block(Block::default().title(title).borders(Borders::ALL))
+            }
+            Popup::CaseStyles { selected } => {
+                let items = std::iter::once("keep the case".to_owned()).chain(
+                    CaseStyle::ALL
+                        .iter()
+                        .map(|style| style.example().to_owned()),
+                );
+                let items: Vec<Spans> = items
+                    .enumerate()
+                    .map(|(idx, item)| {
+                        let style = if idx == *selected {
+                            Style::default().add_modifier(Modifier::REVERSED)
+                        } else {
+                            Style::default()
+                        };
+                        Spans::from(Span::styled(item, style))
+                    })
+                    .collect();
+
+                Paragraph::new(items).block(
+                    Block::default()
+                        .title("Case style (Enter to apply, Esc to close)")
+                        .borders(Borders::ALL),
+                )
+            }
+            Popup::Presets { selected } => {
+                let presets: Vec<Spans> = self
+                    .config
+                    .presets()
+                    .into_iter()
+                    .enumerate()
+                    .map(|(idx, (name, rule))| {
+                        let style = if idx == *selected {
+                            Style::default().add_modifier(Modifier::REVERSED)
+                        } else {
+                            Style::default()
+                        };
+                        Spans::from(vec![
+                            Span::styled(name, style.add_modifier(Modifier::BOLD)),
+                            Span::styled(format!("  {}", rule), style),
+                        ])
+                    })
+                    .collect();
+
+                let presets = if presets.is_empty() {
+                    vec![Spans::from(
+                        "no presets saved yet. Use Ctrl-s to save the edited rule",
+                    )]
+                } else {
+                    presets
+                };
+
+                Paragraph::new(presets).block(
+                    Block::default()
+                        .title("Presets (Enter to load, Esc to close)")
+                        .borders(Borders::ALL),
+                )
+            }
+            Popup::SavePreset { name, error } => {
+                let mut text = vec![
+                    Spans::from(format!("rule: {}", self.rule())),
+                    Spans::default(),
+                    Spans::from(vec![
+                        Span::raw("name: "),
+                        Span::styled(name.as_str(), Style::default().add_modifier(Modifier::BOLD)),
+                    ]),
+                ];
+                if let Some(error) = error {
+                    text.push(Spans::default());
+                    text.push(Spans::from(Span::styled(
+                        error.as_str(),
+                        Style::default().fg(Color::Red),
+                    )));
+                }
+
+                Paragraph::new(text).wrap(Wrap { trim: false }).block(
+                    Block::default()
+                        .title("Save preset (Enter to save, Esc to cancel)")
+                        .borders(Borders::ALL),
+                )
+            }
+            Popup::AddFiles { path, error } => {
+                let mut text = vec![
+                    Spans::from("file, directory or glob pattern, Tab completes the path"),
+                    Spans::default(),
+                    Spans::from(vec![
+                        Span::raw("path: "),
+                        Span::styled(path.as_str(), Style::default().add_modifier(Modifier::BOLD)),
+                    ]),
+                ];
+                if let Some(error) = error {
+                    text.push(Spans::default());
+                    text.push(Spans::from(Span::styled(
+                        error.as_str(),
+                        Style::default().fg(Color::Red),
+                    )));
+                }
+
+                Paragraph::new(text).wrap(Wrap { trim: false }).block(
+                    Block::default()
+                        .title("Add files (Enter to add, Esc to cancel)")
+                        .borders(Borders::ALL),
+                )
+            }
+            Popup::ExportPlan { path, error } => {
+                let mut text = vec![
+                    Spans::from(format!(
+                        "{} renames with their details and the rules as JSON",
+                        self.move_pairs().len()
+                    )),
+                    Spans::default(),
+                    Spans::from(vec![
+                        Span::raw("file: "),
+                        Span::styled(path.as_str(), Style::default().add_modifier(Modifier::BOLD)),
+                    ]),
+                ];
+                if let Some(error) = error {
+                    text.push(Spans::default());
+                    text.push(Spans::from(Span::styled(
+                        error.as_str(),
+                        Style::default().fg(Color::Red),
+                    )));
+                }
+
+                Paragraph::new(text).wrap(Wrap { trim: false }).block(
+                    Block::default()
+                        .title("Export plan (Enter to save, Esc to cancel)")
+                        .borders(Borders::ALL),
+                )
+            }
+            Popup::Occurrence { typed, error } => {
+                let mut text = vec![
+                    Spans::from("number of the match to replace, 'last' or empty for the flags"),
+                    Spans::default(),
+                    Spans::from(vec![
+                        Span::raw("occurrence: "),
+                        Span::styled(
+                            typed.as_str(),
+                            Style::default().add_modifier(Modifier::BOLD),
+                        ),
+                    ]),
+                ];
+                if let Some(error) = error {
+                    text.push(Spans::default());
+                    text.push(Spans::from(Span::styled(
+                        error.as_str(),
+                        Style::default().fg(Color::Red),
+                    )));
+                }
+
+                Paragraph::new(text).wrap(Wrap { trim: false }).block(
+                    Block::default()
+                        .title("Occurrence (Enter to set, Esc to cancel)")
+                        .borders(Borders::ALL),
+                )
+            }
+            Popup::Quit => {
+                let bold = Style::default().add_modifier(Modifier::BOLD);
+                let text = vec![
+                    Spans::from(format!("the edited rules will be lost: {}", self.rule())),
+                    Spans::default(),
+                    Spans::from(vec![
+                        Span::styled("y", bold),
+                        Span::raw(" or "),
+                        Span::styled("Ctrl-c", bold),
+                        Span::raw(" - exit, "),
+                        Span::styled("s", bold),
+                        Span::raw(" - save the rule as a preset, "),
+                        Span::styled("n", bold),
+                        Span::raw(" or "),
+                        Span::styled("Esc", bold),
+                        Span::raw(" - continue editing"),
+                    ]),
+                ];
+
+                Paragraph::new(text).wrap(Wrap { trim: false }).block(
+                    Block::default()
+                        .title("Exit?")
+                        .borders(Borders::ALL)
+                        .style(Style::default().fg(Color::Yellow)),
+                )
+            }
+            Popup::Conflicts { existing } => {
+                let bold = Style::default().add_modifier(Modifier::BOLD);
+                let text = vec![
+                    Spans::from(format!(
+                        "{} new names are taken by existing files",
+                        existing
+                    )),
+                    Spans::default(),
+                    Spans::from(vec![
+                        Span::styled("o", bold),
+                        Span::raw(" - overwrite them, "),
+                        Span::styled("s", bold),
+                        Span::raw(" - skip these files, "),
+                        Span::styled("Esc", bold),
+                        Span::raw(" - continue editing"),
+                    ]),
+                ];
+
+                Paragraph::new(text).wrap(Wrap { trim: false }).block(
+                    Block::default()
+                        .title("Existing files")
+                        .borders(Borders::ALL)
+                        .style(Style::default().fg(Color::Yellow)),
+                )
+            }
+            Popup::Confirm { stats, typed } => {
+                let bold = Style::default().add_modifier(Modifier::BOLD);
+                let text = vec![
+                    Spans::from(format!("files to rename: {}", stats.files)),
+                    Spans::from(format!("existing files to overwrite: {}", stats.overwrites)),
+                    Spans::from(format!("moves to other directories: {}", stats.moves)),
+                    Spans::default(),
+                    Spans::from(vec![
+                        Span::raw("Type "),
+                        Span::styled(stats.files.to_string(), bold),
+                        Span::raw(" and press Enter to execute, Esc to cancel"),
+                    ]),
+                    Spans::from(Span::styled(typed.as_str(), bold)),
+                ];
+
+                Paragraph::new(text).wrap(Wrap { trim: false }).block(
+                    Block::default()
+                        .title("Confirm renaming")
+                        .borders(Borders::ALL)
+                        .style(Style::default().fg(Color::Yellow)),
+                )
+            }
+        };
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(view, area);
+    }
+}
+
+/// names of all the entries of a directory. Empty if it can't be read
+fn list_dir(dir: &Path) -> Vec<String> {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| Some(path_utils::name_to_string(&entry.ok()?.file_name())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// rectangle of a given size (in percents) centered inside `area`
+fn centered_rect(percent_x: u16, percent_y: u16, area: Rect) -> Rect {
+    let vertical = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Percentage((100 - percent_y) / 2),
+            Constraint::Percentage(percent_y),
+            Constraint::Percentage((100 - percent_y) / 2),
+        ])
+        .split(area);
+
+    Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Percentage((100 - percent_x) / 2),
+            Constraint::Percentage(percent_x),
+            Constraint::Percentage((100 - percent_x) / 2),
+        ])
+        .split(vertical[1])[1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scan::ScanOptions;
+    use crate::transform::CommandFilter;
+    use crate::tutorial::Sandbox;
+    use rstest::rstest;
+
+    mod editable_area {
+        use super::*;
+
+        #[rstest]
+        #[case(EditableArea::Regex, EditableArea::Replace)]
+        #[case(EditableArea::Replace, EditableArea::Files)]
+        #[case(EditableArea::Files, EditableArea::Regex)]
+        fn next(#[case] current_area: EditableArea, #[case] expected_next_area: EditableArea) {
+            let next_area = current_area.next();
+            assert_eq!(next_area, expected_next_area);
+        }
+
+        #[rstest]
+        #[case(EditableArea::Regex, EditableArea::Files)]
+        #[case(EditableArea::Replace, EditableArea::Regex)]
+        #[case(EditableArea::Files, EditableArea::Replace)]
+        fn prev(#[case] current_area: EditableArea, #[case] expected_next_area: EditableArea) {
+            let next_area = current_area.prev();
+            assert_eq!(next_area, expected_next_area);
+        }
+    }
+
+    #[rstest]
+    #[case("a", None, "b", Targets::First, ReplacementResult::InvalidRegex)]
+    #[case("abc", Regex::new("bc").ok(), "bc", Targets::First, ReplacementResult::Unchanged)]
+    #[case("abc", Regex::new("(ab)(.*)").ok(), "$2$1", Targets::First, ReplacementResult::Replaced("cab".into()))]
+    // case vs ignore case
+    #[case("aBc", Regex::new("abc").ok(), "", Targets::First, ReplacementResult::NoMatch)]
+    #[case("aBc", Regex::new("(?i:abc)").ok(), "", Targets::First, ReplacementResult::Replaced("".into()))]
+    // non-global vs global
+    #[case("abac", Regex::new("a").ok(), "d", Targets::First, ReplacementResult::Replaced("dbac".into()))]
+    #[case("abac", Regex::new("a").ok(), "d", Targets::All, ReplacementResult::Replaced("dbdc".into()))]
+    fn try_replace_works(
+        #[case] text: &str,
+        #[case] regex: Option<Regex>,
+        #[case] replacement: &str,
+        #[case] targets: Targets,
+        #[case] expected_result: ReplacementResult,
+    ) {
+        let regex = regex.as_ref().map(|regex| regex as &dyn RegexBackend);
+        let replacement_result = try_replace(text, regex, replacement, targets);
+        assert_eq!(replacement_result, expected_result);
+    }
+
+    #[rstest]
+    #[case("a\nb", "^b", "", "", "a\nb")]
+    #[case("a\nb", "^b", "m", "", "a\n")]
+    #[case("a\nb", "a.b", "", "x", "a\nb")]
+    #[case("a\nb", "a.b", "s", "x", "x")]
+    #[case("aaa", "a+", "U", "x", "xaa")]
+    #[case("abc", "a b # comment", "x", "x", "xc")]
+    #[case("ABC", "a b", "ix", "x", "xC")]
+    fn regex_flags_work(
+        #[case] text: &str,
+        #[case] pattern: &str,
+        #[case] flags: &str,
+        #[case] replacement: &str,
+        #[case] expected: &str,
+    ) {
+        let flags: MatchFlags = flags.parse().unwrap();
+        let regex = compose_regex(pattern, flags, MatchMode::Regex, Engine::Std);
+        let result = match try_replace(text, regex.as_deref(), replacement, Targets::First) {
+            ReplacementResult::Replaced(replaced) => replaced,
+            _ => text.to_owned(),
+        };
+        assert_eq!(result, expected);
+    }
+
+    #[rstest]
+    #[case("report-v2.txt", Targets::First, Engine::Std, "report_v2.txt")]
+    #[case("report.txt", Targets::First, Engine::Std, "report_v0.txt")]
+    #[case("report.txt", Targets::First, Engine::Fancy, "report_v0.txt")]
+    #[case("a1b", Targets::All, Engine::Std, "NL")]
+    #[case("a1b", Targets::All, Engine::Fancy, "NL")]
+    #[case("a1b", Targets::Occurrence(Occurrence::Last), Engine::Std, "a1L")]
+    fn conditional_replacement_works(
+        #[case] text: &str,
+        #[case] targets: Targets,
+        #[case] engine: Engine,
+        #[case] expected: &str,
+    ) {
+        let (pattern, replacement) = if text.contains('.') {
+            (
+                r"^(\w+?)(?:-v(?P<ver>\d+))?\.txt$",
+                "${1}_v${ver:+$ver:0}.txt",
+            )
+        } else {
+            (r"[a-z](\d)?", "${1:+N:L}")
+        };
+        let regex = compose_regex(pattern, MatchFlags::NO_FLAGS, MatchMode::Regex, engine);
+        assert_eq!(
+            try_replace(text, regex.as_deref(), replacement, targets),
+            ReplacementResult::Replaced(expected.into())
+        );
+    }
+
+    #[rstest]
+    #[case("a_b_c_d", "2", Engine::Std, "a_b-c_d")]
+    #[case("a_b_c_d", "last", Engine::Std, "a_b_c-d")]
+    #[case("a_b_c_d", "4", Engine::Std, "a_b_c_d")]
+    #[case("a_b_c_d", "2", Engine::Fancy, "a_b-c_d")]
+    #[case("a_b_c_d", "last", Engine::Fancy, "a_b_c-d")]
+    fn occurrence_works(
+        #[case] text: &str,
+        #[case] occurrence: &str,
+        #[case] engine: Engine,
+        #[case] expected: &str,
+    ) {
+        let regex = compose_regex("_", MatchFlags::NO_FLAGS, MatchMode::Regex, engine);
+        let targets = Targets::Occurrence(occurrence.parse().unwrap());
+        let result = match try_replace(text, regex.as_deref(), "-", targets) {
+            ReplacementResult::Replaced(replaced) => replaced,
+            _ => text.to_owned(),
+        };
+        assert_eq!(result, expected);
+    }
+
+    #[rstest]
+    #[case("a1b2", "\\d(?=b)", "x", Engine::Fancy, ReplacementResult::Replaced("axb2".into()))]
+    #[case("a1b2", "(?<=b)\\d", "x", Engine::Fancy, ReplacementResult::Replaced("a1bx".into()))]
+    #[case("aa_b", "(\\w)\\1", "$1", Engine::Fancy, ReplacementResult::Replaced("a_b".into()))]
+    #[case("ABab", "(?i)(b)(?!\\1)", "x", Engine::Fancy, ReplacementResult::Replaced("Axab".into()))]
+    #[case("a1b2", "\\d(?=b)", "x", Engine::Std, ReplacementResult::InvalidRegex)]
+    #[case("aa_b", "(\\w)\\1", "$1", Engine::Std, ReplacementResult::InvalidRegex)]
+    fn fancy_engine_works(
+        #[case] text: &str,
+        #[case] pattern: &str,
+        #[case] replacement: &str,
+        #[case] engine: Engine,
+        #[case] expected_result: ReplacementResult,
+    ) {
+        let regex = compose_regex(pattern, MatchFlags::NO_FLAGS, MatchMode::Regex, engine);
+        assert_eq!(
+            try_replace(text, regex.as_deref(), replacement, Targets::First),
+            expected_result
+        );
+    }
+
+    #[rstest]
+    #[case("abc", vec![], ReplacementResult::NoMatch)]
+    #[case("abc", vec!["s/x/y/"], ReplacementResult::NoMatch)]
+    #[case("abc", vec!["s/a/b/", "s/b/c/g"], ReplacementResult::Replaced("ccc".into()))]
+    #[case("abc", vec!["s/x/y/", "s/c/d/"], ReplacementResult::Replaced("abd".into()))]
+    #[case("abc", vec!["s/a/x/", "s/x/a/"], ReplacementResult::Unchanged)]
+    #[case("abc", vec!["s/a/x/", "s/(/a/"], ReplacementResult::InvalidRegex)]
+    fn try_replace_chain_works(
+        #[case] text: &str,
+        #[case] exprs: Vec<&str>,
+        #[case] expected_result: ReplacementResult,
+    ) {
+        let rules: Vec<CompiledRule> = exprs
+            .into_iter()
+            .map(|expr| RenameRule::parse(expr).unwrap().compile(Engine::Std))
+            .collect();
+        let ctx = TokenContext {
+            counter: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(try_replace_chain(text, &rules, &ctx), expected_result);
+    }
+
+    fn dst_names(app: &App) -> Vec<String> {
+        app.plan_entries()
+            .into_iter()
+            .map(|entry| match entry.result {
+                ReplacementResult::Replaced(dst) => dst,
+                _ => entry.name,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn counter_numbers_matched_files() {
+        let app = App::default()
+            .with_files(vec![
+                "/d/a.jpg".into(),
+                "/d/b.txt".into(),
+                "/d/c.jpg".into(),
+            ])
+            .with_rule(RenameRule::parse("s/.*\\.jpg/img_{n:2}.jpg/").unwrap())
+            .with_counter_start(5);
+
+        assert_eq!(dst_names(&app), vec!["img_05.jpg", "b.txt", "img_06.jpg"]);
+    }
+
+    struct Upper;
+
+    impl NameTransform for Upper {
+        fn apply(&self, name: &str) -> Result<String, String> {
+            if name.contains('!') {
+                Err("bang".into())
+            } else {
+                Ok(name.to_uppercase())
+            }
+        }
+    }
+
+    #[test]
+    fn transforms_run_after_rules() {
+        let app = App::default()
+            .with_files(vec![
+                "/d/a.jpg".into(),
+                "/d/b.txt".into(),
+                "/d/c!.jpg".into(),
+            ])
+            .with_rule(RenameRule::parse("s/jpg/png/").unwrap())
+            .with_transform(Box::new(Upper));
+
+        let results: Vec<ReplacementResult> = app
+            .plan_entries()
+            .into_iter()
+            .map(|entry| entry.result)
+            .collect();
+        assert_eq!(
+            results,
+            vec![
+                ReplacementResult::Replaced("A.PNG".into()),
+                // unmatched names aren't transformed
+                ReplacementResult::NoMatch,
+                ReplacementResult::Failed("bang".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn filtered_names_are_pending() {
+        let app = App::default()
+            .with_files(vec!["/d/a.jpg".into(), "/d/b.jpg".into()])
+            .with_rule(RenameRule::parse("s/jpg/png/").unwrap())
+            .with_transform(Box::new(CommandFilter::new(
+                "sleep 0.2; tr a-z A-Z".into(),
+                Duration::from_secs(5),
+                2,
+            )));
+
+        assert_eq!(app.pending_entries(), 2);
+        let move_pairs = app.plan().unwrap();
+        assert_eq!(
+            move_pairs,
+            vec![
+                ("/d/a.jpg".into(), "/d/A.PNG".into()),
+                ("/d/b.jpg".into(), "/d/B.PNG".into()),
+            ]
+        );
+        assert_eq!(app.pending_entries(), 0);
+    }
+
+    #[test]
+    fn sanitizer_runs_last() {
+        let app = App::default()
+            .with_files(vec!["/d/a.jpg".into()])
+            .with_rule(RenameRule::parse("s/a/a: b?/").unwrap())
+            .with_transform(Box::new(Upper))
+            .with_sanitizer(Sanitizer {
+                replacement: '-',
+                lowercase: true,
+            });
+        assert_eq!(dst_names(&app), vec!["A: B?.JPG"]);
+
+        let app = app.with_sanitize(true);
+        assert_eq!(dst_names(&app), vec!["a- b-.jpg"]);
+    }
+
+    #[test]
+    fn normalization_runs_after_sanitizer() {
+        let app = App::default()
+            .with_files(vec!["/d/a.jpg".into()])
+            .with_rule(RenameRule::parse("s/a/\u{C9}:/").unwrap())
+            .with_sanitize(true)
+            .with_sanitizer(Sanitizer {
+                replacement: '-',
+                lowercase: true,
+            })
+            .with_normalization(Some(Normalization::Nfd));
+        // the sanitizer lowercases to the composed é
+        assert_eq!(dst_names(&app), vec!["e\u{301}-.jpg"]);
+    }
+
+    #[test]
+    fn flatten_with_parent_names() {
+        let app = App::default()
+            .with_files(vec!["/d/x/a.jpg".into(), "/d/y/a.jpg".into()])
+            .with_rule(RenameRule::parse("s|.*|../{parent}_$0|").unwrap());
+
+        assert_eq!(
+            app.move_pairs(),
+            vec![
+                ("/d/x/a.jpg".into(), "/d/x_a.jpg".into()),
+                ("/d/y/a.jpg".into(), "/d/y_a.jpg".into()),
+            ]
+        );
+
+        let app = app.with_rule(RenameRule::parse("s|.*|../{parent}_{name}|").unwrap());
+        assert_eq!(
+            app.move_pairs(),
+            vec![
+                ("/d/x/a.jpg".into(), "/d/x_a.jpg".into()),
+                ("/d/y/a.jpg".into(), "/d/y_a.jpg".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn generators_are_stable() {
+        let app = App::default()
+            .with_files(vec!["/d/a.jpg".into(), "/d/b.jpg".into()])
+            .with_rule(RenameRule::parse("s/.*/{uuid}_{ulid}_{now}/").unwrap());
+
+        let names = dst_names(&app);
+        assert_eq!(dst_names(&app), names);
+        assert_ne!(names[0], names[1]);
+    }
+
+    #[test]
+    fn renumber_sequences() {
+        let app = App::default()
+            .with_files(vec![
+                "/d/img_3.jpg".into(),
+                "/d/notes.txt".into(),
+                "/d/img_10.jpg".into(),
+                "/d/IMG_20.JPG".into(),
+            ])
+            .with_rule(RenameRule::parse("s/\\.jpg$/.jpg/i").unwrap())
+            .with_renumber(true)
+            .with_renumber_width(Some(2));
+
+        assert_eq!(
+            dst_names(&app),
+            vec!["img_01.jpg", "notes.txt", "img_02.jpg", "IMG_03.jpg"]
+        );
+    }
+
+    #[test]
+    fn tutorial_follows_the_user() {
+        let sandbox = Sandbox::create().unwrap();
+        let mut app = App::default()
+            .with_files(sandbox.files())
+            .with_tutorial(Tutorial::default());
+
+        for key in "IMG_(\\d+)\tphoto_$1".chars() {
+            app.handle_key(Key::Char(key));
+            app.advance_tutorial();
+        }
+        assert_eq!(app.tutorial.as_ref().unwrap().goal(), Goal::IgnoreCase);
+
+        app.handle_key(Key::Ctrl('r'));
+        app.advance_tutorial();
+        assert_eq!(app.tutorial.as_ref().unwrap().goal(), Goal::Execute);
+    }
+
+    #[test]
+    fn generated_names_are_unique() {
+        let files: Vec<PathBuf> = (0..300).map(|i| format!("/d/{}.txt", i).into()).collect();
+        let app = App::default()
+            .with_files(files)
+            .with_rule(RenameRule::parse("s/.*/{rand:2}/").unwrap());
+
+        let names = dst_names(&app);
+        assert_eq!(names.iter().collect::<HashSet<_>>().len(), names.len());
+        // the previewed names are the executed ones
+        assert_eq!(dst_names(&app), names);
+        *app.cached_plan.borrow_mut() = None;
+        assert_eq!(dst_names(&app), names);
+    }
+
+    #[test]
+    fn conflicts_are_suffixed() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.txt", "b.txt", "c.txt", "x.txt"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        let app = App::default()
+            .with_files(
+                ["a.txt", "b.txt", "c.txt"]
+                    .iter()
+                    .map(|name| dir.path().join(name))
+                    .collect(),
+            )
+            .with_rule(RenameRule::parse("s/[abc]/x/").unwrap())
+            .with_on_conflict(ConflictPolicy::Suffix);
+
+        assert_eq!(dst_names(&app), vec!["x (1).txt", "x (2).txt", "x (3).txt"]);
+        assert!(app.plan_entries().iter().all(|entry| entry.suffixed));
+        assert_eq!(app.conflicting_files(&app.move_pairs()), 0);
+    }
+
+    #[test]
+    fn names_invalid_on_windows_are_flagged() {
+        let mut app = App::default()
+            .with_files(vec!["/d/a.txt".into(), "/d/b.txt".into()])
+            .with_rule(RenameRule::parse(r"s/^a\.txt$/aux.txt/").unwrap());
+
+        assert_eq!(app.non_portable_entries(), 1);
+        assert!(app.files_title().contains("[invalid on Windows: 1]"));
+        assert!(app.plan().is_ok());
+
+        app = app.with_strict_portability(true);
+        assert_eq!(
+            app.plan().unwrap_err(),
+            "/d/a.txt: AUX is reserved on Windows"
+        );
+        assert!(press(&mut app, Key::Char('\n')).is_none());
+        assert!(app.popup.is_none());
+    }
+
+    #[test]
+    fn too_long_paths_are_flagged() {
+        let app = App::default()
+            .with_files(vec!["/d/a.txt".into(), "/d/b.txt".into()])
+            .with_rule(RenameRule::parse(&format!("s/a/{}/", "a".repeat(300))).unwrap());
+
+        assert_eq!(app.too_long_entries(), 1);
+        assert!(app.files_title().contains("[too long: 1]"));
+    }
+
+    #[test]
+    fn missing_sources_are_marked_or_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        std::fs::write(&file, "").unwrap();
+        let files = vec![file.clone(), dir.path().join("gone.txt")];
+
+        let app = App::default().with_files(files.clone());
+        assert!(app.files_title().contains("[missing or unreadable: 1]"));
+        assert_eq!(app.source_files.len(), 2);
+
+        let app = App::default().with_files(files).with_missing_dropped();
+        assert!(app
+            .files_title()
+            .contains("[missing or unreadable dropped: 1]"));
+        assert_eq!(app.source_files, vec![file]);
+    }
+
+    #[cfg(unix)]
+    #[rstest]
+    #[case(SymlinkPolicy::Rename, &["a.txt", "link"], "(symlink)")]
+    #[case(SymlinkPolicy::Follow, &["a.txt", "target.txt"], "(target of ")]
+    #[case(SymlinkPolicy::Skip, &["a.txt"], "")]
+    fn symlinks_follow_the_policy(
+        #[case] policy: SymlinkPolicy,
+        #[case] expected: &[&str],
+        #[case] marker: &str,
+    ) {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = std::fs::canonicalize(dir.path()).unwrap();
+        std::fs::write(dir.join("a.txt"), "").unwrap();
+        std::fs::write(dir.join("target.txt"), "").unwrap();
+        std::os::unix::fs::symlink("target.txt", dir.join("link")).unwrap();
+
+        let app = App::default()
+            .with_files(vec![dir.join("a.txt"), dir.join("link")])
+            .with_symlink_policy(policy);
+
+        let names: Vec<PathBuf> = expected.iter().map(|name| dir.join(name)).collect();
+        assert_eq!(app.source_files, names);
+        let rows: Vec<String> = app
+            .files_list()
+            .iter()
+            .map(|spans| spans.0.iter().map(|span| span.content.as_ref()).collect())
+            .collect();
+        assert!(rows.last().unwrap().contains(marker));
+        if policy == SymlinkPolicy::Skip {
+            assert!(app.files_title().contains("[symlinks skipped: 1]"));
+        }
+    }
+
+    #[test]
+    fn new_directories_are_shown() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("x")).unwrap();
+        let files = vec![dir.path().join("a_b.txt")];
+        let rule = RenameRule::parse("s|(.)_(.)|x/$1/$2|").unwrap();
+        let row = |app: &App| -> Vec<(String, bool)> {
+            app.files_list()[0]
+                .0
+                .iter()
+                .map(|span| {
+                    let underlined = span.style.add_modifier.contains(Modifier::UNDERLINED);
+                    (span.content.to_string(), underlined)
+                })
+                .collect()
+        };
+
+        let app = App::default()
+            .with_files(files.clone())
+            .with_rule(rule.clone());
+        let spans = row(&app);
+        assert!(spans.contains(&("x/".to_owned(), false)));
+        assert!(spans.contains(&("a/".to_owned(), true)));
+        assert!(spans.contains(&(" (no such directory, see --mkdir)".to_owned(), false)));
+
+        let app = App::default()
+            .with_files(files)
+            .with_rule(rule)
+            .with_mkdir(true);
+        assert!(row(&app).contains(&(" (new directories)".to_owned(), false)));
+    }
+
+    #[test]
+    fn untouched_inputs_are_conflicts() {
+        let mut app = App::default()
+            .with_files(vec!["/d/a.txt".into(), "/d/b.txt".into()])
+            .with_rule(RenameRule::parse("s/a/b/").unwrap());
+
+        assert!(app.files_title().contains("[conflicts: 1]"));
+        assert!(press(&mut app, Key::Char('\n')).is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn names_which_arent_utf8_are_renamed() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join(OsStr::from_bytes(b"caf\xe9.txt"));
+        std::fs::write(&file, "").unwrap();
+
+        let app = App::default()
+            .with_files(vec![file.clone()])
+            .with_rule(RenameRule::parse(r"s/(.*)\.txt/$1 menu.md/").unwrap());
+
+        assert_eq!(
+            app.plan().unwrap(),
+            vec![(file, dir.path().join(OsStr::from_bytes(b"caf\xe9 menu.md")))]
+        );
+        let row: String = app.files_list()[0]
+            .0
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect();
+        assert!(row.ends_with("caf\\xE9.txt->caf\\xE9 menu.md"));
+    }
+
+    #[test]
+    fn inputs_are_recalled_from_the_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("inputs.json");
+        let mut history = InputHistory::default();
+        history.remember("old", "1");
+        history.remember("older", "2");
+        history.remember("latest", "");
+
+        let mut app = App::default().with_input_history(history, path.clone());
+        press(&mut app, Key::Char('x'));
+        press(&mut app, Key::Up);
+        assert_eq!(app.regex, "latest");
+        press(&mut app, Key::Up);
+        press(&mut app, Key::Up);
+        press(&mut app, Key::Up);
+        assert_eq!(app.regex, "old");
+        press(&mut app, Key::Down);
+        assert_eq!(app.regex, "older");
+        press(&mut app, Key::Down);
+        press(&mut app, Key::Down);
+        assert_eq!(app.regex, "x");
+
+        press(&mut app, Key::Char('\t'));
+        press(&mut app, Key::Up);
+        assert_eq!(app.replacement, "2");
+        press(&mut app, Key::Char('3'));
+        app.remember_inputs().unwrap();
+
+        let saved = InputHistory::load(&path).unwrap();
+        assert_eq!(saved.regexes.last().unwrap(), "x");
+        assert_eq!(saved.replacements.last().unwrap(), "23");
+    }
 
-        let regex_input = Paragraph::new(self.regex.as_ref())
-            .style(if re.as_ref().is_some() {
-                Style::default()
-            } else {
-                Style::default().fg(Color::Red)
+    #[test]
+    fn plan_is_exported_from_the_popup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        for name in ["a1", "a2", "b"] {
+            std::fs::write(path(name), "").unwrap();
+        }
+
+        let mut app = App::default()
+            .with_files(vec![path("a1"), path("a2"), path("b")])
+            .with_rule(RenameRule::new(
+                "a\\d".to_owned(),
+                "c".to_owned(),
+                MatchFlags::NO_FLAGS,
+            ))
+            .with_plan_out(Some(path("plan.json")));
+
+        press(&mut app, Key::Alt('w'));
+        press(&mut app, Key::Char('\n'));
+        assert!(app.popup.is_none());
+
+        let plan = PlanFile::load(&path("plan.json")).unwrap();
+        let renames: Vec<(PathBuf, bool)> = plan
+            .renames
+            .into_iter()
+            .map(|planned| (planned.rename.dst, planned.conflict))
+            .collect();
+        assert_eq!(renames, [(path("c"), true), (path("c"), true)]);
+
+        // a path which can't be written keeps the popup open with the error
+        press(&mut app, Key::Alt('w'));
+        press(&mut app, Key::Char('/'));
+        press(&mut app, Key::Char('\n'));
+        assert!(matches!(
+            app.popup,
+            Some(Popup::ExportPlan { error: Some(_), .. })
+        ));
+    }
+
+    #[test]
+    fn input_files_are_filtered_by_globs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        std::fs::create_dir_all(path("node_modules/x")).unwrap();
+        for name in ["a.jpg", "b.png", "node_modules/x/c.jpg"] {
+            std::fs::write(path(name), "").unwrap();
+        }
+        let filter = PathFilter::new(&["*.jpg".to_owned()], &["node_modules/**".to_owned()]);
+
+        let app = App::default()
+            .with_files(vec![path("a.jpg"), path("b.png")])
+            .with_path_filter(filter.clone());
+        assert_eq!(app.source_files(), [path("a.jpg")]);
+
+        // the files found by the walk are filtered the same way
+        let mut app = App::default()
+            .with_path_filter(filter)
+            .with_scan(DirScan::start(
+                vec![dir.path().to_owned()],
+                ScanOptions::default(),
+            ));
+        app.finish_scan();
+        assert_eq!(app.source_files(), [path("a.jpg")]);
+        let title = app.files_title();
+        assert!(title.contains("[include: *.jpg] [exclude: node_modules/**] [filtered out: 2]"));
+    }
+
+    #[test]
+    fn scanned_files_are_added() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        std::fs::create_dir(path("sub")).unwrap();
+        for name in ["b", "sub/a"] {
+            std::fs::write(path(name), "").unwrap();
+        }
+
+        let mut app = App::default()
+            .with_files(vec![path("b")])
+            .with_scan(DirScan::start(
+                vec![dir.path().to_owned()],
+                ScanOptions::default(),
+            ));
+        assert!(app.files_title().contains("[scanning"));
+
+        app.finish_scan();
+        assert_eq!(app.source_files(), [path("b"), path("sub/a")]);
+        assert!(!app.files_title().contains("[scanning"));
+    }
+
+    #[test]
+    fn gitignored_files_are_counted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        for name in ["a.txt", "b.o", "c.o"] {
+            std::fs::write(path(name), "").unwrap();
+        }
+        std::fs::write(path(".ignore"), "*.o\n").unwrap();
+
+        let options = ScanOptions {
+            ignore_files: true,
+            ..ScanOptions::default()
+        };
+        let mut app =
+            App::default().with_scan(DirScan::start(vec![dir.path().to_owned()], options));
+        app.finish_scan();
+        assert_eq!(app.source_files(), [path(".ignore"), path("a.txt")]);
+        assert!(app.files_title().contains("[gitignored: 2]"));
+    }
+
+    #[test]
+    fn missing_files_of_a_list_are_dropped_while_it_is_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        std::fs::write(path("a"), "").unwrap();
+
+        let list = format!("{}\n{}\n", path("a").display(), path("gone").display());
+        let paths = path_utils::path_list(std::io::Cursor::new(list), false);
+        let mut app = App::default()
+            .with_missing_dropped()
+            .with_scan(DirScan::check(paths, ScanOptions::default()));
+        assert!(app.files_title().contains("[checking, "));
+        app.finish_scan();
+        assert_eq!(app.source_files(), [path("a")]);
+        assert_eq!(app.dropped_sources, 1);
+    }
+
+    #[test]
+    fn kinds_of_files_are_shown() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        std::fs::create_dir(path("d")).unwrap();
+        std::fs::write(path("f"), "").unwrap();
+
+        let mut app = App::default()
+            .with_file_types(vec![FileKind::Dir])
+            .with_scan(DirScan::check(
+                vec![path("d"), path("f")],
+                ScanOptions {
+                    types: vec![FileKind::Dir],
+                    ..ScanOptions::default()
+                },
+            ));
+        app.finish_scan();
+        assert_eq!(app.source_files(), [path("d")]);
+        assert!(app.files_title().contains("[type: dir] [other types: 1]"));
+
+        app.files_list();
+        app.kinds.wait(None);
+        let line: String = app.files_list()[0]
+            .0
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect();
+        assert!(line.starts_with("d "));
+    }
+
+    #[test]
+    fn files_are_sorted_from_the_app() {
+        let paths = |names: &[&str]| -> Vec<PathBuf> {
+            names
+                .iter()
+                .map(|name| PathBuf::from("/d").join(name))
+                .collect()
+        };
+        let mut app = App::default()
+            .with_files(paths(&["f10", "F2", "f1"]))
+            .with_file_order(FileOrder::Natural);
+        assert_eq!(app.source_files(), paths(&["f1", "F2", "f10"]));
+
+        press(&mut app, Key::Alt('o'));
+        assert_eq!(app.source_files(), paths(&["F2", "f1", "f10"]));
+        assert!(app.files_title().contains("[sorted by name]"));
+
+        // mtime and size of missing files are equal, so the input order stays
+        press(&mut app, Key::Alt('o'));
+        press(&mut app, Key::Alt('o'));
+        assert_eq!(app.source_files(), paths(&["f10", "F2", "f1"]));
+        press(&mut app, Key::Alt('o'));
+        assert_eq!(app.file_order, FileOrder::Input);
+        assert_eq!(app.source_files(), paths(&["f10", "F2", "f1"]));
+
+        // renamed files keep their place in the input order
+        app.rename_sources(&[(PathBuf::from("/d/f10"), PathBuf::from("/d/a"))]);
+        press(&mut app, Key::Alt('o'));
+        press(&mut app, Key::Alt('o'));
+        press(&mut app, Key::Alt('o'));
+        press(&mut app, Key::Alt('o'));
+        press(&mut app, Key::Alt('o'));
+        assert_eq!(app.source_files(), paths(&["a", "F2", "f1"]));
+    }
+
+    #[test]
+    fn counters_follow_natural_order() {
+        let app = App::default()
+            .with_files(
+                ["/d/part10.txt", "/d/part9.txt", "/d/Part1.txt"]
+                    .iter()
+                    .map(PathBuf::from)
+                    .collect(),
+            )
+            .with_file_order(FileOrder::Natural)
+            .with_rule(RenameRule::parse("s/.*/{n}_$0/").unwrap());
+
+        assert_eq!(
+            app.move_pairs()
+                .iter()
+                .map(|(_, dst)| dst.to_str().unwrap())
+                .collect::<Vec<_>>(),
+            vec!["/d/1_Part1.txt", "/d/2_part9.txt", "/d/3_part10.txt"]
+        );
+    }
+
+    #[test]
+    fn conflicts_are_highlighted() {
+        let mut app = App::default()
+            .with_files(
+                ["a1.txt", "a2.txt", "b.txt"]
+                    .iter()
+                    .map(PathBuf::from)
+                    .collect(),
+            )
+            .with_rule(RenameRule::parse("s/a[0-9]/c/").unwrap());
+
+        let highlighted: Vec<bool> = app
+            .files_list()
+            .iter()
+            .map(|spans| {
+                spans
+                    .0
+                    .iter()
+                    .all(|span| span.style.bg == Some(Color::Yellow))
             })
-            .block(Block::default().title("Regex").borders(Borders::ALL));
-        frame.render_widget(regex_input, input_layout[0]);
+            .collect();
+        assert_eq!(highlighted, vec![true, true, false]);
+        assert!(app.files_title().contains("[conflicts: 2]"));
+        assert!(press(&mut app, Key::Char('\n')).is_none());
 
-        let replace_input = Paragraph::new(self.replacement.as_ref())
-            .block(Block::default().title("Replacement").borders(Borders::ALL));
-        frame.render_widget(replace_input, input_layout[1]);
+        app.set_replacement("c$0".to_owned());
+        assert!(!app.files_title().contains("conflicts"));
+        assert!(matches!(
+            press(&mut app, Key::Char('\n')),
+            Some(AppResult::MoveFiles(_))
+        ));
+    }
 
-        let flags_view = Paragraph::new(self.flags.to_string())
-            .block(Block::default().title("Flags").borders(Borders::ALL));
-        frame.render_widget(flags_view, top_row_layout[1]);
+    #[test]
+    fn destination_collisions_works() {
+        let entry = |name: &str, result: ReplacementResult| PlanEntry {
+            parent: "/d".into(),
+            name: name.into(),
+            result,
+            truncated: false,
+            suffixed: false,
+            not_in_git: false,
+            unchecked: false,
+        };
+        let entries = [
+            entry("a", ReplacementResult::Replaced("x".into())),
+            entry("b", ReplacementResult::Replaced("x".into())),
+            entry("c", ReplacementResult::Replaced("y".into())),
+            entry("y", ReplacementResult::NoMatch),
+            entry("d", ReplacementResult::Replaced("z".into())),
+        ];
+        assert_eq!(destination_collisions(&entries), vec![1, 2]);
+    }
 
-        match self.active_area {
-            EditableArea::Regex => {
-                frame.set_cursor(
-                    // Put cursor past the end of the regex
-                    input_layout[0].x + self.regex.len() as u16 + 1,
-                    // Move one line down, from the border to the regex input
-                    input_layout[0].y + 1,
-                );
+    #[test]
+    fn public_api() {
+        let rule: RenameRule = "s/photo/img/gi".parse().unwrap();
+        assert_eq!(rule.flags, MatchFlags::GLOBAL | MatchFlags::ICASE);
+        assert_eq!(rule.targets(), Targets::All);
+
+        let regex = compose_regex(&rule.regex, rule.flags, rule.mode, Engine::Std).unwrap();
+        assert_eq!(
+            regex.replace("Photo_photo", &rule.replacement, rule.targets()),
+            Ok("img_img".to_owned())
+        );
+        assert!(compose_regex("(", MatchFlags::NO_FLAGS, MatchMode::Regex, Engine::Std).is_none());
+
+        let json = serde_json::to_string(&rule).unwrap();
+        assert_eq!(serde_json::from_str::<RenameRule>(&json).unwrap(), rule);
+    }
+
+    #[test]
+    fn most_changed_first() {
+        let mut app = App::default()
+            .with_files(vec![
+                "/d/a1.txt".into(),
+                "/d/b.txt".into(),
+                "/d/abc12345678.txt".into(),
+            ])
+            .with_rule(RenameRule::parse("s/[0-9]+/_number/").unwrap());
+        let names = |app: &App| -> Vec<String> {
+            app.listed_entries()
+                .into_iter()
+                .map(|entry| entry.name)
+                .collect()
+        };
+
+        assert_eq!(names(&app), vec!["a1.txt", "b.txt", "abc12345678.txt"]);
+        app.handle_key(Key::Alt('c'));
+        assert_eq!(names(&app), vec!["abc12345678.txt", "a1.txt", "b.txt"]);
+        // the renaming order isn't affected
+        assert_eq!(app.move_pairs()[0].0, PathBuf::from("/d/a1.txt"));
+    }
+
+    /// dispatch the key like the main loop does
+    fn press(app: &mut App, key: Key) -> Option<AppResult> {
+        if app.popup.is_some() {
+            app.handle_popup_key(key)
+        } else {
+            app.handle_key(key)
+        }
+    }
+
+    #[test]
+    fn quitting_with_edited_rules_is_confirmed() {
+        let mut app = App::default();
+        assert!(matches!(
+            press(&mut app, Key::Ctrl('c')),
+            Some(AppResult::Exit)
+        ));
+
+        press(&mut app, Key::Char('a'));
+        assert!(press(&mut app, Key::Ctrl('c')).is_none());
+        assert_eq!(app.popup, Some(Popup::Quit));
+        press(&mut app, Key::Esc);
+        assert_eq!(app.popup, None);
+
+        press(&mut app, Key::Ctrl('c'));
+        assert!(matches!(
+            press(&mut app, Key::Ctrl('c')),
+            Some(AppResult::Exit)
+        ));
+
+        press(&mut app, Key::Char('s'));
+        assert!(matches!(app.popup, Some(Popup::SavePreset { .. })));
+
+        // rules given at the start aren't lost
+        let mut app = App::default().with_rule(RenameRule::parse("s/a/b/").unwrap());
+        app.launch = Some(app.session());
+        assert!(matches!(
+            press(&mut app, Key::Ctrl('c')),
+            Some(AppResult::Exit)
+        ));
+    }
+
+    #[test]
+    fn files_are_added_from_the_app() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        for name in ["a.txt", "b.txt", "c.jpg"] {
+            std::fs::write(path(name), "").unwrap();
+        }
+        let mut app = App::default().with_files(vec![path("a.txt")]);
+        let type_text = |app: &mut App, text: &str| {
+            for ch in text.chars() {
+                press(app, Key::Char(ch));
             }
-            EditableArea::Replace => {
-                frame.set_cursor(
-                    // Put cursor past the end of the replacement
-                    input_layout[1].x + self.replacement.len() as u16 + 1,
-                    // Move one line down, from the border to the replacement input
-                    input_layout[1].y + 1,
+        };
+
+        press(&mut app, Key::Alt('a'));
+        type_text(&mut app, &format!("{}/*.txt\n", dir.path().display()));
+        assert_eq!(app.popup, None);
+        assert_eq!(app.source_files(), [path("a.txt"), path("b.txt")]);
+
+        press(&mut app, Key::Alt('a'));
+        type_text(&mut app, &format!("{}/c\t", dir.path().display()));
+        assert!(
+            matches!(&app.popup, Some(Popup::AddFiles { path: typed, .. })
+            if Path::new(typed) == path("c.jpg"))
+        );
+        press(&mut app, Key::Char('\n'));
+        assert_eq!(app.source_files().len(), 3);
+
+        press(&mut app, Key::Alt('a'));
+        type_text(&mut app, &format!("{}/missing\n", dir.path().display()));
+        assert!(matches!(
+            app.popup,
+            Some(Popup::AddFiles { error: Some(_), .. })
+        ));
+    }
+
+    #[test]
+    fn files_are_checked_in_the_list() {
+        let mut app = App::default()
+            .with_files(vec!["/d/a1".into(), "/d/a2".into(), "/d/a3".into()])
+            .with_rule(RenameRule::parse("s/a/b/").unwrap());
+        let sources = |app: &App| -> Vec<PathBuf> {
+            app.move_pairs().into_iter().map(|(src, _)| src).collect()
+        };
+        let rows = |app: &App| -> Vec<String> {
+            app.files_list()
+                .iter()
+                .map(|spans| spans.0.iter().map(|span| span.content.as_ref()).collect())
+                .collect()
+        };
+
+        press(&mut app, Key::Char('\t'));
+        press(&mut app, Key::Char('\t'));
+        press(&mut app, Key::Down);
+        press(&mut app, Key::Char(' '));
+        assert_eq!(sources(&app), [PathBuf::from("/d/a1"), "/d/a3".into()]);
+        assert!(app.files_title().contains("[unchecked: 1]"));
+        // the unchecked file stays in the list with its name
+        assert!(rows(&app)[1].starts_with("[ ] /d/a2"));
+        assert!(rows(&app)[2].starts_with("[x] /d/a3->b3"));
+        // typing doesn't change the rule
+        assert_eq!(app.regex, "a");
+
+        press(&mut app, Key::Char('i'));
+        assert_eq!(sources(&app), [PathBuf::from("/d/a2")]);
+        press(&mut app, Key::Char('n'));
+        assert!(sources(&app).is_empty());
+        press(&mut app, Key::Char('a'));
+        assert_eq!(sources(&app).len(), 3);
+
+        // the checkboxes are hidden when none is unchecked outside the list
+        press(&mut app, Key::Char('d'));
+        press(&mut app, Key::Char('d'));
+        press(&mut app, Key::Char('\t'));
+        assert!(rows(&app)[1].starts_with("/d/a2->b2"));
+    }
+
+    #[test]
+    fn inputs_are_edited_at_the_cursor() {
+        let mut app = App::default();
+        for key in [
+            Key::Char('a'),
+            Key::Char('c'),
+            Key::Left,
+            Key::Char('b'),
+            Key::Home,
+            Key::Delete,
+            Key::Char('x'),
+        ] {
+            press(&mut app, key);
+        }
+        assert_eq!(app.regex, "xbc");
+        assert_eq!(app.text_before_cursor(&app.regex), "x");
+
+        // the other input is edited from its end
+        press(&mut app, Key::Char('\t'));
+        press(&mut app, Key::Char('y'));
+        assert_eq!(app.replacement, "y");
+        press(&mut app, Key::BackTab);
+        press(&mut app, Key::Backspace);
+        assert_eq!(app.regex, "xb");
+
+        for ch in " foo_bar".chars() {
+            press(&mut app, Key::Char(ch));
+        }
+        press(&mut app, Key::Alt('b'));
+        press(&mut app, Key::Ctrl('k'));
+        assert_eq!(app.regex, "xb foo_");
+        press(&mut app, Key::Ctrl('w'));
+        assert_eq!(app.regex, "xb ");
+        press(&mut app, Key::Left);
+        press(&mut app, Key::Ctrl('u'));
+        assert_eq!(app.regex, " ");
+    }
+
+    #[test]
+    fn pasted_text_is_inserted() {
+        let mut app = App::default();
+        press(&mut app, Key::Char('x'));
+        press(&mut app, Key::Home);
+        app.paste("(\\d+)\t\n");
+        assert_eq!(app.regex, "(\\d+)x");
+        assert!(app.popup.is_none());
+
+        press(&mut app, Key::Alt('a'));
+        app.paste("/tmp/a b\n");
+        assert!(matches!(&app.popup, Some(Popup::AddFiles { path, .. }) if path == "/tmp/a b"));
+    }
+
+    #[test]
+    fn mouse_focuses_and_scrolls() {
+        let files = (0..10)
+            .map(|idx| PathBuf::from(format!("/d/{}", idx)))
+            .collect();
+        let mut app = App::default().with_files(files);
+        app.click_areas.set(ClickAreas {
+            regex: Rect::new(0, 0, 20, 3),
+            replacement: Rect::new(20, 0, 20, 3),
+            expression: Rect::new(0, 0, 40, 3),
+            // 3 rows inside the borders
+            files: Rect::new(0, 3, 40, 5),
+        });
+        // the terminal counts from 1
+        let click = |app: &mut App, x: u16, y: u16| {
+            app.handle_mouse(MouseEvent::Press(MouseButton::Left, x + 1, y + 1));
+        };
+
+        app.replacement = "abc".to_owned();
+        click(&mut app, 22, 1);
+        assert_eq!(app.active_area, EditableArea::Replace);
+        assert_eq!(app.text_before_cursor(&app.replacement), "a");
+
+        click(&mut app, 5, 5);
+        assert_eq!(app.active_area, EditableArea::Files);
+        assert_eq!(app.list_cursor, 1);
+
+        app.handle_mouse(MouseEvent::Press(MouseButton::WheelDown, 1, 1));
+        assert_eq!(app.list_scroll.get(), 3);
+        assert_eq!(app.list_cursor, 3);
+        click(&mut app, 5, 6);
+        assert_eq!(app.list_cursor, 5);
+
+        click(&mut app, 5, 1);
+        assert_eq!(app.active_area, EditableArea::Regex);
+    }
+
+    #[test]
+    fn long_names_are_truncated() {
+        let app = App::default()
+            .with_files(vec!["/d/a.txt".into(), "/d/b.txt".into()])
+            .with_rule(RenameRule::parse("s/^a/sub\\/very_long_name/").unwrap())
+            .with_truncation(Some(Truncation { max_bytes: 10 }));
+
+        assert_eq!(dst_names(&app), vec!["sub/very_l.txt", "b.txt"]);
+        let truncated: Vec<bool> = app.plan_entries().iter().map(|e| e.truncated).collect();
+        assert_eq!(truncated, vec![true, false]);
+        assert!(app.files_title().contains("[truncated: 1]"));
+    }
+
+    #[test]
+    fn reset_and_undo() {
+        let mut app = App::default()
+            .with_rule(RenameRule::parse("s/a/b/").unwrap())
+            .with_files(vec!["/d/a.txt".into()]);
+        app.launch = Some(app.session());
+
+        press(&mut app, Key::Char('c'));
+        press(&mut app, Key::Ctrl('g'));
+        press(&mut app, Key::Alt('s'));
+        press(&mut app, Key::Ctrl('n'));
+        let edited = app.session();
+
+        press(&mut app, Key::Ctrl('x'));
+        assert_eq!(app.rule().to_string(), "s/a/b/");
+        assert!(app.rules.is_empty());
+        assert!(!app.sanitize);
+
+        press(&mut app, Key::Ctrl('x'));
+        assert_eq!(app.session(), edited);
+        press(&mut app, Key::Ctrl('x'));
+        press(&mut app, Key::Ctrl('x'));
+        assert_eq!(app.session(), edited);
+    }
+
+    #[cfg(feature = "mp4")]
+    #[test]
+    fn video_tokens() {
+        let dir = tempfile::tempdir().unwrap();
+        let video = dir.path().join("clip.mp4");
+        std::fs::write(&video, media::sample_mp4(b"avc1", 83_500)).unwrap();
+        let text = dir.path().join("notes.mp4");
+        std::fs::write(&text, "not a video").unwrap();
+        let app = App::default()
+            .with_files(vec![video, text])
+            .with_rule(RenameRule::parse("s/(.*)\\.mp4/${1}_{duration}_{vcodec}.mp4/").unwrap());
+
+        let entries = app.plan_entries();
+        assert_eq!(
+            entries[0].result,
+            ReplacementResult::Replaced("clip_01m23s_h264.mp4".into())
+        );
+        assert!(
+            matches!(&entries[1].result, ReplacementResult::Failed(err) if err.starts_with("no video metadata"))
+        );
+        assert_eq!(app.videos.progress(), (2, 2));
+    }
+
+    #[test]
+    fn metadata_is_pending_over_budget() {
+        fn slow_git_info(_: &Path) -> Option<GitInfo> {
+            thread::sleep(Duration::from_millis(100));
+            None
+        }
+
+        let mut app = App::default()
+            .with_files(vec!["/nonexistent/a.md".into(), "/nonexistent/b.md".into()])
+            .with_rule(RenameRule::parse("s/^/{git_author}_/").unwrap())
+            .with_metadata_budget(Some(Duration::ZERO));
+        app.git_infos = Prefetcher::new(1, slow_git_info);
+
+        assert_eq!(app.pending_entries(), 2);
+        assert!(app.files_title().contains("[metadata of 2 files pending…]"));
+        assert!(press(&mut app, Key::Char('\n')).is_none());
+
+        assert_eq!(app.plan().map(|pairs| pairs.len()), Ok(2));
+        assert_eq!(app.pending_entries(), 0);
+        assert!(app.plan_entries().iter().all(|entry| entry.not_in_git));
+    }
+
+    #[test]
+    fn git_tokens() {
+        let repo = tempfile::tempdir().unwrap();
+        std::fs::write(repo.path().join("guide.md"), "").unwrap();
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .arg("-C")
+                .arg(repo.path())
+                .args([
+                    "-c",
+                    "user.name=Jane Doe",
+                    "-c",
+                    "user.email=jane@example.com",
+                ])
+                .args(args)
+                .env("GIT_AUTHOR_DATE", "2021-03-04T05:06:07+00:00")
+                .output()
+                .unwrap()
+                .status;
+            assert!(status.success());
+        };
+        git(&["init", "-q"]);
+        git(&["add", "guide.md"]);
+        git(&["commit", "-q", "-m", "guide"]);
+        std::fs::write(repo.path().join("draft.md"), "").unwrap();
+
+        let app = App::default()
+            .with_files(vec![
+                repo.path().join("guide.md"),
+                repo.path().join("draft.md"),
+            ])
+            .with_rule(RenameRule::parse("s/.*/{git_date:%Y}_{git_author}_$0/").unwrap());
+
+        let entries = app.plan_entries();
+        assert_eq!(
+            entries[0].result,
+            ReplacementResult::Replaced("2021_Jane Doe_guide.md".into())
+        );
+        assert!(!entries[0].not_in_git);
+        assert_eq!(
+            entries[1].result,
+            ReplacementResult::Replaced("__draft.md".into())
+        );
+        assert!(entries[1].not_in_git);
+        assert!(app.files_title().contains("[not in git: 1]"));
+        assert!(!app.is_plan_reproducible());
+    }
+
+    #[test]
+    fn btime_token() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("scan.png");
+        std::fs::write(&file, "").unwrap();
+        let app = App::default()
+            .with_files(vec![file.clone(), dir.path().join("missing.png")])
+            .with_rule(RenameRule::parse("s/.*/{btime:%Y}_$0/").unwrap());
+
+        let entries = app.plan_entries();
+        match std::fs::metadata(&file).unwrap().created() {
+            Ok(created) => {
+                let year = DateTime::<Local>::from(created).format("%Y").to_string();
+                assert_eq!(
+                    entries[0].result,
+                    ReplacementResult::Replaced(format!("{}_scan.png", year))
                 );
             }
+            Err(_) => assert!(matches!(entries[0].result, ReplacementResult::Failed(_))),
         }
+        assert!(
+            matches!(&entries[1].result, ReplacementResult::Failed(err) if err.starts_with("no creation time"))
+        );
+    }
 
-        let files_list: Vec<Spans> = self
-            .source_files
-            .clone()
-            .into_iter()
-            .filter_map(path_utils::split_path)
-            .map(|(parent, name)| {
-                let dir_style = Style::default().add_modifier(Modifier::BOLD);
-                let src_name_style = Style::default().fg(Color::Red);
-                let dst_name_style = Style::default().fg(Color::Green);
-
-                let dir_str = parent.to_str().unwrap().to_owned() + "/";
-
-                match try_replace(&name, &re, &self.replacement, self.is_global()) {
-                    ReplacementResult::Replaced(dst_name) => Spans::from(vec![
-                        Span::styled(dir_str, dir_style),
-                        Span::styled(name, src_name_style),
-                        Span::raw("->"),
-                        Span::styled(dst_name, dst_name_style),
-                    ]),
-                    _ => Spans::from(vec![Span::styled(dir_str, dir_style), Span::from(name)]),
-                }
-            })
-            .collect();
+    #[test]
+    fn case_style_menu() {
+        let mut app = App::default()
+            .with_files(vec!["/d/My Photo 1.JPG".into()])
+            .with_rule(RenameRule::parse("s/Photo/Picture/").unwrap());
 
-        let files_view = Paragraph::new(files_list)
-            .wrap(Wrap { trim: false })
-            .block(Block::default().title("Files").borders(Borders::ALL));
-        frame.render_widget(files_view, editor_layout[1]);
+        press(&mut app, Key::Alt('k'));
+        press(&mut app, Key::Down);
+        press(&mut app, Key::Down);
+        press(&mut app, Key::Char('\n'));
+        assert_eq!(app.case_style, Some(CaseStyle::Kebab));
+        assert_eq!(dst_names(&app), vec!["my-picture-1.JPG"]);
 
-        let help_list: Vec<Spans> = vec![
-            ("Tab", "switch between regex and replacement areas"),
-            ("Enter", "execute renaming"),
-            ("Ctrl-g", "'global' flag"),
-            ("Ctrl-r", "'icase' flag"),
-            ("Ctrl-c", "exit"),
-        ]
-        .into_iter()
-        .map(|(key, descr)| {
-            Spans::from(vec![
-                Span::styled(key, Style::default().add_modifier(Modifier::BOLD)),
-                Span::raw(" - "),
-                Span::raw(descr),
+        press(&mut app, Key::Alt('k'));
+        assert_eq!(app.popup, Some(Popup::CaseStyles { selected: 2 }));
+        press(&mut app, Key::Up);
+        press(&mut app, Key::Up);
+        press(&mut app, Key::Char('\n'));
+        assert_eq!(app.case_style, None);
+        assert_eq!(dst_names(&app), vec!["My Picture 1.JPG"]);
+    }
+
+    #[test]
+    fn filtering_by_extension() {
+        let mut app = App::default()
+            .with_files(vec![
+                "/d/a.jpg".into(),
+                "/d/b.PNG".into(),
+                "/d/c.jpg.part".into(),
+                "/d/d.txt".into(),
             ])
-        })
-        .collect();
-        let help_view = Paragraph::new(help_list)
-            .wrap(Wrap { trim: false })
-            .block(Block::default().title("Help").borders(Borders::ALL));
-        frame.render_widget(help_view, main_layout[1]);
+            .with_rule(RenameRule::parse("s/^/x_/").unwrap())
+            .with_ext_filter(ExtFilter::new(
+                &["jpg".into(), "png".into()],
+                &["part".into()],
+            ));
+
+        assert_eq!(dst_names(&app), vec!["x_a.jpg", "x_b.PNG"]);
+        assert!(app.files_title().contains("[skipped by extension: 2]"));
+
+        app.handle_key(Key::Alt('t'));
+        assert_eq!(dst_names(&app).len(), 4);
+        assert!(app.files_title().contains("[extension filter off]"));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use rstest::rstest;
+    #[test]
+    fn excluding_roots() {
+        let mut app = App::default()
+            .with_files(vec![
+                "/home/a.txt".into(),
+                "/mnt/usb/b.txt".into(),
+                "/mnt/usb/c.md".into(),
+            ])
+            .with_rule(RenameRule::parse(r"s/\.txt$/.bak/").unwrap());
+        app.mount_roots.borrow_mut().extend([
+            ("/home".into(), "/".into()),
+            ("/mnt/usb".into(), "/mnt/usb".into()),
+        ]);
 
-    mod editable_area {
-        use super::*;
+        let summary: Vec<String> = app
+            .root_summary(&app.move_pairs())
+            .iter()
+            .map(|r| r.to_string())
+            .collect();
+        assert_eq!(summary, vec!["/: 1/1", "/mnt/usb: 1/2"]);
 
-        #[rstest]
-        #[case(EditableArea::Regex, EditableArea::Replace)]
-        #[case(EditableArea::Replace, EditableArea::Regex)]
-        fn next(#[case] current_area: EditableArea, #[case] expected_next_area: EditableArea) {
-            let next_area = current_area.next();
-            assert_eq!(next_area, expected_next_area);
+        app.handle_key(Key::Alt('e'));
+        assert_eq!(
+            app.move_pairs(),
+            vec![("/mnt/usb/b.txt".into(), "/mnt/usb/b.bak".into())]
+        );
+
+        app.handle_key(Key::Alt('e'));
+        assert_eq!(
+            app.move_pairs(),
+            vec![("/home/a.txt".into(), "/home/a.bak".into())]
+        );
+        assert_eq!(
+            app.root_summary(&app.move_pairs())[1].to_string(),
+            "/mnt/usb: excluded"
+        );
+
+        app.handle_key(Key::Alt('e'));
+        assert_eq!(app.move_pairs().len(), 2);
+    }
+
+    #[test]
+    fn existing_destinations_are_prefetched() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.txt", "b.txt", "a.bak"] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
         }
+        let app = App::default()
+            .with_files(vec![dir.path().join("a.txt"), dir.path().join("b.txt")])
+            .with_rule(RenameRule::parse(r"s/\.txt$/.bak/").unwrap());
 
-        #[rstest]
-        #[case(EditableArea::Regex, EditableArea::Replace)]
-        #[case(EditableArea::Replace, EditableArea::Regex)]
-        fn prev(#[case] current_area: EditableArea, #[case] expected_next_area: EditableArea) {
-            let next_area = current_area.prev();
-            assert_eq!(next_area, expected_next_area);
+        let move_pairs = app.move_pairs();
+        app.prefetch_destinations(&move_pairs);
+        while app.prefetcher.pending() > 0 {
+            thread::sleep(Duration::from_millis(1));
         }
+        assert_eq!(app.known_overwrites(&move_pairs).len(), 1);
+        assert!(app
+            .files_list()
+            .iter()
+            .any(|spans| spans.0.iter().any(|span| span.content == " (overwrites)")));
     }
 
     #[rstest]
-    #[case("a", None, "b", false, ReplacementResult::InvalidRegex)]
-    #[case("abc", Regex::new("bc").ok(), "bc", false, ReplacementResult::Unchanged)]
-    #[case("abc", Regex::new("(ab)(.*)").ok(), "$2$1", false, ReplacementResult::Replaced("cab".into()))]
-    // case vs ignore case
-    #[case("aBc", Regex::new("abc").ok(), "", false, ReplacementResult::NoMatch)]
-    #[case("aBc", Regex::new("(?i:abc)").ok(), "", false, ReplacementResult::Replaced("".into()))]
-    // non-global vs global 
-    #[case("abac", Regex::new("a").ok(), "d", false, ReplacementResult::Replaced("dbac".into()))]
-    #[case("abac", Regex::new("a").ok(), "d", true, ReplacementResult::Replaced("dbdc".into()))]
-    fn try_replace_works(
+    #[case('o', 2)]
+    #[case('s', 1)]
+    fn existing_destinations_are_asked_about(#[case] key: char, #[case] renamed: usize) {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.txt", "b.txt", "a.bak"] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+        let mut app = App::default()
+            .with_files(vec![dir.path().join("a.txt"), dir.path().join("b.txt")])
+            .with_rule(RenameRule::parse(r"s/\.txt$/.bak/").unwrap())
+            .with_on_conflict(ConflictPolicy::Prompt);
+
+        assert!(press(&mut app, Key::Char('\n')).is_none());
+        assert_eq!(app.popup, Some(Popup::Conflicts { existing: 1 }));
+        match press(&mut app, Key::Char(key)) {
+            Some(AppResult::MoveFiles(move_pairs)) => assert_eq!(move_pairs.len(), renamed),
+            _ => panic!("the files aren't renamed"),
+        }
+    }
+
+    #[test]
+    fn capture_table_works() {
+        let app = App::default()
+            .with_files(vec!["/a/IMG_0012.jpg".into(), "/a/notes.txt".into()])
+            .with_regex(r"([A-Z]+)_(?P<num>\d+)(\.png)?".into());
+        let table = app.capture_table().unwrap();
+
+        assert_eq!(table.groups, vec!["1", "num", "3"]);
+        assert_eq!(
+            table.rows[0].captures,
+            Some(vec![Some("IMG".into()), Some("0012".into()), None])
+        );
+        assert_eq!(table.rows[1].captures, None);
+    }
+
+    #[test]
+    fn match_full_path() {
+        let app = App::default()
+            .with_files(vec![
+                "/photos/2020/a.jpg".into(),
+                "/photos/2021/b.jpg".into(),
+                "/photos/2020/2020_c.jpg".into(),
+            ])
+            .with_rule(RenameRule::parse(r"s|/(\d{4})/([^/]+)$|/$1/${1}_$2|").unwrap())
+            .with_match_path(true);
+
+        assert_eq!(
+            app.move_pairs(),
+            vec![
+                (
+                    "/photos/2020/a.jpg".into(),
+                    "/photos/2020/2020_a.jpg".into()
+                ),
+                (
+                    "/photos/2021/b.jpg".into(),
+                    "/photos/2021/2021_b.jpg".into()
+                ),
+                (
+                    "/photos/2020/2020_c.jpg".into(),
+                    "/photos/2020/2020_2020_c.jpg".into()
+                ),
+            ]
+        );
+
+        let app = app.with_rule(RenameRule::parse("s|2021/b.jpg|x/|").unwrap());
+        assert_eq!(dst_names(&app), vec!["a.jpg", "x", "2020_c.jpg"]);
+    }
+
+    #[test]
+    fn reverse_numbering() {
+        let app = App::default()
+            .with_files(vec![
+                "/d/a.jpg".into(),
+                "/d/b.txt".into(),
+                "/d/c.jpg".into(),
+            ])
+            .with_rule(RenameRule::parse("s/.*\\.jpg/{n}.jpg/").unwrap())
+            .with_numbering_order(NumberingOrder::Reverse);
+
+        assert_eq!(dst_names(&app), vec!["2.jpg", "b.txt", "1.jpg"]);
+    }
+
+    #[test]
+    fn same_and_nested_inputs() {
+        let app = App::default()
+            .with_files(vec![
+                "/d/dir".into(),
+                "/d/./dir/a".into(),
+                "/d/dir/a".into(),
+                "/d/dir/../dir".into(),
+            ])
+            .with_rule(RenameRule::parse("s/^(dir|a)$/${1}_new/").unwrap());
+
+        assert_eq!(app.duplicate_files, 2);
+        assert_eq!(
+            app.move_pairs(),
+            vec![
+                ("/d/dir/a".into(), "/d/dir/a_new".into()),
+                ("/d/dir".into(), "/d/dir_new".into()),
+            ]
+        );
+        assert!(app.files_title().contains("[duplicates ignored: 2]"));
+        assert!(app.files_title().contains("[inside renamed dirs: 1]"));
+
+        let mut app = app;
+        app.rename_sources(&app.move_pairs());
+        assert_eq!(
+            app.source_files,
+            vec![
+                PathBuf::from("/d/dir_new"),
+                PathBuf::from("/d/dir_new/a_new")
+            ]
+        );
+    }
+
+    #[test]
+    fn counter_per_dir() {
+        let app = App::default()
+            .with_files(vec![
+                "/albumA/x.mp3".into(),
+                "/albumB/y.mp3".into(),
+                "/albumA/z.mp3".into(),
+                "/albumB/cover.jpg".into(),
+                "/albumB/w.mp3".into(),
+            ])
+            .with_rule(RenameRule::parse("s/.*\\.mp3/{n:2}.mp3/").unwrap())
+            .with_counter_per_dir(true);
+
+        assert_eq!(
+            dst_names(&app),
+            vec!["01.mp3", "01.mp3", "02.mp3", "cover.jpg", "02.mp3"]
+        );
+    }
+
+    #[test]
+    fn plans_are_restored_from_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        std::fs::write(&file, "").unwrap();
+        // creating the cache directory next to the file would modify their directory
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache = PlanCache::new(cache_dir.path().to_owned());
+        let new_app = |rule: &str| {
+            App::default()
+                .with_files(vec![file.clone()])
+                .with_rule(RenameRule::parse(rule).unwrap())
+                .with_plan_cache(cache.clone())
+        };
+
+        let app = new_app("s/a/b/");
+        app.save_plan().unwrap();
+        let app = new_app("s/a/b/");
+        assert!(app.restore_plan(app.plan_key()).is_some());
+        assert_eq!(dst_names(&app), vec!["b.txt"]);
+
+        let app = new_app("s/a/c/");
+        assert!(app.restore_plan(app.plan_key()).is_none());
+
+        // different in every session
+        let app = new_app("s/a/{uuid}/");
+        app.save_plan().unwrap();
+        assert!(app.restore_plan(app.plan_key()).is_none());
+    }
+
+    #[test]
+    fn continue_numbering_after_existing_files() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["img_001.jpg", "img_041.jpg", "img_100.png", "new.jpg"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+
+        let app = App::default()
+            .with_files(vec![
+                dir.path().join("new.jpg"),
+                dir.path().join("other.jpg"),
+            ])
+            .with_rule(RenameRule::parse("s/.*/img_{n:3}.jpg/").unwrap())
+            .with_continue_numbering(true);
+
+        assert_eq!(dst_names(&app), vec!["img_042.jpg", "img_043.jpg"]);
+    }
+
+    #[rstest]
+    #[case("s/a/b/", "a", "b", MatchFlags::NO_FLAGS)]
+    #[case("s/a/b", "a", "b", MatchFlags::NO_FLAGS)]
+    #[case("s/(\\d+)/x$1/gi", "(\\d+)", "x$1", MatchFlags::GLOBAL | MatchFlags::ICASE)]
+    #[case("s/a\\/b/c\\/d/g", "a/b", "c/d", MatchFlags::GLOBAL)]
+    #[case("s#a/b#c#i", "a/b", "c", MatchFlags::ICASE)]
+    #[case("s///", "", "", MatchFlags::NO_FLAGS)]
+    #[case("s/a/b/xUsm", "a", "b", MatchFlags::MULTILINE | MatchFlags::DOTALL | MatchFlags::UNGREEDY | MatchFlags::EXTENDED)]
+    fn rule_parse_works(
+        #[case] expr: &str,
+        #[case] regex: &str,
+        #[case] replacement: &str,
+        #[case] flags: MatchFlags,
+    ) {
+        let rule = RenameRule::parse(expr).unwrap();
+        assert_eq!(
+            rule,
+            RenameRule::new(regex.into(), replacement.into(), flags)
+        );
+        assert_eq!(RenameRule::parse(&rule.to_string()), Ok(rule));
+    }
+
+    #[rstest]
+    #[case("s/a/b/2", Some(Occurrence::Nth(2)), MatchFlags::NO_FLAGS)]
+    #[case("s/a/b/i12", Some(Occurrence::Nth(12)), MatchFlags::ICASE)]
+    #[case("s/a/b/$g", Some(Occurrence::Last), MatchFlags::GLOBAL)]
+    fn rule_parse_occurrence(
+        #[case] expr: &str,
+        #[case] occurrence: Option<Occurrence>,
+        #[case] flags: MatchFlags,
+    ) {
+        let rule = RenameRule::parse(expr).unwrap();
+        assert_eq!(
+            rule,
+            RenameRule::new("a".into(), "b".into(), flags).with_occurrence(occurrence)
+        );
+        assert_eq!(RenameRule::parse(&rule.to_string()), Ok(rule));
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("s")]
+    #[case("y/a/b/")]
+    #[case("s/a")]
+    #[case("s/a/b/c/d")]
+    #[case("s/a/b/q")]
+    #[case("s/a/b/0")]
+    #[case("s/a/b/2$")]
+    fn rule_parse_fails(#[case] expr: &str) {
+        assert!(RenameRule::parse(expr).is_err());
+    }
+
+    #[test]
+    fn script_parse_works() {
+        let script = "# normalize photos\ns/\\.jpeg$/.jpg/i\n\n  s| |_|g  \n";
+        assert_eq!(
+            RenameRule::parse_script(script),
+            Ok(vec![
+                RenameRule::parse("s/\\.jpeg$/.jpg/i").unwrap(),
+                RenameRule::parse("s| |_|g").unwrap(),
+            ])
+        );
+        assert_eq!(
+            RenameRule::parse_script("s/a/b/\ny/a/b/"),
+            Err("line 2: expression must start with 's': 'y/a/b/'".to_owned())
+        );
+    }
+
+    #[rstest]
+    #[case("photo.jpeg", "*.jpeg", "*.jpg", ReplacementResult::Replaced("photo.jpg".into()))]
+    #[case("photo.jpeg", "*.jpeg", "$1.jpg", ReplacementResult::Replaced("photo.jpg".into()))]
+    #[case("img_01.png", "img_??.*", "?-?.*", ReplacementResult::Replaced("0-1.png".into()))]
+    #[case("a.txt", "[ab].{txt,md}", "*.*.bak", ReplacementResult::Replaced("a.txt.bak".into()))]
+    #[case("c.txt", "[!ab].txt", "*", ReplacementResult::Replaced("c".into()))]
+    #[case("a(1).txt", "a(*).txt", "*", ReplacementResult::Replaced("1".into()))]
+    #[case("photo.jpeg.bak", "*.jpeg", "*.jpg", ReplacementResult::NoMatch)]
+    #[case("x.txt", "\\*.txt", "*", ReplacementResult::NoMatch)]
+    fn glob_mode_works(
         #[case] text: &str,
-        #[case] regex: Option<Regex>,
+        #[case] glob: &str,
         #[case] replacement: &str,
-        #[case] global: bool,
         #[case] expected_result: ReplacementResult,
     ) {
-        let replacement_result = try_replace(text, &regex, replacement, global);
-        assert_eq!(replacement_result, expected_result);
+        let regex = compose_regex(glob, MatchFlags::NO_FLAGS, MatchMode::Glob, Engine::Std);
+        let replacement = compose_replacement(replacement, MatchMode::Glob);
+        assert_eq!(
+            try_replace(text, regex.as_deref(), &replacement, Targets::First),
+            expected_result
+        );
+    }
+
+    #[rstest]
+    #[case("report (1).txt", "(1)", "[2]", ReplacementResult::Replaced("report [2].txt".into()))]
+    #[case("a.b.c", ".", "_", ReplacementResult::Replaced("a_b.c".into()))]
+    #[case("price.txt", "price", "$1", ReplacementResult::Replaced("$1.txt".into()))]
+    #[case("abc", ".*", "", ReplacementResult::NoMatch)]
+    fn literal_mode_works(
+        #[case] text: &str,
+        #[case] pattern: &str,
+        #[case] replacement: &str,
+        #[case] expected_result: ReplacementResult,
+    ) {
+        let regex = compose_regex(
+            pattern,
+            MatchFlags::NO_FLAGS,
+            MatchMode::Literal,
+            Engine::Std,
+        );
+        let replacement = compose_replacement(replacement, MatchMode::Literal);
+        assert_eq!(
+            try_replace(text, regex.as_deref(), &replacement, Targets::First),
+            expected_result
+        );
     }
 }
@@ -0,0 +1,114 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+/// capture groups extracted by the edited regex from every file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureTable {
+    /// group names, numbers for the unnamed groups
+    pub groups: Vec<String>,
+    pub rows: Vec<CaptureRow>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureRow {
+    pub path: PathBuf,
+    /// values of the groups, `None` if the file isn't matched
+    pub captures: Option<Vec<Option<String>>>,
+}
+
+impl CaptureTable {
+    /// one line per file: its path, whether it's matched and the groups
+    pub fn to_csv(&self) -> String {
+        let header = ["path", "matched"]
+            .into_iter()
+            .map(Cow::from)
+            .chain(self.groups.iter().map(|group| csv_field(group)));
+        let mut csv = join_line(header);
+
+        for row in &self.rows {
+            let path = row.path.to_string_lossy();
+            let matched = if row.captures.is_some() { "1" } else { "0" };
+            let captures = match &row.captures {
+                Some(captures) => captures
+                    .iter()
+                    .map(|group| group.as_deref().map_or(Cow::Borrowed(""), csv_field))
+                    .collect(),
+                None => vec![Cow::Borrowed(""); self.groups.len()],
+            };
+
+            csv.push_str(&join_line(
+                [csv_field(&path), Cow::from(matched)]
+                    .into_iter()
+                    .chain(captures),
+            ));
+        }
+
+        csv
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        std::fs::write(path, self.to_csv())
+            .with_context(|| format!("can't write captures to {}", path.display()))
+    }
+}
+
+fn join_line<'a>(fields: impl Iterator<Item = Cow<'a, str>>) -> String {
+    let mut line = fields.collect::<Vec<_>>().join(",");
+    line.push('\n');
+    line
+}
+
+/// quote the field if it contains a separator, a quote or a line break
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("abc", "abc")]
+    #[case("a,b", "\"a,b\"")]
+    #[case("say \"hi\"", "\"say \"\"hi\"\"\"")]
+    #[case("a\nb", "\"a\nb\"")]
+    fn csv_field_works(#[case] field: &str, #[case] expected: &str) {
+        assert_eq!(csv_field(field), expected);
+    }
+
+    #[test]
+    fn to_csv_works() {
+        let table = CaptureTable {
+            groups: vec!["1".into(), "year".into()],
+            rows: vec![
+                CaptureRow {
+                    path: "/a/IMG_1, 2020.jpg".into(),
+                    captures: Some(vec![Some("IMG".into()), Some("2020".into())]),
+                },
+                CaptureRow {
+                    path: "/a/b.jpg".into(),
+                    captures: Some(vec![Some("b".into()), None]),
+                },
+                CaptureRow {
+                    path: "/a/notes.txt".into(),
+                    captures: None,
+                },
+            ],
+        };
+
+        assert_eq!(
+            table.to_csv(),
+            "path,matched,1,year\n\
+             \"/a/IMG_1, 2020.jpg\",1,IMG,2020\n\
+             /a/b.jpg,1,b,\n\
+             /a/notes.txt,0,,\n"
+        );
+    }
+}
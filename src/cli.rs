@@ -1,22 +1,643 @@
 use std::path::PathBuf;
+use std::time::SystemTime;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
+
+use crate::app::{
+    Engine, ListOrder, MatchFlags, MatchMode, Occurrence, RenameRule, DEFAULT_METADATA_JOBS,
+};
+use crate::filter::{self, MetadataFilter};
+use crate::planner::{ConfirmThresholds, ConflictPolicy, FileOrder, NumberingOrder, SymlinkPolicy};
+use crate::scan::FileKind;
+use crate::session::PathRemap;
+use crate::transform::{CaseStyle, Normalization, Sanitizer};
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 #[clap(propagate_version = true)]
+#[clap(args_conflicts_with_subcommands = true)]
 pub struct Args {
-    #[clap(help = "files to rename. If none provided, the files list will be read from stdin")]
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
+    #[clap(
+        value_parser,
+        help = "files to rename. If none provided, the files list will be read from stdin"
+    )]
     pub files: Vec<PathBuf>,
 
+    #[clap(
+        long,
+        action,
+        help = "expand the glob patterns among the files like '*.txt' or 'photos/**/*.jpg', always on Windows"
+    )]
+    pub glob: bool,
+
+    #[clap(
+        short = '0',
+        long,
+        action,
+        conflicts_with = "stdio-protocol",
+        help = "the files list on stdin is separated by NUL bytes, like the output of 'find -print0'"
+    )]
+    pub null: bool,
+
+    #[clap(
+        long,
+        action,
+        help = "rename the files inside the directories among the files, or inside the current one without files"
+    )]
+    pub recursive: bool,
+
+    #[clap(
+        long,
+        requires = "recursive",
+        help = "walk at most this many levels of directories, 1 for the files directly inside them"
+    )]
+    pub max_depth: Option<usize>,
+
+    #[clap(
+        long,
+        action,
+        requires = "recursive",
+        help = "don't skip the paths matched by .gitignore and .ignore files when walking directories"
+    )]
+    pub no_ignore: bool,
+
+    #[clap(
+        long,
+        action,
+        conflicts_with_all = &["files", "recursive", "stdio-protocol", "watch"],
+        help = "rename the files tracked by git under the current directory, like 'git ls-files' lists them"
+    )]
+    pub git_files: bool,
+
     #[clap(long, help = "Initial regex")]
     pub regex: Option<String>,
 
     #[clap(long, help = "Initial replacement string")]
     pub replace: Option<String>,
 
+    #[clap(
+        long,
+        value_parser = RenameRule::parse,
+        conflicts_with_all = &["regex", "replace"],
+        help = "Initial sed-style expression: s/regex/replacement/flags"
+    )]
+    pub expr: Option<RenameRule>,
+
+    #[clap(
+        long = "rule",
+        value_parser = RenameRule::parse,
+        help = "sed-style expression applied before the edited rule. Can be repeated to build a chain"
+    )]
+    pub rules: Vec<RenameRule>,
+
+    #[clap(
+        long,
+        conflicts_with_all = &["regex", "replace", "expr"],
+        help = "Initial rule from a preset saved in the config file"
+    )]
+    pub preset: Option<String>,
+
+    #[clap(
+        long,
+        conflicts_with = "expr",
+        help = "Initial regex flags: g (global), i (ignore case), m (multiline), s (dotall), U (ungreedy), x (extended)"
+    )]
+    pub flags: Option<MatchFlags>,
+
+    #[clap(
+        long,
+        conflicts_with = "expr",
+        help = "replace only this match of the initial regex: a number starting at 1 or 'last'. Overrides the g flag"
+    )]
+    pub occurrence: Option<Occurrence>,
+
+    #[clap(long, value_enum, help = "Initial pattern syntax [default: regex]")]
+    pub mode: Option<MatchMode>,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value = "std",
+        help = "regex engine. 'fancy' supports look-around and backreferences (Alt-r in the app)"
+    )]
+    pub engine: Engine,
+
+    #[clap(
+        long,
+        action,
+        help = "apply the rules to full paths, only the final component of the result is used as the new name (Alt-p in the app)"
+    )]
+    pub match_path: bool,
+
+    #[clap(
+        long,
+        default_value_t = 1,
+        help = "value of the counter token for the first renamed file"
+    )]
+    pub counter_start: u64,
+
+    #[clap(
+        long,
+        action,
+        help = "start the counter after the largest number already used by files in the destination directories"
+    )]
+    pub continue_numbering: bool,
+
+    #[clap(
+        long,
+        value_enum,
+        help = "convert the new names to a case style keeping the extension (Alt-k in the app)"
+    )]
+    pub case_style: Option<CaseStyle>,
+
+    #[clap(
+        long,
+        help = "shorten the new names to this number of bytes keeping the extension, e.g. 143 for eCryptfs"
+    )]
+    pub max_name_bytes: Option<usize>,
+
+    #[clap(
+        long,
+        default_value_t = DEFAULT_METADATA_JOBS,
+        help = "files whose metadata (e.g. for {duration} or {git_date}) is read at once"
+    )]
+    pub metadata_jobs: usize,
+
+    #[clap(
+        long,
+        default_value_t = 200,
+        help = "milliseconds the preview waits for the metadata before showing the files as pending"
+    )]
+    pub metadata_budget: u64,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value = "abort",
+        help = "what to do with new names taken by existing files: 'abort' replaces only the ones confirmed in the app, 'suffix' also numbers colliding names: ' (1)', ' (2)', etc."
+    )]
+    pub on_conflict: ConflictPolicy,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value = "rename",
+        help = "what to do with symlinks among the files: rename them, rename their targets or skip them"
+    )]
+    pub symlinks: SymlinkPolicy,
+
+    #[clap(
+        long = "sort",
+        alias = "file-order",
+        value_enum,
+        default_value = "natural",
+        help = "order of the files in the list, of the counter values and of renaming (Alt-o in the app): natural puts file2 before file10, mtime and size start with the oldest and the smallest"
+    )]
+    pub file_order: FileOrder,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value = "input",
+        help = "order of the files list (Alt-c in the app). The renaming order isn't affected"
+    )]
+    pub list_order: ListOrder,
+
+    #[clap(
+        long,
+        action,
+        help = "treat inner extensions like tar of archive.tar.gz as a part of the {ext} token"
+    )]
+    pub compound_ext: bool,
+
+    #[clap(
+        long,
+        help = "rename only files whose paths end with this glob, e.g. '*.jpg' or 'photos/**'. Can be repeated"
+    )]
+    pub include: Vec<String>,
+
+    #[clap(
+        long,
+        help = "never rename files whose paths end with this glob, e.g. 'node_modules/**'. Can be repeated"
+    )]
+    pub exclude: Vec<String>,
+
+    #[clap(
+        long = "type",
+        value_enum,
+        help = "rename only paths of this kind, with --recursive directories are found only this way. Can be repeated"
+    )]
+    pub types: Vec<FileKind>,
+
+    #[clap(
+        long,
+        value_parser = filter::parse_time,
+        help = "rename only files modified after this moment: an age like 30m, 12h, 7d, 2w or a date like 2024-05-01"
+    )]
+    pub newer_than: Option<SystemTime>,
+
+    #[clap(
+        long,
+        value_parser = filter::parse_time,
+        help = "rename only files modified before this moment: an age like 30m, 12h, 7d, 2w or a date like 2024-05-01"
+    )]
+    pub older_than: Option<SystemTime>,
+
+    #[clap(
+        long,
+        value_parser = filter::parse_size,
+        help = "rename only files of at least this size, e.g. 500, 10K, 1.5M or 2G"
+    )]
+    pub min_size: Option<u64>,
+
+    #[clap(
+        long,
+        value_parser = filter::parse_size,
+        help = "rename only files of at most this size, e.g. 500, 10K, 1.5M or 2G"
+    )]
+    pub max_size: Option<u64>,
+
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help = "rename only files with these extensions, e.g. jpg,png (Alt-t in the app turns it off)"
+    )]
+    pub ext: Vec<String>,
+
+    #[clap(
+        long,
+        value_delimiter = ',',
+        help = "never rename files with these extensions, e.g. tmp,part"
+    )]
+    pub not_ext: Vec<String>,
+
+    #[clap(
+        long,
+        action,
+        help = "restart the counter in every directory, so files of each directory are numbered from --counter-start"
+    )]
+    pub counter_per_dir: bool,
+
+    #[clap(
+        long,
+        value_enum,
+        default_value = "forward",
+        help = "order of assigning counter values"
+    )]
+    pub numbering: NumberingOrder,
+
+    #[clap(
+        long,
+        action,
+        help = "re-sequence numbers in the names contiguously (1, 2, 5, 9 -> 1, 2, 3, 4) starting at --counter-start"
+    )]
+    pub renumber: bool,
+
+    #[clap(
+        long,
+        help = "digits of the re-sequenced numbers padded with zeros. Original width is kept by default"
+    )]
+    pub renumber_width: Option<usize>,
+
+    #[clap(
+        long,
+        help = "file with sed-style expressions, one per line, applied before the --rule ones. '-' reads stdin"
+    )]
+    pub sed_file: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "rhai script with a 'transform(name)' function applied to the names after the rules"
+    )]
+    pub script: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "shell command reading a name from stdin and printing the new name, applied after the rules"
+    )]
+    pub filter_cmd: Option<String>,
+
+    #[clap(
+        long,
+        default_value_t = 1000,
+        help = "timeout of the filter command for a single name in milliseconds"
+    )]
+    pub filter_timeout: u64,
+
+    #[clap(
+        long,
+        default_value_t = DEFAULT_METADATA_JOBS,
+        help = "names filtered by the filter command at once, in the background while the rules are edited"
+    )]
+    pub filter_jobs: usize,
+
+    #[clap(
+        long,
+        action,
+        help = "convert non-ASCII characters of the resulting names to ASCII (é -> e, ß -> ss)"
+    )]
+    pub transliterate: bool,
+
+    #[clap(
+        long,
+        value_enum,
+        help = "normalize unicode of the resulting names, applied after all the other transforms"
+    )]
+    pub normalize: Option<Normalization>,
+
+    #[clap(
+        long,
+        action,
+        help = "replace characters unsafe for web or Windows filenames and collapse whitespace (Alt-s in the app)"
+    )]
+    pub sanitize: bool,
+
+    #[clap(
+        long,
+        default_value = "_",
+        help = "character put instead of unsafe characters by the sanitizer"
+    )]
+    pub sanitize_char: char,
+
+    #[clap(long, action, help = "lowercase names when sanitizing")]
+    pub sanitize_lowercase: bool,
+
+    #[clap(
+        long,
+        action,
+        help = "don't restore the preview saved for the same files and rules in $XDG_CACHE_HOME/irename/plans"
+    )]
+    pub no_plan_cache: bool,
+
+    #[clap(
+        long,
+        action,
+        help = "draw a compact ui below the cursor instead of taking over the whole screen"
+    )]
+    pub inline: bool,
+
+    #[clap(
+        long,
+        default_value_t = 10,
+        requires = "inline",
+        help = "number of lines used by the --inline ui"
+    )]
+    pub height: u16,
+
+    #[clap(
+        long,
+        action,
+        help = "learn the basics on sample files in a temporary directory"
+    )]
+    pub tutorial: bool,
+
+    #[clap(
+        long,
+        action,
+        conflicts_with_all = &["inline", "tutorial"],
+        help = "no ui: read JSON requests (set-regex, set-replace, preview, apply) from stdin and write JSON results to stdout"
+    )]
+    pub stdio_protocol: bool,
+
+    #[clap(
+        long,
+        help = "on exit write a CSV table with the capture groups of the regex for every file, whether or not the files are renamed"
+    )]
+    pub export_captures: Option<PathBuf>,
+
+    #[clap(
+        long,
+        action,
+        help = "refuse to rename files to names which can't be used on Windows, e.g. 'aux.txt' or 'a:b'"
+    )]
+    pub strict_portability: bool,
+
+    #[clap(
+        long,
+        action,
+        help = "leave out input files which don't exist or can't be accessed instead of marking them"
+    )]
+    pub drop_missing: bool,
+
+    #[clap(
+        long,
+        action,
+        help = "create the missing directories of new names with '/', e.g. '$1/$2.txt'"
+    )]
+    pub mkdir: bool,
+
+    #[clap(
+        long,
+        action,
+        conflicts_with = "stdio-protocol",
+        help = "ask about every rename before executing them: [y]es, [n]o, [a]ll the rest, [q]uit"
+    )]
+    pub confirm_each: bool,
+
+    #[clap(
+        long,
+        conflicts_with = "stdio-protocol",
+        help = "write the renames with their details and the rules to this JSON file instead of executing them"
+    )]
+    pub plan_out: Option<PathBuf>,
+
+    #[clap(
+        long,
+        conflicts_with_all = &["files", "stdio-protocol", "plan-out", "tutorial"],
+        help = "check the renames of a plan saved with --plan-out against the files and execute them without the ui"
+    )]
+    pub apply_plan: Option<PathBuf>,
+
+    #[clap(
+        long,
+        conflicts_with_all = &["files", "recursive", "stdio-protocol", "plan-out", "apply-plan", "tutorial", "confirm-each", "inline"],
+        help = "no ui, the files arrive when nobody reviews them: rename the files created in this directory with the rules until interrupted, printing every rename"
+    )]
+    pub watch: Option<PathBuf>,
+
+    #[clap(
+        long,
+        help = "append every executed, skipped, failed or rolled back rename to this file as a JSON line"
+    )]
+    pub log_file: Option<PathBuf>,
+
     #[clap(long, action, help = "only print shell commands w/o executing them")]
     pub dry_run: bool,
+
+    #[clap(
+        long,
+        value_name = "SUFFIX",
+        min_values = 0,
+        require_equals = true,
+        default_missing_value = "~",
+        help = "keep replaced files under their names with this suffix, or numbered like name.~1~ if taken"
+    )]
+    pub backup: Option<String>,
+
+    #[clap(
+        long,
+        action,
+        conflicts_with = "backup",
+        help = "move replaced files to the trash (the freedesktop.org trash, the Recycle Bin or the macOS Trash) instead of deleting them"
+    )]
+    pub use_trash: bool,
+
+    #[clap(
+        long,
+        action,
+        help = "rename with 'git mv', so git records the renames. The files must be tracked"
+    )]
+    pub git: bool,
+
+    #[clap(
+        long,
+        action,
+        help = "write a shell script reverting the renaming next to its journal"
+    )]
+    pub undo_script: bool,
+
+    #[clap(
+        long,
+        action,
+        help = "never execute renaming, Enter only shows the plan (useful for demonstrating or auditing rules)"
+    )]
+    pub preview_only: bool,
+
+    #[clap(
+        long,
+        default_value_t = 500,
+        help = "require typed confirmation when more files are renamed"
+    )]
+    pub confirm_files: usize,
+
+    #[clap(
+        long,
+        default_value_t = 0,
+        help = "require typed confirmation when more existing files are overwritten"
+    )]
+    pub confirm_overwrites: usize,
+
+    #[clap(
+        long,
+        default_value_t = 0,
+        help = "require typed confirmation when more files are moved to another directory"
+    )]
+    pub confirm_moves: usize,
+
+    #[clap(
+        long,
+        action,
+        help = "execute plans above the --confirm-* limits without typing the number of files, e.g. in scripts, --watch or --stdio-protocol"
+    )]
+    pub yes: bool,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Show the rename history of a file or directory
+    Log {
+        #[clap(help = "current (or any past) path of the file")]
+        path: PathBuf,
+
+        #[clap(long, help = "rename the file back to the name with this number")]
+        restore: Option<usize>,
+    },
+    /// List the recorded sessions, undos and redos
+    History,
+    /// Revert the renames of the last sessions which aren't undone yet
+    Undo {
+        #[clap(
+            default_value_t = 1,
+            help = "number of sessions to undo, the latest first"
+        )]
+        count: usize,
+    },
+    /// Rename the files of the last undone sessions again
+    Redo {
+        #[clap(
+            default_value_t = 1,
+            help = "number of undos to revert, the latest first"
+        )]
+        count: usize,
+    },
+    /// Check the terminal, the config and the rename history for problems
+    Doctor {
+        #[clap(long, help = "also validate this rhai script")]
+        script: Option<PathBuf>,
+    },
+    /// Prepare the renaming on one machine and apply it on another
+    Session {
+        #[clap(subcommand)]
+        action: SessionAction,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SessionAction {
+    /// Edit the rules for the files, Enter saves them with the files instead of renaming
+    Export {
+        #[clap(help = "session file to write")]
+        file: PathBuf,
+
+        #[clap(help = "files to rename. If none provided, the files list will be read from stdin")]
+        files: Vec<PathBuf>,
+    },
+    /// Open the files of a session file with its rules and rename them
+    Import {
+        #[clap(help = "session file written by 'session export'")]
+        file: PathBuf,
+
+        #[clap(
+            long = "remap",
+            help = "replace the path prefix FROM of the files with TO: FROM=TO. Can be repeated"
+        )]
+        remaps: Vec<PathRemap>,
+
+        #[clap(long, action, help = "only print shell commands w/o executing them")]
+        dry_run: bool,
+    },
+}
+
+impl Args {
+    /// rule from `--expr` or from separate `--regex` and `--replace`
+    pub fn initial_rule(&self) -> RenameRule {
+        let rule = self.expr.clone().unwrap_or_else(|| {
+            RenameRule::new(
+                self.regex.clone().unwrap_or_default(),
+                self.replace.clone().unwrap_or_default(),
+                self.flags.unwrap_or_default(),
+            )
+            .with_occurrence(self.occurrence)
+        });
+        rule.with_mode(self.mode.unwrap_or_default())
+    }
+
+    pub fn sanitizer(&self) -> Sanitizer {
+        Sanitizer {
+            replacement: self.sanitize_char,
+            lowercase: self.sanitize_lowercase,
+        }
+    }
+
+    pub fn confirm_thresholds(&self) -> ConfirmThresholds {
+        ConfirmThresholds {
+            files: self.confirm_files,
+            overwrites: self.confirm_overwrites,
+            moves: self.confirm_moves,
+        }
+    }
+
+    pub fn metadata_filter(&self) -> MetadataFilter {
+        MetadataFilter {
+            newer_than: self.newer_than,
+            older_than: self.older_than,
+            min_size: self.min_size,
+            max_size: self.max_size,
+        }
+    }
 }
 
 pub fn parse_args() -> Args {
@@ -0,0 +1,201 @@
+use crate::app::RenameRule;
+use crate::path_utils;
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// user settings stored in `$XDG_CONFIG_HOME/irename/config.toml`
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    /// named rules which can be recalled with `--preset` or from the app
+    #[serde(default)]
+    pub presets: BTreeMap<String, RenameRule>,
+    /// settings used only on a specific OS: `[os.linux]`, `[os.macos]`, `[os.windows]`.
+    /// They take precedence over the common ones
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub os: BTreeMap<String, Profile>,
+}
+
+/// OS-specific part of the config
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    #[serde(default)]
+    pub presets: BTreeMap<String, RenameRule>,
+}
+
+/// default location of the config file
+pub fn config_path() -> PathBuf {
+    path_utils::xdg_dir("XDG_CONFIG_HOME", ".config")
+        .join("irename")
+        .join("config.toml")
+}
+
+impl Config {
+    /// load the config from `path`. A missing file means the default config
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("can't read config {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("invalid config {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("can't create config directory {}", dir.display()))?;
+        }
+
+        std::fs::write(path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("can't write config {}", path.display()))
+    }
+
+    /// profile of the OS irename is running on
+    fn profile(&self) -> Option<&Profile> {
+        self.os.get(std::env::consts::OS)
+    }
+
+    /// presets available on the current OS
+    pub fn presets(&self) -> BTreeMap<&str, &RenameRule> {
+        let mut presets: BTreeMap<&str, &RenameRule> = self
+            .presets
+            .iter()
+            .map(|(name, rule)| (name.as_str(), rule))
+            .collect();
+        if let Some(profile) = self.profile() {
+            presets.extend(
+                profile
+                    .presets
+                    .iter()
+                    .map(|(name, rule)| (name.as_str(), rule)),
+            );
+        }
+        presets
+    }
+
+    pub fn preset(&self, name: &str) -> anyhow::Result<RenameRule> {
+        self.presets()
+            .get(name)
+            .map(|rule| (*rule).clone())
+            .ok_or_else(|| anyhow::anyhow!("no preset named '{}'", name))
+    }
+
+    /// add a common preset or replace the one visible on the current OS
+    pub fn insert_preset(&mut self, name: String, rule: RenameRule) {
+        match self.os.get_mut(std::env::consts::OS) {
+            Some(profile) if profile.presets.contains_key(&name) => {
+                profile.presets.insert(name, rule);
+            }
+            _ => {
+                self.presets.insert(name, rule);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_config_is_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = Config::load(&dir.path().join("config.toml")).unwrap();
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn presets_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("irename").join("config.toml");
+
+        let mut config = Config::default();
+        config.presets.insert(
+            "spaces-to-underscores".into(),
+            RenameRule::parse("s/ +/_/g").unwrap(),
+        );
+        config.save(&path).unwrap();
+
+        let loaded = Config::load(&path).unwrap();
+        assert_eq!(loaded, config);
+        assert_eq!(
+            loaded.preset("spaces-to-underscores").unwrap(),
+            RenameRule::parse("s/ +/_/g").unwrap()
+        );
+        assert!(loaded.preset("missing").is_err());
+    }
+
+    #[test]
+    fn handwritten_preset() {
+        let config: Config = toml::from_str(
+            r#"
+            [presets.jpeg]
+            regex = "*.jpeg"
+            replacement = "*.jpg"
+            mode = "glob"
+
+            [presets.lower]
+            regex = "[A-Z]"
+            replacement = "x"
+            flags = "g"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.presets().len(), 2);
+        assert_eq!(
+            config.preset("lower").unwrap(),
+            RenameRule::parse("s/[A-Z]/x/g").unwrap()
+        );
+    }
+
+    #[test]
+    fn os_profile_overrides_common_presets() {
+        let mut config: Config = toml::from_str(&format!(
+            r#"
+            [presets.a]
+            regex = "a"
+            [presets.b]
+            regex = "b"
+
+            [os.{os}.presets.b]
+            regex = "b-{os}"
+            [os.{os}.presets.c]
+            regex = "c-{os}"
+
+            [os.other.presets.a]
+            regex = "a-other"
+            "#,
+            os = std::env::consts::OS
+        ))
+        .unwrap();
+
+        let regexes = |config: &Config| -> Vec<String> {
+            config
+                .presets()
+                .values()
+                .map(|rule| rule.to_string())
+                .collect()
+        };
+        let os = std::env::consts::OS;
+        assert_eq!(
+            regexes(&config),
+            vec![
+                "s/a//".to_owned(),
+                format!("s/b-{}//", os),
+                format!("s/c-{}//", os)
+            ]
+        );
+
+        config.insert_preset("b".into(), RenameRule::parse("s/new-b//").unwrap());
+        config.insert_preset("d".into(), RenameRule::parse("s/d//").unwrap());
+        assert_eq!(config.preset("b").unwrap().to_string(), "s/new-b//");
+        assert_eq!(config.presets["b"].to_string(), "s/b//");
+        assert_eq!(config.presets["d"].to_string(), "s/d//");
+    }
+}
@@ -0,0 +1,139 @@
+/// way of writing counter values. Every style is a token of its own, e.g. `{n}` or `{roman}`
+pub trait CounterStyle: Sync {
+    /// name of the token
+    fn name(&self) -> &'static str;
+
+    /// `value` written in this style. `arg` is the token argument, e.g. `3` of `{n:3}`
+    fn format(&self, value: u64, arg: Option<&str>) -> String;
+}
+
+/// all the counter styles, new ones only need to be added here
+const STYLES: &[&dyn CounterStyle] = &[
+    &Decimal,
+    &Alphabetic { uppercase: false },
+    &Alphabetic { uppercase: true },
+    &Roman,
+];
+
+/// counter style of the token `name`
+pub fn style(name: &str) -> Option<&'static dyn CounterStyle> {
+    STYLES.iter().copied().find(|style| style.name() == name)
+}
+
+/// `{n}`: 1, 2, 3... `{n:3}` pads the number with zeros to 3 digits
+struct Decimal;
+
+impl CounterStyle for Decimal {
+    fn name(&self) -> &'static str {
+        "n"
+    }
+
+    fn format(&self, value: u64, arg: Option<&str>) -> String {
+        let width = arg.and_then(|arg| arg.parse().ok()).unwrap_or(0);
+        format!("{:0width$}", value, width = width)
+    }
+}
+
+/// `{a}`: a, b... z, aa, ab... and `{A}`: A, B... Z, AA, AB...
+struct Alphabetic {
+    uppercase: bool,
+}
+
+impl CounterStyle for Alphabetic {
+    fn name(&self) -> &'static str {
+        if self.uppercase {
+            "A"
+        } else {
+            "a"
+        }
+    }
+
+    fn format(&self, mut value: u64, _arg: Option<&str>) -> String {
+        // there is no letter for zero
+        if value == 0 {
+            return "0".to_owned();
+        }
+
+        let first = if self.uppercase { b'A' } else { b'a' };
+        let mut letters = Vec::new();
+        while value > 0 {
+            value -= 1;
+            letters.push(first + (value % 26) as u8);
+            value /= 26;
+        }
+        letters.iter().rev().map(|&letter| letter as char).collect()
+    }
+}
+
+/// `{roman}`: I, II, III, IV...
+struct Roman;
+
+impl CounterStyle for Roman {
+    fn name(&self) -> &'static str {
+        "roman"
+    }
+
+    fn format(&self, mut value: u64, _arg: Option<&str>) -> String {
+        const NUMERALS: [(u64, &str); 13] = [
+            (1000, "M"),
+            (900, "CM"),
+            (500, "D"),
+            (400, "CD"),
+            (100, "C"),
+            (90, "XC"),
+            (50, "L"),
+            (40, "XL"),
+            (10, "X"),
+            (9, "IX"),
+            (5, "V"),
+            (4, "IV"),
+            (1, "I"),
+        ];
+
+        // there is no numeral for zero
+        if value == 0 {
+            return "0".to_owned();
+        }
+
+        let mut roman = String::new();
+        for (numeral_value, numeral) in NUMERALS {
+            while value >= numeral_value {
+                roman.push_str(numeral);
+                value -= numeral_value;
+            }
+        }
+        roman
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("n", 7, None, "7")]
+    #[case("n", 7, Some("3"), "007")]
+    #[case("a", 1, None, "a")]
+    #[case("a", 26, None, "z")]
+    #[case("a", 27, None, "aa")]
+    #[case("a", 703, None, "aaa")]
+    #[case("A", 28, None, "AB")]
+    #[case("a", 0, None, "0")]
+    #[case("roman", 4, None, "IV")]
+    #[case("roman", 1994, None, "MCMXCIV")]
+    #[case("roman", 0, None, "0")]
+    fn format_works(
+        #[case] name: &str,
+        #[case] value: u64,
+        #[case] arg: Option<&str>,
+        #[case] expected: &str,
+    ) {
+        assert_eq!(style(name).unwrap().format(value, arg), expected);
+    }
+
+    #[test]
+    fn unknown_style() {
+        assert!(style("b").is_none());
+    }
+}
@@ -0,0 +1,367 @@
+use crate::config::{self, Config};
+use crate::journal::{self, Journal};
+use crate::transform::ScriptTransform;
+
+use std::fmt::Display;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// result of a single environment check
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    /// what was found and how to fix it if something is wrong
+    pub message: String,
+}
+
+impl Check {
+    fn ok(name: &'static str, message: impl Into<String>) -> Self {
+        Check {
+            name,
+            status: Status::Ok,
+            message: message.into(),
+        }
+    }
+
+    fn warning(name: &'static str, message: impl Into<String>) -> Self {
+        Check {
+            name,
+            status: Status::Warning,
+            message: message.into(),
+        }
+    }
+
+    fn error(name: &'static str, message: impl Into<String>) -> Self {
+        Check {
+            name,
+            status: Status::Error,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let status = match self.status {
+            Status::Ok => "[ ok ]",
+            Status::Warning => "[warn]",
+            Status::Error => "[fail]",
+        };
+        write!(f, "{} {}: {}", status, self.name, self.message)
+    }
+}
+
+/// run all the checks. `script` is a rhai script to validate
+pub fn run_checks(script: Option<&Path>) -> Vec<Check> {
+    let env = |var: &str| std::env::var(var).ok().filter(|value| !value.is_empty());
+
+    let mut checks = check_terminal();
+    checks.push(check_term(env("TERM").as_deref()));
+    checks.push(check_colors(
+        env("TERM").as_deref(),
+        env("COLORTERM").as_deref(),
+    ));
+    checks.push(check_locale(
+        env("LC_ALL")
+            .or_else(|| env("LC_CTYPE"))
+            .or_else(|| env("LANG"))
+            .as_deref(),
+    ));
+    checks.push(check_config(&config::config_path()));
+    checks.push(check_shell());
+    if let Some(script) = script {
+        checks.push(check_script(script));
+    }
+    checks.extend(check_history(&journal::history_dir()));
+
+    checks
+}
+
+fn check_terminal() -> Vec<Check> {
+    use termion::raw::IntoRawMode;
+
+    let stdout = std::io::stdout();
+    if !termion::is_tty(&stdout) {
+        return vec![Check::error(
+            "terminal",
+            "stdout is not a terminal. Run irename without redirecting its output (--dry-run prints the commands after the app exits)",
+        )];
+    }
+
+    let raw_mode = match stdout.into_raw_mode() {
+        Ok(_) => Check::ok("raw mode", "supported"),
+        Err(err) => Check::error("raw mode", format!("can't be enabled: {}", err)),
+    };
+    vec![Check::ok("terminal", "stdout is a terminal"), raw_mode]
+}
+
+/// alternate screen and cursor movements need a capable `$TERM`
+fn check_term(term: Option<&str>) -> Check {
+    match term {
+        None => Check::error("alternate screen", "$TERM is not set"),
+        Some("dumb") => Check::error(
+            "alternate screen",
+            "$TERM is 'dumb'. Use a terminal emulator supporting the alternate screen",
+        ),
+        Some(term) => Check::ok("alternate screen", format!("$TERM is '{}'", term)),
+    }
+}
+
+fn check_colors(term: Option<&str>, colorterm: Option<&str>) -> Check {
+    if colorterm.is_some() || term.is_some_and(|term| term.contains("color")) {
+        Check::ok("colors", "supported")
+    } else {
+        Check::warning(
+            "colors",
+            "the terminal may not support colors, highlighting of the names can be missing",
+        )
+    }
+}
+
+/// `locale` is the effective value of `LC_ALL`, `LC_CTYPE` or `LANG`
+fn check_locale(locale: Option<&str>) -> Check {
+    match locale {
+        Some(locale) if locale.to_lowercase().replace('-', "").contains("utf8") => {
+            Check::ok("unicode", format!("locale is '{}'", locale))
+        }
+        Some(locale) => Check::warning(
+            "unicode",
+            format!(
+                "locale '{}' is not UTF-8, non-ASCII names can be displayed incorrectly. Set LANG to a UTF-8 locale",
+                locale
+            ),
+        ),
+        None => Check::warning(
+            "unicode",
+            "locale is not set, non-ASCII names can be displayed incorrectly. Set LANG to a UTF-8 locale",
+        ),
+    }
+}
+
+fn check_config(path: &Path) -> Check {
+    if !path.exists() {
+        return Check::ok(
+            "config",
+            format!("{} doesn't exist, defaults are used", path.display()),
+        );
+    }
+
+    match Config::load(path) {
+        Ok(config) => Check::ok(
+            "config",
+            format!(
+                "{} is valid, {} presets",
+                path.display(),
+                config.presets().len()
+            ),
+        ),
+        Err(err) => Check::error("config", format!("{:#}. Fix or remove the file", err)),
+    }
+}
+
+/// `--filter-cmd` runs the commands with `sh`
+fn check_shell() -> Check {
+    let status = Command::new("sh")
+        .args(["-c", "true"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+
+    match status {
+        Ok(status) if status.success() => Check::ok("shell", "'sh' is available for --filter-cmd"),
+        Ok(status) => Check::error("shell", format!("'sh -c true' exited with {}", status)),
+        Err(err) => Check::error(
+            "shell",
+            format!("can't run 'sh': {}. --filter-cmd won't work", err),
+        ),
+    }
+}
+
+fn check_script(path: &Path) -> Check {
+    match ScriptTransform::load(path) {
+        Ok(_) => Check::ok("script", format!("{} is valid", path.display())),
+        Err(err) => Check::error("script", format!("{:#}", err)),
+    }
+}
+
+/// the journals can be written and read back, the undo scripts can be executed
+fn check_history(dir: &Path) -> Vec<Check> {
+    if !dir.exists() {
+        return vec![Check::ok(
+            "history",
+            format!("{} doesn't exist yet", dir.display()),
+        )];
+    }
+
+    let mut checks = Vec::new();
+
+    let probe = dir.join(".irename-doctor");
+    match std::fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&probe);
+            checks.push(Check::ok(
+                "history",
+                format!("{} is writable", dir.display()),
+            ));
+        }
+        Err(err) => checks.push(Check::error(
+            "history",
+            format!(
+                "{} is not writable: {}. Renames won't be recorded",
+                dir.display(),
+                err
+            ),
+        )),
+    }
+
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) => {
+            checks.push(Check::error(
+                "journals",
+                format!("can't read {}: {}", dir.display(), err),
+            ));
+            return checks;
+        }
+    };
+
+    let mut journals = 0;
+    let mut broken = Vec::new();
+    let mut not_executable = Vec::new();
+    for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => {
+                journals += 1;
+                if Journal::load(&path).is_err() {
+                    broken.push(path);
+                }
+            }
+            Some("sh") if !is_executable(&path) => not_executable.push(path),
+            _ => {}
+        }
+    }
+
+    checks.push(if broken.is_empty() {
+        Check::ok("journals", format!("{} journals are valid", journals))
+    } else {
+        Check::error(
+            "journals",
+            format!(
+                "invalid journals: {}. Move them out of the history directory, `irename log` fails on them",
+                list_paths(&broken)
+            ),
+        )
+    });
+    if !not_executable.is_empty() {
+        checks.push(Check::warning(
+            "undo scripts",
+            format!(
+                "not executable: {}. Run them with 'sh <script>' or 'chmod +x' them",
+                list_paths(&not_executable)
+            ),
+        ));
+    }
+
+    checks
+}
+
+fn list_paths(paths: &[std::path::PathBuf]) -> String {
+    paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::metadata(path).is_ok_and(|meta| meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(_path: &Path) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn statuses(checks: &[Check]) -> Vec<(&'static str, Status)> {
+        checks
+            .iter()
+            .map(|check| (check.name, check.status))
+            .collect()
+    }
+
+    #[rstest]
+    #[case(Some("en_US.UTF-8"), Status::Ok)]
+    #[case(Some("C.utf8"), Status::Ok)]
+    #[case(Some("C"), Status::Warning)]
+    #[case(None, Status::Warning)]
+    fn locale_check(#[case] locale: Option<&str>, #[case] expected: Status) {
+        assert_eq!(check_locale(locale).status, expected);
+    }
+
+    #[rstest]
+    #[case(Some("xterm-256color"), Status::Ok)]
+    #[case(Some("dumb"), Status::Error)]
+    #[case(None, Status::Error)]
+    fn term_check(#[case] term: Option<&str>, #[case] expected: Status) {
+        assert_eq!(check_term(term).status, expected);
+    }
+
+    #[test]
+    fn healthy_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = Journal::new(&[("/a".into(), "/b".into())]);
+        journal.save(dir.path()).unwrap();
+        journal.save_undo_script(dir.path()).unwrap();
+
+        assert_eq!(
+            statuses(&check_history(dir.path())),
+            vec![("history", Status::Ok), ("journals", Status::Ok)]
+        );
+        assert!(!dir.path().join(".irename-doctor").exists());
+    }
+
+    #[test]
+    fn broken_history() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("1.json"), "{").unwrap();
+        std::fs::write(dir.path().join("1.sh"), "").unwrap();
+
+        let checks = check_history(dir.path());
+        assert_eq!(
+            statuses(&checks),
+            vec![
+                ("history", Status::Ok),
+                ("journals", Status::Error),
+                ("undo scripts", Status::Warning)
+            ]
+        );
+        assert!(checks[1].message.contains("1.json"));
+    }
+
+    #[test]
+    fn invalid_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        assert_eq!(check_config(&path).status, Status::Ok);
+
+        std::fs::write(&path, "presets = 1").unwrap();
+        assert_eq!(check_config(&path).status, Status::Error);
+    }
+}
@@ -0,0 +1,389 @@
+use crate::path_utils;
+
+use std::fs::Metadata;
+use std::path::Path;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// selects the source files by their extensions
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtFilter {
+    /// only files with these extensions are renamed, all of them if empty
+    pub allow: Vec<String>,
+    /// files with these extensions are never renamed
+    pub deny: Vec<String>,
+}
+
+impl ExtFilter {
+    pub fn new(allow: &[String], deny: &[String]) -> Self {
+        ExtFilter {
+            allow: allow.iter().map(|ext| normalize_ext(ext)).collect(),
+            deny: deny.iter().map(|ext| normalize_ext(ext)).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty()
+    }
+
+    /// extensions are compared case-insensitively.
+    /// Both the simple and the compound extension count, so `a.tar.gz` has `gz` and `tar.gz`
+    pub fn matches(&self, path: &Path) -> bool {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let exts = [false, true].map(|compound| {
+            path_utils::split_extension(&name, compound)
+                .1
+                .to_lowercase()
+        });
+        let has_any = |list: &[String]| exts.iter().any(|ext| list.contains(ext));
+
+        (self.allow.is_empty() || has_any(&self.allow)) && !has_any(&self.deny)
+    }
+}
+
+/// selects the input files by globs. A glob matches the end of a path at a component boundary,
+/// so `*.jpg` matches file names and `node_modules/**` the files inside such directories anywhere.
+/// `*` and `?` don't match `/`, `**` does. A leading `/` anchors the glob at the root
+#[derive(Debug, Default, Clone)]
+pub struct PathFilter {
+    include: Vec<(String, Regex)>,
+    exclude: Vec<(String, Regex)>,
+}
+
+impl PathFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Self {
+        let compile = |globs: &[String]| {
+            globs
+                .iter()
+                .map(|glob| (glob.clone(), path_glob_regex(glob)))
+                .collect()
+        };
+
+        PathFilter {
+            include: compile(include),
+            exclude: compile(exclude),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// the path matches one of the included globs, if there are any, and none of the excluded ones
+    pub fn matches(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy();
+        let any = |globs: &[(String, Regex)]| globs.iter().any(|(_, regex)| regex.is_match(&path));
+
+        (self.include.is_empty() || any(&self.include)) && !any(&self.exclude)
+    }
+
+    pub fn include(&self) -> impl Iterator<Item = &str> {
+        self.include.iter().map(|(glob, _)| glob.as_str())
+    }
+
+    pub fn exclude(&self) -> impl Iterator<Item = &str> {
+        self.exclude.iter().map(|(glob, _)| glob.as_str())
+    }
+}
+
+/// selects the input files by their size and modification time.
+/// Sizes of directories aren't compared
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MetadataFilter {
+    pub newer_than: Option<SystemTime>,
+    pub older_than: Option<SystemTime>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+}
+
+impl MetadataFilter {
+    pub fn is_empty(&self) -> bool {
+        *self == MetadataFilter::default()
+    }
+
+    pub fn matches(&self, meta: &Metadata) -> bool {
+        let modified = meta.modified().ok();
+        let newer = self
+            .newer_than
+            .is_none_or(|time| modified.is_some_and(|modified| modified > time));
+        let older = self
+            .older_than
+            .is_none_or(|time| modified.is_some_and(|modified| modified < time));
+        let size_fits = meta.is_dir()
+            || (self.min_size.is_none_or(|min| meta.len() >= min)
+                && self.max_size.is_none_or(|max| meta.len() <= max));
+        newer && older && size_fits
+    }
+}
+
+/// `10K`, `1.5M`, `2GB`: a number of bytes with an optional binary unit
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let upper = text.to_uppercase();
+    let number = upper.trim_end_matches('B').trim_end_matches('I');
+    let (number, unit) = match number.char_indices().last() {
+        Some((idx, unit @ ('K' | 'M' | 'G' | 'T'))) => (&number[..idx], unit),
+        _ => (number, ' '),
+    };
+    let multiplier: u64 = match unit {
+        'K' => 1 << 10,
+        'M' => 1 << 20,
+        'G' => 1 << 30,
+        'T' => 1 << 40,
+        _ => 1,
+    };
+
+    match number.trim().parse::<f64>() {
+        Ok(number) if number >= 0.0 => Ok((number * multiplier as f64) as u64),
+        _ => Err(format!(
+            "invalid size {:?}, expected e.g. 500, 10K, 1.5M or 2G",
+            text
+        )),
+    }
+}
+
+/// a moment as an age like `30m`, `12h`, `7d`, `2w`, or as a local date `2024-05-01`
+/// with an optional time `2024-05-01 12:30`
+pub fn parse_time(text: &str) -> Result<SystemTime, String> {
+    parse_time_at(text, Local::now())
+}
+
+fn parse_time_at(text: &str, now: DateTime<Local>) -> Result<SystemTime, String> {
+    let text = text.trim();
+    let error = || {
+        format!(
+            "invalid time {:?}, expected an age like 30m, 12h, 7d, 2w or a date like 2024-05-01",
+            text
+        )
+    };
+
+    let age_unit = text.chars().last().and_then(|unit| match unit {
+        's' => Some(1),
+        'm' => Some(60),
+        'h' => Some(60 * 60),
+        'd' => Some(24 * 60 * 60),
+        'w' => Some(7 * 24 * 60 * 60),
+        _ => None,
+    });
+    if let Some(seconds) = age_unit {
+        let count: i64 = text[..text.len() - 1].parse().map_err(|_| error())?;
+        let age = chrono::Duration::try_seconds(count * seconds).ok_or_else(error)?;
+        return Ok((now - age).into());
+    }
+
+    let moment = NaiveDate::parse_from_str(text, "%Y-%m-%d")
+        .map(|date| date.and_time(NaiveTime::MIN))
+        .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M"))
+        .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S"))
+        .map_err(|_| error())?;
+    let moment = moment
+        .and_local_timezone(Local)
+        .earliest()
+        .ok_or_else(error)?;
+    Ok(moment.into())
+}
+
+/// regex matching the paths which end with `glob`
+fn path_glob_regex(glob: &str) -> Regex {
+    let anchor = if glob.starts_with('/') { "^" } else { "(^|/)" };
+    glob_regex_body(glob)
+        .and_then(|body| Regex::new(&format!("{}{}$", anchor, body)).ok())
+        .unwrap_or_else(|| literal_glob_regex(glob))
+}
+
+/// regex matching whole names of single path components, like the shell does.
+/// `*` and `?` don't match the slashes
+pub fn name_glob_regex(glob: &str) -> Regex {
+    glob_regex_body(glob)
+        .and_then(|body| Regex::new(&format!("^{}$", body)).ok())
+        .unwrap_or_else(|| Regex::new(&format!("^{}$", regex::escape(glob))).unwrap())
+}
+
+/// whether `text` has glob wildcards
+pub fn is_glob(text: &str) -> bool {
+    text.contains(['*', '?', '['])
+}
+
+/// `glob` translated to a regex without anchors.
+/// `None` for unfinished classes like `[a`, the glob is taken literally then.
+/// Invalid classes like `[z-a]` make an invalid regex
+fn glob_regex_body(glob: &str) -> Option<String> {
+    let mut regex = String::new();
+    let mut chars = glob.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                regex.push_str(".*");
+            }
+            '*' => regex.push_str("[^/]*"),
+            '?' => regex.push_str("[^/]"),
+            '[' => {
+                regex.push('[');
+                if chars.peek() == Some(&'!') {
+                    chars.next();
+                    regex.push('^');
+                }
+                let mut closed = false;
+                for ch in chars.by_ref() {
+                    if ch == ']' {
+                        closed = true;
+                        break;
+                    }
+                    if ch == '\\' || ch == '[' {
+                        regex.push('\\');
+                    }
+                    regex.push(ch);
+                }
+                if !closed {
+                    return None;
+                }
+                regex.push(']');
+            }
+            ch => regex.push_str(&regex::escape(&ch.to_string())),
+        }
+    }
+
+    Some(regex)
+}
+
+fn literal_glob_regex(glob: &str) -> Regex {
+    Regex::new(&format!("(^|/){}$", regex::escape(glob))).unwrap()
+}
+
+/// `.JPG` -> `jpg`
+fn normalize_ext(ext: &str) -> String {
+    ext.trim().trim_start_matches('.').to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(&[], &[], "a.txt", true)]
+    #[case(&["jpg", "png"], &[], "a.jpg", true)]
+    #[case(&["jpg", "png"], &[], "a.JPG", true)]
+    #[case(&[".PNG"], &[], "a.png", true)]
+    #[case(&["jpg", "png"], &[], "a.gif", false)]
+    #[case(&["jpg"], &[], "jpg", false)]
+    #[case(&[], &["tmp", "part"], "a.part", false)]
+    #[case(&[], &["tmp", "part"], "a.txt", true)]
+    #[case(&["txt"], &["txt"], "a.txt", false)]
+    #[case(&["tar.gz"], &[], "a.tar.gz", true)]
+    #[case(&["gz"], &[], "a.tar.gz", true)]
+    #[case(&["rc"], &[], ".rc", false)]
+    #[case(&[], &["bashrc"], ".bashrc", true)]
+    fn matches_works(
+        #[case] allow: &[&str],
+        #[case] deny: &[&str],
+        #[case] path: &str,
+        #[case] expected: bool,
+    ) {
+        let to_strings = |exts: &[&str]| exts.iter().map(|ext| ext.to_string()).collect::<Vec<_>>();
+        let filter = ExtFilter::new(&to_strings(allow), &to_strings(deny));
+        assert_eq!(filter.matches(Path::new(path)), expected);
+    }
+
+    #[rstest]
+    #[case(&["*.jpg"], &[], "/a/b.jpg", true)]
+    #[case(&["*.jpg"], &[], "/a.jpg/b.png", false)]
+    #[case(&["b/*.jpg"], &[], "/a/b/c.jpg", true)]
+    #[case(&["b/*.jpg"], &[], "/a/b/d/c.jpg", false)]
+    #[case(&["b/**.jpg"], &[], "/a/b/d/c.jpg", true)]
+    #[case(&["/a/*"], &[], "/x/a/c", false)]
+    #[case(&["c?.[!t]xt"], &[], "/c1.pxt", true)]
+    #[case(&["c?.[!t]xt"], &[], "/c1.txt", false)]
+    #[case(&["[a"], &[], "/[a", true)]
+    #[case(&["[z-a]"], &[], "/[z-a]", true)]
+    #[case(&[], &["node_modules/**"], "/p/node_modules/x/y.js", false)]
+    #[case(&[], &["node_modules/**"], "/p/src/y.js", true)]
+    #[case(&["*.js"], &["node_modules/**"], "/p/src/y.js", true)]
+    #[case(&["*.js", "*.ts"], &[], "/p/src/y.ts", true)]
+    fn path_filter_works(
+        #[case] include: &[&str],
+        #[case] exclude: &[&str],
+        #[case] path: &str,
+        #[case] expected: bool,
+    ) {
+        let to_strings = |globs: &[&str]| {
+            globs
+                .iter()
+                .map(|glob| glob.to_string())
+                .collect::<Vec<_>>()
+        };
+        let filter = PathFilter::new(&to_strings(include), &to_strings(exclude));
+        assert_eq!(filter.matches(Path::new(path)), expected);
+    }
+
+    #[rstest]
+    #[case("500", Some(500))]
+    #[case("10K", Some(10 * 1024))]
+    #[case("1.5m", Some(3 * 512 * 1024))]
+    #[case("2GB", Some(2 << 30))]
+    #[case("1KiB", Some(1024))]
+    #[case("-1", None)]
+    #[case("ten", None)]
+    fn size_is_parsed(#[case] text: &str, #[case] expected: Option<u64>) {
+        assert_eq!(parse_size(text).ok(), expected);
+    }
+
+    #[rstest]
+    #[case("30m", Some("2024-05-10 11:30:00"))]
+    #[case("7d", Some("2024-05-03 12:00:00"))]
+    #[case("2w", Some("2024-04-26 12:00:00"))]
+    #[case("2024-05-01", Some("2024-05-01 00:00:00"))]
+    #[case("2024-05-01 08:15", Some("2024-05-01 08:15:00"))]
+    #[case("7y", None)]
+    #[case("yesterday", None)]
+    fn time_is_parsed(#[case] text: &str, #[case] expected: Option<&str>) {
+        let local = |text: &str| {
+            NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S")
+                .unwrap()
+                .and_local_timezone(Local)
+                .unwrap()
+        };
+        let now = local("2024-05-10 12:00:00");
+        let expected = expected.map(|text| SystemTime::from(local(text)));
+        assert_eq!(parse_time_at(text, now).ok(), expected);
+    }
+
+    #[test]
+    fn metadata_filter_works() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a");
+        std::fs::write(&file, "12345").unwrap();
+        let meta = std::fs::metadata(&file).unwrap();
+        let modified = meta.modified().unwrap();
+        let hour = std::time::Duration::from_secs(3600);
+
+        let matches = |filter: MetadataFilter| filter.matches(&meta);
+        assert!(matches(MetadataFilter::default()));
+        assert!(matches(MetadataFilter {
+            min_size: Some(5),
+            max_size: Some(5),
+            newer_than: Some(modified - hour),
+            older_than: Some(modified + hour),
+        }));
+        assert!(!matches(MetadataFilter {
+            min_size: Some(6),
+            ..MetadataFilter::default()
+        }));
+        assert!(!matches(MetadataFilter {
+            newer_than: Some(modified + hour),
+            ..MetadataFilter::default()
+        }));
+        // sizes of directories aren't compared
+        let dir_meta = std::fs::metadata(dir.path()).unwrap();
+        assert!(MetadataFilter {
+            max_size: Some(0),
+            ..MetadataFilter::default()
+        }
+        .matches(&dir_meta));
+    }
+}
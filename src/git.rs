@@ -0,0 +1,127 @@
+use crate::{path_utils, planner};
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use chrono::{DateTime, Local};
+
+/// tokens filled from the git history
+pub const GIT_TOKENS: [&str; 2] = ["git_date", "git_author"];
+
+/// last commit changing a file, used by the `{git_date}` and `{git_author}` tokens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitInfo {
+    pub date: DateTime<Local>,
+    pub author: String,
+}
+
+impl GitInfo {
+    /// `None` if the file isn't in a git repository, isn't committed or git isn't available
+    pub fn of(path: &Path) -> Option<Self> {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["log", "-1", "--format=%aI%n%an", "--"])
+            .arg(path.file_name()?)
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        parse_log(&String::from_utf8_lossy(&output.stdout))
+    }
+}
+
+/// files tracked by git under `dir` joined to it, an empty `dir` is the current one
+pub fn ls_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let output = run(Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["ls-files", "-z"]))
+    .map_err(|err| anyhow::anyhow!("can't list the files tracked by git: {}", err))?;
+    Ok(path_utils::read_path_list(&output.stdout[..], true)
+        .into_iter()
+        .map(|path| dir.join(path))
+        .collect())
+}
+
+/// rename a file with `git mv`, so git records the rename. `force` replaces an existing `dst`
+pub fn mv(src: &Path, dst: &Path, force: bool) -> io::Result<()> {
+    let mut command = Command::new("git");
+    if let Some(dir) = src.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        command.arg("-C").arg(dir);
+    }
+    command.arg("mv");
+    if force {
+        command.arg("-f");
+    }
+    // the paths are absolute, so `-C` doesn't change what they point to
+    run(command
+        .arg("--")
+        .arg(planner::absolute(src))
+        .arg(planner::absolute(dst)))?;
+    Ok(())
+}
+
+/// output of a successful git command, otherwise its error message
+fn run(command: &mut Command) -> io::Result<Output> {
+    let output = command.output()?;
+    if !output.status.success() {
+        let message = String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        return Err(io::Error::other(message));
+    }
+    Ok(output)
+}
+
+/// parse the output of `git log -1 --format=%aI%n%an`
+fn parse_log(output: &str) -> Option<GitInfo> {
+    let mut lines = output.lines();
+    let date = DateTime::parse_from_rfc3339(lines.next()?.trim()).ok()?;
+    let author = lines.next()?.trim().to_owned();
+
+    Some(GitInfo {
+        date: date.with_timezone(&Local),
+        author,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn tracked_files_are_listed_and_moved() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        let git = |args: &[&str]| {
+            run(Command::new("git").arg("-C").arg(dir.path()).args(args)).unwrap();
+        };
+        git(&["init", "-q"]);
+        std::fs::write(path("a"), "").unwrap();
+        std::fs::write(path("untracked"), "").unwrap();
+        git(&["add", "a"]);
+
+        assert_eq!(ls_files(dir.path()).unwrap(), [path("a")]);
+        mv(&path("a"), &path("b"), false).unwrap();
+        assert_eq!(ls_files(dir.path()).unwrap(), [path("b")]);
+        assert!(mv(&path("untracked"), &path("c"), false).is_err());
+    }
+
+    #[rstest]
+    #[case("2021-03-04T05:06:07+00:00\nJane Doe\n", Some("Jane Doe"))]
+    #[case("", None)]
+    #[case("yesterday\nJane Doe\n", None)]
+    fn parse_log_works(#[case] output: &str, #[case] author: Option<&str>) {
+        assert_eq!(
+            parse_log(output).map(|info| info.author),
+            author.map(str::to_owned)
+        );
+    }
+}
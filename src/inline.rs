@@ -0,0 +1,94 @@
+use std::io;
+
+use tui::{backend::Backend, buffer::Cell, layout::Rect};
+
+/// backend drawing into `height` lines of the terminal starting at the line `top`
+/// instead of the whole screen
+pub struct InlineBackend<B: Backend> {
+    inner: B,
+    top: u16,
+    height: u16,
+}
+
+impl<B: Backend> InlineBackend<B> {
+    pub fn new(inner: B, top: u16, height: u16) -> Self {
+        InlineBackend { inner, top, height }
+    }
+}
+
+impl<B: Backend> Backend for InlineBackend<B> {
+    fn draw<'a, I>(&mut self, content: I) -> io::Result<()>
+    where
+        I: Iterator<Item = (u16, u16, &'a Cell)>,
+    {
+        let top = self.top;
+        self.inner
+            .draw(content.map(|(x, y, cell)| (x, y + top, cell)))
+    }
+
+    fn hide_cursor(&mut self) -> io::Result<()> {
+        self.inner.hide_cursor()
+    }
+
+    fn show_cursor(&mut self) -> io::Result<()> {
+        self.inner.show_cursor()
+    }
+
+    fn get_cursor(&mut self) -> io::Result<(u16, u16)> {
+        let (x, y) = self.inner.get_cursor()?;
+        Ok((x, y.saturating_sub(self.top)))
+    }
+
+    fn set_cursor(&mut self, x: u16, y: u16) -> io::Result<()> {
+        self.inner.set_cursor(x, y + self.top)
+    }
+
+    /// clear only the lines of the inline area
+    fn clear(&mut self) -> io::Result<()> {
+        let blank = Cell::default();
+        let blank = &blank;
+        let width = self.inner.size()?.width;
+        let (top, height) = (self.top, self.height);
+
+        self.inner
+            .draw((top..top + height).flat_map(|y| (0..width).map(move |x| (x, y, blank))))
+    }
+
+    fn size(&self) -> io::Result<Rect> {
+        Ok(Rect::new(0, 0, self.inner.size()?.width, self.height))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tui::{backend::TestBackend, buffer::Buffer, widgets::Paragraph, Terminal};
+
+    #[test]
+    fn draws_below_top() {
+        let mut terminal = Terminal::new(InlineBackend::new(TestBackend::new(5, 4), 2, 2)).unwrap();
+        assert_eq!(terminal.size().unwrap(), Rect::new(0, 0, 5, 2));
+
+        terminal
+            .draw(|f| f.render_widget(Paragraph::new("ab\ncd"), f.size()))
+            .unwrap();
+        terminal
+            .backend()
+            .inner
+            .assert_buffer(&Buffer::with_lines(vec![
+                "     ", "     ", "ab   ", "cd   ",
+            ]));
+
+        terminal.clear().unwrap();
+        terminal
+            .backend()
+            .inner
+            .assert_buffer(&Buffer::with_lines(vec![
+                "     ", "     ", "     ", "     ",
+            ]));
+    }
+}
@@ -0,0 +1,183 @@
+use std::io::{self, Read, Write};
+use std::process::Command;
+
+use termion::event::{self, Event, Key, MouseEvent};
+
+/// sent by the terminal around pasted text while bracketed paste is on
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+/// commands printing the clipboard, the first available one is used
+const CLIPBOARD_COMMANDS: [&[&str]; 4] = [
+    &["wl-paste", "--no-newline"],
+    &["xclip", "-selection", "clipboard", "-o"],
+    &["xsel", "--clipboard", "--output"],
+    &["pbpaste"],
+];
+
+/// what the user did in the terminal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    Key(Key),
+    /// text pasted at once, its newlines and tabs don't act as keys
+    Paste(String),
+    /// reported by terminals with mouse capture on, see `termion::input::MouseTerminal`
+    Mouse(MouseEvent),
+}
+
+/// inputs read from the raw bytes of the terminal. Pastes are collected into one input,
+/// the other bytes are parsed by termion, which can't parse the paste brackets itself
+pub struct Inputs<R> {
+    source: R,
+    buf: Vec<u8>,
+}
+
+impl<R: Read> Inputs<R> {
+    /// `source` is expected to be non-blocking like `termion::async_stdin`
+    pub fn new(source: R) -> Self {
+        Inputs {
+            source,
+            buf: Vec::new(),
+        }
+    }
+
+    /// all the inputs which arrived since the previous poll, so bursts of keys
+    /// are handled at once instead of one per frame
+    pub fn poll(&mut self) -> Vec<Input> {
+        let mut chunk = [0; 1024];
+        while let Ok(len @ 1..) = self.source.read(&mut chunk) {
+            self.buf.extend_from_slice(&chunk[..len]);
+        }
+        self.parse()
+    }
+
+    fn parse(&mut self) -> Vec<Input> {
+        let mut inputs = Vec::new();
+        while !self.buf.is_empty() {
+            if let Some(text) = self.buf.strip_prefix(PASTE_START) {
+                // the rest of the paste arrives later
+                let Some(end) = find(text, PASTE_END) else {
+                    break;
+                };
+                inputs.push(Input::Paste(
+                    String::from_utf8_lossy(&text[..end]).into_owned(),
+                ));
+                self.buf.drain(..PASTE_START.len() + end + PASTE_END.len());
+                continue;
+            }
+
+            // the start of a paste split between reads isn't parsed as keys.
+            // A lone escape byte is the Esc key, so it isn't kept
+            let keys_end = find(&self.buf, PASTE_START).unwrap_or_else(|| {
+                let partial = (3..PASTE_START.len())
+                    .find(|len| self.buf.ends_with(&PASTE_START[..*len]))
+                    .unwrap_or(0);
+                self.buf.len() - partial
+            });
+            if keys_end == 0 {
+                break;
+            }
+            inputs.extend(parse_events(&self.buf[..keys_end]));
+            self.buf.drain(..keys_end);
+        }
+        inputs
+    }
+}
+
+/// keys and mouse events of the bytes, a lone escape byte is the Esc key
+fn parse_events(bytes: &[u8]) -> Vec<Input> {
+    let mut inputs = Vec::new();
+    let mut iter = bytes.iter().map(|byte| Ok(*byte));
+    while let Some(Ok(first)) = iter.next() {
+        if first == b'\x1b' && iter.len() == 0 {
+            inputs.push(Input::Key(Key::Esc));
+            break;
+        }
+        match event::parse_event(first, &mut iter) {
+            Ok(Event::Key(key)) => inputs.push(Input::Key(key)),
+            Ok(Event::Mouse(mouse)) => inputs.push(Input::Mouse(mouse)),
+            _ => {}
+        }
+    }
+    inputs
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// bracketed paste of the terminal, on while this lives
+pub struct BracketedPaste;
+
+impl BracketedPaste {
+    pub fn enable() -> io::Result<Self> {
+        let mut stdout = io::stdout();
+        stdout.write_all(b"\x1b[?2004h")?;
+        stdout.flush()?;
+        Ok(BracketedPaste)
+    }
+}
+
+impl Drop for BracketedPaste {
+    fn drop(&mut self) {
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(b"\x1b[?2004l");
+        let _ = stdout.flush();
+    }
+}
+
+/// text of the system clipboard, `None` if no clipboard tool works
+pub fn clipboard_text() -> Option<String> {
+    CLIPBOARD_COMMANDS.iter().find_map(|command| {
+        let output = Command::new(command[0]).args(&command[1..]).output().ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mouse_events_are_parsed() {
+        use termion::event::MouseButton;
+
+        let mut inputs = Inputs::new(&b"\x1b[<0;5;3Mx\x1b[<65;1;1M"[..]);
+        assert_eq!(
+            inputs.poll(),
+            [
+                Input::Mouse(MouseEvent::Press(MouseButton::Left, 5, 3)),
+                Input::Key(Key::Char('x')),
+                Input::Mouse(MouseEvent::Press(MouseButton::WheelDown, 1, 1)),
+            ]
+        );
+    }
+
+    #[test]
+    fn pastes_are_told_from_keys() {
+        let mut inputs = Inputs::new(&b"a\x1b[200~(\\d+)\n\tx\x1b[201~\x1b[D\x1b[200~b"[..]);
+        assert_eq!(
+            inputs.poll(),
+            [
+                Input::Key(Key::Char('a')),
+                Input::Paste("(\\d+)\n\tx".to_owned()),
+                Input::Key(Key::Left),
+            ]
+        );
+        // the unfinished paste waits for its end
+        inputs.buf.extend_from_slice(b"c\x1b[201~\x1b");
+        assert_eq!(
+            inputs.parse(),
+            [Input::Paste("bc".to_owned()), Input::Key(Key::Esc)]
+        );
+        inputs.buf.extend_from_slice(b"d\x1b[20");
+        assert_eq!(inputs.parse(), [Input::Key(Key::Char('d'))]);
+        inputs.buf.extend_from_slice(b"0~e\x1b[201~");
+        assert_eq!(inputs.parse(), [Input::Paste("e".to_owned())]);
+    }
+}
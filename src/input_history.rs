@@ -0,0 +1,108 @@
+use crate::path_utils;
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// how many values of each input are remembered
+pub const MAX_ENTRIES: usize = 100;
+
+/// regexes and replacements used for renaming in previous sessions, the latest last
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputHistory {
+    #[serde(default)]
+    pub regexes: Vec<String>,
+    #[serde(default)]
+    pub replacements: Vec<String>,
+}
+
+/// default location of the history: `$XDG_DATA_HOME/irename/inputs.json`
+pub fn history_path() -> PathBuf {
+    path_utils::xdg_dir("XDG_DATA_HOME", ".local/share")
+        .join("irename")
+        .join("inputs.json")
+}
+
+/// move `value` to the end of `values`, dropping the oldest ones above the limit
+fn remember_value(values: &mut Vec<String>, value: &str) {
+    if value.is_empty() {
+        return;
+    }
+
+    values.retain(|old| old != value);
+    values.push(value.to_owned());
+    if values.len() > MAX_ENTRIES {
+        values.drain(..values.len() - MAX_ENTRIES);
+    }
+}
+
+impl InputHistory {
+    /// load the history from `path`. A missing file means an empty history
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(InputHistory::default());
+        }
+
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("can't read input history {}", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("invalid input history {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("can't create directory {}", dir.display()))?;
+        }
+
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("can't write input history {}", path.display()))
+    }
+
+    /// add the inputs used for renaming as the latest ones
+    pub fn remember(&mut self, regex: &str, replacement: &str) {
+        remember_value(&mut self.regexes, regex);
+        remember_value(&mut self.replacements, replacement);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_values_move_to_the_end() {
+        let mut history = InputHistory::default();
+        history.remember("a", "1");
+        history.remember("b", "");
+        history.remember("a", "2");
+
+        assert_eq!(history.regexes, ["b", "a"]);
+        assert_eq!(history.replacements, ["1", "2"]);
+    }
+
+    #[test]
+    fn oldest_values_are_dropped() {
+        let mut history = InputHistory::default();
+        for idx in 0..MAX_ENTRIES + 5 {
+            history.remember(&idx.to_string(), "");
+        }
+
+        assert_eq!(history.regexes.len(), MAX_ENTRIES);
+        assert_eq!(history.regexes[0], "5");
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sub").join("inputs.json");
+        assert_eq!(InputHistory::load(&path).unwrap(), InputHistory::default());
+
+        let mut history = InputHistory::default();
+        history.remember("(\\d+)", "$1");
+        history.save(&path).unwrap();
+
+        assert_eq!(InputHistory::load(&path).unwrap(), history);
+    }
+}
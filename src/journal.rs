@@ -0,0 +1,434 @@
+use crate::path_utils;
+use crate::planner::MovePair;
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rename {
+    #[serde(with = "path_utils::text_path")]
+    pub src: PathBuf,
+    #[serde(with = "path_utils::text_path")]
+    pub dst: PathBuf,
+}
+
+/// record of all the renames applied in one session
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Journal {
+    pub timestamp: DateTime<Local>,
+    pub renames: Vec<Rename>,
+    /// timestamp of the journal whose renames this one reverts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub undoes: Option<DateTime<Local>>,
+}
+
+/// a name which a file had starting from some moment
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimelineEntry {
+    /// when the file got this name. `None` if it had it before the first recorded rename
+    pub timestamp: Option<DateTime<Local>>,
+    pub path: PathBuf,
+}
+
+/// directory where the journals are stored: `$XDG_DATA_HOME/irename/history`
+pub fn history_dir() -> PathBuf {
+    path_utils::xdg_dir("XDG_DATA_HOME", ".local/share")
+        .join("irename")
+        .join("history")
+}
+
+impl Journal {
+    pub fn new(move_pairs: &[MovePair]) -> Self {
+        Journal {
+            timestamp: Local::now(),
+            renames: move_pairs
+                .iter()
+                .map(|(src, dst)| Rename {
+                    src: src.clone(),
+                    dst: dst.clone(),
+                })
+                .collect(),
+            undoes: None,
+        }
+    }
+
+    /// journal of the `move_pairs` reverting the renames of this one
+    pub fn reverted_by(&self, move_pairs: &[MovePair]) -> Self {
+        Journal {
+            undoes: Some(self.timestamp),
+            ..Journal::new(move_pairs)
+        }
+    }
+
+    /// renames of the journal as pairs
+    pub fn move_pairs(&self) -> Vec<MovePair> {
+        self.renames
+            .iter()
+            .map(|rename| (rename.src.clone(), rename.dst.clone()))
+            .collect()
+    }
+
+    fn file_stem(&self) -> String {
+        self.timestamp.format("%Y%m%dT%H%M%S%.6f").to_string()
+    }
+
+    /// save the journal into `dir` as `<timestamp>.json`
+    pub fn save(&self, dir: &Path) -> anyhow::Result<PathBuf> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("can't create history directory {}", dir.display()))?;
+
+        let path = dir.join(format!("{}.json", self.file_stem()));
+        let file = std::fs::File::create(&path)
+            .with_context(|| format!("can't create journal {}", path.display()))?;
+        serde_json::to_writer_pretty(file, self)?;
+
+        Ok(path)
+    }
+
+    /// shell script reverting all the renames of the journal. Paths which aren't UTF-8
+    /// are written as they are, so the script is bytes
+    pub fn undo_script(&self) -> Vec<u8> {
+        let mut script = b"#!/bin/sh\nset -e\n".to_vec();
+        for rename in self.renames.iter().rev() {
+            script.extend_from_slice(b"mv -n -- ");
+            script.extend(path_utils::shell_quote(&rename.dst));
+            script.push(b' ');
+            script.extend(path_utils::shell_quote(&rename.src));
+            script.push(b'\n');
+        }
+        script
+    }
+
+    /// save the undo script into `dir` as `<timestamp>.sh` next to the journal
+    pub fn save_undo_script(&self, dir: &Path) -> anyhow::Result<PathBuf> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("can't create history directory {}", dir.display()))?;
+
+        let path = dir.join(format!("{}.sh", self.file_stem()));
+        std::fs::write(&path, self.undo_script())
+            .with_context(|| format!("can't write undo script {}", path.display()))?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+        }
+
+        Ok(path)
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("can't open journal {}", path.display()))?;
+
+        serde_json::from_reader(std::io::BufReader::new(file))
+            .with_context(|| format!("invalid journal {}", path.display()))
+    }
+
+    /// all the journals from `dir` in chronological order
+    pub fn load_all(dir: &Path) -> anyhow::Result<Vec<Self>> {
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut journals = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                journals.push(Journal::load(&path)?);
+            }
+        }
+        journals.sort_by_key(|journal| journal.timestamp);
+
+        Ok(journals)
+    }
+}
+
+/// whether each of the chronologically ordered `journals` is in effect: it isn't undone by
+/// a later journal in effect. Redoing undoes the undo, so the original session is in effect again
+pub fn in_effect(journals: &[Journal]) -> Vec<bool> {
+    let mut undone = HashSet::new();
+    let mut result: Vec<bool> = journals
+        .iter()
+        .rev()
+        .map(|journal| {
+            let active = !undone.contains(&journal.timestamp);
+            if let (true, Some(target)) = (active, journal.undoes) {
+                undone.insert(target);
+            }
+            active
+        })
+        .collect();
+    result.reverse();
+    result
+}
+
+/// sessions which can be undone, the latest first
+pub fn undoable(journals: &[Journal]) -> Vec<&Journal> {
+    journals
+        .iter()
+        .zip(in_effect(journals))
+        .rev()
+        .filter(|(journal, active)| *active && journal.undoes.is_none())
+        .map(|(journal, _)| journal)
+        .collect()
+}
+
+/// undos of sessions which can be redone, the latest first
+pub fn redoable(journals: &[Journal]) -> Vec<&Journal> {
+    let sessions: HashSet<DateTime<Local>> = journals
+        .iter()
+        .filter(|journal| journal.undoes.is_none())
+        .map(|journal| journal.timestamp)
+        .collect();
+
+    journals
+        .iter()
+        .zip(in_effect(journals))
+        .rev()
+        .filter(|(journal, active)| {
+            *active
+                && journal
+                    .undoes
+                    .is_some_and(|target| sessions.contains(&target))
+        })
+        .map(|(journal, _)| journal)
+        .collect()
+}
+
+/// the recorded renames which can't be reverted anymore because the files changed since.
+/// The renames are checked from the last one as if the previous ones were reverted already
+pub fn revert_problems(move_pairs: &[MovePair]) -> Vec<(MovePair, String)> {
+    let mut removed = HashSet::new();
+    let mut created = HashSet::new();
+    let exists = |path: &Path, removed: &HashSet<PathBuf>, created: &HashSet<PathBuf>| {
+        created.contains(path)
+            || (!removed.contains(path) && std::fs::symlink_metadata(path).is_ok())
+    };
+
+    let mut problems = Vec::new();
+    for (src, dst) in move_pairs.iter().rev() {
+        let problem = if !src.is_absolute() || !dst.is_absolute() {
+            Some("recorded path isn't absolute".to_owned())
+        } else if !exists(dst, &removed, &created) {
+            Some(format!("{} doesn't exist anymore", dst.display()))
+        } else if exists(src, &removed, &created) {
+            Some(format!("{} exists again", src.display()))
+        } else {
+            None
+        };
+
+        match problem {
+            Some(problem) => problems.push(((src.clone(), dst.clone()), problem)),
+            None => {
+                created.remove(dst);
+                removed.insert(dst.clone());
+                removed.remove(src);
+                created.insert(src.clone());
+            }
+        }
+    }
+    problems
+}
+
+/// all the names the file at `path` had (or will have later) according to the journals
+pub fn timeline(journals: &[Journal], path: &Path) -> Vec<TimelineEntry> {
+    let records: Vec<(DateTime<Local>, &Rename)> = journals
+        .iter()
+        .flat_map(|journal| {
+            journal
+                .renames
+                .iter()
+                .map(move |rename| (journal.timestamp, rename))
+        })
+        .collect();
+
+    // walk back in time to the first known name
+    let mut backward = Vec::new();
+    let mut current = path;
+    let mut forward_start = 0;
+    for (idx, (timestamp, rename)) in records.iter().enumerate().rev() {
+        if rename.dst == current {
+            if backward.is_empty() {
+                forward_start = idx + 1;
+            }
+            backward.push(TimelineEntry {
+                timestamp: Some(*timestamp),
+                path: rename.dst.clone(),
+            });
+            current = &rename.src;
+        }
+    }
+
+    let mut entries = vec![TimelineEntry {
+        timestamp: None,
+        path: current.to_owned(),
+    }];
+    entries.extend(backward.into_iter().rev());
+
+    // then forward to the latest one
+    let mut current = path;
+    for (timestamp, rename) in &records[forward_start..] {
+        if rename.src == current {
+            entries.push(TimelineEntry {
+                timestamp: Some(*timestamp),
+                path: rename.dst.clone(),
+            });
+            current = &rename.dst;
+        }
+    }
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn journal(pairs: &[(&str, &str)]) -> Journal {
+        let pairs: Vec<MovePair> = pairs
+            .iter()
+            .map(|(src, dst)| (PathBuf::from(src), PathBuf::from(dst)))
+            .collect();
+        Journal::new(&pairs)
+    }
+
+    fn paths(entries: Vec<TimelineEntry>) -> Vec<PathBuf> {
+        entries.into_iter().map(|entry| entry.path).collect()
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+
+        journal(&[("/a", "/b")]).save(dir.path()).unwrap();
+        journal(&[("/b", "/c")]).save(dir.path()).unwrap();
+
+        let journals = Journal::load_all(dir.path()).unwrap();
+        assert_eq!(journals.len(), 2);
+        assert_eq!(journals[0].renames[0].src, PathBuf::from("/a"));
+        assert_eq!(journals[1].renames[0].src, PathBuf::from("/b"));
+    }
+
+    #[test]
+    fn undo_script_reverts_in_reverse_order() {
+        let script = journal(&[("/a", "/b"), ("/it's", "/c d")]).undo_script();
+
+        assert_eq!(
+            script,
+            b"#!/bin/sh\nset -e\nmv -n -- '/c d' '/it'\\''s'\nmv -n -- '/b' '/a'\n"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn undo_script_reverts_names_which_arent_utf8() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join(path_utils::path_from_bytes(b"a\xff"));
+        let dst = dir.path().join(path_utils::path_from_bytes(b"b\xfe"));
+        std::fs::write(&dst, "").unwrap();
+
+        let script = Journal::new(&[(src.clone(), dst.clone())]).save_undo_script(dir.path());
+        let status = std::process::Command::new("sh")
+            .arg(script.unwrap())
+            .status()
+            .unwrap();
+
+        assert!(status.success());
+        assert!(src.exists());
+        assert!(!dst.exists());
+    }
+
+    #[test]
+    fn undo_and_redo_follow_the_history() {
+        let first = journal(&[("/a", "/b")]);
+        let second = journal(&[("/b", "/c")]);
+        let timestamps =
+            |journals: Vec<&Journal>| -> Vec<_> { journals.iter().map(|j| j.timestamp).collect() };
+
+        let mut journals = vec![first.clone(), second.clone()];
+        assert_eq!(
+            timestamps(undoable(&journals)),
+            [second.timestamp, first.timestamp]
+        );
+        assert!(redoable(&journals).is_empty());
+
+        let undo = second.reverted_by(&[(PathBuf::from("/c"), PathBuf::from("/b"))]);
+        journals.push(undo.clone());
+        assert_eq!(timestamps(undoable(&journals)), [first.timestamp]);
+        assert_eq!(timestamps(redoable(&journals)), [undo.timestamp]);
+
+        journals.push(undo.reverted_by(&[(PathBuf::from("/b"), PathBuf::from("/c"))]));
+        assert_eq!(in_effect(&journals), [true, true, false, true]);
+        assert_eq!(
+            timestamps(undoable(&journals)),
+            [second.timestamp, first.timestamp]
+        );
+        assert!(redoable(&journals).is_empty());
+    }
+
+    #[test]
+    fn changed_files_cant_be_reverted() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        std::fs::write(path("c"), "").unwrap();
+        std::fs::write(path("x"), "").unwrap();
+
+        // a -> b -> c is reverted from the end, so b is free again when a is restored
+        let pairs = vec![
+            (path("a"), path("b")),
+            (path("b"), path("c")),
+            (path("y"), path("z")),
+            (path("x"), path("w")),
+            (PathBuf::from("rel"), path("c")),
+        ];
+        let problems: Vec<PathBuf> = revert_problems(&pairs)
+            .into_iter()
+            .map(|((src, _), _)| src)
+            .collect();
+
+        assert_eq!(problems, [PathBuf::from("rel"), path("x"), path("y")]);
+    }
+
+    #[test]
+    fn load_from_missing_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let journals = Journal::load_all(&dir.path().join("missing")).unwrap();
+        assert!(journals.is_empty());
+    }
+
+    #[test]
+    fn timeline_follows_renames() {
+        let journals = vec![
+            journal(&[("/a", "/b"), ("/x", "/y")]),
+            journal(&[("/y", "/a")]),
+            journal(&[("/b", "/c")]),
+            journal(&[("/c", "/d")]),
+        ];
+
+        let expected: Vec<PathBuf> = ["/a", "/b", "/c", "/d"].iter().map(PathBuf::from).collect();
+        assert_eq!(paths(timeline(&journals, Path::new("/d"))), expected);
+        assert_eq!(paths(timeline(&journals, Path::new("/b"))), expected);
+
+        let expected: Vec<PathBuf> = ["/x", "/y", "/a"].iter().map(PathBuf::from).collect();
+        assert_eq!(paths(timeline(&journals, Path::new("/y"))), expected);
+    }
+
+    #[test]
+    fn timeline_of_unknown_file() {
+        let journals = vec![journal(&[("/a", "/b")])];
+
+        let entries = timeline(&journals, Path::new("/z"));
+        assert_eq!(
+            entries,
+            vec![TimelineEntry {
+                timestamp: None,
+                path: PathBuf::from("/z")
+            }]
+        );
+    }
+}
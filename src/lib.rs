@@ -1,3 +1,31 @@
 pub mod app;
+pub mod captures;
 pub mod cli;
-mod path_utils;
+pub mod config;
+mod counter;
+pub mod doctor;
+pub mod filter;
+pub mod git;
+pub mod inline;
+pub mod input;
+pub mod input_history;
+pub mod journal;
+mod line_edit;
+pub mod media;
+pub mod path_utils;
+pub mod plan_cache;
+pub mod plan_file;
+pub mod planner;
+pub mod portability;
+pub mod prefetch;
+pub mod prompt;
+pub mod protocol;
+pub mod rename_log;
+pub mod scan;
+pub mod session;
+mod template;
+pub mod transfer;
+pub mod transform;
+pub mod trash;
+pub mod tutorial;
+pub mod watch;
@@ -0,0 +1,135 @@
+/// change of a single line input at its cursor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    Left,
+    Right,
+    Home,
+    End,
+    Insert(char),
+    /// remove the char before the cursor
+    Backspace,
+    /// remove the char at the cursor
+    Delete,
+    /// to the start of the word before the cursor
+    WordLeft,
+    /// past the end of the word after the cursor
+    WordRight,
+    /// remove the chars before the cursor up to a whitespace, like `Ctrl-W` of shells
+    DeleteWordBack,
+    DeleteToStart,
+    DeleteToEnd,
+}
+
+/// apply the edit to `text` with the cursor at byte offset `cursor`, returns the new cursor.
+/// A cursor past the end or inside a char is moved back to a char boundary first
+pub fn apply(text: &mut String, cursor: usize, edit: Edit) -> usize {
+    let mut cursor = cursor.min(text.len());
+    while !text.is_char_boundary(cursor) {
+        cursor -= 1;
+    }
+
+    match edit {
+        Edit::Left => prev_boundary(text, cursor),
+        Edit::Right => next_boundary(text, cursor),
+        Edit::Home => 0,
+        Edit::End => text.len(),
+        Edit::Insert(ch) => {
+            text.insert(cursor, ch);
+            cursor + ch.len_utf8()
+        }
+        Edit::Backspace => {
+            let start = prev_boundary(text, cursor);
+            text.replace_range(start..cursor, "");
+            start
+        }
+        Edit::Delete => {
+            let end = next_boundary(text, cursor);
+            text.replace_range(cursor..end, "");
+            cursor
+        }
+        Edit::WordLeft => word_start(text, cursor, char::is_alphanumeric),
+        Edit::WordRight => {
+            let rest = &text[cursor..];
+            let word = rest.find(char::is_alphanumeric).unwrap_or(rest.len());
+            let end = rest[word..]
+                .find(|ch: char| !ch.is_alphanumeric())
+                .map_or(rest.len(), |len| word + len);
+            cursor + end
+        }
+        Edit::DeleteWordBack => {
+            let start = word_start(text, cursor, |ch| !ch.is_whitespace());
+            text.replace_range(start..cursor, "");
+            start
+        }
+        Edit::DeleteToStart => {
+            text.replace_range(..cursor, "");
+            0
+        }
+        Edit::DeleteToEnd => {
+            text.truncate(cursor);
+            cursor
+        }
+    }
+}
+
+/// start of the word before the cursor, skipping the chars between them
+fn word_start(text: &str, cursor: usize, in_word: impl Fn(char) -> bool) -> usize {
+    let before = text[..cursor].trim_end_matches(|ch| !in_word(ch));
+    before.trim_end_matches(&in_word).len()
+}
+
+fn prev_boundary(text: &str, cursor: usize) -> usize {
+    text[..cursor]
+        .char_indices()
+        .next_back()
+        .map_or(0, |(idx, _)| idx)
+}
+
+fn next_boundary(text: &str, cursor: usize) -> usize {
+    text[cursor..]
+        .chars()
+        .next()
+        .map_or(cursor, |ch| cursor + ch.len_utf8())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("abc", 1, Edit::Insert('x'), "axbc", 2)]
+    #[case("abc", 3, Edit::Insert('é'), "abcé", 5)]
+    #[case("abc", 1, Edit::Backspace, "bc", 0)]
+    #[case("abc", 0, Edit::Backspace, "abc", 0)]
+    #[case("abc", 1, Edit::Delete, "ac", 1)]
+    #[case("abc", 3, Edit::Delete, "abc", 3)]
+    #[case("aéb", 3, Edit::Left, "aéb", 1)]
+    #[case("aéb", 1, Edit::Right, "aéb", 3)]
+    #[case("aéb", 3, Edit::Backspace, "ab", 1)]
+    #[case("aéb", 2, Edit::Delete, "ab", 1)]
+    #[case("abc", 9, Edit::Left, "abc", 2)]
+    #[case("abc", 1, Edit::End, "abc", 3)]
+    #[case("abc", 2, Edit::Home, "abc", 0)]
+    #[case("foo_bar baz", 11, Edit::WordLeft, "foo_bar baz", 8)]
+    #[case("foo_bar baz", 8, Edit::WordLeft, "foo_bar baz", 4)]
+    #[case("foo_bar baz", 0, Edit::WordRight, "foo_bar baz", 3)]
+    #[case("foo_bar baz", 3, Edit::WordRight, "foo_bar baz", 7)]
+    #[case("foo_bar baz", 11, Edit::WordRight, "foo_bar baz", 11)]
+    #[case("(\\d+) - x", 9, Edit::DeleteWordBack, "(\\d+) - ", 8)]
+    #[case("(\\d+) - ", 8, Edit::DeleteWordBack, "(\\d+) ", 6)]
+    #[case("(\\d+)", 5, Edit::DeleteWordBack, "", 0)]
+    #[case("abc def", 4, Edit::DeleteToStart, "def", 0)]
+    #[case("abc def", 3, Edit::DeleteToEnd, "abc", 3)]
+    fn edits_are_applied(
+        #[case] text: &str,
+        #[case] cursor: usize,
+        #[case] edit: Edit,
+        #[case] expected_text: &str,
+        #[case] expected_cursor: usize,
+    ) {
+        let mut text = text.to_owned();
+        assert_eq!(apply(&mut text, cursor, edit), expected_cursor);
+        assert_eq!(text, expected_text);
+    }
+}
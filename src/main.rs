@@ -1,75 +1,1371 @@
-use irename::app::{App, AppResult};
-use irename::cli::parse_args;
+use irename::app::{App, AppResult, RenameRule};
+use irename::cli::{parse_args, Args, Command, SessionAction};
+use irename::config::{self, Config};
+use irename::doctor;
+use irename::filter::{self, ExtFilter, PathFilter};
+use irename::git;
+use irename::inline::InlineBackend;
+use irename::input::BracketedPaste;
+use irename::input_history::{self, InputHistory};
+use irename::journal::{self, Journal};
+use irename::path_utils;
+use irename::plan_cache::{self, PlanCache};
+use irename::plan_file::PlanFile;
+use irename::planner::{self, ConflictPolicy, FileOrder, MovePair};
+use irename::portability::PathLimits;
+use irename::prompt::{self, ConflictAnswer, LinePrompt};
+use irename::protocol::{Request, Response};
+use irename::rename_log::{self, LogEntry, LogResult};
+use irename::scan::{self, DirScan, ScanOptions};
+use irename::session::SessionFile;
+use irename::transfer;
+use irename::transform::{CommandFilter, ScriptTransform, Transliteration, Truncation};
+use irename::trash;
+use irename::tutorial::{Sandbox, Tutorial};
+use irename::watch::DirWatcher;
 
 use std::collections::HashSet;
-use std::path::PathBuf;
-use std::str::FromStr;
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
+use anyhow::Context;
+use chrono::Local;
+use termion::cursor::DetectCursorPos;
+use termion::input::MouseTerminal;
 use termion::raw::IntoRawMode;
 use termion::screen::AlternateScreen;
 
 use tui::{backend::TermionBackend, Terminal};
 
-/// check if all items of an iterator are unique
-fn unique<T>(mut items: T) -> bool
-where
-    T: Iterator,
-    <T as Iterator>::Item: Eq,
-    <T as Iterator>::Item: std::hash::Hash,
-{
-    let mut set: HashSet<T::Item> = HashSet::new();
+/// print all the names a file had and optionally rename it back to one of them
+fn show_log(path: &Path, restore: Option<usize>) -> anyhow::Result<()> {
+    let history_dir = journal::history_dir();
+    let journals = Journal::load_all(&history_dir)?;
+    let timeline = journal::timeline(&journals, &path_utils::normalize_path(path));
 
-    items.all(move |item| set.insert(item))
+    for (idx, entry) in timeline.iter().enumerate() {
+        let timestamp = entry
+            .timestamp
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_else(|| "-".repeat(19));
+        println!("{:>3}  {}  {}", idx, timestamp, entry.path.display());
+    }
+
+    if let Some(idx) = restore {
+        let target = &timeline
+            .get(idx)
+            .ok_or_else(|| anyhow::anyhow!("no name with number {} in the history", idx))?
+            .path;
+        let current = &timeline.last().unwrap().path;
+
+        if target == current {
+            return Ok(());
+        }
+        if target.exists() {
+            anyhow::bail!("{} already exists. Aborting", target.display());
+        }
+
+        std::fs::rename(current, target)?;
+        Journal::new(&[(current.clone(), target.clone())]).save(&history_dir)?;
+        println!("restored {} -> {}", current.display(), target.display());
+    }
+
+    Ok(())
+}
+
+/// list the journals with their numbers, the latest last
+fn show_history() -> anyhow::Result<()> {
+    let journals = Journal::load_all(&journal::history_dir())?;
+    let sessions: HashSet<_> = journals
+        .iter()
+        .filter(|journal| journal.undoes.is_none())
+        .map(|journal| journal.timestamp)
+        .collect();
+    let format = |timestamp: &chrono::DateTime<chrono::Local>| {
+        timestamp.format("%Y-%m-%d %H:%M:%S").to_string()
+    };
+
+    for (idx, (journal, active)) in journals
+        .iter()
+        .zip(journal::in_effect(&journals))
+        .enumerate()
+    {
+        let mut kind = match journal.undoes {
+            None => "session".to_owned(),
+            Some(target) if sessions.contains(&target) => format!("undo of {}", format(&target)),
+            Some(target) => format!("redo of {}", format(&target)),
+        };
+        if !active {
+            kind.push_str(if journal.undoes.is_none() {
+                ", undone"
+            } else {
+                ", redone"
+            });
+        }
+        println!(
+            "{:>3}  {}  {:>4} renames  {}",
+            idx,
+            format(&journal.timestamp),
+            journal.renames.len(),
+            kind
+        );
+    }
+
+    Ok(())
+}
+
+/// revert the renames of the `count` last sessions which aren't undone yet, or with `redo`
+/// the `count` last undos
+fn undo_sessions(count: usize, redo: bool) -> anyhow::Result<()> {
+    let history_dir = journal::history_dir();
+    let journals = Journal::load_all(&history_dir)?;
+    let (candidates, verb) = if redo {
+        (journal::redoable(&journals), "redo")
+    } else {
+        (journal::undoable(&journals), "undo")
+    };
+    if candidates.is_empty() {
+        anyhow::bail!("nothing to {}", verb);
+    }
+
+    for journal in candidates.into_iter().take(count) {
+        // a redo is reported with the session it renames again
+        let timestamp = journal
+            .undoes
+            .filter(|_| redo)
+            .unwrap_or(journal.timestamp)
+            .format("%Y-%m-%d %H:%M:%S");
+        let move_pairs = journal.move_pairs();
+
+        // the files changed since are checked before anything is renamed
+        let problems = journal::revert_problems(&move_pairs);
+        if !problems.is_empty() {
+            eprintln!(
+                "{} renames of {} can't be reverted:",
+                problems.len(),
+                timestamp
+            );
+            for ((src, dst), problem) in &problems {
+                eprintln!("  {} -> {}: {}", dst.display(), src.display(), problem);
+            }
+            anyhow::bail!("the files were changed since. Aborting {}", verb);
+        }
+
+        let rollback = transfer::rollback(&move_pairs);
+        if !rollback.reverted.is_empty() {
+            journal.reverted_by(&rollback.reverted).save(&history_dir)?;
+        }
+
+        println!(
+            "{} of {}: {} renames",
+            verb,
+            timestamp,
+            rollback.reverted.len()
+        );
+        for (src, dst) in &rollback.reverted {
+            println!(
+                "  {} -> {}",
+                path_utils::display_path(src),
+                path_utils::display_path(dst)
+            );
+        }
+        if !rollback.failed.is_empty() {
+            eprintln!("{} renames couldn't be reverted:", rollback.failed.len());
+            for ((src, dst), err) in &rollback.failed {
+                eprintln!("  {} -> {}: {}", dst.display(), src.display(), err);
+            }
+            anyhow::bail!("{} of {} is done partially", verb, timestamp);
+        }
+    }
+
+    Ok(())
+}
+
+/// run the app on the alternate screen, or the line prompt if raw mode isn't available
+fn run_fullscreen(app: &mut App, preview_only: bool) -> std::io::Result<AppResult> {
+    let stdout = match std::io::stdout().into_raw_mode() {
+        Ok(stdout) => stdout,
+        Err(_) => return run_line_prompt(app, preview_only),
+    };
+    // the inline app leaves the mouse to the terminal, so its scrollback stays usable
+    let stdout = MouseTerminal::from(AlternateScreen::from(stdout));
+    let _paste = BracketedPaste::enable()?;
+    let backend = TermionBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    app.run(&mut terminal)
+}
+
+/// ask for the rule line by line and print the preview, for terminals that can't show the app
+fn run_line_prompt(app: &mut App, preview_only: bool) -> std::io::Result<AppResult> {
+    // the preview is printed once, so it needs all the files
+    app.finish_scan();
+    // stdin may have been used for the file list
+    let input = BufReader::new(termion::get_tty()?);
+    let page_size = termion::terminal_size()
+        .map(|(_, rows)| (rows as usize).saturating_sub(1))
+        .unwrap_or(prompt::DEFAULT_PAGE_SIZE);
+
+    LinePrompt::new(input, std::io::stdout(), page_size)
+        .with_preview_only(preview_only)
+        .run(app)
+}
+
+/// the line prompt on terminals which can't show the app and when the output is redirected
+fn is_dumb_terminal() -> bool {
+    prompt::is_dumb_terminal(std::env::var("TERM").ok().as_deref())
+        || !termion::is_tty(&std::io::stdout())
+}
+
+/// paths from stdin, one per line or NUL-separated with `null`.
+/// They are read as bytes, so paths which aren't UTF-8 are kept
+fn read_file_list(null: bool) -> Vec<PathBuf> {
+    path_utils::read_path_list(std::io::stdin().lock(), null)
+}
+
+/// the files matching the glob patterns among `files`, the other files as they are.
+/// Patterns matching nothing are reported
+fn expand_globs(files: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut expanded = Vec::new();
+    for file in files {
+        let pattern = file.to_string_lossy();
+        // a file can be named like a pattern
+        if !filter::is_glob(&pattern) || std::fs::symlink_metadata(file).is_ok() {
+            expanded.push(file.clone());
+            continue;
+        }
+        let matched = scan::expand_glob(&pattern);
+        if matched.is_empty() {
+            eprintln!("no files match {}", pattern);
+        }
+        expanded.extend(matched);
+    }
+
+    // stdin isn't read instead of the patterns
+    if expanded.is_empty() && !files.is_empty() {
+        anyhow::bail!("no files to rename");
+    }
+    Ok(expanded)
+}
+
+/// run the app in `height` lines below the cursor and clear them on exit
+fn run_inline(app: &mut App, height: u16) -> std::io::Result<AppResult> {
+    let mut stdout = std::io::stdout().into_raw_mode()?;
+    let (_, rows) = termion::terminal_size()?;
+    // at least 2 lines, unless the terminal is smaller
+    let height = height.max(2).min(rows).max(1);
+
+    // scroll the screen if there are not enough lines below the cursor
+    write!(stdout, "{}", "\n".repeat(height as usize - 1))?;
+    stdout.flush()?;
+    let (_, cursor_row) = stdout.cursor_pos()?;
+    let top = cursor_row.saturating_sub(height);
+
+    let backend = InlineBackend::new(TermionBackend::new(stdout), top, height);
+    let mut terminal = Terminal::new(backend)?;
+
+    let res = {
+        let _paste = BracketedPaste::enable()?;
+        app.run(&mut terminal)
+    };
+
+    terminal.clear()?;
+    terminal.set_cursor(0, 0)?;
+    terminal.show_cursor()?;
+
+    res
+}
+
+/// show what the tutorial renaming did
+fn print_tutorial_result(sandbox: &Sandbox) -> anyhow::Result<()> {
+    let mut names: Vec<String> = std::fs::read_dir(sandbox.dir())?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+
+    println!("The sample files are renamed:");
+    for name in names {
+        println!("  {}", name);
+    }
+    println!("Run irename with your own files now, e.g. 'irename some/dir/*'");
+
+    Ok(())
+}
+
+/// ask on the terminal what to do with an existing destination
+fn ask_conflict(destination: &Path) -> std::io::Result<ConflictAnswer> {
+    let input = BufReader::new(termion::get_tty()?);
+    LinePrompt::new(input, std::io::stdout(), prompt::DEFAULT_PAGE_SIZE).ask_conflict(destination)
+}
+
+/// ask on the terminal about every rename
+fn confirm_each(move_pairs: Vec<MovePair>) -> std::io::Result<Vec<MovePair>> {
+    let input = BufReader::new(termion::get_tty()?);
+    LinePrompt::new(input, std::io::stdout(), prompt::DEFAULT_PAGE_SIZE).confirm_each(move_pairs)
+}
+
+/// how `move_files` treats the destinations which already exist
+#[derive(Default)]
+struct MoveOptions {
+    /// existing files confirmed by the user, they are replaced
+    overwrites: HashSet<PathBuf>,
+    /// what to do with the other existing files
+    on_conflict: ConflictPolicy,
+    /// replaced files are kept under their names with this suffix
+    backup: Option<String>,
+    /// replaced files are moved to the trash
+    use_trash: bool,
+    /// rename with `git mv`
+    git: bool,
+    /// create missing destination directories
+    mkdir: bool,
+    /// only print the commands
+    dry_run: bool,
+    /// every executed step is appended to this file
+    log_file: Option<PathBuf>,
+}
+
+/// what `move_files` did
+struct MoveOutcome {
+    /// executed renames including the backups, without the rolled back ones
+    applied: Vec<MovePair>,
+    /// renames not executed because their destinations exist
+    skipped: Vec<MovePair>,
+    /// renames reverted after the failure
+    rollback: transfer::Rollback,
+    /// files which have their new names in the end
+    renamed: usize,
+    /// the failed rename with the reason
+    failed: Vec<(MovePair, String)>,
+    result: std::io::Result<()>,
+}
+
+/// rename the files. If one of them fails, the already executed renames are reverted,
+/// so the files end up as they were
+fn move_files(move_pairs: Vec<MovePair>, options: &MoveOptions) -> MoveOutcome {
+    let planned = move_pairs.len();
+    let mut created_dirs = Vec::new();
+    let mut log = Vec::new();
+    let (mut applied, skipped, result) =
+        execute_renames(move_pairs, options, &mut created_dirs, &mut log);
+    let mut rollback = transfer::Rollback::default();
+    if result.is_err() {
+        rollback = if options.git {
+            // backups and trashed files aren't tracked, they are moved back without git
+            transfer::rollback_with(&applied, |src, dst| {
+                git::mv(src, dst, false)
+                    .or_else(|_| transfer::rename_or_move(src, dst, |_, _| {}).map(|_| ()))
+            })
+        } else {
+            transfer::rollback(&applied)
+        };
+        for (dst, src) in &rollback.reverted {
+            log.push(LogEntry::new(src, dst, LogResult::RolledBack));
+        }
+        for ((src, dst), err) in &rollback.failed {
+            log.push(LogEntry {
+                error: Some(format!("can't roll back: {}", err)),
+                ..LogEntry::new(src, dst, LogResult::Renamed)
+            });
+        }
+        // directories still containing files which couldn't be moved back stay
+        for dir in created_dirs.iter().rev() {
+            let _ = std::fs::remove_dir(dir);
+        }
+        // the renames which couldn't be reverted stay in the history
+        applied = rollback
+            .failed
+            .iter()
+            .map(|(pair, _)| pair.clone())
+            .collect();
+    }
+
+    if let Some(path) = options.log_file.as_deref().filter(|_| !log.is_empty()) {
+        // the renaming is done anyway, a failed log doesn't change its outcome
+        if let Err(err) = rename_log::append(path, &log) {
+            eprintln!("{:#}", err);
+        }
+    }
+
+    let renamed = match result {
+        Ok(()) => planned - skipped.len(),
+        Err(_) => rollback.failed.len(),
+    };
+    let failed = log
+        .iter()
+        .filter(|entry| entry.result == LogResult::Failed)
+        .map(|entry| {
+            (
+                (entry.rename.src.clone(), entry.rename.dst.clone()),
+                entry.error.clone().unwrap_or_default(),
+            )
+        })
+        .collect();
+
+    MoveOutcome {
+        applied,
+        skipped,
+        rollback,
+        renamed,
+        failed,
+        result,
+    }
+}
+
+/// rename the files until the first failure. Returns the executed renames including
+/// the backups, the skipped renames and the failure. Directories made for the destinations
+/// are added to `created_dirs`
+fn execute_renames(
+    move_pairs: Vec<MovePair>,
+    options: &MoveOptions,
+    created_dirs: &mut Vec<PathBuf>,
+    log: &mut Vec<LogEntry>,
+) -> (Vec<MovePair>, Vec<MovePair>, std::io::Result<()>) {
+    let mut applied = Vec::new();
+    let mut skipped = Vec::new();
+    // answer given for all the following conflicts
+    let mut answer_for_all = None;
+    // swapped names go through temporary ones, so the steps are recorded as they are
+    for (src, dst) in planner::schedule_renames(move_pairs) {
+        let replaces = planner::is_overwritten(&src, &dst);
+        let missing_dirs = match dst.parent() {
+            Some(dir) if options.mkdir => planner::missing_dirs(dir),
+            _ => Vec::new(),
+        };
+        if options.dry_run {
+            // nothing is created, the same directories would be printed again
+            let new_dirs: Vec<&PathBuf> = missing_dirs
+                .iter()
+                .filter(|dir| !created_dirs.contains(dir))
+                .collect();
+            if let Some(dir) = new_dirs.last() {
+                println!("mkdir -p {}", path_utils::display_path(dir));
+            }
+            created_dirs.extend(new_dirs.into_iter().cloned());
+            if let Some(suffix) = options.backup.as_deref().filter(|_| replaces) {
+                let backup = planner::backup_path(&dst, suffix);
+                println!(
+                    "mv {} {}",
+                    path_utils::display_path(&dst),
+                    path_utils::display_path(&backup)
+                );
+            }
+            if options.use_trash && replaces {
+                println!("trash {}", path_utils::display_path(&dst));
+            }
+            println!(
+                "{} {} {}",
+                if options.git { "git mv" } else { "mv" },
+                path_utils::display_path(&src),
+                path_utils::display_path(&dst)
+            );
+            continue;
+        }
+
+        // the file could appear after the preview, `rename` would silently replace it
+        if replaces && !options.overwrites.contains(&dst) {
+            let answer = match options.on_conflict {
+                ConflictPolicy::Skip => ConflictAnswer::Skip,
+                ConflictPolicy::Overwrite => ConflictAnswer::Overwrite,
+                ConflictPolicy::Prompt => match answer_for_all {
+                    Some(answer) => answer,
+                    None => match ask_conflict(&dst) {
+                        Ok(answer) => answer,
+                        Err(err) => {
+                            log.push(LogEntry::failed(&src, &dst, &err));
+                            return (applied, skipped, Err(err));
+                        }
+                    },
+                },
+                ConflictPolicy::Abort | ConflictPolicy::Suffix => ConflictAnswer::Abort,
+            };
+
+            match answer {
+                ConflictAnswer::Overwrite => {}
+                ConflictAnswer::OverwriteAll => answer_for_all = Some(ConflictAnswer::Overwrite),
+                ConflictAnswer::Skip | ConflictAnswer::SkipAll => {
+                    if answer == ConflictAnswer::SkipAll {
+                        answer_for_all = Some(ConflictAnswer::Skip);
+                    }
+                    log.push(LogEntry::new(&src, &dst, LogResult::Skipped));
+                    skipped.push((src, dst));
+                    continue;
+                }
+                ConflictAnswer::Abort => {
+                    let err = std::io::Error::new(
+                        std::io::ErrorKind::AlreadyExists,
+                        format!("{} already exists. Aborting", dst.display()),
+                    );
+                    log.push(LogEntry::failed(&src, &dst, &err));
+                    return (applied, skipped, Err(err));
+                }
+            }
+        }
+
+        for dir in missing_dirs {
+            if let Err(err) = std::fs::create_dir(&dir) {
+                log.push(LogEntry::failed(&src, &dst, &err));
+                return (applied, skipped, Err(err));
+            }
+            created_dirs.push(dir);
+        }
+
+        // the backup is a separate step of the journal, so undoing restores the replaced file
+        if let Some(suffix) = options.backup.as_deref().filter(|_| replaces) {
+            let backup = planner::backup_path(&dst, suffix);
+            if let Err(err) = std::fs::rename(&dst, &backup) {
+                log.push(LogEntry::failed(&dst, &backup, &err));
+                return (applied, skipped, Err(err));
+            }
+            log.push(LogEntry::new(&dst, &backup, LogResult::Renamed));
+            applied.push((dst.clone(), backup));
+        }
+        if options.use_trash && replaces {
+            match trash::move_to_trash(&dst) {
+                Ok(Some(trashed)) => {
+                    log.push(LogEntry::new(&dst, &trashed, LogResult::Renamed));
+                    applied.push((dst.clone(), trashed));
+                }
+                // the trashes of macOS and Windows are restored by the file manager only
+                Ok(None) => {}
+                Err(err) => {
+                    log.push(LogEntry::failed(&src, &dst, &err));
+                    return (applied, skipped, Err(err));
+                }
+            }
+        }
+        let res = if options.git {
+            git::mv(&src, &dst, replaces).map(|()| None)
+        } else {
+            transfer::rename_or_move(&src, &dst, |copied, total| {
+                report_copy_progress(&dst, copied, total)
+            })
+        };
+        match res {
+            Ok(None) => log.push(LogEntry::new(&src, &dst, LogResult::Renamed)),
+            // the copy is complete, the pair stays in the history with the leftover noted
+            Ok(Some(err)) => {
+                eprintln!(
+                    "moved {} to {}, but the original couldn't be removed completely: {}",
+                    src.display(),
+                    dst.display(),
+                    err
+                );
+                log.push(LogEntry {
+                    error: Some(format!("original not removed completely: {}", err)),
+                    ..LogEntry::new(&src, &dst, LogResult::Renamed)
+                });
+            }
+            Err(err) => {
+                log.push(LogEntry::failed(&src, &dst, &err));
+                return (applied, skipped, Err(err));
+            }
+        }
+        applied.push((src, dst));
+    }
+
+    (applied, skipped, Ok(()))
+}
+
+/// check all the renames before executing any. If some would fail, they are listed
+/// and the user is asked whether to execute the other ones. A dry run lists them
+/// as comments of the printed commands and shows only the other ones
+fn preflight(
+    move_pairs: Vec<MovePair>,
+    mkdir: bool,
+    dry_run: bool,
+) -> anyhow::Result<Vec<MovePair>> {
+    let failures = planner::preflight(&move_pairs, mkdir);
+    if dry_run {
+        println!("# path length limits: {}", PathLimits::native());
+        for ((src, _), reason) in &failures {
+            println!("# would fail: {}: {}", src.display(), reason);
+        }
+    }
+    if failures.is_empty() {
+        return Ok(move_pairs);
+    }
+
+    let failed: HashSet<&PathBuf> = failures.iter().map(|((src, _), _)| src).collect();
+    let valid: Vec<MovePair> = move_pairs
+        .iter()
+        .filter(|(src, _)| !failed.contains(src))
+        .cloned()
+        .collect();
+    if dry_run {
+        return Ok(valid);
+    }
+    let input = BufReader::new(termion::get_tty()?);
+    let confirmed = LinePrompt::new(input, std::io::stdout(), prompt::DEFAULT_PAGE_SIZE)
+        .confirm_valid(&failures, valid.len())?;
+    if !confirmed {
+        anyhow::bail!("{} renames would fail. Nothing is renamed", failures.len());
+    }
+
+    Ok(valid)
+}
+
+/// show how much of a file moved to another file system is copied
+fn report_copy_progress(dst: &Path, copied: u64, total: u64) {
+    let percent = (copied * 100).checked_div(total).unwrap_or(100);
+    eprint!("\rcopying to {}: {}%", dst.display(), percent);
+    if copied == total {
+        eprintln!();
+    }
+}
+
+/// list the files which kept their names because their destinations exist
+fn print_skipped(skipped: &[MovePair]) {
+    if skipped.is_empty() {
+        return;
+    }
+
+    eprintln!(
+        "skipped {} files, their new names are taken:",
+        skipped.len()
+    );
+    for (src, dst) in skipped {
+        eprintln!("  {} -> {}", src.display(), dst.display());
+    }
+}
+
+/// print what the renaming did and how to revert it. The applied renames are expected
+/// in the journal already. A failure is reported here, so the returned error only sets
+/// the exit code
+fn print_summary(outcome: &MoveOutcome) -> anyhow::Result<()> {
+    println!(
+        "renamed {} files, skipped {}, failed {}",
+        outcome.renamed,
+        outcome.skipped.len(),
+        outcome.failed.len()
+    );
+    for ((src, dst), reason) in &outcome.failed {
+        println!("  {} -> {}: {}", src.display(), dst.display(), reason);
+    }
+    if !outcome.applied.is_empty() {
+        println!("undo with: irename undo");
+    }
+
+    if outcome.result.is_err() {
+        anyhow::bail!("renaming failed");
+    }
+    Ok(())
+}
+
+/// list the renames reverted after a failure and the ones which stay
+fn print_rollback(rollback: &transfer::Rollback) {
+    if !rollback.reverted.is_empty() {
+        eprintln!("rolled back {} renames:", rollback.reverted.len());
+        for (src, dst) in &rollback.reverted {
+            eprintln!("  {} -> {}", src.display(), dst.display());
+        }
+    }
+    if !rollback.failed.is_empty() {
+        eprintln!("{} renames couldn't be rolled back:", rollback.failed.len());
+        for ((src, dst), err) in &rollback.failed {
+            eprintln!("  {} -> {}: {}", src.display(), dst.display(), err);
+        }
+    }
+}
+
+/// record the executed renames in the history
+fn save_journal(applied: &[MovePair], undo_script: bool) -> anyhow::Result<()> {
+    if let Some(script) = write_journal(applied, undo_script)? {
+        println!("undo script: {}", script.display());
+    }
+
+    Ok(())
+}
+
+/// `save_journal` returning the path of the undo script instead of printing it
+fn write_journal(applied: &[MovePair], undo_script: bool) -> anyhow::Result<Option<PathBuf>> {
+    if applied.is_empty() {
+        return Ok(None);
+    }
+
+    let journal = Journal::new(applied);
+    journal.save(&journal::history_dir())?;
+    if !undo_script {
+        return Ok(None);
+    }
+    Ok(Some(journal.save_undo_script(&journal::history_dir())?))
+}
+
+/// the plan if none of its renames would fail, there is nobody to ask about executing the rest
+fn checked_plan(app: &App, mkdir: bool) -> Result<Vec<MovePair>, String> {
+    let move_pairs = app.plan()?;
+    let failures = planner::preflight(&move_pairs, mkdir);
+    match failures.first() {
+        Some(((src, _), reason)) => Err(format!(
+            "{} renames would fail, e.g. {}: {}",
+            failures.len(),
+            src.display(),
+            reason
+        )),
+        None => Ok(move_pairs),
+    }
+}
+
+/// how the renames are executed with the options of the command line. The existing
+/// destinations were shown and confirmed, unless they are skipped anyway
+fn move_options(args: &Args, move_pairs: &[MovePair], dry_run: bool) -> MoveOptions {
+    let overwrites = match args.on_conflict {
+        ConflictPolicy::Skip => HashSet::new(),
+        _ => planner::existing_destinations(move_pairs),
+    };
+    MoveOptions {
+        overwrites,
+        on_conflict: args.on_conflict,
+        backup: args.backup.clone(),
+        use_trash: args.use_trash,
+        git: args.git,
+        mkdir: args.mkdir,
+        dry_run,
+        log_file: args.log_file.clone(),
+    }
+}
+
+/// a plan above the `--confirm-*` thresholds is executed only after the number of its files
+/// is typed on the terminal, or with `--yes`. Without `interactive` there is nobody to type it
+fn check_thresholds(
+    move_pairs: &[MovePair],
+    on_conflict: ConflictPolicy,
+    args: &Args,
+    interactive: bool,
+) -> anyhow::Result<()> {
+    if args.yes || args.dry_run {
+        return Ok(());
+    }
+    let Some(stats) = args.confirm_thresholds().check(move_pairs, on_conflict) else {
+        return Ok(());
+    };
+    if !interactive {
+        anyhow::bail!(
+            "{} renames with {} overwrites and {} moves to other directories exceed the --confirm-* limits, pass --yes to execute them",
+            stats.files,
+            stats.overwrites,
+            stats.moves
+        );
+    }
+
+    let input = BufReader::new(termion::get_tty()?);
+    let confirmed = LinePrompt::new(input, std::io::stdout(), prompt::DEFAULT_PAGE_SIZE)
+        .confirm_count(&stats)?;
+    if !confirmed {
+        anyhow::bail!("the number of files isn't confirmed. Nothing is renamed");
+    }
+    Ok(())
+}
+
+/// read JSON requests from stdin line by line and answer each with a JSON line on stdout
+fn run_stdio_protocol(app: &mut App, args: &Args) -> anyhow::Result<()> {
+    app.finish_scan();
+    let mut stdout = std::io::stdout();
+    // stdin is taken by the requests
+    let on_conflict = match args.on_conflict {
+        ConflictPolicy::Prompt => ConflictPolicy::Abort,
+        on_conflict => on_conflict,
+    };
+
+    for line in std::io::stdin().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match Request::parse(&line) {
+            Ok(Request::SetRegex { value }) => {
+                app.set_regex(value);
+                Response::ok()
+            }
+            Ok(Request::SetReplace { value }) => {
+                app.set_replacement(value);
+                Response::ok()
+            }
+            Ok(Request::Preview) => match app.plan() {
+                Ok(move_pairs) => Response::plan(&move_pairs),
+                Err(err) => Response::error(err),
+            },
+            Ok(Request::Apply) if args.preview_only => {
+                Response::error("renaming is disabled by --preview-only")
+            }
+            Ok(Request::Apply) => match checked_plan(app, args.mkdir) {
+                Ok(move_pairs)
+                    if !planner::conflicting_destinations(&move_pairs, app.source_files())
+                        .is_empty() =>
+                {
+                    Response::error("destination files are not unique or are kept input files")
+                }
+                // there is nobody to confirm overwriting existing files
+                Ok(move_pairs) => match planner::existing_destinations(&move_pairs).iter().min() {
+                    Some(existing)
+                        if !matches!(
+                            on_conflict,
+                            ConflictPolicy::Skip | ConflictPolicy::Overwrite
+                        ) =>
+                    {
+                        Response::error(format!("{} already exists", existing.display()))
+                    }
+                    _ if args.dry_run => Response::plan(&move_pairs),
+                    _ => match check_thresholds(&move_pairs, on_conflict, args, false) {
+                        Err(err) => Response::error(err.to_string()),
+                        Ok(()) => {
+                            let options = MoveOptions {
+                                on_conflict,
+                                backup: args.backup.clone(),
+                                use_trash: args.use_trash,
+                                git: args.git,
+                                mkdir: args.mkdir,
+                                log_file: args.log_file.clone(),
+                                ..MoveOptions::default()
+                            };
+                            let outcome = move_files(move_pairs, &options);
+                            app.rename_sources(&outcome.applied);
+                            // stdout is for the responses only
+                            let script = write_journal(&outcome.applied, args.undo_script)?;
+                            let response = match outcome.result {
+                                Ok(()) => Response::renames(&outcome.applied),
+                                Err(err) => Response {
+                                    error: Some(err.to_string()),
+                                    ok: false,
+                                    ..Response::renames(&outcome.applied)
+                                },
+                            };
+                            response
+                                .with_skipped(&outcome.skipped)
+                                .with_rolled_back(&outcome.rollback.reverted)
+                                .with_undo_script(script)
+                        }
+                    },
+                },
+                Err(err) => Response::error(err),
+            },
+            Err(err) => Response::error(err),
+        };
+
+        writeln!(stdout, "{}", response.to_json())?;
+        stdout.flush()?;
+    }
+
+    Ok(())
+}
+
+/// how often the watched directory is listed
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// rename the new files of `dir` with the rules of the app until the process is interrupted
+fn run_watch(app: &mut App, dir: &Path, args: &Args) -> anyhow::Result<()> {
+    app.finish_scan();
+    let mut watcher =
+        DirWatcher::new(dir).with_context(|| format!("can't watch {}", dir.display()))?;
+    // there is nobody to confirm overwriting existing files
+    let on_conflict = match args.on_conflict {
+        ConflictPolicy::Prompt => ConflictPolicy::Abort,
+        on_conflict => on_conflict,
+    };
+    if watcher.uses_events() {
+        println!("watching {}, press Ctrl-C to stop", dir.display());
+    } else {
+        println!(
+            "watching {} by listing it every {}ms, press Ctrl-C to stop",
+            dir.display(),
+            WATCH_INTERVAL.as_millis()
+        );
+    }
+
+    loop {
+        watcher.wait(WATCH_INTERVAL);
+        let files = watcher
+            .poll()
+            .with_context(|| format!("can't read {}", dir.display()))?;
+        if files.is_empty() {
+            continue;
+        }
+        app.replace_files(files.clone());
+
+        let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
+        let move_pairs = match checked_plan(app, args.mkdir) {
+            Ok(move_pairs) => move_pairs,
+            Err(err) => {
+                println!("{} not renamed: {}", timestamp, err);
+                watcher.retry(files);
+                continue;
+            }
+        };
+        if !planner::conflicting_destinations(&move_pairs, app.source_files()).is_empty() {
+            println!(
+                "{} not renamed: destination files are not unique or are kept input files",
+                timestamp
+            );
+            watcher.retry(files);
+            continue;
+        }
+        let existing = planner::existing_destinations(&move_pairs);
+        if let Some(existing) = existing.iter().min() {
+            if !matches!(
+                on_conflict,
+                ConflictPolicy::Skip | ConflictPolicy::Overwrite
+            ) {
+                println!(
+                    "{} not renamed: {} already exists",
+                    timestamp,
+                    existing.display()
+                );
+                watcher.retry(files);
+                continue;
+            }
+        }
+
+        if args.dry_run {
+            for (src, dst) in &move_pairs {
+                println!(
+                    "{} would rename {} -> {}",
+                    timestamp,
+                    src.display(),
+                    dst.display()
+                );
+            }
+            continue;
+        }
+        if let Err(err) = check_thresholds(&move_pairs, on_conflict, args, false) {
+            println!("{} not renamed: {}", timestamp, err);
+            watcher.retry(files);
+            continue;
+        }
+        let options = MoveOptions {
+            on_conflict,
+            backup: args.backup.clone(),
+            use_trash: args.use_trash,
+            git: args.git,
+            mkdir: args.mkdir,
+            log_file: args.log_file.clone(),
+            ..MoveOptions::default()
+        };
+        let sources: Vec<PathBuf> = move_pairs.iter().map(|(src, _)| src.clone()).collect();
+        let outcome = move_files(move_pairs, &options);
+        for (src, dst) in &outcome.applied {
+            println!(
+                "{} renamed {} -> {}",
+                timestamp,
+                src.display(),
+                dst.display()
+            );
+        }
+        for ((src, dst), reason) in &outcome.failed {
+            println!(
+                "{} failed {} -> {}: {}",
+                timestamp,
+                src.display(),
+                dst.display(),
+                reason
+            );
+        }
+        print_skipped(&outcome.skipped);
+        print_rollback(&outcome.rollback);
+        // skipped, failed and rolled back renames are tried again when the directory changes
+        let applied: HashSet<&PathBuf> = outcome.applied.iter().map(|(src, _)| src).collect();
+        watcher.retry(sources.into_iter().filter(|src| !applied.contains(src)));
+        watcher.mark_known(outcome.applied.iter().map(|(_, dst)| dst.clone()));
+        save_journal(&outcome.applied, args.undo_script)?;
+    }
+}
+
+/// write the capture groups of the final regex if `--export-captures` is given
+fn export_captures(app: &App, args: &Args) -> anyhow::Result<()> {
+    if let Some(path) = &args.export_captures {
+        app.capture_table()
+            .map_err(|err| anyhow::anyhow!("can't export captures: {}", err))?
+            .save(path)?;
+    }
+
+    Ok(())
+}
+
+/// save the rules edited for the files into a session file, or rename the files of one
+fn run_session(action: &SessionAction, args: &Args) -> anyhow::Result<()> {
+    let (mut app, dry_run) = match action {
+        SessionAction::Export { files, .. } => {
+            let files = if files.is_empty() {
+                read_file_list(false)
+            } else {
+                files.clone()
+            };
+            let app = App::default()
+                .with_file_order(FileOrder::Natural)
+                .with_files(files);
+            (app, false)
+        }
+        SessionAction::Import {
+            file,
+            remaps,
+            dry_run,
+        } => {
+            let mut session = SessionFile::load(file)?;
+            session.remap(remaps);
+            let app = App::default()
+                .with_session(session)
+                .with_on_conflict(args.on_conflict)
+                .with_use_trash(args.use_trash)
+                .with_mkdir(args.mkdir)
+                .with_confirm_thresholds(args.confirm_thresholds());
+            (app, *dry_run)
+        }
+    };
+
+    let res = if is_dumb_terminal() {
+        run_line_prompt(&mut app, false)?
+    } else {
+        run_fullscreen(&mut app, false)?
+    };
+    let AppResult::MoveFiles(move_pairs) = res else {
+        return Ok(());
+    };
+
+    match action {
+        SessionAction::Export { file, .. } => {
+            app.export_session().save(file)?;
+            println!(
+                "session of {} renames saved to {}",
+                move_pairs.len(),
+                file.display()
+            );
+        }
+        SessionAction::Import { .. } => {
+            if !planner::conflicting_destinations(&move_pairs, app.source_files()).is_empty() {
+                anyhow::bail!("destination files are not unique or are kept input files. Aborting")
+            }
+
+            let move_pairs = preflight(move_pairs, args.mkdir, dry_run)?;
+            let options = move_options(args, &move_pairs, dry_run);
+            let outcome = move_files(move_pairs, &options);
+            save_journal(&outcome.applied, args.undo_script)?;
+            print_skipped(&outcome.skipped);
+            print_rollback(&outcome.rollback);
+            if dry_run {
+                outcome.result?;
+            } else {
+                print_summary(&outcome)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// check the renames of a plan saved with `--plan-out` against the files and execute them
+fn apply_plan(path: &Path, args: &Args) -> anyhow::Result<()> {
+    let plan = PlanFile::load(path)?;
+    let move_pairs = plan.move_pairs();
+
+    let mut problems = plan.problems();
+    problems.extend(planner::preflight(&move_pairs, args.mkdir));
+    if !problems.is_empty() {
+        eprintln!("{} renames of the plan can't be applied:", problems.len());
+        for ((src, _), reason) in &problems {
+            eprintln!("  {}: {}", src.display(), reason);
+        }
+        anyhow::bail!("the files changed since the plan was saved. Nothing is renamed");
+    }
+
+    check_thresholds(
+        &move_pairs,
+        args.on_conflict,
+        args,
+        termion::is_tty(&std::io::stdin()),
+    )?;
+
+    // every existing destination is one the plan replaces
+    let options = move_options(args, &move_pairs, args.dry_run);
+    let outcome = move_files(move_pairs, &options);
+    save_journal(&outcome.applied, args.undo_script)?;
+    print_skipped(&outcome.skipped);
+    print_rollback(&outcome.rollback);
+    if args.dry_run {
+        outcome.result?;
+    } else {
+        print_summary(&outcome)?;
+    }
+
+    Ok(())
+}
+
+/// print the environment diagnostics
+fn run_doctor(script: Option<&Path>) -> anyhow::Result<()> {
+    let checks = doctor::run_checks(script);
+    for check in &checks {
+        println!("{}", check);
+    }
+
+    let failed = checks
+        .iter()
+        .filter(|check| check.status == doctor::Status::Error)
+        .count();
+    if failed > 0 {
+        anyhow::bail!("{} checks failed", failed);
+    }
+
+    Ok(())
 }
 
 fn main() -> anyhow::Result<()> {
-    let args = parse_args();
+    let mut args = parse_args();
+
+    match &args.command {
+        Some(Command::Log { path, restore }) => return show_log(path, *restore),
+        Some(Command::History) => return show_history(),
+        Some(Command::Undo { count }) => return undo_sessions(*count, false),
+        Some(Command::Redo { count }) => return undo_sessions(*count, true),
+        Some(Command::Doctor { script }) => return run_doctor(script.as_deref()),
+        Some(Command::Session { action }) => return run_session(action, &args),
+        None => {}
+    }
+
+    if let Some(path) = &args.apply_plan {
+        return apply_plan(path, &args);
+    }
+
+    // shells of Windows pass the patterns as they are
+    if args.glob || cfg!(windows) {
+        args.files = expand_globs(&args.files)?;
+    }
+
+    let config_path = config::config_path();
+    let config = Config::load(&config_path)?;
+    let initial_rule = match (&args.preset, args.mode) {
+        (Some(name), Some(mode)) => config.preset(name)?.with_mode(mode),
+        (Some(name), None) => config.preset(name)?,
+        (None, _) => args.initial_rule(),
+    };
+
+    let sandbox = args.tutorial.then(Sandbox::create).transpose()?;
+
+    let stdin_path = Path::new("-");
+    let sed_rules = match &args.sed_file {
+        Some(path) if path == stdin_path => {
+            if args.files.is_empty() || args.stdio_protocol {
+                anyhow::bail!("stdin can't be read for both the files and the sed script");
+            }
+            RenameRule::parse_script(&std::io::read_to_string(std::io::stdin())?)
+                .map_err(|err| anyhow::anyhow!("invalid sed script: {}", err))?
+        }
+        Some(path) => RenameRule::parse_script(
+            &std::fs::read_to_string(path)
+                .with_context(|| format!("can't read {}", path.display()))?,
+        )
+        .map_err(|err| anyhow::anyhow!("invalid sed script {}: {}", path.display(), err))?,
+        None => vec![],
+    };
+
+    // the directories are walked while the app is running
+    let scan = (args.recursive && sandbox.is_none()).then(|| {
+        let roots = if args.files.is_empty() {
+            vec![PathBuf::from(".")]
+        } else {
+            args.files.clone()
+        };
+        let options = ScanOptions {
+            max_depth: args.max_depth,
+            ignore_files: !args.no_ignore,
+            types: args.types.clone(),
+            metadata: args.metadata_filter(),
+        };
+        DirScan::start(roots, options)
+    });
 
-    let files = {
-        if !args.files.is_empty() {
+    // piped paths are added while the app runs, slow producers don't delay it.
+    // The line prompt is shown once all the paths are read
+    let stream_stdin = sandbox.is_none()
+        && scan.is_none()
+        && args.files.is_empty()
+        && !args.git_files
+        && !args.stdio_protocol
+        && args.watch.is_none()
+        && !termion::is_tty(&std::io::stdin());
+
+    let mut files = {
+        if let Some(sandbox) = &sandbox {
+            sandbox.files()
+        } else if scan.is_some() || stream_stdin {
+            Vec::new()
+        } else if args.git_files {
+            git::ls_files(Path::new(""))?
+        } else if !args.files.is_empty() || args.stdio_protocol || args.watch.is_some() {
+            // stdin is used by the protocol, the watched files are added later
             args.files.clone()
         } else {
             // if there are no files provided - read paths from stdin
-            std::io::stdin()
-                .lines()
-                .filter_map(|l| l.ok())
-                .filter_map(|s| PathBuf::from_str(&s).ok())
-                .collect()
+            read_file_list(args.null)
         }
     };
 
-    let stdout = std::io::stdout().into_raw_mode()?;
-    let stdout = AlternateScreen::from(stdout);
-    let backend = TermionBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    // the kinds and metadata of the given files are checked in the background too
+    let scan = scan.or_else(|| {
+        let options = ScanOptions {
+            types: args.types.clone(),
+            metadata: args.metadata_filter(),
+            ..ScanOptions::default()
+        };
+        if stream_stdin {
+            return Some(DirScan::read_stdin(args.null, options));
+        }
+        (!(options.types.is_empty() && options.metadata.is_empty()) && sandbox.is_none())
+            .then(|| DirScan::check(std::mem::take(&mut files), options))
+    });
+
+    let script = args
+        .script
+        .as_deref()
+        .map(ScriptTransform::load)
+        .transpose()?;
 
     // run the app
     let mut app = App::default()
         .with_files(files)
-        .with_regex(args.regex.unwrap_or_default())
-        .with_replacement(args.replace.unwrap_or_default());
+        .with_path_filter(PathFilter::new(&args.include, &args.exclude))
+        .with_file_types(args.types.clone())
+        .with_file_order(args.file_order)
+        .with_symlink_policy(args.symlinks)
+        .with_rules(sed_rules.into_iter().chain(args.rules.clone()).collect())
+        .with_rule(initial_rule)
+        .with_counter_start(args.counter_start)
+        .with_continue_numbering(args.continue_numbering)
+        .with_counter_per_dir(args.counter_per_dir)
+        .with_compound_ext(args.compound_ext)
+        .with_list_order(args.list_order)
+        .with_case_style(args.case_style)
+        .with_on_conflict(args.on_conflict)
+        .with_use_trash(args.use_trash)
+        .with_mkdir(args.mkdir)
+        .with_strict_portability(args.strict_portability)
+        .with_truncation(
+            args.max_name_bytes
+                .map(|max_bytes| Truncation { max_bytes }),
+        )
+        .with_ext_filter(ExtFilter::new(&args.ext, &args.not_ext))
+        .with_metadata_jobs(args.metadata_jobs)
+        .with_metadata_budget(Some(Duration::from_millis(args.metadata_budget)))
+        .with_numbering_order(args.numbering)
+        .with_engine(args.engine)
+        .with_match_path(args.match_path)
+        .with_renumber(args.renumber)
+        .with_renumber_width(args.renumber_width)
+        .with_preview_only(args.preview_only)
+        .with_plan_out(args.plan_out.clone())
+        .with_inline(args.inline)
+        .with_confirm_thresholds(args.confirm_thresholds())
+        .with_sanitize(args.sanitize)
+        .with_sanitizer(args.sanitizer())
+        .with_normalization(args.normalize)
+        .with_config(config, config_path);
+    if args.drop_missing {
+        app = app.with_missing_dropped();
+    }
+    if let Some(scan) = scan {
+        app = app.with_scan(scan);
+    }
+    if sandbox.is_some() {
+        app = app.with_tutorial(Tutorial::default());
+    }
+    if let Some(script) = script {
+        app = app.with_transform(Box::new(script));
+    }
+    if let Some(command) = &args.filter_cmd {
+        app = app.with_transform(Box::new(CommandFilter::new(
+            command.clone(),
+            Duration::from_millis(args.filter_timeout),
+            args.filter_jobs,
+        )));
+    }
+    if !args.no_plan_cache && sandbox.is_none() {
+        app = app.with_plan_cache(PlanCache::new(plan_cache::cache_dir()));
+    }
+    if sandbox.is_none() {
+        let path = input_history::history_path();
+        app = app.with_input_history(InputHistory::load(&path)?, path);
+    }
+    if args.transliterate {
+        app = app.with_transform(Box::new(Transliteration));
+    }
 
-    let res = app.run(&mut terminal);
-    drop(terminal); // restore terminal state
+    if args.stdio_protocol {
+        run_stdio_protocol(&mut app, &args)?;
+        return export_captures(&app, &args);
+    }
+    if let Some(dir) = &args.watch {
+        return run_watch(&mut app, dir, &args);
+    }
+
+    let res = if is_dumb_terminal() {
+        run_line_prompt(&mut app, args.preview_only)
+    } else if args.inline {
+        run_inline(&mut app, args.height)
+    } else {
+        run_fullscreen(&mut app, args.preview_only)
+    };
+
+    if res.is_ok() {
+        // also when the app is exited without renaming
+        export_captures(&app, &args)?;
+    }
 
     match res {
         Ok(result) => match result {
             AppResult::MoveFiles(move_pairs) => {
-                if !unique(move_pairs.iter().map(|pair| &pair.1)) {
-                    anyhow::bail!("destination files are not unique. Aborting")
+                if let Err(err) = app.remember_inputs() {
+                    eprintln!("{:#}", err);
                 }
 
-                for (src, dst) in move_pairs {
-                    let command = format!("mv {} {}", src.to_str().unwrap(), dst.to_str().unwrap());
+                if let Some(path) = &args.plan_out {
+                    app.plan_file().save(path)?;
+                    println!(
+                        "plan of {} renames saved to {}",
+                        move_pairs.len(),
+                        path.display()
+                    );
+                    return Ok(());
+                }
 
-                    if args.dry_run {
-                        println!("{}", command);
-                    } else {
-                        std::fs::rename(src, dst)?;
-                    }
+                // the pairs are the previewed ones, including the generated token values
+                let move_pairs = if args.confirm_each {
+                    // the declined sources stay in place, so they are checked as kept input files
+                    confirm_each(move_pairs)?
+                } else {
+                    move_pairs
+                };
+                if !planner::conflicting_destinations(&move_pairs, app.source_files()).is_empty() {
+                    anyhow::bail!(
+                        "destination files are not unique or are kept input files. Aborting"
+                    )
+                }
+
+                let move_pairs = preflight(move_pairs, args.mkdir, args.dry_run)?;
+                let options = move_options(&args, &move_pairs, args.dry_run);
+                let outcome = move_files(move_pairs, &options);
+                print_skipped(&outcome.skipped);
+                print_rollback(&outcome.rollback);
+
+                if let Some(sandbox) = &sandbox {
+                    // sample files aren't worth a journal
+                    outcome.result?;
+                    print_tutorial_result(sandbox)?;
+                    return Ok(());
+                }
+
+                save_journal(&outcome.applied, args.undo_script)?;
+                if args.dry_run {
+                    outcome.result?;
+                } else {
+                    print_summary(&outcome)?;
+                }
+            }
+            AppResult::Exit => {
+                // the renamed files wouldn't match the saved plan anyway
+                if let Err(err) = app.save_plan() {
+                    eprintln!("{:?}", err)
                 }
             }
-            AppResult::Exit => {}
         },
         Err(err) => {
             eprintln!("{:?}", err)
@@ -82,15 +1378,28 @@ fn main() -> anyhow::Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rstest::rstest;
-
-    #[rstest]
-    #[case(vec![], true)]
-    #[case(vec!["a"], true)]
-    #[case(vec!["a", "b", "c", "d"], true)]
-    #[case(vec!["a", "a"], false)]
-    #[case(vec!["a", "b", "c", "a", "d"], false)]
-    fn unique_works(#[case] items: Vec<&str>, #[case] expected_result: bool) {
-        assert_eq!(unique(items.iter()), expected_result);
+    use clap::Parser;
+
+    #[test]
+    fn thresholds_refuse_without_terminal() {
+        let move_pairs: Vec<MovePair> = ["a", "b"]
+            .iter()
+            .map(|name| {
+                (
+                    PathBuf::from(format!("/nonexistent/{}", name)),
+                    PathBuf::from(format!("/nonexistent/{}2", name)),
+                )
+            })
+            .collect();
+
+        let args = Args::parse_from(["irename", "--confirm-files", "1"]);
+        let err = check_thresholds(&move_pairs, ConflictPolicy::Abort, &args, false).unwrap_err();
+        assert!(err.to_string().contains("pass --yes"));
+
+        let args = Args::parse_from(["irename", "--confirm-files", "2"]);
+        assert!(check_thresholds(&move_pairs, ConflictPolicy::Abort, &args, false).is_ok());
+
+        let args = Args::parse_from(["irename", "--confirm-files", "1", "--yes"]);
+        assert!(check_thresholds(&move_pairs, ConflictPolicy::Abort, &args, false).is_ok());
     }
 }
@@ -0,0 +1,339 @@
+use std::path::Path;
+use std::process::Command;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+/// tokens filled from the video metadata
+pub const VIDEO_TOKENS: [&str; 2] = ["duration", "vcodec"];
+
+/// format of `{duration}` without an argument, `%H` is dropped for videos shorter than an hour
+const DEFAULT_DURATION_FORMAT: &str = "%Hh%Mm%Ss";
+
+/// metadata of a video file used by the `{duration}` and `{vcodec}` tokens
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VideoInfo {
+    pub duration: Duration,
+    /// codec of the first video stream like ffprobe names it, e.g. `h264`
+    pub codec: String,
+}
+
+impl VideoInfo {
+    /// read the metadata with the built-in parser if it supports the container, with ffprobe otherwise
+    pub fn probe(path: &Path) -> Result<Self, String> {
+        #[cfg(feature = "mp4")]
+        if mp4::is_supported(path) {
+            if let Ok(info) = mp4::probe(path) {
+                return Ok(info);
+            }
+        }
+
+        probe_ffprobe(path)
+    }
+}
+
+/// `arg` is a format with `%H`, `%M`, `%S` (zero-padded) and `%s` (total seconds)
+pub fn format_duration(duration: Duration, arg: Option<&str>) -> String {
+    let total = duration.as_secs();
+    let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+    let format = match arg {
+        Some(format) => format,
+        None if hours == 0 => &DEFAULT_DURATION_FORMAT[3..],
+        None => DEFAULT_DURATION_FORMAT,
+    };
+
+    format
+        .replace("%H", &format!("{:02}", hours))
+        .replace("%M", &format!("{:02}", minutes))
+        .replace("%S", &format!("{:02}", seconds))
+        .replace("%s", &total.to_string())
+}
+
+#[derive(Deserialize)]
+struct FfprobeOutput {
+    #[serde(default)]
+    streams: Vec<FfprobeStream>,
+    format: Option<FfprobeFormat>,
+}
+
+#[derive(Deserialize)]
+struct FfprobeStream {
+    codec_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FfprobeFormat {
+    duration: Option<String>,
+}
+
+fn probe_ffprobe(path: &Path) -> Result<VideoInfo, String> {
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-select_streams", "v:0"])
+        .args(["-show_entries", "format=duration:stream=codec_name"])
+        .args(["-of", "json"])
+        .arg(path)
+        .output()
+        .map_err(|err| format!("can't run ffprobe: {}", err))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_owned());
+    }
+
+    parse_ffprobe(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_ffprobe(json: &str) -> Result<VideoInfo, String> {
+    let output: FfprobeOutput =
+        serde_json::from_str(json).map_err(|err| format!("unexpected ffprobe output: {}", err))?;
+
+    let codec = output
+        .streams
+        .into_iter()
+        .find_map(|stream| stream.codec_name)
+        .ok_or_else(|| "no video stream".to_owned())?;
+    let duration = output
+        .format
+        .and_then(|format| format.duration)
+        .and_then(|duration| duration.parse::<f64>().ok())
+        .filter(|duration| duration.is_finite() && *duration >= 0.0)
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| "unknown duration".to_owned())?;
+
+    Ok(VideoInfo { duration, codec })
+}
+
+#[cfg(all(test, feature = "mp4"))]
+pub(crate) use mp4::tests::sample_mp4;
+
+/// parser of the ISO base media container (mp4, mov, m4v, 3gp), so ffprobe isn't needed for them
+#[cfg(feature = "mp4")]
+mod mp4 {
+    use super::VideoInfo;
+
+    use std::fs::File;
+    use std::io::{Read, Seek, SeekFrom};
+    use std::path::Path;
+    use std::time::Duration;
+
+    const EXTENSIONS: [&str; 4] = ["mp4", "m4v", "mov", "3gp"];
+
+    pub fn is_supported(path: &Path) -> bool {
+        path.extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .is_some_and(|ext| EXTENSIONS.contains(&ext.as_str()))
+    }
+
+    pub fn probe(path: &Path) -> Result<VideoInfo, String> {
+        let mut file = File::open(path).map_err(|err| err.to_string())?;
+        parse(&read_moov(&mut file).map_err(|err| err.to_string())?)
+    }
+
+    /// content of the top-level `moov` box. The media data before it is skipped
+    fn read_moov(file: &mut (impl Read + Seek)) -> std::io::Result<Vec<u8>> {
+        let invalid = |msg: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, msg);
+
+        loop {
+            let mut header = [0; 8];
+            file.read_exact(&mut header)?;
+            let mut size = u32::from_be_bytes(header[..4].try_into().unwrap()) as u64;
+            let mut header_size = 8;
+            if size == 1 {
+                let mut large_size = [0; 8];
+                file.read_exact(&mut large_size)?;
+                size = u64::from_be_bytes(large_size);
+                header_size = 16;
+            }
+            if size != 0 && size < header_size {
+                return Err(invalid("invalid box size"));
+            }
+
+            if &header[4..] == b"moov" {
+                let mut moov = Vec::new();
+                match size {
+                    // the box lasts until the end of the file
+                    0 => file.read_to_end(&mut moov)?,
+                    size => file.take(size - header_size).read_to_end(&mut moov)?,
+                };
+                return Ok(moov);
+            }
+            if size == 0 {
+                return Err(invalid("no moov box"));
+            }
+            file.seek(SeekFrom::Current((size - header_size) as i64))?;
+        }
+    }
+
+    /// child boxes of a box content as (type, content) pairs
+    fn boxes(mut data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+        std::iter::from_fn(move || {
+            let size = u32::from_be_bytes(data.get(..4)?.try_into().unwrap()) as usize;
+            let kind = data.get(4..8)?;
+            let (content, rest) = match size {
+                0 => (data.get(8..)?, &[][..]),
+                size if size >= 8 => (data.get(8..size)?, data.get(size..)?),
+                _ => return None,
+            };
+            data = rest;
+            Some((kind, content))
+        })
+    }
+
+    fn child<'a>(data: &'a [u8], kind: &[u8]) -> Option<&'a [u8]> {
+        boxes(data)
+            .find(|(child_kind, _)| *child_kind == kind)
+            .map(|(_, content)| content)
+    }
+
+    fn be_u32(data: &[u8], offset: usize) -> Option<u64> {
+        Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?) as u64)
+    }
+
+    fn be_u64(data: &[u8], offset: usize) -> Option<u64> {
+        Some(u64::from_be_bytes(
+            data.get(offset..offset + 8)?.try_into().ok()?,
+        ))
+    }
+
+    fn parse(moov: &[u8]) -> Result<VideoInfo, String> {
+        let mvhd = child(moov, b"mvhd").ok_or("no movie header")?;
+        let (timescale, duration) = match mvhd.first() {
+            Some(1) => (be_u32(mvhd, 20), be_u64(mvhd, 24)),
+            _ => (be_u32(mvhd, 12), be_u32(mvhd, 16)),
+        };
+        let duration = match (timescale, duration) {
+            (Some(timescale), Some(duration)) if timescale > 0 => {
+                Duration::from_secs_f64(duration as f64 / timescale as f64)
+            }
+            _ => return Err("invalid movie header".to_owned()),
+        };
+
+        let fourcc = boxes(moov)
+            .filter(|(kind, _)| *kind == b"trak")
+            .filter_map(|(_, trak)| child(trak, b"mdia"))
+            .filter(|mdia| child(mdia, b"hdlr").and_then(|hdlr| hdlr.get(8..12)) == Some(b"vide"))
+            .find_map(|mdia| {
+                let stsd = child(child(child(mdia, b"minf")?, b"stbl")?, b"stsd")?;
+                // version, flags and the entry count precede the first entry
+                stsd.get(12..16)
+            })
+            .ok_or("no video track")?;
+
+        Ok(VideoInfo {
+            duration,
+            codec: codec_name(fourcc),
+        })
+    }
+
+    /// ffprobe name of the codec with the sample entry code `fourcc`
+    fn codec_name(fourcc: &[u8]) -> String {
+        match fourcc {
+            b"avc1" | b"avc3" => "h264".to_owned(),
+            b"hvc1" | b"hev1" => "hevc".to_owned(),
+            b"av01" => "av1".to_owned(),
+            b"vp08" => "vp8".to_owned(),
+            b"vp09" => "vp9".to_owned(),
+            b"mp4v" => "mpeg4".to_owned(),
+            b"apcn" | b"apch" | b"apcs" | b"apco" | b"ap4h" => "prores".to_owned(),
+            fourcc => String::from_utf8_lossy(fourcc).trim().to_owned(),
+        }
+    }
+
+    #[cfg(test)]
+    pub(super) mod tests {
+        use super::*;
+
+        /// box of `kind` with `content`
+        pub fn mp4_box(kind: &[u8; 4], content: &[u8]) -> Vec<u8> {
+            let mut data = ((content.len() + 8) as u32).to_be_bytes().to_vec();
+            data.extend_from_slice(kind);
+            data.extend_from_slice(content);
+            data
+        }
+
+        /// file with a video track of `fourcc` lasting `duration` units of 1/1000 s
+        pub fn sample_mp4(fourcc: &[u8; 4], duration: u32) -> Vec<u8> {
+            let mut mvhd = vec![0; 12];
+            mvhd.extend_from_slice(&1000u32.to_be_bytes());
+            mvhd.extend_from_slice(&duration.to_be_bytes());
+
+            let hdlr = [&[0; 8][..], b"vide", &[0; 12]].concat();
+            let stsd = [&[0, 0, 0, 0, 0, 0, 0, 1][..], &mp4_box(fourcc, &[0; 8])].concat();
+            let stbl = mp4_box(b"stbl", &mp4_box(b"stsd", &stsd));
+            let mdia = [mp4_box(b"hdlr", &hdlr), mp4_box(b"minf", &stbl)].concat();
+            let sound = mp4_box(
+                b"trak",
+                &mp4_box(
+                    b"mdia",
+                    &mp4_box(b"hdlr", &[&[0; 8][..], b"soun", &[0; 12]].concat()),
+                ),
+            );
+            let moov = [
+                mp4_box(b"mvhd", &mvhd),
+                sound,
+                mp4_box(b"trak", &mp4_box(b"mdia", &mdia)),
+            ]
+            .concat();
+
+            [
+                mp4_box(b"ftyp", b"isom"),
+                mp4_box(b"mdat", &[0; 100]),
+                mp4_box(b"moov", &moov),
+            ]
+            .concat()
+        }
+
+        #[test]
+        fn parse_works() {
+            let mut file = std::io::Cursor::new(sample_mp4(b"hvc1", 83_500));
+            let info = parse(&read_moov(&mut file).unwrap()).unwrap();
+
+            assert_eq!(
+                info,
+                VideoInfo {
+                    duration: Duration::from_millis(83_500),
+                    codec: "hevc".to_owned(),
+                }
+            );
+        }
+
+        #[test]
+        fn parse_fails() {
+            let mut file = std::io::Cursor::new(mp4_box(b"ftyp", b"isom"));
+            assert!(read_moov(&mut file).is_err());
+            assert_eq!(
+                parse(&mp4_box(b"mvhd", &[0; 4])),
+                Err("invalid movie header".to_owned())
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(83, None, "01m23s")]
+    #[case(3723, None, "01h02m03s")]
+    #[case(3723, Some("%s"), "3723")]
+    #[case(83, Some("%H-%M-%S"), "00-01-23")]
+    #[case(83, Some("%Mmin"), "01min")]
+    fn format_duration_works(#[case] secs: u64, #[case] arg: Option<&str>, #[case] expected: &str) {
+        assert_eq!(format_duration(Duration::from_secs(secs), arg), expected);
+    }
+
+    #[test]
+    fn parse_ffprobe_works() {
+        let json = r#"{"programs": [], "streams": [{"codec_name": "h264"}], "format": {"duration": "12.500000"}}"#;
+        assert_eq!(
+            parse_ffprobe(json),
+            Ok(VideoInfo {
+                duration: Duration::from_millis(12_500),
+                codec: "h264".to_owned(),
+            })
+        );
+
+        let json = r#"{"programs": [], "streams": [], "format": {"duration": "3.0"}}"#;
+        assert_eq!(parse_ffprobe(json), Err("no video stream".to_owned()));
+    }
+}
@@ -1,3 +1,5 @@
+use std::ffi::{OsStr, OsString};
+use std::io::BufRead;
 use std::path::{Component, Path, PathBuf};
 
 pub fn normalize_path(path: &Path) -> PathBuf {
@@ -34,22 +36,471 @@ pub fn normalize_path(path: &Path) -> PathBuf {
     }
 }
 
+/// `normalize_path` for input files: `..` after a symlinked directory leads to the parent
+/// of its target, as the file system resolves it. The file itself isn't resolved
+pub fn normalize_input_path(path: &Path) -> PathBuf {
+    if !path.components().any(|c| c == Component::ParentDir) {
+        return normalize_path(path);
+    }
+    match (path.parent().map(std::fs::canonicalize), path.file_name()) {
+        (Some(Ok(parent)), Some(name)) => parent.join(name),
+        _ => normalize_path(path),
+    }
+}
+
 pub fn split_path(mut path: PathBuf) -> Option<(PathBuf, String)> {
     if let Some(name) = path.file_name().map(|s| s.to_owned()) {
         path.pop();
-        Some((path, name.to_str().unwrap().to_owned()))
+        Some((path, name_to_string(&name)))
     } else {
         None
     }
 }
 
+/// first of the private use characters standing for the bytes of names which aren't UTF-8
+#[cfg(unix)]
+const RAW_BYTE_BASE: u32 = 0x10FD00;
+
+/// a name or a path as text. Bytes which aren't valid UTF-8 become private use characters,
+/// so such names are matched and renamed like the others. `name_to_os` reverses it
+#[cfg(unix)]
+pub fn name_to_string(name: &OsStr) -> String {
+    use std::os::unix::ffi::OsStrExt;
+
+    let raw = |byte: &u8| char::from_u32(RAW_BYTE_BASE + *byte as u32);
+    let mut text = String::new();
+    for chunk in name.as_bytes().utf8_chunks() {
+        for ch in chunk.valid().chars() {
+            // the characters standing for bytes are stored as bytes themselves
+            match raw_byte(ch) {
+                Some(_) => text.extend(
+                    ch.encode_utf8(&mut [0; 4])
+                        .as_bytes()
+                        .iter()
+                        .filter_map(raw),
+                ),
+                None => text.push(ch),
+            }
+        }
+        text.extend(chunk.invalid().iter().filter_map(raw));
+    }
+    text
+}
+
+#[cfg(not(unix))]
+pub fn name_to_string(name: &OsStr) -> String {
+    name.to_string_lossy().into_owned()
+}
+
+/// the byte a character of `name_to_string` stands for
+#[cfg(unix)]
+fn raw_byte(ch: char) -> Option<u8> {
+    (ch as u32)
+        .checked_sub(RAW_BYTE_BASE)
+        .filter(|&byte| (0x80..=0xff).contains(&byte))
+        .map(|byte| byte as u8)
+}
+
+/// a name or a path stored as text by `name_to_string`
+#[cfg(unix)]
+pub fn name_to_os(name: &str) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+
+    let mut bytes = Vec::with_capacity(name.len());
+    for ch in name.chars() {
+        match raw_byte(ch) {
+            Some(byte) => bytes.push(byte),
+            None => bytes.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    OsString::from_vec(bytes)
+}
+
+#[cfg(not(unix))]
+pub fn name_to_os(name: &str) -> OsString {
+    name.into()
+}
+
+/// text of `name_to_string` for showing it, the bytes which aren't UTF-8 as `\xNN`
+pub fn display_name(name: &str) -> String {
+    #[cfg(unix)]
+    return name
+        .chars()
+        .map(|ch| match raw_byte(ch) {
+            Some(byte) => format!("\\x{:02X}", byte),
+            None => ch.to_string(),
+        })
+        .collect();
+    #[cfg(not(unix))]
+    return name.to_owned();
+}
+
+/// a path for showing it, the bytes which aren't UTF-8 as `\xNN`
+pub fn display_path(path: &Path) -> String {
+    display_name(&name_to_string(path.as_os_str()))
+}
+
+/// path read as bytes, e.g. a line of a file list
+#[cfg(unix)]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+
+    OsStr::from_bytes(bytes).into()
+}
+
+#[cfg(not(unix))]
+pub fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    String::from_utf8_lossy(bytes).as_ref().into()
+}
+
+/// bytes of a path, e.g. for writing it into a script
+#[cfg(unix)]
+pub fn path_to_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+pub fn path_to_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().as_bytes().to_vec()
+}
+
+/// paths of a file list, one per line, or separated by NUL bytes with `null`
+/// like the output of `find -print0`, so names may contain newlines
+pub fn read_path_list(input: impl BufRead, null: bool) -> Vec<PathBuf> {
+    path_list(input, null).collect()
+}
+
+/// paths of a file list as they are read, see `read_path_list`
+pub fn path_list(input: impl BufRead, null: bool) -> impl Iterator<Item = PathBuf> {
+    input
+        .split(if null { b'\0' } else { b'\n' })
+        .map_while(Result::ok)
+        // trailing and doubled NUL bytes don't separate paths
+        .filter(move |path| !(null && path.is_empty()))
+        .map(move |path| match path.strip_suffix(b"\r") {
+            Some(line) if !null => path_from_bytes(line),
+            _ => path_from_bytes(&path),
+        })
+}
+
+/// serde of a path as the text of `name_to_string`, so paths which aren't UTF-8 can be saved.
+/// UTF-8 paths are saved as they are
+pub mod text_path {
+    use std::path::{Path, PathBuf};
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&super::name_to_string(path.as_os_str()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Ok(super::name_to_os(&text).into())
+    }
+}
+
+/// `dir` joined with a name stored as text, which can also be a relative path
+pub fn join_name(dir: &Path, name: &str) -> PathBuf {
+    dir.join(name_to_os(name))
+}
+
+/// XDG base directory from the environment variable `var` or `$HOME/<fallback>`
+pub fn xdg_dir(var: &str, fallback: &str) -> PathBuf {
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .unwrap_or_else(|| {
+            PathBuf::from(std::env::var_os("HOME").unwrap_or_default()).join(fallback)
+        })
+}
+
+/// mount point of the file system containing `path`: its highest ancestor on the same device.
+/// Nonexistent paths belong to the mount point of their nearest existing ancestor
+#[cfg(unix)]
+pub fn mount_root(path: &Path) -> PathBuf {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut ancestors = path.ancestors();
+    let Some((mut root, dev)) = ancestors
+        .by_ref()
+        .find_map(|dir| Some((dir, std::fs::metadata(dir).ok()?.dev())))
+    else {
+        return PathBuf::from("/");
+    };
+
+    for dir in ancestors {
+        match std::fs::metadata(dir) {
+            Ok(meta) if meta.dev() == dev => root = dir,
+            _ => break,
+        }
+    }
+
+    root.to_owned()
+}
+
+/// drive or root directory of the path
+#[cfg(not(unix))]
+pub fn mount_root(path: &Path) -> PathBuf {
+    path.components()
+        .take_while(|c| matches!(c, Component::Prefix(..) | Component::RootDir))
+        .collect()
+}
+
+/// check if both paths exist and are the same file, e.g. `a.txt` and `A.txt`
+/// on a case-insensitive file system
+#[cfg(unix)]
+pub fn is_same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (std::fs::symlink_metadata(a), std::fs::symlink_metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev() && a.ino() == b.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+pub fn is_same_file(a: &Path, b: &Path) -> bool {
+    a.exists() && normalize_path(a) == normalize_path(b)
+}
+
+/// identity of an existing directory entry: its directory and its name. Paths through
+/// symlinked directories or `..` have the same one, hard links to one file don't
+#[cfg(unix)]
+pub fn entry_id(path: &Path) -> Option<(u64, u64, OsString)> {
+    use std::os::unix::fs::MetadataExt;
+
+    std::fs::symlink_metadata(path).ok()?;
+    let dir = std::fs::metadata(path.parent()?).ok()?;
+    Some((dir.dev(), dir.ino(), path.file_name()?.to_owned()))
+}
+
+#[cfg(not(unix))]
+pub fn entry_id(path: &Path) -> Option<(PathBuf, OsString)> {
+    std::fs::symlink_metadata(path).ok()?;
+    let dir = std::fs::canonicalize(path.parent()?).ok()?;
+    Some((dir, path.file_name()?.to_owned()))
+}
+
+/// check if files can be created and removed in the directory
+#[cfg(unix)]
+pub fn is_writable_dir(dir: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(dir) = std::ffi::CString::new(dir.as_os_str().as_bytes()) else {
+        return false;
+    };
+    unsafe { libc::access(dir.as_ptr(), libc::W_OK | libc::X_OK) == 0 }
+}
+
+#[cfg(not(unix))]
+pub fn is_writable_dir(dir: &Path) -> bool {
+    std::fs::metadata(dir).is_ok_and(|meta| meta.is_dir() && !meta.permissions().readonly())
+}
+
+/// inner extensions forming a compound extension with the last one, e.g. `tar.gz`
+const COMPOUND_EXTENSIONS: &[&str] = &["tar"];
+
+/// split a file name into a stem and an extension without the dot.
+/// A leading dot doesn't start an extension, so `.bashrc` is a stem only.
+/// With `compound` the extension of `archive.tar.gz` is `tar.gz` instead of `gz`
+pub fn split_extension(name: &str, compound: bool) -> (&str, &str) {
+    let dot = match name.rfind('.') {
+        Some(dot) if dot > 0 && dot + 1 < name.len() => dot,
+        _ => return (name, ""),
+    };
+    let (stem, ext) = (&name[..dot], &name[dot + 1..]);
+
+    if compound {
+        let (inner_stem, inner_ext) = split_extension(stem, false);
+        if COMPOUND_EXTENSIONS.contains(&inner_ext.to_lowercase().as_str()) {
+            return (inner_stem, &name[inner_stem.len() + 1..]);
+        }
+    }
+
+    (stem, ext)
+}
+
+/// quote a path for POSIX shells. The bytes are kept as they are,
+/// so names which aren't UTF-8 stay the same
+pub fn shell_quote(path: &Path) -> Vec<u8> {
+    let mut quoted = vec![b'\''];
+    for byte in path_to_bytes(path) {
+        match byte {
+            b'\'' => quoted.extend_from_slice(b"'\\''"),
+            byte => quoted.push(byte),
+        }
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+/// the typed path completed as far as the entries of its directory agree,
+/// a single directory gets a trailing slash. Hidden entries need a typed dot
+pub fn complete_path(typed: &str) -> String {
+    let (dir, prefix) = match typed.rfind(std::path::MAIN_SEPARATOR) {
+        Some(idx) => typed.split_at(idx + 1),
+        None => ("", typed),
+    };
+    let Ok(entries) = std::fs::read_dir(if dir.is_empty() { "." } else { dir }) else {
+        return typed.to_owned();
+    };
+    let mut matches: Vec<(String, bool)> = entries
+        .map_while(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let is_dir = entry.path().is_dir();
+            Some((name, is_dir))
+        })
+        .filter(|(name, _)| {
+            name.starts_with(prefix) && (prefix.starts_with('.') || !name.starts_with('.'))
+        })
+        .collect();
+
+    match matches.as_mut_slice() {
+        [] => typed.to_owned(),
+        [(name, is_dir)] => {
+            if *is_dir {
+                name.push(std::path::MAIN_SEPARATOR);
+            }
+            format!("{}{}", dir, name)
+        }
+        [(first, _), rest @ ..] => {
+            let common = rest.iter().fold(first.chars().count(), |len, (name, _)| {
+                first
+                    .chars()
+                    .zip(name.chars())
+                    .take(len)
+                    .take_while(|(a, b)| a == b)
+                    .count()
+            });
+            let common: String = first.chars().take(common).collect();
+            format!("{}{}", dir, common)
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use proptest::prop_assert_eq;
     use proptest::proptest;
+    use rstest::rstest;
 
     use super::*;
 
+    #[cfg(unix)]
+    #[test]
+    fn invalid_utf8_names_are_kept() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = OsStr::from_bytes(b"caf\xe9 \xff.txt");
+        let text = name_to_string(name);
+        assert_eq!(name_to_os(&text), name);
+        assert_eq!(display_name(&text), "caf\\xE9 \\xFF.txt");
+        assert_eq!(name_to_string(OsStr::new("кафе.txt")), "кафе.txt");
+        let private = OsStr::new("\u{10FDE9}");
+        assert_eq!(name_to_os(&name_to_string(private)), private);
+    }
+
+    #[rstest]
+    #[case(b"a\nb c\r\n", false, &["a", "b c"])]
+    #[case(b"a\nb\0c\0", true, &["a\nb", "c"])]
+    #[case(b"a\0\0b", true, &["a", "b"])]
+    fn path_lists_are_split(#[case] input: &[u8], #[case] null: bool, #[case] expected: &[&str]) {
+        let expected: Vec<PathBuf> = expected.iter().map(PathBuf::from).collect();
+        assert_eq!(read_path_list(input, null), expected);
+    }
+
+    #[test]
+    fn paths_are_completed() {
+        let dir = tempfile::tempdir().unwrap();
+        let typed = |name: &str| format!("{}/{}", dir.path().display(), name);
+        std::fs::create_dir(dir.path().join("photos")).unwrap();
+        std::fs::write(dir.path().join("notes-1.txt"), "").unwrap();
+        std::fs::write(dir.path().join("notes-2.txt"), "").unwrap();
+        std::fs::write(dir.path().join(".hidden"), "").unwrap();
+
+        assert_eq!(complete_path(&typed("p")), typed("photos/"));
+        assert_eq!(complete_path(&typed("n")), typed("notes-"));
+        assert_eq!(complete_path(&typed("x")), typed("x"));
+        assert_eq!(complete_path(&typed("")), typed(""));
+        assert_eq!(complete_path(&typed(".")), typed(".hidden"));
+    }
+
+    #[cfg(unix)]
+    proptest! {
+        #[test]
+        fn names_survive_text_form(bytes: Vec<u8>) {
+            use std::os::unix::ffi::OsStrExt;
+
+            let name = OsStr::from_bytes(&bytes);
+            prop_assert_eq!(name_to_os(&name_to_string(name)), name);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn parent_dirs_are_resolved_through_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = std::fs::canonicalize(dir.path()).unwrap();
+        std::fs::create_dir_all(dir.join("real/sub")).unwrap();
+        std::os::unix::fs::symlink(dir.join("real/sub"), dir.join("link")).unwrap();
+
+        let path = dir.join("link/../x");
+        assert_eq!(normalize_input_path(&path), dir.join("real/x"));
+        assert_eq!(normalize_path(&path), dir.join("x"));
+        assert_eq!(normalize_input_path(&dir.join("link")), dir.join("link"));
+    }
+
+    #[rstest]
+    #[case("a", "'a'")]
+    #[case("/a b/c", "'/a b/c'")]
+    #[case("it's", "'it'\\''s'")]
+    #[case("$x`y`", "'$x`y`'")]
+    fn shell_quote_works(#[case] path: &str, #[case] expected: &str) {
+        assert_eq!(shell_quote(Path::new(path)), expected.as_bytes());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn shell_quote_keeps_raw_bytes() {
+        assert_eq!(
+            shell_quote(&path_from_bytes(b"/d/\xff'")),
+            b"'/d/\xff'\\'''"
+        );
+    }
+
+    #[rstest]
+    #[case("a.txt", false, ("a", "txt"))]
+    #[case("a", false, ("a", ""))]
+    #[case(".bashrc", false, (".bashrc", ""))]
+    #[case(".config.json", false, (".config", "json"))]
+    #[case("a.", false, ("a.", ""))]
+    #[case("archive.tar.gz", false, ("archive.tar", "gz"))]
+    #[case("archive.tar.gz", true, ("archive", "tar.gz"))]
+    #[case("archive.TAR.xz", true, ("archive", "TAR.xz"))]
+    #[case("my.photo.jpg", true, ("my.photo", "jpg"))]
+    #[case(".tar.gz", true, (".tar", "gz"))]
+    fn split_extension_works(
+        #[case] name: &str,
+        #[case] compound: bool,
+        #[case] expected: (&str, &str),
+    ) {
+        assert_eq!(split_extension(name, compound), expected);
+    }
+
+    #[test]
+    fn mount_root_is_shared_by_the_same_device() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = mount_root(dir.path());
+
+        assert!(dir.path().starts_with(&root));
+        assert_eq!(mount_root(&dir.path().join("missing/file")), root);
+        assert_eq!(mount_root(Path::new("/")), Path::new("/"));
+    }
+
     mod split_path {
         use super::*;
 
@@ -62,7 +513,7 @@ mod tests {
                 let src_path = expected_dir.join(PathBuf::from(expected_filename.clone()));
 
                 let (dir, filename) = split_path(src_path.clone())
-                    .expect(&format!("can't split path: {:?}", src_path));
+                    .unwrap_or_else(|| panic!("can't split path: {:?}", src_path));
 
                 prop_assert_eq!(dir, expected_dir);
                 prop_assert_eq!(filename, expected_filename);
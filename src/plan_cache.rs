@@ -0,0 +1,117 @@
+use crate::path_utils;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeSet;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::Context;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// plans computed in previous sessions, so reopening the same files with the same rules
+/// restores the preview without recomputing it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanCache {
+    dir: PathBuf,
+}
+
+/// plan stored with the modification times it was computed at
+#[derive(Serialize, Deserialize)]
+struct CachedPlan<T> {
+    /// the source files and their directories
+    mtimes: Vec<(PathBuf, Option<SystemTime>)>,
+    entries: T,
+}
+
+/// default location of the cache: `$XDG_CACHE_HOME/irename/plans`
+pub fn cache_dir() -> PathBuf {
+    path_utils::xdg_dir("XDG_CACHE_HOME", ".cache")
+        .join("irename")
+        .join("plans")
+}
+
+/// key of a plan of `files` computed with the settings described by `settings`
+pub fn plan_key(files: &[PathBuf], settings: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    files.hash(&mut hasher);
+    settings.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl PlanCache {
+    pub fn new(dir: PathBuf) -> Self {
+        PlanCache { dir }
+    }
+
+    fn path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.json", key))
+    }
+
+    /// plan saved with `key`. `None` if there is none or some of the files
+    /// or their directories were modified after it was saved
+    pub fn load<T: DeserializeOwned>(&self, key: u64) -> Option<T> {
+        let json = std::fs::read_to_string(self.path(key)).ok()?;
+        let plan: CachedPlan<T> = serde_json::from_str(&json).ok()?;
+
+        plan.mtimes
+            .iter()
+            .all(|(path, mtime)| modified(path) == *mtime)
+            .then_some(plan.entries)
+    }
+
+    /// save the plan of `files` with `key`
+    pub fn save<T: Serialize>(
+        &self,
+        key: u64,
+        files: &[PathBuf],
+        entries: &T,
+    ) -> anyhow::Result<()> {
+        let paths: BTreeSet<&Path> = files
+            .iter()
+            .flat_map(|file| std::iter::once(file.as_path()).chain(file.parent()))
+            .collect();
+        let plan = CachedPlan {
+            mtimes: paths
+                .into_iter()
+                .map(|path| (path.to_owned(), modified(path)))
+                .collect(),
+            entries,
+        };
+
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("can't create {}", self.dir.display()))?;
+        let path = self.path(key);
+        std::fs::write(&path, serde_json::to_string(&plan)?)
+            .with_context(|| format!("can't write the plan to {}", path.display()))
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::symlink_metadata(path).ok()?.modified().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plans_are_restored_until_files_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("a.txt");
+        std::fs::write(&file, b"").unwrap();
+        let files = vec![file.clone()];
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache = PlanCache::new(cache_dir.path().to_owned());
+        let key = plan_key(&files, "s/a/b/");
+
+        assert_eq!(cache.load::<Vec<String>>(key), None);
+        cache.save(key, &files, &vec!["b.txt".to_owned()]).unwrap();
+        assert_eq!(cache.load(key), Some(vec!["b.txt".to_owned()]));
+        assert_eq!(cache.load::<Vec<String>>(plan_key(&files, "s/a/c/")), None);
+
+        let file = std::fs::File::options().write(true).open(&file).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH).unwrap();
+        assert_eq!(cache.load::<Vec<String>>(key), None);
+    }
+}
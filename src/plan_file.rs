@@ -0,0 +1,204 @@
+use crate::app::Session;
+use crate::journal::Rename;
+use crate::planner::{self, MovePair, RenameDetails};
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+/// format of the plan files, increased on incompatible changes
+pub const PLAN_VERSION: u32 = 1;
+
+/// renames computed by the app, written by `--plan-out` or `Alt-w` instead of executing them
+/// so other tools can review them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanFile {
+    pub version: u32,
+    pub created: DateTime<Local>,
+    /// the rules chain and the settings the new names were computed with
+    pub settings: Session,
+    pub renames: Vec<PlannedRename>,
+}
+
+/// rename of a plan file with the facts about it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedRename {
+    #[serde(flatten)]
+    pub rename: Rename,
+    #[serde(flatten)]
+    pub details: RenameDetails,
+    /// the destination is taken by another destination or by a kept input file
+    pub conflict: bool,
+}
+
+impl PlanFile {
+    pub fn new(move_pairs: &[MovePair], conflicts: &HashSet<PathBuf>, settings: Session) -> Self {
+        PlanFile {
+            version: PLAN_VERSION,
+            created: Local::now(),
+            settings,
+            renames: move_pairs
+                .iter()
+                .zip(RenameDetails::of(move_pairs))
+                .map(|((src, dst), details)| PlannedRename {
+                    rename: Rename {
+                        src: src.clone(),
+                        dst: dst.clone(),
+                    },
+                    details,
+                    conflict: conflicts.contains(dst),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn move_pairs(&self) -> Vec<MovePair> {
+        // the file may be edited by hand, so files inside renamed directories
+        // aren't guaranteed to come first
+        planner::order_nested(
+            self.renames
+                .iter()
+                .map(|planned| (planned.rename.src.clone(), planned.rename.dst.clone()))
+                .collect(),
+        )
+    }
+
+    /// renames which can't be applied as they were reviewed: their destinations conflict,
+    /// or exist now although the plan didn't replace them
+    pub fn problems(&self) -> Vec<(MovePair, String)> {
+        let sources: HashSet<&PathBuf> = self
+            .renames
+            .iter()
+            .map(|planned| &planned.rename.src)
+            .collect();
+
+        self.renames
+            .iter()
+            .filter_map(|planned| {
+                let Rename { src, dst } = &planned.rename;
+                let problem = if planned.conflict {
+                    format!("{} is a conflicting destination", dst.display())
+                } else if !planned.details.overwrites
+                    && !sources.contains(dst)
+                    && planner::is_overwritten(src, dst)
+                {
+                    format!("{} exists now", dst.display())
+                } else {
+                    return None;
+                };
+                Some(((src.clone(), dst.clone()), problem))
+            })
+            .collect()
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("can't create plan file {}", path.display()))?;
+        serde_json::to_writer_pretty(file, self)?;
+
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("can't open plan file {}", path.display()))?;
+        let plan: PlanFile = serde_json::from_reader(std::io::BufReader::new(file))
+            .with_context(|| format!("invalid plan file {}", path.display()))?;
+
+        if plan.version != PLAN_VERSION {
+            anyhow::bail!(
+                "plan file {} has version {}, only version {} is supported",
+                path.display(),
+                plan.version,
+                PLAN_VERSION
+            );
+        }
+
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::App;
+
+    #[test]
+    fn plan_is_saved_and_loaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        std::fs::write(path("a"), "").unwrap();
+        std::fs::write(path("b"), "").unwrap();
+        std::fs::write(path("taken"), "").unwrap();
+
+        let move_pairs = vec![(path("a"), path("c")), (path("b"), path("taken"))];
+        let conflicts = HashSet::from([path("taken")]);
+        let plan = PlanFile::new(
+            &move_pairs,
+            &conflicts,
+            App::default().export_session().settings,
+        );
+        plan.save(&path("plan.json")).unwrap();
+
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(path("plan.json")).unwrap()).unwrap();
+        assert_eq!(json["version"], PLAN_VERSION);
+        assert_eq!(json["renames"][0]["dst"], path("c").to_str().unwrap());
+        assert_eq!(json["renames"][0]["conflict"], false);
+        assert_eq!(json["renames"][1]["conflict"], true);
+        assert_eq!(json["renames"][1]["overwrites"], true);
+
+        assert_eq!(PlanFile::load(&path("plan.json")).unwrap(), plan);
+    }
+
+    #[test]
+    fn files_are_moved_before_their_directories() {
+        let move_pairs = vec![
+            (PathBuf::from("/d1"), PathBuf::from("/d2")),
+            (PathBuf::from("/d1/f1"), PathBuf::from("/d1/f2")),
+        ];
+        let plan = PlanFile::new(
+            &move_pairs,
+            &HashSet::new(),
+            App::default().export_session().settings,
+        );
+        assert_eq!(
+            plan.move_pairs(),
+            [move_pairs[1].clone(), move_pairs[0].clone()]
+        );
+    }
+
+    #[test]
+    fn changed_files_are_problems() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        for name in ["a", "b", "c", "old"] {
+            std::fs::write(path(name), "").unwrap();
+        }
+
+        // b -> c is fine as c is renamed too, "old" is replaced as planned
+        let move_pairs = vec![
+            (path("a"), path("new")),
+            (path("b"), path("c")),
+            (path("c"), path("d")),
+            (path("d0"), path("old")),
+        ];
+        let plan = PlanFile::new(
+            &move_pairs,
+            &HashSet::new(),
+            App::default().export_session().settings,
+        );
+        assert!(plan.problems().is_empty());
+
+        std::fs::write(path("new"), "").unwrap();
+        let problems: Vec<PathBuf> = plan
+            .problems()
+            .into_iter()
+            .map(|((src, _), _)| src)
+            .collect();
+        assert_eq!(problems, [path("a")]);
+    }
+}
@@ -0,0 +1,1074 @@
+use crate::path_utils;
+use crate::portability::{self, PathLimits};
+use crate::template::COUNTER_MARKER;
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+/// a single rename operation: (source, destination)
+pub type MovePair = (PathBuf, PathBuf);
+
+/// destinations shared by several pairs
+pub fn colliding_destinations(move_pairs: &[MovePair]) -> HashSet<PathBuf> {
+    let mut seen = HashSet::new();
+    move_pairs
+        .iter()
+        .filter(|(_, dst)| !seen.insert(dst))
+        .map(|(_, dst)| dst.clone())
+        .collect()
+}
+
+/// destinations shared by several pairs or taken by input files which aren't renamed,
+/// e.g. `b` of `a -> b` when `b` is an input file too but doesn't match the rules
+pub fn conflicting_destinations(move_pairs: &[MovePair], inputs: &[PathBuf]) -> HashSet<PathBuf> {
+    let sources: HashSet<&PathBuf> = move_pairs.iter().map(|(src, _)| src).collect();
+    let untouched: HashSet<&PathBuf> = inputs
+        .iter()
+        .filter(|input| !sources.contains(input))
+        .collect();
+
+    let mut conflicts = colliding_destinations(move_pairs);
+    conflicts.extend(
+        move_pairs
+            .iter()
+            .filter(|(_, dst)| untouched.contains(dst))
+            .map(|(_, dst)| dst.clone()),
+    );
+    conflicts
+}
+
+/// numbers describing how destructive a plan is
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PlanStats {
+    /// number of renamed files
+    pub files: usize,
+    /// number of destinations which already exist on disk and aren't renamed themselves
+    pub overwrites: usize,
+    /// number of files moved to another directory
+    pub moves: usize,
+}
+
+impl PlanStats {
+    pub fn of(move_pairs: &[MovePair]) -> Self {
+        let details = RenameDetails::of(move_pairs);
+
+        PlanStats {
+            files: move_pairs.len(),
+            overwrites: details.iter().filter(|details| details.overwrites).count(),
+            moves: details.iter().filter(|details| details.changes_dir).count(),
+        }
+    }
+}
+
+/// facts about a single rename, so reviewers of an exported plan don't have to derive them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenameDetails {
+    /// edit distance between the old and the new file name
+    pub edit_distance: usize,
+    /// the file is moved to another directory
+    pub changes_dir: bool,
+    /// the destination already exists on disk and isn't renamed itself
+    pub overwrites: bool,
+}
+
+impl RenameDetails {
+    /// details of every pair of the plan
+    pub fn of(move_pairs: &[MovePair]) -> Vec<Self> {
+        let sources: HashSet<&PathBuf> = move_pairs.iter().map(|(src, _)| src).collect();
+        let file_name = |path: &Path| {
+            path.file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned()
+        };
+
+        move_pairs
+            .iter()
+            .map(|(src, dst)| RenameDetails {
+                edit_distance: edit_distance(&file_name(src), &file_name(dst)),
+                changes_dir: src.parent() != dst.parent(),
+                overwrites: !sources.contains(dst) && is_overwritten(src, dst),
+            })
+            .collect()
+    }
+}
+
+/// check if renaming `src` to `dst` replaces another existing file
+pub fn is_overwritten(src: &Path, dst: &Path) -> bool {
+    std::fs::symlink_metadata(dst).is_ok() && !path_utils::is_same_file(src, dst)
+}
+
+/// destinations which already exist on disk and aren't renamed themselves
+pub fn existing_destinations(move_pairs: &[MovePair]) -> HashSet<PathBuf> {
+    move_pairs
+        .iter()
+        .zip(RenameDetails::of(move_pairs))
+        .filter(|(_, details)| details.overwrites)
+        .map(|((_, dst), _)| dst.clone())
+        .collect()
+}
+
+/// what to do with destinations taken by other renamed files or by existing files
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ConflictPolicy {
+    /// replace existing files only if they are confirmed in the preview,
+    /// stop renaming at any other one
+    #[default]
+    Abort,
+    /// keep the files whose destinations exist under their old names
+    Skip,
+    /// replace existing files without asking
+    Overwrite,
+    /// ask what to do with every existing destination
+    Prompt,
+    /// append ` (1)`, ` (2)`, etc. to the names of the later files
+    Suffix,
+}
+
+/// `name` with the conflict number `n` before its extension: `a (1).txt`
+pub fn conflict_suffixed(name: &str, n: usize, compound: bool) -> String {
+    match path_utils::split_extension(name, compound) {
+        (stem, "") => format!("{} ({})", stem, n),
+        (stem, ext) => format!("{} ({}).{}", stem, n, ext),
+    }
+}
+
+/// why a source file can't be renamed: it doesn't exist or can't be accessed
+pub fn source_problem(path: &Path) -> Option<String> {
+    match std::fs::symlink_metadata(path) {
+        Ok(_) => None,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Some("missing".to_owned()),
+        Err(err) => Some(format!("unreadable: {}", err)),
+    }
+}
+
+/// directories of `dir` and its ancestors which don't exist yet, the outermost first
+pub fn missing_dirs(dir: &Path) -> Vec<PathBuf> {
+    let mut missing: Vec<PathBuf> = dir
+        .ancestors()
+        .take_while(|dir| !dir.as_os_str().is_empty() && !dir.exists())
+        .map(Path::to_owned)
+        .collect();
+    missing.reverse();
+    missing
+}
+
+/// renames which would fail because of a missing file or directory, a read-only directory
+/// or a too long destination path, with the reasons. Checked before renaming anything,
+/// so the plan isn't stopped half-way. With `mkdir` missing destination directories
+/// are fine if they can be created
+pub fn preflight(move_pairs: &[MovePair], mkdir: bool) -> Vec<(MovePair, String)> {
+    let mut writable: HashMap<PathBuf, bool> = HashMap::new();
+    let mut check_dir = |dir: &Path| -> Result<(), String> {
+        if !dir.is_dir() {
+            return Err(format!("directory {} doesn't exist", dir.display()));
+        }
+        let is_writable = *writable
+            .entry(dir.to_owned())
+            .or_insert_with(|| path_utils::is_writable_dir(dir));
+        if !is_writable {
+            return Err(format!("directory {} isn't writable", dir.display()));
+        }
+        Ok(())
+    };
+
+    let limits = PathLimits::native();
+    let mut failures = Vec::new();
+    for (src, dst) in move_pairs {
+        let res = std::fs::symlink_metadata(src)
+            .map_err(|err| format!("{}: {}", src.display(), err))
+            .and_then(|_| check_dir(src.parent().unwrap_or(Path::new("/"))))
+            .and_then(|()| {
+                let dst_dir = dst.parent().unwrap_or(Path::new("/"));
+                match missing_dirs(dst_dir).first().and_then(|dir| dir.parent()) {
+                    Some(existing) if mkdir => check_dir(existing),
+                    _ => check_dir(dst_dir),
+                }
+            })
+            .and_then(
+                |()| match portability::length_problem(&absolute(dst), &limits) {
+                    Some(problem) => Err(format!("{}: {}", dst.display(), problem)),
+                    None => Ok(()),
+                },
+            );
+        if let Err(reason) = res {
+            failures.push(((src.clone(), dst.clone()), reason));
+        }
+    }
+    failures
+}
+
+/// `path` from the root of the file system, the form whose length is limited
+pub fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_owned())
+}
+
+/// limits above which a plan must be confirmed by typing the number of affected files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfirmThresholds {
+    pub files: usize,
+    pub overwrites: usize,
+    pub moves: usize,
+}
+
+impl Default for ConfirmThresholds {
+    fn default() -> Self {
+        ConfirmThresholds {
+            files: 500,
+            overwrites: 0,
+            moves: 0,
+        }
+    }
+}
+
+impl ConfirmThresholds {
+    pub fn exceeded_by(&self, stats: &PlanStats) -> bool {
+        stats.files > self.files || stats.overwrites > self.overwrites || stats.moves > self.moves
+    }
+
+    /// stats of a plan which must be confirmed. Existing files aren't counted when
+    /// `on_conflict` asks about them or skips them anyway
+    pub fn check(&self, move_pairs: &[MovePair], on_conflict: ConflictPolicy) -> Option<PlanStats> {
+        let mut stats = PlanStats::of(move_pairs);
+        if matches!(on_conflict, ConflictPolicy::Prompt | ConflictPolicy::Skip) {
+            stats.overwrites = 0;
+        }
+
+        self.exceeded_by(&stats).then_some(stats)
+    }
+}
+
+/// order in which counter values are assigned to the renamed files
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum NumberingOrder {
+    /// first file gets the first number
+    #[default]
+    Forward,
+    /// last file gets the first number
+    Reverse,
+    /// invert numbers already present in the names (1, 2, 5 -> 5, 4, 1).
+    /// Files without a number are numbered forward after the largest one
+    Mirror,
+}
+
+lazy_static::lazy_static! {
+    static ref NUMBER_REGEX: Regex = Regex::new("[0-9]+").unwrap();
+}
+
+/// counter values of the files. `names` contains source names of the renamed files
+/// and `None` for the files which aren't renamed
+pub fn assign_counters(
+    names: &[Option<&str>],
+    start: u64,
+    order: NumberingOrder,
+) -> Vec<Option<u64>> {
+    let renamed = names.iter().filter(|name| name.is_some()).count() as u64;
+
+    let existing_numbers: Vec<Option<u64>> = names
+        .iter()
+        .map(|name| NUMBER_REGEX.find((*name)?)?.as_str().parse().ok())
+        .collect();
+    let min = existing_numbers
+        .iter()
+        .flatten()
+        .min()
+        .copied()
+        .unwrap_or(0);
+    let max = existing_numbers.iter().flatten().max().copied();
+
+    let mut idx = 0;
+    // mirrored numbers stay between min and max, the files without one must not take them
+    let mut unnumbered = 0;
+    let unnumbered_start = max.map_or(start, |max| start.max(max + 1));
+    names
+        .iter()
+        .zip(existing_numbers)
+        .map(|(name, existing)| {
+            name.as_ref()?;
+            let counter = match (order, existing) {
+                (NumberingOrder::Reverse, _) => start + renamed - 1 - idx,
+                (NumberingOrder::Mirror, Some(existing)) => min + max.unwrap_or(0) - existing,
+                (NumberingOrder::Mirror, None) => {
+                    unnumbered += 1;
+                    unnumbered_start + unnumbered - 1
+                }
+                (NumberingOrder::Forward, _) => start + idx,
+            };
+            idx += 1;
+            Some(counter)
+        })
+        .collect()
+}
+
+/// indices of the items grouped by their keys. Groups are in the order of their first items
+pub fn group_indices<K: Eq + std::hash::Hash>(keys: impl Iterator<Item = K>) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of_key = std::collections::HashMap::new();
+    for (idx, key) in keys.enumerate() {
+        let group = *group_of_key.entry(key).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push(idx);
+    }
+    groups
+}
+
+/// what is renamed when an input file is a symlink
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SymlinkPolicy {
+    /// the symlink itself, its target keeps its name
+    #[default]
+    Rename,
+    /// the file the symlink points to
+    Follow,
+    /// neither, symlinks are left out
+    Skip,
+}
+
+/// order of the source files, used for the list, for assigning counters and for renaming
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FileOrder {
+    /// as given on the command line or stdin
+    #[default]
+    Input,
+    /// numbers compared by value (`file2` before `file10`), letters ignoring case and accents
+    Natural,
+    /// paths compared as they are
+    Name,
+    /// modification time, the oldest first
+    Mtime,
+    /// size, the smallest first
+    Size,
+}
+
+impl FileOrder {
+    pub const ALL: [FileOrder; 5] = [
+        FileOrder::Input,
+        FileOrder::Natural,
+        FileOrder::Name,
+        FileOrder::Mtime,
+        FileOrder::Size,
+    ];
+
+    /// the next order of `ALL`
+    pub fn toggled(self) -> Self {
+        let idx = FileOrder::ALL
+            .iter()
+            .position(|order| *order == self)
+            .unwrap_or(0);
+        FileOrder::ALL[(idx + 1) % FileOrder::ALL.len()]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FileOrder::Input => "input",
+            FileOrder::Natural => "natural",
+            FileOrder::Name => "name",
+            FileOrder::Mtime => "mtime",
+            FileOrder::Size => "size",
+        }
+    }
+}
+
+/// sort the paths in the given order, component by component. The sort is stable,
+/// paths with the same time or size, or missing ones, keep their order
+pub fn sort_paths(paths: &mut [PathBuf], order: FileOrder) {
+    let metadata = |path: &PathBuf| std::fs::symlink_metadata(path).ok();
+    match order {
+        FileOrder::Input => {}
+        FileOrder::Natural => paths.sort_by(|a, b| {
+            let components = |path: &PathBuf| {
+                path.components()
+                    .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                    .collect::<Vec<_>>()
+            };
+            let (a, b) = (components(a), components(b));
+            a.iter()
+                .zip(&b)
+                .map(|(a, b)| natural_cmp(a, b))
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or_else(|| a.len().cmp(&b.len()))
+        }),
+        FileOrder::Name => paths.sort(),
+        FileOrder::Mtime => {
+            paths.sort_by_cached_key(|path| metadata(path).and_then(|meta| meta.modified().ok()))
+        }
+        FileOrder::Size => paths.sort_by_cached_key(|path| metadata(path).map(|meta| meta.len())),
+    }
+}
+
+/// runs of digits or of other characters
+fn natural_chunks(s: &str) -> impl Iterator<Item = &str> {
+    let mut rest = s;
+    std::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let end = rest
+            .find(|ch: char| ch.is_ascii_digit() != first.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (chunk, tail) = rest.split_at(end);
+        rest = tail;
+        Some(chunk)
+    })
+}
+
+/// compare strings like people do: numbers by their values, letters ignoring case and accents.
+/// Strings equal in this sense are compared as is, so the order is total
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let chunk_cmp = |a: &str, b: &str| {
+        if a.starts_with(|ch: char| ch.is_ascii_digit())
+            && b.starts_with(|ch: char| ch.is_ascii_digit())
+        {
+            // numbers of any length, leading zeros don't count
+            let (a, b) = (a.trim_start_matches('0'), b.trim_start_matches('0'));
+            a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+        } else {
+            let fold = |s: &str| deunicode::deunicode(s).to_lowercase();
+            fold(a).cmp(&fold(b))
+        }
+    };
+
+    let mut a_chunks = natural_chunks(a);
+    let mut b_chunks = natural_chunks(b);
+    loop {
+        match (a_chunks.next(), b_chunks.next()) {
+            (Some(a_chunk), Some(b_chunk)) => match chunk_cmp(a_chunk, b_chunk) {
+                Ordering::Equal => {}
+                ordering => return ordering,
+            },
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (None, None) => return a.cmp(b),
+        }
+    }
+}
+
+/// drop repeated paths, keeping the first occurrence. Returns the number of dropped ones.
+/// Paths must be normalized, so `./a` and `a` are the same. Existing files reached through
+/// different paths, e.g. a symlinked directory, are repeated too, renaming them twice would fail
+pub fn dedup_paths(paths: Vec<PathBuf>) -> (Vec<PathBuf>, usize) {
+    let mut seen = HashSet::new();
+    let mut seen_entries = HashSet::new();
+    let total = paths.len();
+    let unique: Vec<PathBuf> = paths
+        .into_iter()
+        .filter(|path| {
+            seen.insert(path.clone())
+                && path_utils::entry_id(path).is_none_or(|id| seen_entries.insert(id))
+        })
+        .collect();
+    let duplicates = total - unique.len();
+    (unique, duplicates)
+}
+
+/// number of sources inside directories which are moved by other pairs
+pub fn nested_sources(move_pairs: &[MovePair]) -> usize {
+    let sources: HashSet<&Path> = move_pairs.iter().map(|(src, _)| src.as_path()).collect();
+    move_pairs
+        .iter()
+        .filter(|(src, _)| src.ancestors().skip(1).any(|dir| sources.contains(dir)))
+        .count()
+}
+
+/// order the pairs so files are moved before the directories containing them.
+/// Otherwise moving a directory first would make the paths of its files invalid
+pub fn order_nested(mut move_pairs: Vec<MovePair>) -> Vec<MovePair> {
+    if nested_sources(&move_pairs) > 0 {
+        move_pairs.sort_by_key(|(src, _)| std::cmp::Reverse(src.components().count()));
+    }
+    move_pairs
+}
+
+/// free path next to `path` to park it while a cycle of renames is executed
+pub fn temporary_path(path: &Path) -> PathBuf {
+    let name = path.file_name().unwrap_or_default();
+    (0..)
+        .map(|n| {
+            let mut tmp_name = OsString::from(".");
+            tmp_name.push(name);
+            tmp_name.push(format!(".irename-{}", n));
+            path.with_file_name(tmp_name)
+        })
+        .find(|tmp| std::fs::symlink_metadata(tmp).is_err())
+        .unwrap()
+}
+
+/// free name for a backup of the replaced `path`: the name with `suffix`, like `name~`,
+/// or a numbered `name.~1~`, `name.~2~`, etc. if it's taken
+pub fn backup_path(path: &Path, suffix: &str) -> PathBuf {
+    let name = path.file_name().unwrap_or_default();
+    let with_suffix = |suffix: &str| {
+        let mut backup_name = name.to_owned();
+        backup_name.push(suffix);
+        path.with_file_name(backup_name)
+    };
+    std::iter::once(with_suffix(suffix))
+        .chain((1..).map(|n| with_suffix(&format!(".~{}~", n))))
+        .find(|backup| std::fs::symlink_metadata(backup).is_err())
+        .unwrap()
+}
+
+/// order the renames so no destination is taken by a source which isn't moved away yet.
+/// Chains like `b -> c, a -> b` are executed from the end, and cycles like swapping
+/// `a` and `b` go through a temporary name: `a -> tmp, b -> a, tmp -> b`
+pub fn schedule_renames(move_pairs: Vec<MovePair>) -> Vec<MovePair> {
+    let by_source: HashMap<&Path, usize> = move_pairs
+        .iter()
+        .enumerate()
+        .map(|(idx, (src, _))| (src.as_path(), idx))
+        .collect();
+    // the pair which has to be executed before the given one to free its destination
+    let blocker = |idx: usize| {
+        by_source
+            .get(move_pairs[idx].1.as_path())
+            .copied()
+            .filter(|&blocker| blocker != idx)
+    };
+
+    let mut done = vec![false; move_pairs.len()];
+    let mut steps = Vec::with_capacity(move_pairs.len());
+    for start in 0..move_pairs.len() {
+        if done[start] {
+            continue;
+        }
+
+        let mut chain = vec![start];
+        let mut is_cycle = false;
+        while let Some(next) = blocker(*chain.last().unwrap()) {
+            if next == start {
+                is_cycle = true;
+                break;
+            }
+            if done[next] || chain.contains(&next) {
+                break;
+            }
+            chain.push(next);
+        }
+
+        let (src, dst) = &move_pairs[start];
+        let parked = is_cycle.then(|| temporary_path(src));
+        if let Some(tmp) = &parked {
+            steps.push((src.clone(), tmp.clone()));
+        }
+        for &idx in chain.iter().skip(is_cycle as usize).rev() {
+            steps.push(move_pairs[idx].clone());
+        }
+        if let Some(tmp) = parked {
+            steps.push((tmp, dst.clone()));
+        }
+        for idx in chain {
+            done[idx] = true;
+        }
+    }
+    steps
+}
+
+/// number of inserted, deleted and substituted characters turning `a` into `b`
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+
+    for (i, a_char) in a.chars().enumerate() {
+        cur[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(a_char != *b_char);
+            cur[j + 1] = substitution.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+    prev[b.len()]
+}
+
+/// replace the first number of every name with a contiguous sequence starting at `start`.
+/// `width` is the number of digits padded with zeros, `None` keeps the width of the original number.
+/// Names without numbers and `None` entries are left as is
+pub fn renumber(names: &[Option<&str>], start: u64, width: Option<usize>) -> Vec<Option<String>> {
+    let mut next = start;
+    names
+        .iter()
+        .map(|name| {
+            let name = (*name)?;
+            let number = NUMBER_REGEX.find(name)?;
+            let width = width.unwrap_or(number.as_str().len());
+
+            let renumbered = format!(
+                "{}{:0width$}{}",
+                &name[..number.start()],
+                next,
+                &name[number.end()..],
+                width = width
+            );
+            next += 1;
+            Some(renumbered)
+        })
+        .collect()
+}
+
+/// the largest number found in `existing` names at the counter position of `name_pattern`.
+/// `name_pattern` is a destination name with `COUNTER_MARKER` in place of the counter
+pub fn max_existing_number<'a>(
+    name_pattern: &str,
+    existing: impl IntoIterator<Item = &'a str>,
+) -> Option<u64> {
+    if !name_pattern.contains(COUNTER_MARKER) {
+        return None;
+    }
+
+    let regex_str = name_pattern
+        .split(COUNTER_MARKER)
+        .map(regex::escape)
+        .collect::<Vec<_>>()
+        .join("(\\d+)");
+    let regex = Regex::new(&format!("^{}$", regex_str)).ok()?;
+
+    existing
+        .into_iter()
+        .filter_map(|name| regex.captures(name))
+        .filter_map(|captures| captures.get(1)?.as_str().parse().ok())
+        .max()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn group_indices_works() {
+        let keys = ["a", "b", "a", "c", "b"];
+        assert_eq!(
+            group_indices(keys.iter()),
+            vec![vec![0, 2], vec![1, 4], vec![3]]
+        );
+    }
+
+    #[test]
+    fn preflight_finds_failing_renames() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a"), "").unwrap();
+        let move_pairs = vec![
+            (dir.path().join("a"), dir.path().join("b")),
+            (dir.path().join("missing"), dir.path().join("c")),
+            (dir.path().join("a"), dir.path().join("sub/d")),
+            (dir.path().join("a"), dir.path().join("e".repeat(256))),
+        ];
+
+        let failures = preflight(&move_pairs, false);
+        assert_eq!(
+            failures.iter().map(|(pair, _)| pair).collect::<Vec<_>>(),
+            vec![&move_pairs[1], &move_pairs[2], &move_pairs[3]]
+        );
+        assert!(failures[1].1.contains("doesn't exist"));
+        assert!(failures[2]
+            .1
+            .ends_with("name is 256 long, the limit is 255"));
+    }
+
+    #[test]
+    fn missing_dirs_are_found() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            missing_dirs(&dir.path().join("a/b")),
+            vec![dir.path().join("a"), dir.path().join("a/b")]
+        );
+        assert!(missing_dirs(dir.path()).is_empty());
+    }
+
+    #[test]
+    fn backups_are_numbered_when_taken() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+
+        assert_eq!(backup_path(&path, "~"), dir.path().join("a.txt~"));
+        std::fs::write(dir.path().join("a.txt~"), "").unwrap();
+        std::fs::write(dir.path().join("a.txt.~1~"), "").unwrap();
+        assert_eq!(backup_path(&path, "~"), dir.path().join("a.txt.~2~"));
+        assert_eq!(backup_path(&path, ".bak"), dir.path().join("a.txt.bak"));
+    }
+
+    #[test]
+    fn chains_are_scheduled_from_the_end() {
+        let scheduled = schedule_renames(pairs(&[
+            ("/d/a", "/d/b"),
+            ("/d/b", "/d/c"),
+            ("/d/x", "/d/y"),
+        ]));
+        assert_eq!(
+            scheduled,
+            pairs(&[("/d/b", "/d/c"), ("/d/a", "/d/b"), ("/d/x", "/d/y")])
+        );
+    }
+
+    #[test]
+    fn cycles_are_renamed_through_temporary_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let names = ["a", "b", "c"];
+        for name in names {
+            std::fs::write(dir.path().join(name), name).unwrap();
+        }
+        // a -> b -> c -> a
+        let move_pairs: Vec<MovePair> = (0..3)
+            .map(|idx| {
+                (
+                    dir.path().join(names[idx]),
+                    dir.path().join(names[(idx + 1) % 3]),
+                )
+            })
+            .collect();
+
+        let scheduled = schedule_renames(move_pairs);
+        assert_eq!(scheduled.len(), 4);
+        for (src, dst) in scheduled {
+            assert!(!dst.exists());
+            std::fs::rename(src, dst).unwrap();
+        }
+
+        for idx in 0..3 {
+            let content = std::fs::read_to_string(dir.path().join(names[(idx + 1) % 3])).unwrap();
+            assert_eq!(content, names[idx]);
+        }
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 3);
+    }
+
+    #[rstest]
+    #[case("a.txt", 1, false, "a (1).txt")]
+    #[case("a", 2, false, "a (2)")]
+    #[case(".bashrc", 1, false, ".bashrc (1)")]
+    #[case("b.tar.gz", 3, true, "b (3).tar.gz")]
+    #[case("b.tar.gz", 3, false, "b.tar (3).gz")]
+    fn conflict_suffixed_works(
+        #[case] name: &str,
+        #[case] n: usize,
+        #[case] compound: bool,
+        #[case] expected: &str,
+    ) {
+        assert_eq!(conflict_suffixed(name, n, compound), expected);
+    }
+
+    #[rstest]
+    #[case("", "", 0)]
+    #[case("abc", "abc", 0)]
+    #[case("", "abc", 3)]
+    #[case("kitten", "sitting", 3)]
+    #[case("IMG_1.jpg", "img_1.jpg", 3)]
+    #[case("фото.jpg", "foto.jpg", 4)]
+    fn edit_distance_works(#[case] a: &str, #[case] b: &str, #[case] expected: usize) {
+        assert_eq!(edit_distance(a, b), expected);
+        assert_eq!(edit_distance(b, a), expected);
+    }
+
+    #[test]
+    fn dedup_paths_works() {
+        let paths = ["/a", "/b", "/a", "/a"].map(PathBuf::from).to_vec();
+        assert_eq!(
+            dedup_paths(paths),
+            (vec![PathBuf::from("/a"), PathBuf::from("/b")], 2)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn same_files_through_symlinks_are_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let real = dir.path().join("real");
+        std::fs::create_dir(&real).unwrap();
+        std::fs::write(real.join("a"), "").unwrap();
+        std::fs::hard_link(real.join("a"), real.join("b")).unwrap();
+        std::os::unix::fs::symlink(&real, dir.path().join("link")).unwrap();
+
+        let paths = vec![real.join("a"), dir.path().join("link/a"), real.join("b")];
+        assert_eq!(
+            dedup_paths(paths),
+            (vec![real.join("a"), real.join("b")], 1)
+        );
+    }
+
+    #[test]
+    fn nested_sources_are_moved_first() {
+        let move_pairs = pairs(&[
+            ("/d/dir", "/d/folder"),
+            ("/d/other", "/d/other2"),
+            ("/d/dir/sub/x", "/d/dir/sub/y"),
+            ("/d/dir/a", "/d/dir/b"),
+        ]);
+        assert_eq!(nested_sources(&move_pairs), 2);
+        assert_eq!(
+            order_nested(move_pairs),
+            pairs(&[
+                ("/d/dir/sub/x", "/d/dir/sub/y"),
+                ("/d/dir/a", "/d/dir/b"),
+                ("/d/dir", "/d/folder"),
+                ("/d/other", "/d/other2"),
+            ])
+        );
+
+        let flat = pairs(&[("/d/b", "/d/c"), ("/a/b/c", "/a/b/d")]);
+        assert_eq!(nested_sources(&flat), 0);
+        assert_eq!(order_nested(flat.clone()), flat);
+    }
+
+    fn pairs(items: &[(&str, &str)]) -> Vec<MovePair> {
+        items
+            .iter()
+            .map(|(src, dst)| (PathBuf::from(src), PathBuf::from(dst)))
+            .collect()
+    }
+
+    #[rstest]
+    #[case("file2", "file10", Ordering::Less)]
+    #[case("file10", "file9", Ordering::Greater)]
+    #[case("a", "B", Ordering::Less)]
+    #[case("Émile", "Eva", Ordering::Less)]
+    #[case("écrire", "zèbre", Ordering::Less)]
+    #[case("x01", "x1", Ordering::Less)]
+    #[case("x1", "x1", Ordering::Equal)]
+    #[case("page", "page1", Ordering::Less)]
+    #[case("v1.10", "v1.9", Ordering::Greater)]
+    #[case(
+        "track99999999999999999999",
+        "track100000000000000000000",
+        Ordering::Less
+    )]
+    fn natural_cmp_works(#[case] a: &str, #[case] b: &str, #[case] expected: Ordering) {
+        assert_eq!(natural_cmp(a, b), expected);
+    }
+
+    #[test]
+    fn sort_paths_works() {
+        let mut paths: Vec<PathBuf> = ["/d10/a", "/d2/b10", "/d2/b9", "/d2/B1"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+
+        sort_paths(&mut paths, FileOrder::Input);
+        assert_eq!(paths[0], PathBuf::from("/d10/a"));
+
+        sort_paths(&mut paths, FileOrder::Natural);
+        assert_eq!(
+            paths,
+            ["/d2/B1", "/d2/b9", "/d2/b10", "/d10/a"]
+                .iter()
+                .map(PathBuf::from)
+                .collect::<Vec<_>>()
+        );
+
+        sort_paths(&mut paths, FileOrder::Name);
+        assert_eq!(
+            paths,
+            ["/d10/a", "/d2/B1", "/d2/b10", "/d2/b9"]
+                .iter()
+                .map(PathBuf::from)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn paths_are_sorted_by_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        let day = std::time::Duration::from_secs(24 * 60 * 60);
+        let now = std::time::SystemTime::now();
+        for (name, content, age) in [
+            ("a", "333", 1),
+            ("b", "1", 3),
+            ("c", "22", 2),
+            ("d", "1", 0),
+        ] {
+            let file = std::fs::File::create(path(name)).unwrap();
+            file.set_len(content.len() as u64).unwrap();
+            file.set_modified(now - day * age).unwrap();
+        }
+
+        let mut paths: Vec<PathBuf> = ["a", "b", "c", "d", "missing"].map(path).to_vec();
+        sort_paths(&mut paths, FileOrder::Mtime);
+        assert_eq!(paths, ["missing", "b", "c", "a", "d"].map(path));
+
+        // stable: b stays before d
+        sort_paths(&mut paths, FileOrder::Size);
+        assert_eq!(paths, ["missing", "b", "d", "c", "a"].map(path));
+    }
+
+    #[test]
+    fn colliding_destinations_works() {
+        let collisions = colliding_destinations(&pairs(&[
+            ("/d/a", "/d/x"),
+            ("/d/b", "/d/y"),
+            ("/d/c", "/d/x"),
+            ("/d/e", "/d/x"),
+        ]));
+        assert_eq!(collisions, HashSet::from([PathBuf::from("/d/x")]));
+    }
+
+    #[test]
+    fn untouched_inputs_are_conflicts() {
+        let move_pairs = pairs(&[("/d/a", "/d/b"), ("/d/b", "/d/c"), ("/d/e", "/d/f")]);
+        let inputs = ["/d/a", "/d/b", "/d/e", "/d/f", "/d/g"].map(PathBuf::from);
+
+        assert_eq!(
+            conflicting_destinations(&move_pairs, &inputs),
+            HashSet::from([PathBuf::from("/d/f")])
+        );
+    }
+
+    #[test]
+    fn stats_count_moves() {
+        let stats = PlanStats::of(&pairs(&[
+            ("/nonexistent/a", "/nonexistent/b"),
+            ("/nonexistent/c", "/nonexistent/sub/c"),
+        ]));
+
+        assert_eq!(
+            stats,
+            PlanStats {
+                files: 2,
+                overwrites: 0,
+                moves: 1
+            }
+        );
+    }
+
+    #[test]
+    fn stats_count_overwrites() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("b"), "").unwrap();
+        std::fs::write(dir.path().join("c"), "").unwrap();
+
+        let stats = PlanStats::of(&[
+            (dir.path().join("a"), dir.path().join("b")),
+            // "c" is renamed itself, so it's not overwritten
+            (dir.path().join("c"), dir.path().join("d")),
+            (dir.path().join("e"), dir.path().join("c")),
+        ]);
+
+        assert_eq!(stats.overwrites, 1);
+        assert_eq!(stats.moves, 0);
+    }
+
+    #[test]
+    fn existing_destinations_works() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a", "b", "c"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+
+        let existing = existing_destinations(&[
+            (dir.path().join("a"), dir.path().join("b")),
+            (dir.path().join("c"), dir.path().join("a")),
+            (dir.path().join("d"), dir.path().join("e")),
+        ]);
+        assert_eq!(existing, HashSet::from([dir.path().join("b")]));
+        // renaming a file to itself, e.g. changing the case on a case-insensitive file system
+        assert!(!is_overwritten(
+            &dir.path().join("c"),
+            &dir.path().join("c")
+        ));
+    }
+
+    #[test]
+    fn rename_details_works() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("b"), "").unwrap();
+
+        let details = RenameDetails::of(&[
+            (dir.path().join("a"), dir.path().join("b")),
+            (dir.path().join("report"), dir.path().join("sub/reports")),
+        ]);
+
+        assert_eq!(
+            details,
+            vec![
+                RenameDetails {
+                    edit_distance: 1,
+                    changes_dir: false,
+                    overwrites: true,
+                },
+                RenameDetails {
+                    edit_distance: 1,
+                    changes_dir: true,
+                    overwrites: false,
+                },
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case(vec![Some("a"), None, Some("b"), Some("c")], 1, NumberingOrder::Forward, vec![Some(1), None, Some(2), Some(3)])]
+    #[case(vec![Some("a"), None, Some("b"), Some("c")], 1, NumberingOrder::Reverse, vec![Some(3), None, Some(2), Some(1)])]
+    #[case(vec![Some("a"), Some("b")], 10, NumberingOrder::Reverse, vec![Some(11), Some(10)])]
+    #[case(vec![Some("t1"), Some("t2"), None, Some("t5")], 1, NumberingOrder::Mirror, vec![Some(5), Some(4), None, Some(1)])]
+    #[case(vec![Some("03 a"), Some("b"), Some("10 c")], 1, NumberingOrder::Mirror, vec![Some(10), Some(11), Some(3)])]
+    #[case(vec![Some("03 a"), Some("b"), Some("c"), Some("10 d")], 1, NumberingOrder::Mirror, vec![Some(10), Some(11), Some(12), Some(3)])]
+    #[case(vec![Some("03 a"), Some("b")], 20, NumberingOrder::Mirror, vec![Some(3), Some(20)])]
+    #[case(vec![Some("a"), Some("b")], 5, NumberingOrder::Mirror, vec![Some(5), Some(6)])]
+    #[case(vec![], 1, NumberingOrder::Reverse, vec![])]
+    fn assign_counters_works(
+        #[case] names: Vec<Option<&str>>,
+        #[case] start: u64,
+        #[case] order: NumberingOrder,
+        #[case] expected: Vec<Option<u64>>,
+    ) {
+        assert_eq!(assign_counters(&names, start, order), expected);
+    }
+
+    #[rstest]
+    #[case(vec![Some("a1"), Some("a2"), Some("a5"), Some("a9")], None, vec![Some("a1"), Some("a2"), Some("a3"), Some("a4")])]
+    #[case(vec![Some("05 x 7"), None, Some("x"), Some("10 y")], None, vec![Some("01 x 7"), None, None, Some("02 y")])]
+    #[case(vec![Some("t9.mp3"), Some("t12.mp3")], Some(3), vec![Some("t001.mp3"), Some("t002.mp3")])]
+    fn renumber_works(
+        #[case] names: Vec<Option<&str>>,
+        #[case] width: Option<usize>,
+        #[case] expected: Vec<Option<&str>>,
+    ) {
+        let expected: Vec<Option<String>> = expected
+            .into_iter()
+            .map(|name| name.map(str::to_owned))
+            .collect();
+        assert_eq!(renumber(&names, 1, width), expected);
+    }
+
+    #[rstest]
+    #[case("img_\u{1f}.jpg", vec!["img_001.jpg", "img_040.jpg", "img_7.jpg"], Some(40))]
+    #[case("img_\u{1f}.jpg", vec!["img_001.png", "img_x.jpg", "photo_3.jpg"], None)]
+    #[case("img.jpg", vec!["img.jpg"], None)]
+    #[case("a.b_\u{1f}", vec!["a.b_3", "axb_5"], Some(3))]
+    fn max_existing_number_works(
+        #[case] pattern: &str,
+        #[case] existing: Vec<&str>,
+        #[case] expected: Option<u64>,
+    ) {
+        assert_eq!(max_existing_number(pattern, existing), expected);
+    }
+
+    #[rstest]
+    #[case(PlanStats { files: 500, overwrites: 0, moves: 0 }, false)]
+    #[case(PlanStats { files: 501, overwrites: 0, moves: 0 }, true)]
+    #[case(PlanStats { files: 1, overwrites: 1, moves: 0 }, true)]
+    #[case(PlanStats { files: 1, overwrites: 0, moves: 1 }, true)]
+    fn default_thresholds(#[case] stats: PlanStats, #[case] expected: bool) {
+        assert_eq!(ConfirmThresholds::default().exceeded_by(&stats), expected);
+    }
+
+    #[rstest]
+    #[case(ConflictPolicy::Abort, true)]
+    #[case(ConflictPolicy::Overwrite, true)]
+    #[case(ConflictPolicy::Skip, false)]
+    #[case(ConflictPolicy::Prompt, false)]
+    fn overwrites_are_checked_by_policy(
+        #[case] on_conflict: ConflictPolicy,
+        #[case] expected: bool,
+    ) {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("b"), "").unwrap();
+        let move_pairs = [(dir.path().join("a"), dir.path().join("b"))];
+
+        let stats = ConfirmThresholds::default().check(&move_pairs, on_conflict);
+        assert_eq!(stats.is_some(), expected);
+    }
+}
@@ -0,0 +1,148 @@
+use std::ffi::OsStr;
+use std::path::Path;
+
+/// characters Windows doesn't allow in file names, in addition to the control ones
+const WINDOWS_FORBIDDEN_CHARS: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// device names which can't be used as file names on Windows, with any extension
+const WINDOWS_RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// why the file name can't be used on Windows, e.g. on a network share. `None` if it can
+pub fn windows_problem(name: &str) -> Option<String> {
+    if let Some(ch) = name
+        .chars()
+        .find(|ch| ch.is_ascii_control() || WINDOWS_FORBIDDEN_CHARS.contains(ch))
+    {
+        return Some(format!("{:?} isn't allowed on Windows", ch));
+    }
+
+    let stem = name.split('.').next().unwrap_or_default().trim_end();
+    if WINDOWS_RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem))
+    {
+        return Some(format!("{} is reserved on Windows", stem.to_uppercase()));
+    }
+
+    if name.ends_with('.') || name.ends_with(' ') {
+        return Some("Windows drops trailing dots and spaces".to_owned());
+    }
+    None
+}
+
+/// lengths of file names and of whole paths a file system accepts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PathLimits {
+    pub name: usize,
+    pub path: usize,
+}
+
+impl PathLimits {
+    /// limits of the system the app runs on
+    pub fn native() -> Self {
+        #[cfg(unix)]
+        return PathLimits {
+            name: 255,
+            // PATH_MAX counts the terminating NUL too
+            path: libc::PATH_MAX as usize - 1,
+        };
+        #[cfg(not(unix))]
+        return Self::windows(windows_long_paths_enabled());
+    }
+
+    /// Windows limits, the 260 characters of `MAX_PATH` unless long paths are enabled
+    pub fn windows(long_paths: bool) -> Self {
+        PathLimits {
+            name: 255,
+            path: if long_paths { 32767 } else { 259 },
+        }
+    }
+}
+
+impl std::fmt::Display for PathLimits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} per name, {} per path", self.name, self.path)
+    }
+}
+
+/// `LongPathsEnabled` value of the registry, `false` if it can't be read
+#[cfg(not(unix))]
+fn windows_long_paths_enabled() -> bool {
+    std::process::Command::new("reg")
+        .args([
+            "query",
+            r"HKLM\SYSTEM\CurrentControlSet\Control\FileSystem",
+            "/v",
+            "LongPathsEnabled",
+        ])
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).contains("0x1"))
+        .unwrap_or(false)
+}
+
+/// bytes on unix, UTF-16 units on Windows
+fn length(s: &OsStr) -> usize {
+    #[cfg(unix)]
+    return s.len();
+    #[cfg(not(unix))]
+    return s.to_string_lossy().encode_utf16().count();
+}
+
+/// why the absolute path `path` exceeds the limits. `None` if it doesn't
+pub fn length_problem(path: &Path, limits: &PathLimits) -> Option<String> {
+    let name = length(path.file_name().unwrap_or_default());
+    if name > limits.name {
+        return Some(format!(
+            "name is {} long, the limit is {}",
+            name, limits.name
+        ));
+    }
+    let path = length(path.as_os_str());
+    if path > limits.path {
+        return Some(format!(
+            "path is {} long, the limit is {}",
+            path, limits.path
+        ));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("photo.jpg", None)]
+    #[case("a:b.txt", Some("':' isn't allowed on Windows"))]
+    #[case("tab\there", Some("'\\t' isn't allowed on Windows"))]
+    #[case("con", Some("CON is reserved on Windows"))]
+    #[case("Lpt1.tar.gz", Some("LPT1 is reserved on Windows"))]
+    #[case("console.log", None)]
+    #[case("notes.", Some("Windows drops trailing dots and spaces"))]
+    #[case("notes ", Some("Windows drops trailing dots and spaces"))]
+    fn windows_problem_works(#[case] name: &str, #[case] expected: Option<&str>) {
+        assert_eq!(windows_problem(name).as_deref(), expected);
+    }
+
+    #[rstest]
+    #[case(200, 50, None)]
+    #[case(256, 100, Some("name is 256 long, the limit is 255"))]
+    #[case(100, 157, None)]
+    #[case(255, 3, Some("path is 260 long, the limit is 259"))]
+    fn length_problem_works(
+        #[case] name: usize,
+        #[case] dir: usize,
+        #[case] expected: Option<&str>,
+    ) {
+        let path = Path::new("/").join("d".repeat(dir)).join("n".repeat(name));
+
+        assert_eq!(
+            length_problem(&path, &PathLimits::windows(false)).as_deref(),
+            expected
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use std::borrow::Borrow;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime};
+
+/// metadata of an existing path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stat {
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl Stat {
+    /// `None` if the path doesn't exist or can't be accessed
+    pub fn of(path: &Path) -> Option<Self> {
+        let meta = std::fs::symlink_metadata(path).ok()?;
+        Some(Stat {
+            size: meta.len(),
+            modified: meta.modified().ok(),
+        })
+    }
+}
+
+struct Shared<T, K> {
+    fetch: Box<dyn Fn(&K) -> T + Send + Sync>,
+    /// paths to fetch with the generation of the request they belong to
+    queue: Mutex<VecDeque<(u64, K)>>,
+    queued: Condvar,
+    /// incremented by every request, so the jobs of the previous ones are dropped
+    generation: AtomicU64,
+    /// paths being fetched right now
+    in_flight: AtomicUsize,
+    results: Mutex<HashMap<K, T>>,
+    stop: AtomicBool,
+}
+
+impl<T, K: Eq + Hash> Shared<T, K> {
+    fn work(&self) {
+        loop {
+            let (generation, path) = {
+                let mut queue = self.queue.lock().unwrap();
+                loop {
+                    if self.stop.load(Ordering::Relaxed) {
+                        return;
+                    }
+                    if let Some(job) = queue.pop_front() {
+                        // counted before the lock is released, so `pending` never misses it
+                        self.in_flight.fetch_add(1, Ordering::Relaxed);
+                        break job;
+                    }
+                    queue = self.queued.wait(queue).unwrap();
+                }
+            };
+            if generation == self.generation.load(Ordering::Relaxed) {
+                let value = (self.fetch)(&path);
+                self.results.lock().unwrap().insert(path, value);
+            }
+            self.in_flight.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// fetches metadata of paths on background threads, so slow file systems
+/// and expensive tokens don't block the ui. Stats the paths by default.
+/// Other keys than paths can be fetched with `with_fetch`
+pub struct Prefetcher<T = Option<Stat>, K = PathBuf> {
+    threads: usize,
+    shared: Arc<Shared<T, K>>,
+    /// spawned with the first request
+    workers: Mutex<Vec<JoinHandle<()>>>,
+    /// paths of the last request to skip repeated ones
+    requested: Mutex<Vec<K>>,
+}
+
+impl Default for Prefetcher {
+    fn default() -> Self {
+        Prefetcher::new(4, Stat::of)
+    }
+}
+
+impl<T: Clone + Send + 'static> Prefetcher<T> {
+    /// `fetch` runs on at most `threads` paths at once
+    pub fn new(threads: usize, fetch: fn(&Path) -> T) -> Self {
+        Prefetcher::with_fetch(threads, move |path: &PathBuf| fetch(path))
+    }
+}
+
+impl<T: Clone + Send + 'static, K: Clone + Eq + Hash + Send + 'static> Prefetcher<T, K> {
+    /// `fetch` runs on at most `threads` keys at once
+    pub fn with_fetch(threads: usize, fetch: impl Fn(&K) -> T + Send + Sync + 'static) -> Self {
+        Prefetcher {
+            threads: threads.max(1),
+            shared: Arc::new(Shared {
+                fetch: Box::new(fetch),
+                queue: Mutex::default(),
+                queued: Condvar::new(),
+                generation: AtomicU64::default(),
+                in_flight: AtomicUsize::default(),
+                results: Mutex::default(),
+                stop: AtomicBool::default(),
+            }),
+            workers: Mutex::new(Vec::new()),
+            requested: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// fetch the metadata of `paths` which aren't fetched yet.
+    /// Paths of the previous request which are still queued are cancelled.
+    /// `false` if the paths are the same as the previous time
+    pub fn request(&self, paths: Vec<K>) -> bool {
+        let mut requested = self.requested.lock().unwrap();
+        if *requested == paths {
+            return false;
+        }
+
+        let generation = self.shared.generation.fetch_add(1, Ordering::Relaxed) + 1;
+        {
+            let results = self.shared.results.lock().unwrap();
+            let mut queue = self.shared.queue.lock().unwrap();
+            queue.clear();
+            queue.extend(
+                paths
+                    .iter()
+                    .filter(|path| !results.contains_key(*path))
+                    .map(|path| (generation, path.clone())),
+            );
+        }
+        *requested = paths;
+
+        self.spawn_workers();
+        self.shared.queued.notify_all();
+        true
+    }
+
+    fn spawn_workers(&self) {
+        let mut workers = self.workers.lock().unwrap();
+        while workers.len() < self.threads {
+            let shared = Arc::clone(&self.shared);
+            workers.push(std::thread::spawn(move || shared.work()));
+        }
+    }
+
+    /// `None` if the path isn't fetched yet
+    pub fn get<Q>(&self, path: &Q) -> Option<T>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.shared.results.lock().unwrap().get(path).cloned()
+    }
+
+    /// number of queued and running checks
+    pub fn pending(&self) -> usize {
+        let queue = self.shared.queue.lock().unwrap();
+        queue.len() + self.shared.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// number of fetched paths of the last request and its size
+    pub fn progress(&self) -> (usize, usize) {
+        let requested = self.requested.lock().unwrap();
+        let results = self.shared.results.lock().unwrap();
+        let fetched = requested
+            .iter()
+            .filter(|path| results.contains_key(*path))
+            .count();
+        (fetched, requested.len())
+    }
+
+    /// block until the last request is fetched, or at most for `budget`
+    pub fn wait(&self, budget: Option<Duration>) {
+        let started = Instant::now();
+        while self.pending() > 0 && budget.is_none_or(|budget| started.elapsed() < budget) {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+impl<T, K> Drop for Prefetcher<T, K> {
+    fn drop(&mut self) {
+        {
+            // the lock makes sure no worker misses the notification between its checks
+            let _queue = self.shared.queue.lock().unwrap();
+            self.shared.stop.store(true, Ordering::Relaxed);
+        }
+        self.shared.queued.notify_all();
+        for worker in self.workers.get_mut().unwrap().drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wait_until_done(prefetcher: &Prefetcher) {
+        prefetcher.wait(Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn fetches_in_background() {
+        let dir = tempfile::tempdir().unwrap();
+        let existing = dir.path().join("a");
+        std::fs::write(&existing, b"abc").unwrap();
+        let missing = dir.path().join("b");
+
+        let prefetcher = Prefetcher::new(2, Stat::of);
+        assert_eq!(prefetcher.get(&existing), None);
+
+        prefetcher.request(vec![existing.clone(), missing.clone()]);
+        wait_until_done(&prefetcher);
+
+        assert_eq!(prefetcher.pending(), 0);
+        assert_eq!(prefetcher.get(&existing).unwrap().unwrap().size, 3);
+        assert_eq!(prefetcher.get(&missing), Some(None));
+    }
+
+    #[test]
+    fn new_request_cancels_queued_paths() {
+        let prefetcher = Prefetcher::new(1, Stat::of);
+        let first: Vec<PathBuf> = (0..1000)
+            .map(|i| format!("/nonexistent/{}", i).into())
+            .collect();
+        prefetcher.request(first);
+        prefetcher.request(vec!["/nonexistent/last".into()]);
+        assert!(prefetcher.shared.queue.lock().unwrap().len() <= 1);
+
+        wait_until_done(&prefetcher);
+        assert_eq!(prefetcher.get(Path::new("/nonexistent/last")), Some(None));
+    }
+
+    #[test]
+    fn progress_of_custom_fetch() {
+        fn slow_len(path: &Path) -> usize {
+            std::thread::sleep(Duration::from_millis(50));
+            path.as_os_str().len()
+        }
+
+        let prefetcher = Prefetcher::new(1, slow_len);
+        assert!(prefetcher.request(vec!["a".into(), "bb".into(), "ccc".into()]));
+        assert!(!prefetcher.request(vec!["a".into(), "bb".into(), "ccc".into()]));
+
+        prefetcher.wait(Some(Duration::from_millis(1)));
+        assert!(prefetcher.progress().0 < 3);
+
+        prefetcher.wait(None);
+        assert_eq!(prefetcher.progress(), (3, 3));
+        assert_eq!(prefetcher.get(Path::new("bb")), Some(2));
+    }
+}
@@ -0,0 +1,598 @@
+use crate::app::{App, AppResult};
+use crate::path_utils;
+use crate::planner::{self, ConflictPolicy, MovePair, PlanStats};
+use crate::portability::{self, PathLimits};
+
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+/// lines of preview shown when the terminal size is unknown
+pub const DEFAULT_PAGE_SIZE: usize = 20;
+
+/// terminals that can't show the full-screen app
+pub fn is_dumb_terminal(term: Option<&str>) -> bool {
+    matches!(term, None | Some("") | Some("dumb"))
+}
+
+/// line-based replacement of the full-screen app for terminals without raw mode
+/// or the alternate screen, e.g. serial consoles
+pub struct LinePrompt<R, W> {
+    input: R,
+    output: W,
+    /// preview lines printed before asking to continue
+    page_size: usize,
+    preview_only: bool,
+}
+
+/// what to do after the preview
+enum Answer {
+    Apply,
+    Edit,
+    Quit,
+}
+
+/// what to do with a destination which already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictAnswer {
+    Overwrite,
+    Skip,
+    Abort,
+    /// overwrite this and all the following existing destinations
+    OverwriteAll,
+    /// skip this and all the following files with existing destinations
+    SkipAll,
+}
+
+impl<R: BufRead, W: Write> LinePrompt<R, W> {
+    pub fn new(input: R, output: W, page_size: usize) -> Self {
+        LinePrompt {
+            input,
+            output,
+            page_size: page_size.max(1),
+            preview_only: false,
+        }
+    }
+
+    pub fn with_preview_only(mut self, preview_only: bool) -> Self {
+        self.preview_only = preview_only;
+        self
+    }
+
+    /// edit the rule, preview and confirm until the renames are accepted or the input ends
+    pub fn run(&mut self, app: &mut App) -> std::io::Result<AppResult> {
+        loop {
+            let Some(regex) = self.ask(&format!("pattern [{}]: ", app.regex()))? else {
+                return Ok(AppResult::Exit);
+            };
+            if !regex.is_empty() {
+                app.set_regex(regex);
+            }
+
+            let Some(replacement) = self.ask(&format!("replacement [{}]: ", app.replacement()))?
+            else {
+                return Ok(AppResult::Exit);
+            };
+            if !replacement.is_empty() {
+                app.set_replacement(replacement);
+            }
+
+            let move_pairs = match app.plan() {
+                Ok(move_pairs) => move_pairs,
+                Err(err) => {
+                    writeln!(self.output, "error: {}", err)?;
+                    continue;
+                }
+            };
+            if !self.print_preview(&move_pairs, app.overwrite_marker())? {
+                return Ok(AppResult::Exit);
+            }
+
+            match self.confirm(move_pairs.len())? {
+                Answer::Apply => {}
+                Answer::Edit => continue,
+                Answer::Quit => return Ok(AppResult::Exit),
+            }
+
+            // the typed number already confirms the overwrites it lists
+            let counted = match app
+                .confirm_thresholds()
+                .check(&move_pairs, app.on_conflict())
+            {
+                Some(stats) if !self.confirm_count(&stats)? => continue,
+                Some(_) => true,
+                None => false,
+            };
+            if let Some(move_pairs) =
+                self.confirm_overwrites(move_pairs, app.on_conflict(), counted)?
+            {
+                return Ok(AppResult::MoveFiles(move_pairs));
+            }
+        }
+    }
+
+    /// ask about the renames replacing existing files, the renaming doesn't ask again.
+    /// `--on-conflict prompt` overwrites or skips them all, the other policies need `yes` typed
+    /// unless `counted`. `None` goes back to editing
+    fn confirm_overwrites(
+        &mut self,
+        move_pairs: Vec<MovePair>,
+        on_conflict: ConflictPolicy,
+        counted: bool,
+    ) -> std::io::Result<Option<Vec<MovePair>>> {
+        let existing = planner::existing_destinations(&move_pairs);
+        // skipped by the renaming anyway
+        if existing.is_empty() || on_conflict == ConflictPolicy::Skip {
+            return Ok(Some(move_pairs));
+        }
+
+        if on_conflict == ConflictPolicy::Prompt {
+            let prompt = format!(
+                "{} new names are taken by existing files. [o]verwrite them, [s]kip these files, [e]dit: ",
+                existing.len()
+            );
+            loop {
+                match self.ask(&prompt)?.as_deref().map(str::trim) {
+                    Some("o") => return Ok(Some(move_pairs)),
+                    Some("s") => {
+                        return Ok(Some(
+                            move_pairs
+                                .into_iter()
+                                .filter(|(_, dst)| !existing.contains(dst))
+                                .collect(),
+                        ))
+                    }
+                    None | Some("e") => return Ok(None),
+                    Some(_) => {}
+                }
+            }
+        }
+
+        if counted {
+            return Ok(Some(move_pairs));
+        }
+        let prompt = format!(
+            "{} existing files will be replaced, type yes to continue: ",
+            existing.len()
+        );
+        let typed = self.ask(&prompt)?;
+        Ok((typed.as_deref().map(str::trim) == Some("yes")).then_some(move_pairs))
+    }
+
+    /// print `prompt` and read a line without the line break. `None` at the end of input
+    fn ask(&mut self, prompt: &str) -> std::io::Result<Option<String>> {
+        write!(self.output, "{}", prompt)?;
+        self.output.flush()?;
+
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            writeln!(self.output)?;
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\n', '\r']).to_owned()))
+    }
+
+    /// print the renames page by page. `false` if the input ended meanwhile
+    fn print_preview(
+        &mut self,
+        move_pairs: &[MovePair],
+        overwrite_marker: &str,
+    ) -> std::io::Result<bool> {
+        if move_pairs.is_empty() {
+            writeln!(self.output, "nothing to rename")?;
+            return Ok(true);
+        }
+
+        let existing = planner::existing_destinations(move_pairs);
+        let limits = PathLimits::native();
+        let pages: Vec<_> = move_pairs.chunks(self.page_size).collect();
+        for (idx, page) in pages.iter().enumerate() {
+            for (source, destination) in page.iter() {
+                let warning = if existing.contains(destination) {
+                    overwrite_marker
+                } else {
+                    ""
+                };
+                let length_warning =
+                    portability::length_problem(&planner::absolute(destination), &limits)
+                        .map(|problem| format!(" ({})", problem))
+                        .unwrap_or_default();
+                writeln!(
+                    self.output,
+                    "{} -> {}{}{}",
+                    source.display(),
+                    destination.display(),
+                    warning,
+                    length_warning
+                )?;
+            }
+
+            if idx + 1 < pages.len() {
+                let prompt = format!(
+                    "-- page {}/{}, Enter: next page, q: skip the rest -- ",
+                    idx + 1,
+                    pages.len()
+                );
+                match self.ask(&prompt)?.as_deref() {
+                    None => return Ok(false),
+                    Some("q") => break,
+                    Some(_) => {}
+                }
+            }
+        }
+
+        if !existing.is_empty() {
+            writeln!(
+                self.output,
+                "warning: {} existing files will be overwritten",
+                existing.len()
+            )?;
+        }
+        Ok(true)
+    }
+
+    /// ask what to do with the existing `destination`. The end of input aborts
+    pub fn ask_conflict(&mut self, destination: &Path) -> std::io::Result<ConflictAnswer> {
+        let prompt = format!(
+            "{} already exists. [o]verwrite, [s]kip, [a]bort, [O]verwrite all, [S]kip all: ",
+            destination.display()
+        );
+
+        loop {
+            match self.ask(&prompt)?.as_deref().map(str::trim) {
+                None | Some("a") => return Ok(ConflictAnswer::Abort),
+                Some("o") => return Ok(ConflictAnswer::Overwrite),
+                Some("s") => return Ok(ConflictAnswer::Skip),
+                Some("O") => return Ok(ConflictAnswer::OverwriteAll),
+                Some("S") => return Ok(ConflictAnswer::SkipAll),
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// ask about every rename, like `git add -p`. Returns the accepted ones,
+    /// quitting or the end of input keeps the ones accepted so far
+    pub fn confirm_each(&mut self, move_pairs: Vec<MovePair>) -> std::io::Result<Vec<MovePair>> {
+        let total = move_pairs.len();
+        let mut accepted = Vec::new();
+        let mut pairs = move_pairs.into_iter().enumerate();
+        while let Some((idx, pair)) = pairs.next() {
+            let prompt = format!(
+                "({}/{}) {} -> {}  [y]es, [n]o, [a]ll the rest, [q]uit: ",
+                idx + 1,
+                total,
+                path_utils::display_path(&pair.0),
+                path_utils::display_path(&pair.1)
+            );
+            loop {
+                match self.ask(&prompt)?.as_deref().map(str::trim) {
+                    Some("y") => {
+                        accepted.push(pair);
+                        break;
+                    }
+                    Some("n") => break,
+                    Some("a") => {
+                        accepted.push(pair);
+                        accepted.extend(pairs.map(|(_, pair)| pair));
+                        return Ok(accepted);
+                    }
+                    None | Some("q") => return Ok(accepted),
+                    Some(_) => {}
+                }
+            }
+        }
+        Ok(accepted)
+    }
+
+    /// list the renames which would fail and ask whether to execute the `valid` other ones
+    pub fn confirm_valid(
+        &mut self,
+        failures: &[(MovePair, String)],
+        valid: usize,
+    ) -> std::io::Result<bool> {
+        writeln!(self.output, "{} renames would fail:", failures.len())?;
+        for ((source, _), reason) in failures {
+            writeln!(self.output, "  {}: {}", source.display(), reason)?;
+        }
+        if valid == 0 {
+            return Ok(false);
+        }
+
+        let prompt = format!("rename the other {} files? [y]es, [n]o: ", valid);
+        loop {
+            match self.ask(&prompt)?.as_deref().map(str::trim) {
+                None | Some("n") => return Ok(false),
+                Some("y") => return Ok(true),
+                Some(_) => {}
+            }
+        }
+    }
+
+    /// show how much a plan above the confirmation thresholds changes and ask to type
+    /// the number of its files. Anything else or the end of input declines it
+    pub fn confirm_count(&mut self, stats: &PlanStats) -> std::io::Result<bool> {
+        writeln!(self.output, "files to rename: {}", stats.files)?;
+        writeln!(
+            self.output,
+            "existing files to overwrite: {}",
+            stats.overwrites
+        )?;
+        writeln!(self.output, "moves to other directories: {}", stats.moves)?;
+
+        let prompt = format!("type {} to execute: ", stats.files);
+        let typed = self.ask(&prompt)?;
+        Ok(typed.as_deref().map(str::trim) == Some(stats.files.to_string().as_str()))
+    }
+
+    fn confirm(&mut self, renames: usize) -> std::io::Result<Answer> {
+        let prompt = if self.preview_only || renames == 0 {
+            "[e]dit, [q]uit: ".to_owned()
+        } else {
+            format!("rename {} files? [y]es, [e]dit, [q]uit: ", renames)
+        };
+
+        loop {
+            match self.ask(&prompt)?.as_deref().map(str::trim) {
+                None | Some("q") => return Ok(Answer::Quit),
+                Some("y") if !self.preview_only && renames > 0 => return Ok(Answer::Apply),
+                Some("e") | Some("") => return Ok(Answer::Edit),
+                Some(_) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::planner::ConfirmThresholds;
+    use rstest::rstest;
+    use std::path::PathBuf;
+
+    fn run(input: &str, page_size: usize) -> (AppResult, String) {
+        let mut app = App::default().with_files(
+            ["a1.txt", "a2.txt", "a3.txt", "b.txt"]
+                .iter()
+                .map(PathBuf::from)
+                .collect(),
+        );
+        let mut output = Vec::new();
+        let res = LinePrompt::new(input.as_bytes(), &mut output, page_size)
+            .run(&mut app)
+            .unwrap();
+        (res, String::from_utf8(output).unwrap())
+    }
+
+    #[rstest]
+    #[case(None, true)]
+    #[case(Some(""), true)]
+    #[case(Some("dumb"), true)]
+    #[case(Some("xterm-256color"), false)]
+    fn is_dumb_terminal_works(#[case] term: Option<&str>, #[case] expected: bool) {
+        assert_eq!(is_dumb_terminal(term), expected);
+    }
+
+    /// destination names of the applied renames
+    fn destinations(res: AppResult) -> Vec<String> {
+        match res {
+            AppResult::MoveFiles(pairs) => pairs
+                .iter()
+                .map(|(_, dst)| dst.file_name().unwrap().to_string_lossy().into_owned())
+                .collect(),
+            AppResult::Exit => vec![],
+        }
+    }
+
+    #[test]
+    fn renames_are_applied() {
+        let (res, output) = run("^a\nc\ny\n", 10);
+
+        assert_eq!(destinations(res), vec!["c1.txt", "c2.txt", "c3.txt"]);
+        assert!(output.contains("a1.txt -> "));
+        assert!(output.contains("rename 3 files?"));
+    }
+
+    #[test]
+    fn preview_is_paginated() {
+        let (res, output) = run("^a\nc\n\nq\n", 2);
+
+        assert!(matches!(res, AppResult::Exit));
+        assert!(output.contains("-- page 1/2"));
+        assert!(output.contains("c3.txt"));
+    }
+
+    #[test]
+    fn rest_of_preview_is_skipped() {
+        let (_, output) = run("^a\nc\nq\nq\n", 2);
+
+        assert!(output.contains("c2.txt"));
+        assert!(!output.contains("c3.txt"));
+    }
+
+    #[test]
+    fn rule_is_edited_again() {
+        let (res, output) = run("^a\nc\ne\n\nd\ny\n", 10);
+
+        assert!(output.contains("pattern [^a]: "));
+        assert_eq!(destinations(res), vec!["d1.txt", "d2.txt", "d3.txt"]);
+    }
+
+    #[test]
+    fn overwrites_are_marked() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.txt", "b.txt"] {
+            std::fs::write(dir.path().join(name), "").unwrap();
+        }
+        let mut app = App::default().with_files(vec![dir.path().join("a.txt")]);
+        let mut output = Vec::new();
+        LinePrompt::new("^a\nb\nq\n".as_bytes(), &mut output, 10)
+            .run(&mut app)
+            .unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("b.txt (overwrites)"));
+        assert!(output.contains("warning: 1 existing files will be overwritten"));
+    }
+
+    /// the app with "a.txt" and "c.txt", "a.md" exists
+    fn overwriting_app(dir: &Path) -> App {
+        for name in ["a.txt", "a.md", "c.txt"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        App::default().with_files(vec![dir.join("a.txt"), dir.join("c.txt")])
+    }
+
+    #[rstest]
+    #[case("txt$\nmd\ny\nyes\n", vec!["a.md", "c.md"])]
+    #[case("txt$\nmd\ny\n\n", vec![])]
+    #[case("txt$\nmd\ny\nno\n\n\ny\nyes\n", vec!["a.md", "c.md"])]
+    fn overwrites_are_confirmed(#[case] input: &str, #[case] expected: Vec<&str>) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = overwriting_app(dir.path()).with_confirm_thresholds(ConfirmThresholds {
+            overwrites: 1,
+            ..ConfirmThresholds::default()
+        });
+        let mut output = Vec::new();
+        let res = LinePrompt::new(input.as_bytes(), &mut output, 10)
+            .run(&mut app)
+            .unwrap();
+
+        assert_eq!(destinations(res), expected);
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("1 existing files will be replaced, type yes to continue: "));
+    }
+
+    #[rstest]
+    #[case("s\n", vec!["c.md"])]
+    #[case("o\n", vec!["a.md", "c.md"])]
+    #[case("e\n", vec![])]
+    fn overwrites_are_prompted(#[case] answer: &str, #[case] expected: Vec<&str>) {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = overwriting_app(dir.path()).with_on_conflict(ConflictPolicy::Prompt);
+        let input = format!("txt$\nmd\ny\n{}", answer);
+        let mut output = Vec::new();
+        let res = LinePrompt::new(input.as_bytes(), &mut output, 10)
+            .run(&mut app)
+            .unwrap();
+
+        assert_eq!(destinations(res), expected);
+    }
+
+    #[test]
+    fn thresholds_are_confirmed() {
+        let mut app = App::default()
+            .with_files(
+                ["a1.txt", "a2.txt", "a3.txt"]
+                    .iter()
+                    .map(PathBuf::from)
+                    .collect(),
+            )
+            .with_confirm_thresholds(ConfirmThresholds {
+                files: 2,
+                ..ConfirmThresholds::default()
+            });
+        let mut output = Vec::new();
+        let res = LinePrompt::new("^a\nc\ny\n2\n\n\ny\n3\n".as_bytes(), &mut output, 10)
+            .run(&mut app)
+            .unwrap();
+
+        assert_eq!(destinations(res), vec!["c1.txt", "c2.txt", "c3.txt"]);
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("type 3 to execute: "));
+    }
+
+    #[rstest]
+    #[case("o\n", ConflictAnswer::Overwrite)]
+    #[case("x\nS\n", ConflictAnswer::SkipAll)]
+    #[case("", ConflictAnswer::Abort)]
+    fn conflict_is_asked(#[case] input: &str, #[case] expected: ConflictAnswer) {
+        let mut output = Vec::new();
+        let answer = LinePrompt::new(input.as_bytes(), &mut output, 10)
+            .ask_conflict(Path::new("b.txt"))
+            .unwrap();
+
+        assert_eq!(answer, expected);
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .starts_with("b.txt already exists."));
+    }
+
+    #[rstest]
+    #[case("y\n", 1, true)]
+    #[case("n\n", 1, false)]
+    #[case("y\n", 0, false)]
+    fn valid_renames_are_confirmed(
+        #[case] input: &str,
+        #[case] valid: usize,
+        #[case] expected: bool,
+    ) {
+        let failures = [(
+            ("a".into(), "b".into()),
+            "directory / isn't writable".to_owned(),
+        )];
+        let mut output = Vec::new();
+        let confirmed = LinePrompt::new(input.as_bytes(), &mut output, 10)
+            .confirm_valid(&failures, valid)
+            .unwrap();
+
+        assert_eq!(confirmed, expected);
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .contains("a: directory / isn't writable"));
+    }
+
+    #[rstest]
+    #[case("y\nn\ny\n", &["a", "c"])]
+    #[case("n\nx\na\n", &["b", "c"])]
+    #[case("y\nq\n", &["a"])]
+    #[case("y\n", &["a"])]
+    fn renames_are_confirmed_one_by_one(#[case] input: &str, #[case] expected: &[&str]) {
+        let move_pairs: Vec<MovePair> = ["a", "b", "c"]
+            .iter()
+            .map(|name| (PathBuf::from(name), PathBuf::from(format!("{}2", name))))
+            .collect();
+        let mut output = Vec::new();
+        let accepted = LinePrompt::new(input.as_bytes(), &mut output, 10)
+            .confirm_each(move_pairs)
+            .unwrap();
+
+        let sources: Vec<&str> = accepted
+            .iter()
+            .map(|(src, _)| src.to_str().unwrap())
+            .collect();
+        assert_eq!(sources, expected);
+        assert!(String::from_utf8(output)
+            .unwrap()
+            .starts_with("(1/3) a -> a2"));
+    }
+
+    #[rstest]
+    #[case("3\n", true)]
+    #[case("y\n", false)]
+    #[case("", false)]
+    fn count_is_typed(#[case] input: &str, #[case] expected: bool) {
+        let stats = PlanStats {
+            files: 3,
+            overwrites: 1,
+            moves: 0,
+        };
+        let mut output = Vec::new();
+        let confirmed = LinePrompt::new(input.as_bytes(), &mut output, 10)
+            .confirm_count(&stats)
+            .unwrap();
+
+        assert_eq!(confirmed, expected);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("existing files to overwrite: 1"));
+        assert!(output.contains("type 3 to execute: "));
+    }
+
+    #[test]
+    fn invalid_regex_is_reported() {
+        let (res, output) = run("(\n\n", 10);
+
+        assert!(matches!(res, AppResult::Exit));
+        assert!(output.contains("error: invalid regex"));
+    }
+}
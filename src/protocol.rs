@@ -0,0 +1,187 @@
+use crate::journal::Rename;
+use crate::planner::{MovePair, RenameDetails};
+
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// command read from a line of stdin in `--stdio-protocol` mode, e.g.
+/// `{"command": "set-regex", "value": "\\.jpeg$"}`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Request {
+    SetRegex {
+        value: String,
+    },
+    SetReplace {
+        value: String,
+    },
+    /// show the renames without executing them
+    Preview,
+    /// execute the renames
+    Apply,
+}
+
+impl Request {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        serde_json::from_str(line).map_err(|err| format!("invalid request: {}", err))
+    }
+}
+
+/// line written to stdout for every request
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// planned renames for `preview`, executed ones for `apply`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renames: Option<Vec<RenameEntry>>,
+    /// renames of `apply` which aren't executed because their destinations exist
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<Rename>,
+    /// renames of a failed `apply` reverted to restore the original names
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rolled_back: Vec<Rename>,
+    /// script reverting an `apply`, written with `--undo-script`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub undo_script: Option<PathBuf>,
+}
+
+/// rename in a response, planned ones come with their details
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RenameEntry {
+    #[serde(flatten)]
+    pub rename: Rename,
+    #[serde(flatten)]
+    pub details: Option<RenameDetails>,
+}
+
+impl Response {
+    pub fn ok() -> Self {
+        Response {
+            ok: true,
+            error: None,
+            renames: None,
+            skipped: Vec::new(),
+            rolled_back: Vec::new(),
+            undo_script: None,
+        }
+    }
+
+    pub fn error(error: impl Into<String>) -> Self {
+        Response {
+            ok: false,
+            error: Some(error.into()),
+            renames: None,
+            skipped: Vec::new(),
+            rolled_back: Vec::new(),
+            undo_script: None,
+        }
+    }
+
+    pub fn renames(move_pairs: &[MovePair]) -> Self {
+        Response {
+            renames: Some(
+                move_pairs
+                    .iter()
+                    .map(|(src, dst)| RenameEntry {
+                        rename: Rename {
+                            src: src.clone(),
+                            dst: dst.clone(),
+                        },
+                        details: None,
+                    })
+                    .collect(),
+            ),
+            ..Response::ok()
+        }
+    }
+
+    /// renames which aren't executed yet with their details
+    pub fn plan(move_pairs: &[MovePair]) -> Self {
+        let mut response = Response::renames(move_pairs);
+        if let Some(renames) = &mut response.renames {
+            for (entry, details) in renames.iter_mut().zip(RenameDetails::of(move_pairs)) {
+                entry.details = Some(details);
+            }
+        }
+        response
+    }
+
+    pub fn with_skipped(mut self, skipped: &[MovePair]) -> Self {
+        self.skipped = to_renames(skipped);
+        self
+    }
+
+    pub fn with_rolled_back(mut self, rolled_back: &[MovePair]) -> Self {
+        self.rolled_back = to_renames(rolled_back);
+        self
+    }
+
+    pub fn with_undo_script(mut self, undo_script: Option<PathBuf>) -> Self {
+        self.undo_script = undo_script;
+        self
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("response is always serializable")
+    }
+}
+
+fn to_renames(move_pairs: &[MovePair]) -> Vec<Rename> {
+    move_pairs
+        .iter()
+        .map(|(src, dst)| Rename {
+            src: src.clone(),
+            dst: dst.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(r#"{"command": "set-regex", "value": "a+"}"#, Request::SetRegex { value: "a+".into() })]
+    #[case(r#"{"command": "set-replace", "value": "$1"}"#, Request::SetReplace { value: "$1".into() })]
+    #[case(r#"{"command": "preview"}"#, Request::Preview)]
+    #[case(r#"{"command": "apply"}"#, Request::Apply)]
+    fn request_parse_works(#[case] line: &str, #[case] expected: Request) {
+        assert_eq!(Request::parse(line), Ok(expected));
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("preview")]
+    #[case(r#"{"command": "undo"}"#)]
+    #[case(r#"{"command": "set-regex"}"#)]
+    #[case(r#"{"command": "set-regex", "regex": "a"}"#)]
+    fn request_parse_fails(#[case] line: &str) {
+        assert!(Request::parse(line).is_err());
+    }
+
+    #[test]
+    fn responses_are_json_lines() {
+        assert_eq!(Response::ok().to_json(), r#"{"ok":true}"#);
+        assert_eq!(
+            Response::error("invalid regex").to_json(),
+            r#"{"ok":false,"error":"invalid regex"}"#
+        );
+        assert_eq!(
+            Response::renames(&[("a".into(), "b".into())]).to_json(),
+            r#"{"ok":true,"renames":[{"src":"a","dst":"b"}]}"#
+        );
+        assert_eq!(
+            Response::renames(&[("a".into(), "b".into())])
+                .with_undo_script(Some("/j/undo.sh".into()))
+                .to_json(),
+            r#"{"ok":true,"renames":[{"src":"a","dst":"b"}],"undo_script":"/j/undo.sh"}"#
+        );
+        assert_eq!(
+            Response::plan(&[("/nonexistent/ab".into(), "/nonexistent/sub/ac".into())]).to_json(),
+            r#"{"ok":true,"renames":[{"src":"/nonexistent/ab","dst":"/nonexistent/sub/ac","edit_distance":1,"changes_dir":true,"overwrites":false}]}"#
+        );
+    }
+}
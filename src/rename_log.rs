@@ -0,0 +1,108 @@
+use crate::journal::Rename;
+
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Context;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+/// what happened to a rename
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogResult {
+    Renamed,
+    /// kept under the old name because the destination exists
+    Skipped,
+    Failed,
+    /// renamed and reverted after a later failure
+    RolledBack,
+}
+
+/// line of the `--log-file`: one step of the renaming as a JSON object
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub timestamp: DateTime<Local>,
+    #[serde(flatten)]
+    pub rename: Rename,
+    pub result: LogResult,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl LogEntry {
+    pub fn new(src: &Path, dst: &Path, result: LogResult) -> Self {
+        LogEntry {
+            timestamp: Local::now(),
+            rename: Rename {
+                src: src.to_owned(),
+                dst: dst.to_owned(),
+            },
+            result,
+            error: None,
+        }
+    }
+
+    pub fn failed(src: &Path, dst: &Path, err: &std::io::Error) -> Self {
+        LogEntry {
+            error: Some(err.to_string()),
+            ..LogEntry::new(src, dst, LogResult::Failed)
+        }
+    }
+}
+
+/// append the `entries` to the log at `path`, one JSON object per line
+pub fn append(path: &Path, entries: &[LogEntry]) -> anyhow::Result<()> {
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("can't open log file {}", path.display()))?;
+
+    let mut lines = String::new();
+    for entry in entries {
+        lines.push_str(&serde_json::to_string(entry)?);
+        lines.push('\n');
+    }
+    file.write_all(lines.as_bytes())
+        .with_context(|| format!("can't write log file {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_appended_as_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("irename.log");
+        let err = std::io::Error::new(std::io::ErrorKind::NotFound, "gone");
+
+        append(
+            &path,
+            &[LogEntry::new(
+                Path::new("/a"),
+                Path::new("/b"),
+                LogResult::Renamed,
+            )],
+        )
+        .unwrap();
+        append(
+            &path,
+            &[LogEntry::failed(Path::new("/c"), Path::new("/d"), &err)],
+        )
+        .unwrap();
+
+        let lines: Vec<serde_json::Value> = std::fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["src"], "/a");
+        assert_eq!(lines[0]["result"], "renamed");
+        assert!(lines[0].get("error").is_none());
+        assert_eq!(lines[1]["result"], "failed");
+        assert_eq!(lines[1]["error"], "gone");
+    }
+}
@@ -0,0 +1,673 @@
+use crate::filter::{self, MetadataFilter};
+use crate::path_utils;
+use crate::planner;
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+use std::fs::FileType;
+use std::io::BufReader;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+/// names of the files with ignore rules, the later ones take precedence
+const IGNORE_FILES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// kind of a directory entry, symlinks aren't followed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+}
+
+impl FileKind {
+    /// `None` if the path doesn't exist or can't be accessed
+    pub fn of(path: &Path) -> Option<Self> {
+        Some(Self::of_type(
+            std::fs::symlink_metadata(path).ok()?.file_type(),
+        ))
+    }
+
+    fn of_type(file_type: FileType) -> Self {
+        if file_type.is_symlink() {
+            FileKind::Symlink
+        } else if file_type.is_dir() {
+            FileKind::Dir
+        } else {
+            FileKind::File
+        }
+    }
+
+    /// name of the kind for `--type`
+    pub fn name(self) -> &'static str {
+        match self {
+            FileKind::File => "file",
+            FileKind::Dir => "dir",
+            FileKind::Symlink => "symlink",
+        }
+    }
+
+    /// one letter for the column of the files list
+    pub fn marker(self) -> char {
+        match self {
+            FileKind::File => 'f',
+            FileKind::Dir => 'd',
+            FileKind::Symlink => 'l',
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    /// levels of directories to walk, 1 for the direct children
+    pub max_depth: Option<usize>,
+    /// skip the paths matched by `.gitignore` and `.ignore` files
+    pub ignore_files: bool,
+    /// kinds of the found paths, files and symlinks if empty.
+    /// Directories are walked even if they aren't among them
+    pub types: Vec<FileKind>,
+    /// limits of the size and modification time of the found paths
+    pub metadata: MetadataFilter,
+}
+
+impl ScanOptions {
+    fn accepts(&self, kind: FileKind) -> bool {
+        if self.types.is_empty() {
+            kind != FileKind::Dir
+        } else {
+            self.types.contains(&kind)
+        }
+    }
+}
+
+/// paths left out by a walk
+#[derive(Debug, Default)]
+pub struct ScanCounters {
+    /// matched by ignore rules
+    pub ignored: AtomicUsize,
+    /// not of the requested types
+    pub other_types: AtomicUsize,
+    /// outside of the size or modification time limits
+    pub out_of_limits: AtomicUsize,
+}
+
+impl ScanCounters {
+    /// count a path which isn't passed on
+    fn skip(&self, kind_accepted: bool) {
+        let counter = if kind_accepted {
+            &self.out_of_limits
+        } else {
+            &self.other_types
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// paths under `root` in name order, passed to `found` until it returns `false`.
+/// A `root` which isn't a directory is passed itself.
+/// Symlinked directories aren't followed and unreadable ones are skipped.
+/// Returns `false` if `found` stopped the walk
+pub fn walk(
+    root: &Path,
+    options: &ScanOptions,
+    counters: &ScanCounters,
+    found: &mut dyn FnMut(PathBuf) -> bool,
+) -> bool {
+    if !root.is_dir() {
+        return found(root.to_owned());
+    }
+    let mut ignores = if options.ignore_files {
+        parent_ignores(root)
+    } else {
+        Vec::new()
+    };
+    walk_dir(root, 1, options, &mut ignores, counters, found)
+}
+
+fn walk_dir(
+    dir: &Path,
+    depth: usize,
+    options: &ScanOptions,
+    ignores: &mut Vec<IgnoreFile>,
+    counters: &ScanCounters,
+    found: &mut dyn FnMut(PathBuf) -> bool,
+) -> bool {
+    if options.max_depth.is_some_and(|max_depth| depth > max_depth) {
+        return true;
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return true;
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .collect();
+    paths.sort();
+
+    let outer_ignores = ignores.len();
+    if options.ignore_files {
+        ignores.extend(IgnoreFile::load_all(dir, dir, PathBuf::new()));
+    }
+
+    let mut proceed = true;
+    for path in paths {
+        let Ok(meta) = std::fs::symlink_metadata(&path) else {
+            continue;
+        };
+        let kind = FileKind::of_type(meta.file_type());
+        let is_dir = kind == FileKind::Dir;
+        if options.ignore_files {
+            // git never lists its own directory
+            if is_dir && path.file_name().is_some_and(|name| name == ".git") {
+                continue;
+            }
+            if is_ignored(ignores, &path, is_dir) {
+                counters.ignored.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+        }
+
+        let kind_accepted = options.accepts(kind);
+        if kind_accepted && options.metadata.matches(&meta) {
+            proceed = found(path.clone());
+        } else if !is_dir || kind_accepted {
+            // directories aren't counted when they are only walked
+            counters.skip(kind_accepted);
+        }
+        if proceed && is_dir {
+            proceed = walk_dir(&path, depth + 1, options, ignores, counters, found);
+        }
+        if !proceed {
+            break;
+        }
+    }
+
+    ignores.truncate(outer_ignores);
+    proceed
+}
+
+/// rules of the `.gitignore` and `.ignore` files of a directory, or of the exclude files of git
+struct IgnoreFile {
+    /// the walked directory the paths are relative to
+    dir: PathBuf,
+    /// path of `dir` relative to the directory with the file, for files above the walked root
+    prefix: PathBuf,
+    matcher: Gitignore,
+}
+
+impl IgnoreFile {
+    /// ignore files in `location` applying to the paths under `dir`.
+    /// `None` if there are none
+    fn load_all(location: &Path, dir: &Path, prefix: PathBuf) -> Option<IgnoreFile> {
+        let files: Vec<PathBuf> = IGNORE_FILES
+            .iter()
+            .map(|name| location.join(name))
+            .filter(|path| path.is_file())
+            .collect();
+        Self::load(location, &files, dir, prefix)
+    }
+
+    /// rules of `files` whose patterns are relative to `location`, the later files
+    /// take precedence. Invalid patterns are left out
+    fn load(location: &Path, files: &[PathBuf], dir: &Path, prefix: PathBuf) -> Option<IgnoreFile> {
+        if files.is_empty() {
+            return None;
+        }
+        // the paths are passed relative to `location`, an absolute root is never stripped from them
+        let mut builder = GitignoreBuilder::new(planner::absolute(location));
+        for file in files {
+            builder.add(file);
+        }
+        let matcher = builder.build().ok()?;
+
+        Some(IgnoreFile {
+            dir: dir.to_owned(),
+            prefix,
+            matcher,
+        })
+    }
+}
+
+/// ignore rules of the directories above `root` up to the root of its git repository,
+/// with `.git/info/exclude` and the global `core.excludesFile` of git.
+/// Outside of repositories only the files of the walked directories are used
+fn parent_ignores(root: &Path) -> Vec<IgnoreFile> {
+    let Ok(canonical) = root.canonicalize() else {
+        return Vec::new();
+    };
+    let Some(repo) = canonical.ancestors().find(|dir| dir.join(".git").exists()) else {
+        return Vec::new();
+    };
+    let repo_prefix = canonical
+        .strip_prefix(repo)
+        .unwrap_or(&canonical)
+        .to_owned();
+
+    // from the lowest precedence to the highest
+    let mut ignores = Vec::new();
+    let (global, _) = Gitignore::global();
+    if !global.is_empty() {
+        ignores.push(IgnoreFile {
+            dir: root.to_owned(),
+            prefix: repo_prefix.clone(),
+            matcher: global,
+        });
+    }
+    ignores.extend(IgnoreFile::load(
+        repo,
+        &[repo.join(".git/info/exclude")]
+            .into_iter()
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>(),
+        root,
+        repo_prefix,
+    ));
+
+    let mut parents: Vec<&Path> = canonical
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(repo))
+        .collect();
+    parents.reverse();
+    ignores.extend(parents.into_iter().filter_map(|parent| {
+        let prefix = canonical.strip_prefix(parent).unwrap_or(&canonical);
+        IgnoreFile::load_all(parent, root, prefix.to_owned())
+    }));
+    ignores
+}
+
+/// whether the last rule matching `path` ignores it
+fn is_ignored(ignores: &[IgnoreFile], path: &Path, is_dir: bool) -> bool {
+    let mut ignored = false;
+    for file in ignores {
+        let Ok(relative) = path.strip_prefix(&file.dir) else {
+            continue;
+        };
+        match file.matcher.matched(file.prefix.join(relative), is_dir) {
+            Match::Ignore(_) => ignored = true,
+            Match::Whitelist(_) => ignored = false,
+            Match::None => {}
+        }
+    }
+    ignored
+}
+
+/// paths matching a shell-like glob pattern, in name order. `*`, `?` and `[...]` match
+/// within a path component and `**` matches any number of directories.
+/// Names starting with a dot are matched only by components starting with a dot
+pub fn expand_glob(pattern: &str) -> Vec<PathBuf> {
+    let mut matched = vec![PathBuf::new()];
+    for component in Path::new(pattern).components() {
+        let part = component.as_os_str().to_string_lossy();
+        matched = match component {
+            Component::Normal(_) if part == "**" => matched
+                .iter()
+                .flat_map(|base| std::iter::once(base.clone()).chain(subdirs(base)))
+                .collect(),
+            Component::Normal(_) if filter::is_glob(&part) => {
+                let regex = filter::name_glob_regex(&part);
+                matched
+                    .iter()
+                    .flat_map(|base| {
+                        let Ok(entries) = std::fs::read_dir(dir_or_current(base)) else {
+                            return Vec::new();
+                        };
+                        let mut names: Vec<_> = entries
+                            .filter_map(|entry| Some(entry.ok()?.file_name()))
+                            .filter(|name| {
+                                let name = name.to_string_lossy();
+                                regex.is_match(&name)
+                                    && (!name.starts_with('.') || part.starts_with('.'))
+                            })
+                            .collect();
+                        names.sort();
+                        names.into_iter().map(|name| base.join(name)).collect()
+                    })
+                    .collect()
+            }
+            _ => matched.iter().map(|base| base.join(component)).collect(),
+        };
+    }
+
+    matched
+        .into_iter()
+        .filter(|path| !path.as_os_str().is_empty() && std::fs::symlink_metadata(path).is_ok())
+        .collect()
+}
+
+/// directories under `dir` at any depth, except hidden ones and the ones inside them
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    let root = dir_or_current(dir);
+    let options = ScanOptions {
+        types: vec![FileKind::Dir],
+        ..ScanOptions::default()
+    };
+    let mut dirs = Vec::new();
+    walk(&root, &options, &ScanCounters::default(), &mut |path| {
+        let relative = path.strip_prefix(&root).unwrap_or(&path);
+        let hidden = relative
+            .components()
+            .any(|component| component.as_os_str().to_string_lossy().starts_with('.'));
+        if !hidden {
+            dirs.push(dir.join(relative));
+        }
+        true
+    });
+    dirs
+}
+
+/// `.` for the empty path of relative patterns
+fn dir_or_current(dir: &Path) -> PathBuf {
+    if dir.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        dir.to_owned()
+    }
+}
+
+/// walk of directories in a background thread, so the app starts before huge trees are read
+pub struct DirScan {
+    found: Receiver<PathBuf>,
+    counters: Arc<ScanCounters>,
+    /// what the scan does, for the title of the files list
+    activity: &'static str,
+}
+
+impl DirScan {
+    pub fn start(roots: Vec<PathBuf>, options: ScanOptions) -> Self {
+        Self::spawn("scanning", move |counters, found| {
+            for root in roots {
+                if !walk(&root, &options, counters, found) {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// keep the paths of the kinds and metadata of `options`, all kinds if none are given.
+    /// They are stat-ed in the background like the files of a walk
+    pub fn check(
+        paths: impl IntoIterator<Item = PathBuf> + Send + 'static,
+        options: ScanOptions,
+    ) -> Self {
+        Self::spawn("checking", move |counters, found| {
+            for path in paths {
+                // missing paths are kept, so their problems are shown
+                let Ok(meta) = std::fs::symlink_metadata(&path) else {
+                    if !found(path) {
+                        break;
+                    }
+                    continue;
+                };
+                let kind = FileKind::of_type(meta.file_type());
+                let kind_accepted = options.types.is_empty() || options.types.contains(&kind);
+                if !kind_accepted || !options.metadata.matches(&meta) {
+                    counters.skip(kind_accepted);
+                } else if !found(path) {
+                    break;
+                }
+            }
+        })
+    }
+
+    /// paths of a file list on stdin added as they arrive, so slow producers like `find`
+    /// don't keep the app waiting. They are checked like the paths of `check`
+    pub fn read_stdin(null: bool, options: ScanOptions) -> Self {
+        let paths = path_utils::path_list(BufReader::new(std::io::stdin()), null);
+        DirScan {
+            activity: "reading stdin",
+            ..Self::check(paths, options)
+        }
+    }
+
+    fn spawn(
+        activity: &'static str,
+        job: impl FnOnce(&ScanCounters, &mut dyn FnMut(PathBuf) -> bool) + Send + 'static,
+    ) -> Self {
+        let (sender, found) = mpsc::channel();
+        let counters = Arc::new(ScanCounters::default());
+        let shared = counters.clone();
+        thread::spawn(move || {
+            // the receiver is gone when the app doesn't need the files anymore
+            job(&shared, &mut |path| sender.send(path).is_ok());
+        });
+
+        DirScan {
+            found,
+            counters,
+            activity,
+        }
+    }
+
+    pub fn activity(&self) -> &'static str {
+        self.activity
+    }
+
+    /// number of paths skipped by ignore rules so far
+    pub fn ignored(&self) -> usize {
+        self.counters.ignored.load(Ordering::Relaxed)
+    }
+
+    /// number of paths skipped for their kind so far
+    pub fn other_types(&self) -> usize {
+        self.counters.other_types.load(Ordering::Relaxed)
+    }
+
+    /// number of paths skipped for their size or modification time so far
+    pub fn out_of_limits(&self) -> usize {
+        self.counters.out_of_limits.load(Ordering::Relaxed)
+    }
+
+    /// files found since the previous call, and whether the walk is finished
+    pub fn poll(&self) -> (Vec<PathBuf>, bool) {
+        let mut files = Vec::new();
+        loop {
+            match self.found.try_recv() {
+                Ok(path) => files.push(path),
+                Err(TryRecvError::Empty) => return (files, false),
+                Err(TryRecvError::Disconnected) => return (files, true),
+            }
+        }
+    }
+
+    /// wait for the walk and return the files not polled yet
+    pub fn finish(&self) -> Vec<PathBuf> {
+        self.found.iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case(None, &["a", "b/c", "b/d/e", "f"])]
+    #[case(Some(2), &["a", "b/c", "f"])]
+    #[case(Some(1), &["a", "f"])]
+    fn tree_is_walked(#[case] max_depth: Option<usize>, #[case] expected: &[&str]) {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["f", "b/d/e", "a", "b/c"] {
+            let path = dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.path().join("b"), dir.path().join("link")).unwrap();
+
+        let expected: Vec<PathBuf> = expected
+            .iter()
+            .map(|name| dir.path().join(name))
+            .chain(cfg!(unix).then(|| dir.path().join("link")))
+            .collect();
+        let options = ScanOptions {
+            max_depth,
+            ..ScanOptions::default()
+        };
+        let scan = DirScan::start(vec![dir.path().to_owned()], options);
+        assert_eq!(scan.finish(), expected);
+    }
+
+    #[test]
+    fn paths_are_selected_by_kind() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        std::fs::create_dir_all(path("b/c")).unwrap();
+        for name in ["a", "b/d"] {
+            std::fs::write(path(name), "").unwrap();
+        }
+
+        let options = ScanOptions {
+            types: vec![FileKind::Dir],
+            ..ScanOptions::default()
+        };
+        let scan = DirScan::start(vec![dir.path().to_owned()], options);
+        assert_eq!(scan.finish(), [path("b"), path("b/c")]);
+        assert_eq!(scan.other_types(), 2);
+
+        // missing files are kept to show their problems
+        let options = ScanOptions {
+            types: vec![FileKind::File],
+            ..ScanOptions::default()
+        };
+        let scan = DirScan::check(vec![path("a"), path("b"), path("e")], options);
+        assert_eq!(scan.finish(), [path("a"), path("e")]);
+        assert_eq!(scan.other_types(), 1);
+    }
+
+    #[rstest]
+    #[case("*.txt", &["a.txt", "b.txt"])]
+    #[case("**/*.txt", &["a.txt", "b.txt", "sub/c.txt", "sub/deep/d.txt"])]
+    #[case(".*.txt", &[".h.txt"])]
+    #[case("s?b/[a-c].txt", &["sub/c.txt"])]
+    #[case("sub", &["sub"])]
+    #[case("*.png", &[])]
+    fn globs_are_expanded(#[case] pattern: &str, #[case] expected: &[&str]) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        for name in [
+            "a.txt",
+            "b.txt",
+            ".h.txt",
+            "sub/c.txt",
+            "sub/deep/d.txt",
+            ".git/e.txt",
+        ] {
+            std::fs::create_dir_all(path(name).parent().unwrap()).unwrap();
+            std::fs::write(path(name), "").unwrap();
+        }
+
+        let pattern = dir.path().join(pattern);
+        let expected: Vec<PathBuf> = expected.iter().map(|name| path(name)).collect();
+        assert_eq!(expand_glob(&pattern.to_string_lossy()), expected);
+    }
+
+    #[rstest]
+    #[case("*.o", "a.o", false, true)]
+    #[case("*.o", "sub/a.o", false, true)]
+    #[case("/a.o", "sub/a.o", false, false)]
+    #[case("sub/*.o", "sub/a.o", false, true)]
+    #[case("sub/*.o", "x/sub/a.o", false, false)]
+    #[case("**/sub/a.o", "sub/a.o", false, true)]
+    #[case("**/sub/a.o", "x/sub/a.o", false, true)]
+    #[case("a/**/b", "a/b", true, true)]
+    #[case("a/**/b", "a/x/y/b", true, true)]
+    #[case("build/", "build", true, true)]
+    #[case("build/", "build", false, false)]
+    #[case("[!a].txt", "b.txt", false, true)]
+    #[case("[!a].txt", "a.txt", false, false)]
+    #[case("\\#a", "#a", false, true)]
+    #[case("[]a].txt", "].txt", false, true)]
+    #[case("[]a].txt", "b.txt", false, false)]
+    #[case("a\\ ", "a ", false, true)]
+    #[case("a ", "a", false, true)]
+    fn ignore_patterns_match(
+        #[case] pattern: &str,
+        #[case] path: &str,
+        #[case] is_dir: bool,
+        #[case] expected: bool,
+    ) {
+        let mut builder = GitignoreBuilder::new("/root");
+        builder.add_line(None, pattern).unwrap();
+        let file = IgnoreFile {
+            dir: PathBuf::from("/root"),
+            prefix: PathBuf::new(),
+            matcher: builder.build().unwrap(),
+        };
+        let path = Path::new("/root").join(path);
+        assert_eq!(is_ignored(&[file], &path, is_dir), expected);
+    }
+
+    #[test]
+    fn ignored_files_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        std::fs::create_dir(path(".git")).unwrap();
+        for name in [
+            "a.txt",
+            "a.o",
+            "keep.o",
+            "target/b",
+            "sub/c",
+            "sub/d.log",
+            "sub/e.o",
+            ".git/HEAD",
+        ] {
+            std::fs::create_dir_all(path(name).parent().unwrap()).unwrap();
+            std::fs::write(path(name), "").unwrap();
+        }
+        std::fs::write(path(".gitignore"), "# build\n*.o\n/target/\n").unwrap();
+        std::fs::write(path(".ignore"), "!keep.o\n").unwrap();
+        std::fs::write(path("sub/.gitignore"), "*.log\n").unwrap();
+
+        let options = ScanOptions {
+            ignore_files: true,
+            ..ScanOptions::default()
+        };
+        let scan = DirScan::start(vec![dir.path().to_owned()], options.clone());
+        let expected: Vec<PathBuf> = [
+            ".gitignore",
+            ".ignore",
+            "a.txt",
+            "keep.o",
+            "sub/.gitignore",
+            "sub/c",
+        ]
+        .iter()
+        .map(|name| path(name))
+        .collect();
+        assert_eq!(scan.finish(), expected);
+        // a.o, target, sub/d.log and sub/e.o
+        assert_eq!(scan.ignored(), 4);
+
+        // the rules of the repository apply when a subdirectory is walked
+        let scan = DirScan::start(vec![path("sub")], options);
+        assert_eq!(scan.finish(), [path("sub/.gitignore"), path("sub/c")]);
+        assert_eq!(scan.ignored(), 2);
+    }
+
+    #[test]
+    fn git_excludes_are_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        for name in ["a.txt", "b.tmp", "sub/c.tmp", ".git/info/exclude"] {
+            std::fs::create_dir_all(path(name).parent().unwrap()).unwrap();
+            std::fs::write(path(name), "").unwrap();
+        }
+        std::fs::write(path(".git/info/exclude"), "*.tmp\n").unwrap();
+
+        let options = ScanOptions {
+            ignore_files: true,
+            ..ScanOptions::default()
+        };
+        let scan = DirScan::start(vec![dir.path().to_owned()], options.clone());
+        assert_eq!(scan.finish(), [path("a.txt")]);
+        assert_eq!(scan.ignored(), 2);
+
+        let scan = DirScan::start(vec![path("sub")], options);
+        assert!(scan.finish().is_empty());
+        assert_eq!(scan.ignored(), 1);
+    }
+}
@@ -0,0 +1,153 @@
+use crate::app::Session;
+use crate::filter::ExtFilter;
+
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// format of the session files, increased on incompatible changes
+pub const SESSION_VERSION: u32 = 1;
+
+/// the files and the rules saved by `irename session export`, so the renaming prepared
+/// on one machine can be reviewed and applied on another
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionFile {
+    pub version: u32,
+    pub files: Vec<PathBuf>,
+    pub settings: Session,
+    #[serde(default)]
+    pub ext_filter: ExtFilter,
+}
+
+/// replacement of a path prefix, e.g. of a directory mounted elsewhere on another machine
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathRemap {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+impl FromStr for PathRemap {
+    type Err = String;
+
+    /// `FROM=TO`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('=') {
+            Some((from, to)) if !from.is_empty() && !to.is_empty() => Ok(PathRemap {
+                from: from.into(),
+                to: to.into(),
+            }),
+            _ => Err(format!("expected FROM=TO, got '{}'", s)),
+        }
+    }
+}
+
+/// `path` with the prefix of the first matching remap replaced. Only whole components match
+pub fn remap_path(path: &Path, remaps: &[PathRemap]) -> PathBuf {
+    remaps
+        .iter()
+        .find_map(|remap| Some(remap.to.join(path.strip_prefix(&remap.from).ok()?)))
+        .unwrap_or_else(|| path.to_owned())
+}
+
+impl SessionFile {
+    pub fn new(files: Vec<PathBuf>, settings: Session, ext_filter: ExtFilter) -> Self {
+        SessionFile {
+            version: SESSION_VERSION,
+            files,
+            settings,
+            ext_filter,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let file = std::fs::File::create(path)
+            .with_context(|| format!("can't create session file {}", path.display()))?;
+        serde_json::to_writer_pretty(file, self)?;
+
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("can't open session file {}", path.display()))?;
+        let session: SessionFile = serde_json::from_reader(std::io::BufReader::new(file))
+            .with_context(|| format!("invalid session file {}", path.display()))?;
+
+        if session.version != SESSION_VERSION {
+            anyhow::bail!(
+                "session file {} has version {}, only version {} is supported",
+                path.display(),
+                session.version,
+                SESSION_VERSION
+            );
+        }
+        Ok(session)
+    }
+
+    /// move the files and the paths of the settings to their locations on this machine
+    pub fn remap(&mut self, remaps: &[PathRemap]) {
+        for file in &mut self.files {
+            *file = remap_path(file, remaps);
+        }
+        self.settings.map_paths(|path| remap_path(path, remaps));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::app::{App, RenameRule};
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("/home/me/photos/a.jpg", "/srv/photos/a.jpg")]
+    #[case("/home/me/photos", "/srv/photos")]
+    #[case("/home/me/photos2/a.jpg", "/users/me/photos2/a.jpg")]
+    #[case("/tmp/a.jpg", "/tmp/a.jpg")]
+    fn remap_path_works(#[case] path: &str, #[case] expected: &str) {
+        let remaps =
+            ["/home/me/photos=/srv/photos", "/home=/users"].map(|remap| remap.parse().unwrap());
+
+        assert_eq!(remap_path(Path::new(path), &remaps), Path::new(expected));
+    }
+
+    #[rstest]
+    #[case("a")]
+    #[case("=b")]
+    #[case("a=")]
+    fn invalid_remap_is_rejected(#[case] remap: &str) {
+        assert!(remap.parse::<PathRemap>().is_err());
+    }
+
+    #[test]
+    fn session_is_reopened_elsewhere() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.json");
+        let app = App::default()
+            .with_files(vec!["/home/me/a.txt".into(), "/home/me/b.txt".into()])
+            .with_rule(RenameRule::parse("s/a/c/").unwrap());
+        app.export_session().save(&path).unwrap();
+
+        let mut session = SessionFile::load(&path).unwrap();
+        session.remap(&["/home/me=/srv".parse().unwrap()]);
+        let app = App::default().with_session(session);
+
+        assert_eq!(
+            app.plan().unwrap(),
+            vec![("/srv/a.txt".into(), "/srv/c.txt".into())]
+        );
+    }
+
+    #[test]
+    fn unknown_version_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.json");
+        let mut session = App::default().export_session();
+        session.version = SESSION_VERSION + 1;
+        session.save(&path).unwrap();
+
+        assert!(SessionFile::load(&path).is_err());
+    }
+}
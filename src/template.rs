@@ -0,0 +1,583 @@
+/// stands for the counter value when numbers of existing files are searched
+pub const COUNTER_MARKER: &str = "\u{1f}";
+
+use crate::counter;
+use crate::git::{self, GitInfo};
+use crate::media::{self, VideoInfo};
+use crate::path_utils;
+
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Local};
+use ulid::Ulid;
+use uuid::Uuid;
+
+/// error of the files whose metadata isn't read yet
+pub const PENDING: &str = "pending";
+
+/// format of `{now}` without an argument
+const DEFAULT_TIME_FORMAT: &str = "%Y%m%dT%H%M%S";
+
+/// length of `{rand}` without an argument
+const DEFAULT_RAND_LENGTH: usize = 8;
+
+/// values of the generator tokens for a single file.
+/// Generated once per file, so the preview matches the execution
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Generated {
+    pub uuid: Uuid,
+    pub ulid: Ulid,
+    /// seed of the `{rand}` strings
+    pub seed: u64,
+}
+
+impl Generated {
+    pub fn random() -> Self {
+        Generated {
+            uuid: Uuid::new_v4(),
+            ulid: Ulid::generate(),
+            seed: rand::random(),
+        }
+    }
+}
+
+/// values available to the tokens when expanding a replacement for a single file
+#[derive(Debug, Default, Clone)]
+pub struct TokenContext {
+    /// counter value, `None` to put `COUNTER_MARKER` instead of it
+    pub counter: Option<u64>,
+    /// directory of the file
+    pub dir: PathBuf,
+    /// original name of the file
+    pub name: String,
+    /// `{ext}` includes inner extensions like `tar` of `tar.gz`
+    pub compound_ext: bool,
+    /// time of the plan
+    pub now: DateTime<Local>,
+    /// creation time of the file, or why it isn't available. `None` if it isn't fetched
+    pub btime: Option<Result<DateTime<Local>, String>>,
+    /// metadata of the video, or why it isn't available. `None` if it isn't fetched
+    pub video: Option<Result<VideoInfo, String>>,
+    /// last commit of the file, `Some(None)` outside of git repositories. `None` if it isn't fetched
+    pub git: Option<Option<GitInfo>>,
+    pub generated: Generated,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Text(String),
+    /// `{name}` or `{name:arg}`
+    Token {
+        name: String,
+        arg: Option<String>,
+    },
+}
+
+/// replacement string with `{token}` placeholders.
+/// Regex references like `$1` and `${name}` are left intact
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    pieces: Vec<Piece>,
+}
+
+impl Template {
+    pub fn parse(replacement: &str) -> Self {
+        let mut pieces = Vec::new();
+        let mut text = String::new();
+        let mut chars = replacement.chars().peekable();
+
+        while let Some(ch) = chars.next() {
+            match ch {
+                '$' => {
+                    text.push(ch);
+                    match chars.peek() {
+                        Some('$') => text.push(chars.next().unwrap()),
+                        Some('{') => {
+                            // conditionals like `${1:+a${2}:b}` contain nested braces
+                            let mut depth = 0;
+                            for ch in chars.by_ref() {
+                                text.push(ch);
+                                match ch {
+                                    '{' => depth += 1,
+                                    '}' if depth == 1 => break,
+                                    '}' => depth -= 1,
+                                    _ => {}
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                }
+                '{' => {
+                    let body: String = chars.clone().take_while(|ch| *ch != '}').collect();
+                    let closed = chars.clone().nth(body.chars().count()) == Some('}');
+                    let (name, arg) = match body.split_once(':') {
+                        Some((name, arg)) => (name, Some(arg.to_owned())),
+                        None => (body.as_str(), None),
+                    };
+
+                    if closed && is_known_token(name) {
+                        chars.nth(body.chars().count());
+                        pieces.push(Piece::Text(std::mem::take(&mut text)));
+                        pieces.push(Piece::Token {
+                            name: name.to_owned(),
+                            arg,
+                        });
+                    } else {
+                        text.push(ch);
+                    }
+                }
+                ch => text.push(ch),
+            }
+        }
+        pieces.push(Piece::Text(text));
+        pieces.retain(|piece| piece != &Piece::Text(String::new()));
+
+        Template { pieces }
+    }
+
+    pub fn has_counter(&self) -> bool {
+        self.pieces.iter().any(
+            |piece| matches!(piece, Piece::Token { name, .. } if counter::style(name).is_some()),
+        )
+    }
+
+    /// whether the template has tokens with values differing between sessions
+    pub fn has_generators(&self) -> bool {
+        self.pieces.iter().any(|piece| {
+            matches!(piece, Piece::Token { name, .. } if matches!(name.as_str(), "uuid" | "ulid" | "rand" | "now"))
+        })
+    }
+
+    pub fn has_token(&self, token: &str) -> bool {
+        self.pieces
+            .iter()
+            .any(|piece| matches!(piece, Piece::Token { name, .. } if name == token))
+    }
+
+    /// like `expand`, but fails if a value used by the template isn't available for the file
+    pub fn try_expand(&self, ctx: &TokenContext) -> Result<String, String> {
+        if self.has_token("btime") {
+            match &ctx.btime {
+                Some(Ok(_)) => {}
+                Some(Err(err)) => return Err(format!("no creation time: {}", err)),
+                None => return Err("no creation time".to_owned()),
+            }
+        }
+        if media::VIDEO_TOKENS
+            .iter()
+            .any(|token| self.has_token(token))
+        {
+            match &ctx.video {
+                Some(Ok(_)) => {}
+                Some(Err(err)) => return Err(format!("no video metadata: {}", err)),
+                None => return Err(PENDING.to_owned()),
+            }
+        }
+        if git::GIT_TOKENS.iter().any(|token| self.has_token(token)) && ctx.git.is_none() {
+            return Err(PENDING.to_owned());
+        }
+        Ok(self.expand(ctx))
+    }
+
+    /// replacement string for a single file with all the tokens replaced by their values
+    pub fn expand(&self, ctx: &TokenContext) -> String {
+        self.pieces
+            .iter()
+            .enumerate()
+            .map(|(idx, piece)| match piece {
+                Piece::Text(text) => text.clone(),
+                Piece::Token { name, arg } => {
+                    // token values are plain text, not regex references
+                    expand_token(name, arg.as_deref(), idx as u64, ctx).replace('$', "$$")
+                }
+            })
+            .collect()
+    }
+}
+
+/// check if the replacement may contain `${group:+present:absent}` conditionals
+pub fn has_conditionals(replacement: &str) -> bool {
+    replacement.contains(":+")
+}
+
+/// replace `${group:+present:absent}` conditionals with `present` if the capture group
+/// matched non-empty text and with `absent` otherwise. `:absent` is optional.
+/// `\:` and `\}` are literal characters inside the branches
+pub fn resolve_conditionals(replacement: &str, is_set: &dyn Fn(&str) -> bool) -> String {
+    let mut resolved = String::new();
+    let mut rest = replacement;
+
+    while let Some(pos) = rest.find('$') {
+        resolved.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+
+        if rest.starts_with("$$") {
+            resolved.push_str("$$");
+            rest = &rest[2..];
+        } else if let Some((conditional, len)) = Conditional::parse(rest) {
+            let branch = if is_set(conditional.group) {
+                conditional.present
+            } else {
+                conditional.absent
+            };
+            resolved.push_str(&resolve_conditionals(&branch, is_set));
+            rest = &rest[len..];
+        } else {
+            resolved.push('$');
+            rest = &rest[1..];
+        }
+    }
+
+    resolved.push_str(rest);
+    resolved
+}
+
+struct Conditional<'a> {
+    group: &'a str,
+    present: String,
+    absent: String,
+}
+
+impl<'a> Conditional<'a> {
+    /// parse a conditional at the start of `text`. Returns it with its length in bytes
+    fn parse(text: &'a str) -> Option<(Self, usize)> {
+        let body = text.strip_prefix("${")?;
+        let (group, body) = body.split_once(":+")?;
+        if group.is_empty() || !group.chars().all(|ch| ch.is_alphanumeric() || ch == '_') {
+            return None;
+        }
+
+        // the present branch, followed by the absent one after the separator
+        let mut branches = vec![String::new()];
+        let mut depth = 0;
+        let mut chars = body.char_indices();
+        while let Some((idx, ch)) = chars.next() {
+            let separated = branches.len() > 1;
+            let branch = branches.last_mut().unwrap();
+            match ch {
+                '\\' => match chars.next() {
+                    Some((_, escaped @ (':' | '}' | '\\'))) => branch.push(escaped),
+                    Some((_, escaped)) => {
+                        branch.push(ch);
+                        branch.push(escaped);
+                    }
+                    None => branch.push(ch),
+                },
+                ':' if depth == 0 && !separated => branches.push(String::new()),
+                '}' if depth == 0 => {
+                    let len = text.len() - body.len() + idx + 1;
+                    let mut branches = branches.into_iter();
+                    let conditional = Conditional {
+                        group,
+                        present: branches.next().unwrap_or_default(),
+                        absent: branches.next().unwrap_or_default(),
+                    };
+                    return Some((conditional, len));
+                }
+                '{' => {
+                    depth += 1;
+                    branch.push(ch);
+                }
+                '}' => {
+                    depth -= 1;
+                    branch.push(ch);
+                }
+                ch => branch.push(ch),
+            }
+        }
+
+        None
+    }
+}
+
+fn is_known_token(name: &str) -> bool {
+    matches!(
+        name,
+        "uuid"
+            | "ulid"
+            | "now"
+            | "btime"
+            | "rand"
+            | "name"
+            | "stem"
+            | "ext"
+            | "duration"
+            | "vcodec"
+            | "git_date"
+            | "git_author"
+    ) || counter::style(name).is_some()
+        || parent_level(name).is_some()
+}
+
+/// level of `{parent}` (1), `{parent2}` (2), etc
+fn parent_level(name: &str) -> Option<usize> {
+    match name.strip_prefix("parent")? {
+        "" => Some(1),
+        level => level.parse().ok().filter(|level| *level > 0),
+    }
+}
+
+/// value of a time token with a strftime format argument
+fn format_time(name: &str, time: &DateTime<Local>, arg: Option<&str>) -> String {
+    let format = arg.unwrap_or(DEFAULT_TIME_FORMAT);
+    let mut value = String::new();
+    match write!(value, "{}", time.format(format)) {
+        Ok(()) => value,
+        // invalid format is left as is
+        Err(_) => format!("{{{}:{}}}", name, format),
+    }
+}
+
+/// `position` distinguishes several tokens of the same kind in a template
+fn expand_token(name: &str, arg: Option<&str>, position: u64, ctx: &TokenContext) -> String {
+    if let Some(style) = counter::style(name) {
+        return match ctx.counter {
+            Some(counter) => style.format(counter, arg),
+            None => COUNTER_MARKER.to_owned(),
+        };
+    }
+
+    match name {
+        "uuid" => ctx.generated.uuid.to_string(),
+        "ulid" => ctx.generated.ulid.to_string(),
+        "rand" => {
+            use rand::{distributions::Alphanumeric, rngs::StdRng, Rng, SeedableRng};
+
+            let length = arg
+                .and_then(|arg| arg.parse().ok())
+                .unwrap_or(DEFAULT_RAND_LENGTH);
+            StdRng::seed_from_u64(ctx.generated.seed.wrapping_add(position))
+                .sample_iter(Alphanumeric)
+                .take(length)
+                .map(char::from)
+                .collect()
+        }
+        "now" => format_time(name, &ctx.now, arg),
+        "btime" => match &ctx.btime {
+            Some(Ok(btime)) => format_time(name, btime, arg),
+            _ => String::new(),
+        },
+        "duration" => match &ctx.video {
+            Some(Ok(video)) => media::format_duration(video.duration, arg),
+            _ => String::new(),
+        },
+        "vcodec" => match &ctx.video {
+            Some(Ok(video)) => video.codec.clone(),
+            _ => String::new(),
+        },
+        // files out of git repositories are flagged in the preview, not failed
+        "git_date" => match &ctx.git {
+            Some(Some(git)) => format_time(name, &git.date, arg),
+            _ => String::new(),
+        },
+        "git_author" => match &ctx.git {
+            Some(Some(git)) => git.author.clone(),
+            _ => String::new(),
+        },
+        "name" => ctx.name.clone(),
+        "stem" => path_utils::split_extension(&ctx.name, ctx.compound_ext)
+            .0
+            .to_owned(),
+        "ext" => match path_utils::split_extension(&ctx.name, ctx.compound_ext).1 {
+            "" => String::new(),
+            ext => format!(".{}", ext),
+        },
+        name if parent_level(name).is_some() => {
+            let level = parent_level(name).unwrap();
+            ctx.dir
+                .ancestors()
+                .nth(level - 1)
+                .and_then(|dir| dir.file_name())
+                .map(path_utils::name_to_string)
+                .unwrap_or_default()
+        }
+        _ => unreachable!("unknown token {}", name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn expand(replacement: &str, counter: u64) -> String {
+        Template::parse(replacement).expand(&TokenContext {
+            counter: Some(counter),
+            ..Default::default()
+        })
+    }
+
+    #[rstest]
+    #[case("", 1, "")]
+    #[case("abc", 1, "abc")]
+    #[case("{n}", 7, "7")]
+    #[case("img_{n:3}.jpg", 7, "img_007.jpg")]
+    #[case("{n:2}", 123, "123")]
+    #[case("$1_{n}", 2, "$1_2")]
+    #[case("${n}", 2, "${n}")]
+    #[case("$${n}", 2, "$$2")]
+    #[case("{{n}}", 2, "{n}")]
+    #[case("{unknown}", 2, "{unknown}")]
+    #[case("{n", 2, "{n")]
+    #[case("appendix-{a}", 2, "appendix-b")]
+    #[case("chapter-{roman}", 4, "chapter-IV")]
+    #[case("${1:+v${2}:{n}}_{n}", 2, "${1:+v${2}:{n}}_2")]
+    fn expand_works(#[case] replacement: &str, #[case] counter: u64, #[case] expected: &str) {
+        assert_eq!(expand(replacement, counter), expected);
+    }
+
+    #[rstest]
+    #[case("${1:+yes:no}", "yes")]
+    #[case("${2:+yes:no}", "no")]
+    #[case("${2:+yes}", "")]
+    #[case("a${1:+_v$1}b", "a_v$1b")]
+    #[case("${ver:+-${ver}:-0}", "-0")]
+    #[case("${1:+${2:+both:one}:none}", "one")]
+    #[case("${1:+a\\:b\\}:c}", "a:b}")]
+    #[case("${1:+a:b:c}", "a")]
+    #[case("${2:+a:b:c}", "b:c")]
+    #[case("$${1:+yes:no}", "$${1:+yes:no}")]
+    #[case("${1:+yes", "${1:+yes")]
+    #[case("${:+yes}", "${:+yes}")]
+    #[case("$1 ${1}", "$1 ${1}")]
+    fn resolve_conditionals_works(#[case] replacement: &str, #[case] expected: &str) {
+        let is_set = |group: &str| group == "1";
+        assert_eq!(resolve_conditionals(replacement, &is_set), expected);
+    }
+
+    #[rstest]
+    #[case("{now}", "20240102T030405")]
+    #[case("{now:%Y-%m-%d}_x", "2024-01-02_x")]
+    #[case("{now:%}", "{now:%}")]
+    #[case("scan_{btime:%Y%m%d}", "scan_20200506")]
+    #[case("{duration}_{vcodec}", "01m23s_h264")]
+    #[case("{duration:%s}", "83")]
+    #[case("{git_date:%Y-%m-%d} by {git_author}", "2019-01-02 by Jane Doe")]
+    #[case("{uuid}", "67e55044-10b1-426f-9247-bb680e5fe0c8")]
+    #[case("{ulid}", "01ARZ3NDEKTSV4RRFFQ69G5FAV")]
+    fn expand_generators(#[case] replacement: &str, #[case] expected: &str) {
+        use chrono::TimeZone;
+
+        let ctx = TokenContext {
+            counter: Some(1),
+            dir: PathBuf::new(),
+            name: String::new(),
+            compound_ext: false,
+            now: Local.with_ymd_and_hms(2024, 1, 2, 3, 4, 5).unwrap(),
+            btime: Some(Ok(Local.with_ymd_and_hms(2020, 5, 6, 7, 8, 9).unwrap())),
+            video: Some(Ok(VideoInfo {
+                duration: std::time::Duration::from_secs(83),
+                codec: "h264".to_owned(),
+            })),
+            git: Some(Some(GitInfo {
+                date: Local.with_ymd_and_hms(2019, 1, 2, 3, 4, 5).unwrap(),
+                author: "Jane Doe".to_owned(),
+            })),
+            generated: Generated {
+                uuid: "67e55044-10b1-426f-9247-bb680e5fe0c8".parse().unwrap(),
+                ulid: "01ARZ3NDEKTSV4RRFFQ69G5FAV".parse().unwrap(),
+                seed: 0,
+            },
+        };
+        assert_eq!(Template::parse(replacement).expand(&ctx), expected);
+    }
+
+    #[rstest]
+    #[case("{parent}_$0", "c_$0")]
+    #[case("{parent2}/{parent}", "b/c")]
+    #[case("{parent4}", "")]
+    #[case("{parent0}", "{parent0}")]
+    #[case("{parents}", "{parents}")]
+    fn expand_parents(#[case] replacement: &str, #[case] expected: &str) {
+        let ctx = TokenContext {
+            dir: PathBuf::from("/a/b/c"),
+            ..Default::default()
+        };
+        assert_eq!(Template::parse(replacement).expand(&ctx), expected);
+    }
+
+    #[rstest]
+    #[case("{stem}_old{ext}", "photo.jpg", false, "photo_old.jpg")]
+    #[case("{stem}_old{ext}", ".bashrc", false, ".bashrc_old")]
+    #[case("{stem}_old{ext}", "archive.tar.gz", false, "archive.tar_old.gz")]
+    #[case("{stem}_old{ext}", "archive.tar.gz", true, "archive_old.tar.gz")]
+    #[case("{name}.bak", "archive.tar.gz", false, "archive.tar.gz.bak")]
+    fn expand_stem_and_ext(
+        #[case] replacement: &str,
+        #[case] name: &str,
+        #[case] compound_ext: bool,
+        #[case] expected: &str,
+    ) {
+        let ctx = TokenContext {
+            name: name.to_owned(),
+            compound_ext,
+            ..Default::default()
+        };
+        assert_eq!(Template::parse(replacement).expand(&ctx), expected);
+    }
+
+    #[test]
+    fn missing_btime_fails() {
+        let template = Template::parse("{btime}_$0");
+        let ctx = TokenContext {
+            btime: Some(Err("not supported".into())),
+            ..Default::default()
+        };
+        assert_eq!(
+            template.try_expand(&ctx),
+            Err("no creation time: not supported".into())
+        );
+        assert_eq!(
+            Template::parse("{now:%Y}")
+                .try_expand(&ctx)
+                .map(|v| v.len()),
+            Ok(4)
+        );
+    }
+
+    #[test]
+    fn rand_is_stable() {
+        let template = Template::parse("{rand}_{rand:4}_{rand:4}");
+        let ctx = TokenContext {
+            generated: Generated::random(),
+            ..Default::default()
+        };
+
+        let name = template.expand(&ctx);
+        assert_eq!(template.expand(&ctx), name);
+
+        let parts: Vec<&str> = name.split('_').collect();
+        assert_eq!(
+            parts.iter().map(|part| part.len()).collect::<Vec<_>>(),
+            vec![8, 4, 4]
+        );
+        assert_ne!(parts[1], parts[2]);
+        assert!(name
+            .chars()
+            .all(|ch| ch == '_' || ch.is_ascii_alphanumeric()));
+    }
+
+    #[test]
+    fn has_counter() {
+        assert!(Template::parse("a{n:2}").has_counter());
+        assert!(!Template::parse("a${n}").has_counter());
+        assert!(Template::parse("{A}").has_counter());
+    }
+
+    #[test]
+    fn has_generators() {
+        assert!(Template::parse("{n}_{uuid}").has_generators());
+        assert!(Template::parse("{now:%Y}").has_generators());
+        assert!(!Template::parse("{n}_{parent}").has_generators());
+    }
+}
@@ -0,0 +1,273 @@
+use crate::planner::{self, MovePair};
+use crate::trash;
+
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+/// bytes copied at once, also how often the progress is reported
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// rename `src` to `dst`, or copy it and remove the original if `dst` is on another file system.
+/// `progress` gets the copied and the total number of bytes. Returns the error of removing
+/// the original if the copy is complete but some of the original is left
+pub fn rename_or_move(
+    src: &Path,
+    dst: &Path,
+    progress: impl FnMut(u64, u64),
+) -> io::Result<Option<io::Error>> {
+    match fs::rename(src, dst) {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            move_across_devices(src, dst, progress)
+        }
+        res => res.map(|()| None),
+    }
+}
+
+/// copy a file or a directory to another file system and remove the original.
+/// The copy is made under a temporary name next to `dst` and removed if anything fails,
+/// so a failure never leaves a partial destination. Once the copy is in place the move
+/// succeeded: the original may be partly removed already, so a failed removal is returned
+/// instead of removing the only complete copy
+pub fn move_across_devices(
+    src: &Path,
+    dst: &Path,
+    mut progress: impl FnMut(u64, u64),
+) -> io::Result<Option<io::Error>> {
+    let total = total_size(src)?;
+    let tmp = planner::temporary_path(dst);
+    let mut copied = 0;
+    let res = copy_tree(src, &tmp, &mut |bytes| {
+        copied += bytes;
+        progress(copied, total);
+    })
+    .and_then(|()| fs::rename(&tmp, dst));
+
+    if let Err(err) = res {
+        let _ = remove_tree(&tmp);
+        return Err(err);
+    }
+    Ok(remove_tree(src).err())
+}
+
+/// result of reverting the renames executed before a failure
+#[derive(Debug, Default)]
+pub struct Rollback {
+    /// renames reverted, in the order they were reverted
+    pub reverted: Vec<MovePair>,
+    /// renames which couldn't be reverted and stay applied
+    pub failed: Vec<(MovePair, io::Error)>,
+}
+
+/// revert the `applied` renames from the last one to the first. A failed revert doesn't stop
+/// the others, the original names which are taken again are never overwritten
+pub fn rollback(applied: &[MovePair]) -> Rollback {
+    // the file is back once its copy is, a leftover of it isn't a failed revert
+    rollback_with(applied, |src, dst| {
+        rename_or_move(src, dst, |_, _| {}).map(|_| ())
+    })
+}
+
+/// `rollback` moving the files back with `rename`, e.g. `git mv`
+pub fn rollback_with(
+    applied: &[MovePair],
+    mut rename: impl FnMut(&Path, &Path) -> io::Result<()>,
+) -> Rollback {
+    let mut result = Rollback::default();
+    for (src, dst) in applied.iter().rev() {
+        let res = if fs::symlink_metadata(src).is_ok() {
+            Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists again", src.display()),
+            ))
+        } else {
+            rename(dst, src)
+        };
+
+        match res {
+            Ok(()) => {
+                // a restored file doesn't belong to the trash anymore
+                if trash::is_trashed(dst) {
+                    trash::forget(dst);
+                }
+                result.reverted.push((dst.clone(), src.clone()));
+            }
+            Err(err) => result.failed.push(((src.clone(), dst.clone()), err)),
+        }
+    }
+    result
+}
+
+/// size of a file or of all the files in a directory
+fn total_size(path: &Path) -> io::Result<u64> {
+    let meta = fs::symlink_metadata(path)?;
+    if !meta.is_dir() {
+        return Ok(if meta.is_file() { meta.len() } else { 0 });
+    }
+
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        size += total_size(&entry?.path())?;
+    }
+    Ok(size)
+}
+
+/// copy a file, a symlink or a directory with its content. Files are synced to disk,
+/// so the original isn't removed before its copy is complete
+fn copy_tree(src: &Path, dst: &Path, copied: &mut dyn FnMut(u64)) -> io::Result<()> {
+    let meta = fs::symlink_metadata(src)?;
+
+    if meta.is_symlink() {
+        let target = fs::read_link(src)?;
+        #[cfg(unix)]
+        return std::os::unix::fs::symlink(target, dst);
+        #[cfg(not(unix))]
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "can't copy symlink {} to {}",
+                src.display(),
+                target.display()
+            ),
+        ));
+    }
+
+    if meta.is_dir() {
+        fs::create_dir(dst)?;
+        for entry in fs::read_dir(src)? {
+            let entry = entry?;
+            copy_tree(&entry.path(), &dst.join(entry.file_name()), copied)?;
+        }
+        return fs::set_permissions(dst, meta.permissions());
+    }
+
+    let mut reader = File::open(src)?;
+    let mut writer = File::create(dst)?;
+    let mut buf = vec![0; CHUNK_SIZE];
+    loop {
+        let read = reader.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        writer.write_all(&buf[..read])?;
+        copied(read as u64);
+    }
+    writer.set_permissions(meta.permissions())?;
+    writer.set_modified(meta.modified()?)?;
+    writer.sync_all()
+}
+
+fn remove_tree(path: &Path) -> io::Result<()> {
+    if fs::symlink_metadata(path)?.is_dir() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directories_are_moved_with_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("src");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        fs::write(src.join("a.txt"), "abc").unwrap();
+        fs::write(src.join("sub/b.txt"), vec![7; CHUNK_SIZE + 1]).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink("a.txt", src.join("link")).unwrap();
+
+        let dst = dir.path().join("dst");
+        let mut reports = Vec::new();
+        let leftover =
+            move_across_devices(&src, &dst, |copied, total| reports.push((copied, total))).unwrap();
+        assert!(leftover.is_none());
+
+        assert!(!src.exists());
+        assert_eq!(fs::read_to_string(dst.join("a.txt")).unwrap(), "abc");
+        assert_eq!(
+            fs::read(dst.join("sub/b.txt")).unwrap().len(),
+            CHUNK_SIZE + 1
+        );
+        #[cfg(unix)]
+        assert_eq!(fs::read_link(dst.join("link")).unwrap(), Path::new("a.txt"));
+        assert_eq!(
+            reports.last(),
+            Some(&(CHUNK_SIZE as u64 + 4, CHUNK_SIZE as u64 + 4))
+        );
+    }
+
+    #[test]
+    fn failed_copy_is_cleaned_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let src = dir.path().join("a.txt");
+        fs::write(&src, "abc").unwrap();
+        // the destination directory doesn't exist
+        let dst = dir.path().join("missing/a.txt");
+
+        assert!(move_across_devices(&src, &dst, |_, _| {}).is_err());
+        assert!(src.exists());
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn undeletable_source_keeps_the_copy() {
+        use std::os::unix::fs::PermissionsExt;
+
+        // root removes files from read-only directories too
+        if unsafe { libc::geteuid() } == 0 {
+            return;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let parent = dir.path().join("ro");
+        fs::create_dir(&parent).unwrap();
+        let src = parent.join("a.txt");
+        fs::write(&src, "abc").unwrap();
+        fs::set_permissions(&parent, fs::Permissions::from_mode(0o555)).unwrap();
+        let dst = dir.path().join("b.txt");
+
+        let leftover = move_across_devices(&src, &dst, |_, _| {});
+        fs::set_permissions(&parent, fs::Permissions::from_mode(0o755)).unwrap();
+
+        assert!(leftover.unwrap().is_some());
+        assert_eq!(fs::read_to_string(&dst).unwrap(), "abc");
+        assert!(src.exists());
+    }
+
+    #[test]
+    fn applied_renames_are_rolled_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        for name in ["b", "d", "f"] {
+            fs::write(path(name), name).unwrap();
+        }
+        // "e" appeared again after it was renamed to "f"
+        fs::write(path("e"), "new").unwrap();
+        let applied = vec![
+            (path("a"), path("b")),
+            (path("c"), path("d")),
+            (path("e"), path("f")),
+        ];
+
+        let rollback = rollback(&applied);
+
+        assert_eq!(
+            rollback.reverted,
+            vec![(path("d"), path("c")), (path("b"), path("a"))]
+        );
+        assert_eq!(
+            rollback
+                .failed
+                .iter()
+                .map(|(pair, _)| pair)
+                .collect::<Vec<_>>(),
+            vec![&applied[2]]
+        );
+        assert_eq!(fs::read_to_string(path("a")).unwrap(), "b");
+        assert_eq!(fs::read_to_string(path("e")).unwrap(), "new");
+        assert!(path("f").exists());
+    }
+}
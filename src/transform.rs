@@ -0,0 +1,583 @@
+use crate::path_utils;
+use crate::prefetch::Prefetcher;
+use crate::template;
+
+use std::{
+    cell::RefCell,
+    io::{Read, Write},
+    path::Path,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+/// post-processing step applied to destination names after the rules
+pub trait NameTransform {
+    fn apply(&self, name: &str) -> Result<String, String>;
+
+    /// called after `apply` got all the names of a plan. Transforms computing the names
+    /// in the background start on the ones they returned as pending
+    fn plan_done(&self) {}
+
+    /// number of names computed in the background so far, the plan is computed again
+    /// when it changes
+    fn progress(&self) -> usize {
+        0
+    }
+
+    /// block until the names of the last plan are computed
+    fn wait(&self) {}
+}
+
+/// unicode normalization form of the resulting names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Normalization {
+    /// canonical composition (common on Linux and Windows)
+    Nfc,
+    /// canonical decomposition (used by macOS)
+    Nfd,
+}
+
+impl NameTransform for Normalization {
+    fn apply(&self, name: &str) -> Result<String, String> {
+        use unicode_normalization::UnicodeNormalization;
+
+        Ok(match self {
+            Normalization::Nfc => name.nfc().collect(),
+            Normalization::Nfd => name.nfd().collect(),
+        })
+    }
+}
+
+/// converts non-ASCII characters to their ASCII approximations
+pub struct Transliteration;
+
+impl NameTransform for Transliteration {
+    fn apply(&self, name: &str) -> Result<String, String> {
+        Ok(deunicode::deunicode(name))
+    }
+}
+
+/// case conventions the names can be converted to. The extension is kept as is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaseStyle {
+    /// `my_file_name`
+    Snake,
+    /// `my-file-name`
+    Kebab,
+    /// `myFileName`
+    Camel,
+    /// `My File Name`
+    Title,
+}
+
+impl CaseStyle {
+    pub const ALL: [CaseStyle; 4] = [
+        CaseStyle::Snake,
+        CaseStyle::Kebab,
+        CaseStyle::Camel,
+        CaseStyle::Title,
+    ];
+
+    /// the style applied to `My File Name`
+    pub fn example(&self) -> &'static str {
+        match self {
+            CaseStyle::Snake => "my_file_name",
+            CaseStyle::Kebab => "my-file-name",
+            CaseStyle::Camel => "myFileName",
+            CaseStyle::Title => "My File Name",
+        }
+    }
+
+    fn convert(&self, text: &str) -> String {
+        let words = split_words(text);
+        if words.is_empty() {
+            return text.to_owned();
+        }
+
+        let lower = || words.iter().map(|word| word.to_lowercase());
+        match self {
+            CaseStyle::Snake => lower().collect::<Vec<_>>().join("_"),
+            CaseStyle::Kebab => lower().collect::<Vec<_>>().join("-"),
+            CaseStyle::Camel => lower()
+                .enumerate()
+                .map(|(idx, word)| if idx == 0 { word } else { capitalize(&word) })
+                .collect(),
+            CaseStyle::Title => lower()
+                .map(|word| capitalize(&word))
+                .collect::<Vec<_>>()
+                .join(" "),
+        }
+    }
+}
+
+impl NameTransform for CaseStyle {
+    fn apply(&self, name: &str) -> Result<String, String> {
+        // only the last component of a moved file
+        let (dir, file_name) = match name.rfind('/') {
+            Some(slash) => name.split_at(slash + 1),
+            None => ("", name),
+        };
+        let (stem, _) = path_utils::split_extension(file_name, false);
+        // dotfiles keep their dots
+        let dots = stem.len() - stem.trim_start_matches('.').len();
+
+        Ok(format!(
+            "{}{}{}{}",
+            dir,
+            &stem[..dots],
+            self.convert(&stem[dots..]),
+            &file_name[stem.len()..]
+        ))
+    }
+}
+
+/// words separated by non-alphanumeric characters and case changes: `myHTTPServer_v2` -> my HTTP Server v2
+fn split_words(text: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    for part in text.split(|ch: char| !ch.is_alphanumeric()) {
+        let chars: Vec<char> = part.chars().collect();
+        let mut word = String::new();
+        for (idx, &ch) in chars.iter().enumerate() {
+            let prev = idx.checked_sub(1).map(|idx| chars[idx]);
+            let next = chars.get(idx + 1);
+            let boundary = ch.is_uppercase()
+                && prev.is_some_and(|prev| {
+                    !prev.is_uppercase() || next.is_some_and(|next| next.is_lowercase())
+                });
+            if boundary && !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            word.push(ch);
+        }
+        if !word.is_empty() {
+            words.push(word);
+        }
+    }
+    words
+}
+
+/// first character in uppercase
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// characters which are unsafe in web or Windows filenames. `/` is kept to allow moving files
+const UNSAFE_CHARS: &[char] = &['\\', ':', '*', '?', '"', '<', '>', '|', '#', '%'];
+
+/// replaces unsafe characters, collapses whitespace and optionally lowercases names
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sanitizer {
+    /// put instead of every unsafe character
+    pub replacement: char,
+    pub lowercase: bool,
+}
+
+impl Default for Sanitizer {
+    fn default() -> Self {
+        Sanitizer {
+            replacement: '_',
+            lowercase: false,
+        }
+    }
+}
+
+impl NameTransform for Sanitizer {
+    fn apply(&self, name: &str) -> Result<String, String> {
+        let mut sanitized = String::with_capacity(name.len());
+        for ch in name.trim().chars() {
+            if ch.is_whitespace() {
+                if !sanitized.ends_with(' ') {
+                    sanitized.push(' ');
+                }
+            } else if ch.is_control() || UNSAFE_CHARS.contains(&ch) {
+                sanitized.push(self.replacement);
+            } else {
+                sanitized.push(ch);
+            }
+        }
+
+        if self.lowercase {
+            sanitized = sanitized.to_lowercase();
+        }
+        if sanitized.is_empty() {
+            return Err("name is empty after sanitizing".into());
+        }
+
+        Ok(sanitized)
+    }
+}
+
+/// shortens names longer than a number of bytes, e.g. for eCryptfs with its 143-byte limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Truncation {
+    pub max_bytes: usize,
+}
+
+impl Truncation {
+    /// `None` if the name fits. The extension is kept and characters aren't split
+    pub fn truncate(&self, name: &str) -> Option<String> {
+        if name.len() <= self.max_bytes {
+            return None;
+        }
+
+        let (stem, ext) = path_utils::split_extension(name, false);
+        let ext_len = if ext.is_empty() { 0 } else { ext.len() + 1 };
+        if ext_len >= self.max_bytes {
+            // no room for the stem
+            return Some(truncate_bytes(name, self.max_bytes).to_owned());
+        }
+
+        Some(format!(
+            "{}{}",
+            truncate_bytes(stem, self.max_bytes - ext_len),
+            &name[stem.len()..]
+        ))
+    }
+}
+
+impl NameTransform for Truncation {
+    fn apply(&self, name: &str) -> Result<String, String> {
+        Ok(self.truncate(name).unwrap_or_else(|| name.to_owned()))
+    }
+}
+
+/// the longest prefix of `text` which fits in `max_bytes` without splitting characters
+fn truncate_bytes(text: &str, max_bytes: usize) -> &str {
+    let mut end = max_bytes.min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// user-provided rhai script defining a `transform(name) -> name` function
+pub struct ScriptTransform {
+    engine: rhai::Engine,
+    ast: rhai::AST,
+}
+
+impl ScriptTransform {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let engine = rhai::Engine::new();
+        let ast = engine
+            .compile_file(path.to_owned())
+            .map_err(|err| anyhow::anyhow!("{}", err))
+            .with_context(|| format!("can't load script {}", path.display()))?;
+
+        Self::from_ast(engine, ast)
+    }
+
+    fn from_ast(engine: rhai::Engine, ast: rhai::AST) -> anyhow::Result<Self> {
+        if !ast
+            .iter_functions()
+            .any(|f| f.name == "transform" && f.params.len() == 1)
+        {
+            anyhow::bail!("script must define a 'transform(name)' function");
+        }
+
+        Ok(ScriptTransform { engine, ast })
+    }
+}
+
+impl NameTransform for ScriptTransform {
+    fn apply(&self, name: &str) -> Result<String, String> {
+        self.engine
+            .call_fn::<String>(
+                &mut rhai::Scope::new(),
+                &self.ast,
+                "transform",
+                (name.to_owned(),),
+            )
+            .map_err(|err| format!("script error: {}", err))
+    }
+}
+
+/// shell command reading a name from stdin and printing the new name to stdout.
+/// The command runs on background threads, names are pending until it's done
+pub struct CommandFilter {
+    /// results for already processed names, so the command is run once per name
+    results: Prefetcher<Result<String, String>, String>,
+    /// names asked for since the last plan
+    wanted: RefCell<Vec<String>>,
+}
+
+impl CommandFilter {
+    /// at most `jobs` commands run at once
+    pub fn new(command: String, timeout: Duration, jobs: usize) -> Self {
+        CommandFilter {
+            results: Prefetcher::with_fetch(jobs, move |name: &String| {
+                run_filter(&command, timeout, name)
+            }),
+            wanted: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+/// read the whole `pipe` on another thread, so the command isn't blocked by a full pipe
+fn read_in_background(pipe: Option<impl Read + Send + 'static>) -> std::thread::JoinHandle<String> {
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut output);
+        }
+        String::from_utf8_lossy(&output).into_owned()
+    })
+}
+
+fn run_filter(command: &str, timeout: Duration, name: &str) -> Result<String, String> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("can't run filter command: {}", err))?;
+
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+    // the command may exit without reading its input, so write errors are ignored
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = writeln!(stdin, "{}", name);
+    }
+
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if started.elapsed() > timeout => {
+                let _ = child.kill();
+                let _ = child.wait();
+                // processes started by the command may keep the pipes open,
+                // so the readers are left to finish on their own
+                return Err("filter command timed out".into());
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(1)),
+            Err(err) => return Err(format!("filter command failed: {}", err)),
+        }
+    };
+
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        let reason = stderr.lines().next().unwrap_or_default();
+        return Err(format!("filter command failed ({}): {}", status, reason));
+    }
+
+    let output = stdout.trim_end_matches(['\n', '\r']);
+    if output.is_empty() {
+        Err("filter command produced an empty name".into())
+    } else {
+        Ok(output.to_owned())
+    }
+}
+
+impl NameTransform for CommandFilter {
+    fn apply(&self, name: &str) -> Result<String, String> {
+        self.wanted.borrow_mut().push(name.to_owned());
+        self.results
+            .get(name)
+            .unwrap_or_else(|| Err(template::PENDING.to_owned()))
+    }
+
+    fn plan_done(&self) {
+        self.results.request(self.wanted.take());
+    }
+
+    fn progress(&self) -> usize {
+        self.results.progress().0
+    }
+
+    fn wait(&self) {
+        self.results.wait(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn normalization() {
+        let composed = "caf\u{e9}";
+        let decomposed = "cafe\u{301}";
+
+        assert_eq!(Normalization::Nfc.apply(decomposed), Ok(composed.into()));
+        assert_eq!(Normalization::Nfc.apply(composed), Ok(composed.into()));
+        assert_eq!(Normalization::Nfd.apply(composed), Ok(decomposed.into()));
+    }
+
+    #[test]
+    fn transliteration() {
+        assert_eq!(
+            Transliteration.apply("Café Straße Ελλάδα.txt"),
+            Ok("Cafe Strasse Ellada.txt".into())
+        );
+    }
+
+    #[test]
+    fn sanitizer() {
+        let sanitizer = Sanitizer::default();
+        assert_eq!(
+            sanitizer.apply("  what?  a: <b>|c\t\"d\".txt "),
+            Ok("what_ a_ _b__c _d_.txt".into())
+        );
+        assert_eq!(sanitizer.apply("Ok.TXT"), Ok("Ok.TXT".into()));
+        assert!(sanitizer.apply("   ").is_err());
+
+        let sanitizer = Sanitizer {
+            replacement: '-',
+            lowercase: true,
+        };
+        assert_eq!(sanitizer.apply("A*B.TXT"), Ok("a-b.txt".into()));
+    }
+
+    #[rstest]
+    #[case(10, "short.txt", None)]
+    #[case(10, "long_name.txt", Some("long_n.txt"))]
+    #[case(10, "ааааааа.txt", Some("ааа.txt"))]
+    #[case(9, "ааааааа.txt", Some("аа.txt"))]
+    #[case(4, "name.jpeg", Some("name"))]
+    #[case(5, "ффф", Some("фф"))]
+    #[case(6, ".bashrc", Some(".bashr"))]
+    fn truncation(#[case] max_bytes: usize, #[case] name: &str, #[case] expected: Option<&str>) {
+        let truncated = Truncation { max_bytes }.truncate(name);
+        assert_eq!(truncated.as_deref(), expected);
+    }
+
+    #[rstest]
+    #[case(CaseStyle::Snake, "My File-name.TXT", "my_file_name.TXT")]
+    #[case(CaseStyle::Kebab, "myHTTPServer_v2.rs", "my-http-server-v2.rs")]
+    #[case(CaseStyle::Camel, "some_file name.md", "someFileName.md")]
+    #[case(CaseStyle::Title, "the-big_picture", "The Big Picture")]
+    #[case(CaseStyle::Snake, "Фото Отпуск.jpg", "фото_отпуск.jpg")]
+    #[case(CaseStyle::Snake, ".myConfig", ".my_config")]
+    #[case(CaseStyle::Kebab, "dir/Some Name.txt", "dir/some-name.txt")]
+    #[case(CaseStyle::Camel, "___.txt", "___.txt")]
+    fn case_style(#[case] style: CaseStyle, #[case] name: &str, #[case] expected: &str) {
+        assert_eq!(style.apply(name), Ok(expected.into()));
+    }
+
+    fn compile(source: &str) -> anyhow::Result<ScriptTransform> {
+        let engine = rhai::Engine::new();
+        let ast = engine.compile(source).unwrap();
+        ScriptTransform::from_ast(engine, ast)
+    }
+
+    #[test]
+    fn script_transforms_name() {
+        let script = compile(r#"fn transform(name) { name.to_upper() + ".bak" }"#).unwrap();
+        assert_eq!(script.apply("a.txt"), Ok("A.TXT.bak".into()));
+    }
+
+    #[test]
+    fn script_errors_are_reported() {
+        let script = compile(r#"fn transform(name) { throw "nope" }"#).unwrap();
+        assert!(script.apply("a.txt").unwrap_err().contains("nope"));
+
+        let script = compile(r#"fn transform(name) { 42 }"#).unwrap();
+        assert!(script.apply("a.txt").is_err());
+    }
+
+    #[test]
+    fn script_without_transform_is_rejected() {
+        assert!(compile(r#"fn other(name) { name }"#).is_err());
+    }
+
+    /// run `filter` on `name` like a plan does and wait for the result
+    fn apply_filter(filter: &CommandFilter, name: &str) -> Result<String, String> {
+        if let Err(err) = filter.apply(name) {
+            assert_eq!(err, template::PENDING);
+            filter.plan_done();
+            filter.wait();
+        }
+        filter.apply(name)
+    }
+
+    fn filter(command: &str) -> CommandFilter {
+        CommandFilter::new(command.into(), Duration::from_secs(5), 2)
+    }
+
+    #[test]
+    fn command_filter_uses_stdout() {
+        assert_eq!(
+            apply_filter(&filter("tr a-z A-Z"), "a b.txt"),
+            Ok("A B.TXT".into())
+        );
+        assert_eq!(apply_filter(&filter("sed s/x/y/"), "xx"), Ok("yx".into()));
+    }
+
+    #[test]
+    fn command_filter_errors() {
+        let err = apply_filter(&filter("echo oops >&2; exit 3"), "a").unwrap_err();
+        assert!(err.contains("oops"), "{}", err);
+
+        assert!(apply_filter(&filter("true"), "a").is_err());
+
+        let slow = CommandFilter::new("sleep 5".into(), Duration::from_millis(50), 1);
+        assert_eq!(
+            apply_filter(&slow, "a"),
+            Err("filter command timed out".into())
+        );
+    }
+
+    #[test]
+    fn command_filter_reads_output_while_running() {
+        // more than a pipe buffer holds, the command would block until the timeout
+        let chatty = CommandFilter::new(
+            "head -c 200000 /dev/zero >&2; cat".into(),
+            Duration::from_secs(5),
+            1,
+        );
+        assert_eq!(apply_filter(&chatty, "a"), Ok("a".into()));
+    }
+
+    #[test]
+    fn command_filter_is_pending_until_done() {
+        let filter = filter("sleep 0.2; cat");
+        assert_eq!(filter.apply("a"), Err(template::PENDING.into()));
+        filter.plan_done();
+        assert_eq!(filter.apply("a"), Err(template::PENDING.into()));
+        assert_eq!(filter.progress(), 0);
+
+        filter.wait();
+        assert_eq!(filter.progress(), 1);
+        assert_eq!(filter.apply("a"), Ok("a".into()));
+    }
+
+    #[test]
+    fn command_filter_caches_results() {
+        let dir = tempfile::tempdir().unwrap();
+        let counter = dir.path().join("calls");
+        let filter = filter(&format!("echo >> '{}'; cat", counter.display()));
+
+        assert_eq!(apply_filter(&filter, "a"), Ok("a".into()));
+        assert_eq!(apply_filter(&filter, "a"), Ok("a".into()));
+        assert_eq!(apply_filter(&filter, "b"), Ok("b".into()));
+        assert_eq!(std::fs::read_to_string(counter).unwrap().lines().count(), 2);
+    }
+
+    #[test]
+    fn script_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("script.rhai");
+        std::fs::write(
+            &path,
+            r#"fn transform(name) { name.replace("-", "_"); name }"#,
+        )
+        .unwrap();
+
+        let script = ScriptTransform::load(&path).unwrap();
+        assert_eq!(script.apply("a-b-c"), Ok("a_b_c".into()));
+        assert!(ScriptTransform::load(&dir.path().join("missing.rhai")).is_err());
+    }
+}
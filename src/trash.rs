@@ -0,0 +1,155 @@
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// move a file to the trash of the system: the freedesktop.org trash, the Recycle Bin
+/// or the macOS Trash, so it can be restored by file managers. Returns its path in
+/// the trash where it can be renamed back from, `None` for the trashes of macOS and Windows
+pub fn move_to_trash(path: &Path) -> io::Result<Option<PathBuf>> {
+    #[cfg(all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    ))]
+    {
+        // the trash crate doesn't tell where the file went, it's the only new item of the name
+        let name = path.file_name().unwrap_or_default();
+        let items_named = || -> io::Result<HashSet<PathBuf>> {
+            Ok(trash::os_limited::list()
+                .map_err(io::Error::other)?
+                .into_iter()
+                .filter(|item| item.name == name)
+                .map(|item| PathBuf::from(item.id))
+                .collect())
+        };
+        let before = items_named()?;
+        trash::delete(path).map_err(io::Error::other)?;
+        let added = items_named()?
+            .into_iter()
+            .find(|info| !before.contains(info));
+        Ok(added.as_deref().and_then(trashed_path))
+    }
+
+    #[cfg(not(all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )))]
+    {
+        trash::delete(path).map_err(io::Error::other)?;
+        Ok(None)
+    }
+}
+
+/// the trashed file described by the info file `info`: `$trash/info/name.trashinfo`
+/// describes `$trash/files/name`
+#[cfg_attr(
+    not(all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )),
+    allow(dead_code)
+)]
+fn trashed_path(info: &Path) -> Option<PathBuf> {
+    let name = info.file_name()?.to_str()?.strip_suffix(".trashinfo")?;
+    let trash = info.parent()?.parent()?;
+    Some(trash.join("files").join(name))
+}
+
+/// whether `path` is a file in one of the freedesktop.org trash directories
+pub fn is_trashed(path: &Path) -> bool {
+    #[cfg(all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    ))]
+    return trash::os_limited::trash_folders()
+        .is_ok_and(|trash_dirs| is_trashed_in(path, &trash_dirs));
+
+    #[cfg(not(all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )))]
+    {
+        let _ = path;
+        false
+    }
+}
+
+/// whether `path` is a file in one of the `trash_dirs`
+#[cfg_attr(
+    not(all(
+        unix,
+        not(target_os = "macos"),
+        not(target_os = "ios"),
+        not(target_os = "android")
+    )),
+    allow(dead_code)
+)]
+fn is_trashed_in(path: &Path, trash_dirs: &HashSet<PathBuf>) -> bool {
+    path.parent()
+        .filter(|dir| dir.file_name() == Some(OsStr::new("files")))
+        .and_then(Path::parent)
+        .is_some_and(|trash| trash_dirs.contains(trash))
+}
+
+/// remove the description of a file restored from the trash
+pub fn forget(trashed: &Path) {
+    let (Some(files), Some(name)) = (trashed.parent(), trashed.file_name()) else {
+        return;
+    };
+    let mut info_name = name.to_owned();
+    info_name.push(".trashinfo");
+    if let Some(trash) = files.parent() {
+        let _ = std::fs::remove_file(trash.join("info").join(info_name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("/t/info/a.txt.trashinfo", Some("/t/files/a.txt"))]
+    #[case("/t/info/a.txt.2.trashinfo", Some("/t/files/a.txt.2"))]
+    #[case("/t/info/a.txt", None)]
+    fn trashed_paths_of_infos(#[case] info: &str, #[case] expected: Option<&str>) {
+        assert_eq!(trashed_path(Path::new(info)), expected.map(PathBuf::from));
+    }
+
+    #[rstest]
+    #[case("/home/u/.local/share/Trash/files/a", true)]
+    #[case("/mnt/.Trash-1000/files/a", true)]
+    #[case("/mnt/.Trash/1000/files/a", false)]
+    #[case("/mnt/files/a", false)]
+    #[case("/mnt/.Trash-1000/info/a", false)]
+    fn trashed_paths(#[case] path: &str, #[case] expected: bool) {
+        let trash_dirs = HashSet::from([
+            PathBuf::from("/home/u/.local/share/Trash"),
+            PathBuf::from("/mnt/.Trash-1000"),
+        ]);
+        assert_eq!(is_trashed_in(Path::new(path), &trash_dirs), expected);
+    }
+
+    #[test]
+    fn restored_files_are_forgotten() {
+        let dir = tempfile::tempdir().unwrap();
+        let trash = dir.path();
+        std::fs::create_dir(trash.join("info")).unwrap();
+        std::fs::write(trash.join("info/a.txt.trashinfo"), "").unwrap();
+        std::fs::write(trash.join("info/a.txt.2.trashinfo"), "").unwrap();
+
+        forget(&trash.join("files/a.txt.2"));
+        assert!(!trash.join("info/a.txt.2.trashinfo").exists());
+        assert!(trash.join("info/a.txt.trashinfo").exists());
+    }
+}
@@ -0,0 +1,153 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+/// files created in the tutorial sandbox
+const SAMPLE_FILES: &[&str] = &[
+    "IMG_0012.jpg",
+    "IMG_0013.jpg",
+    "img_0014.JPG",
+    "holiday photo.png",
+    "notes.txt",
+];
+
+/// temporary directory with sample files, removed when dropped
+pub struct Sandbox {
+    dir: PathBuf,
+}
+
+impl Sandbox {
+    pub fn create() -> anyhow::Result<Self> {
+        let dir = std::env::temp_dir().join(format!("irename-tutorial-{}", std::process::id()));
+        Self::create_in(dir)
+    }
+
+    fn create_in(dir: PathBuf) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("can't create sandbox {}", dir.display()))?;
+        let sandbox = Sandbox { dir };
+
+        for name in SAMPLE_FILES {
+            std::fs::write(sandbox.dir.join(name), b"")
+                .with_context(|| format!("can't create sample file {}", name))?;
+        }
+
+        Ok(sandbox)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn files(&self) -> Vec<PathBuf> {
+        SAMPLE_FILES
+            .iter()
+            .map(|name| self.dir.join(name))
+            .collect()
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// what the user has to do to finish a tutorial step
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Goal {
+    /// type a regex matching some files and go to the replacement
+    Match,
+    /// type a replacement changing some names
+    Replace,
+    /// turn on the 'ignore case' flag
+    IgnoreCase,
+    /// execute the renaming
+    Execute,
+}
+
+struct Step {
+    goal: Goal,
+    text: &'static str,
+}
+
+const STEPS: &[Step] = &[
+    Step {
+        goal: Goal::Match,
+        text: "The regex area is active. Type a regex, e.g. IMG_(\\d+). \
+               Files it matches are shown in the Files panel. Then press Tab",
+    },
+    Step {
+        goal: Goal::Replace,
+        text: "Now type the new name in the replacement area, e.g. photo_$1. \
+               $1 is the text matched by the first group in parentheses. \
+               Each renamed file is shown as 'old -> new'",
+    },
+    Step {
+        goal: Goal::IgnoreCase,
+        text: "img_0014.JPG isn't renamed because of its lowercase prefix. \
+               Press Ctrl-r to turn on the 'ignore case' flag (i in the Flags panel)",
+    },
+    Step {
+        goal: Goal::Execute,
+        text: "Check the preview: every 'old -> new' line is a file to rename. \
+               Press Enter to rename the sample files. They are removed after the tutorial",
+    },
+];
+
+/// interactive walkthrough showing one step at a time
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Tutorial {
+    step: usize,
+}
+
+impl Tutorial {
+    pub fn goal(&self) -> Goal {
+        STEPS[self.step].goal
+    }
+
+    pub fn text(&self) -> &'static str {
+        STEPS[self.step].text
+    }
+
+    pub fn title(&self) -> String {
+        format!("Tutorial {}/{}", self.step + 1, STEPS.len())
+    }
+
+    /// go to the next steps while their goals are reached
+    pub fn advance(&mut self, reached: impl Fn(Goal) -> bool) {
+        while self.step + 1 < STEPS.len() && reached(self.goal()) {
+            self.step += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sandbox_is_removed() {
+        let root = tempfile::tempdir().unwrap();
+        let dir = root.path().join("sandbox");
+
+        let sandbox = Sandbox::create_in(dir.clone()).unwrap();
+        assert!(sandbox.files().iter().all(|file| file.exists()));
+
+        drop(sandbox);
+        assert!(!dir.exists());
+    }
+
+    #[test]
+    fn tutorial_advances_to_execution() {
+        let mut tutorial = Tutorial::default();
+        tutorial.advance(|goal| goal == Goal::Replace);
+        assert_eq!(tutorial.goal(), Goal::Match);
+
+        tutorial.advance(|goal| goal == Goal::Match || goal == Goal::Replace);
+        assert_eq!(tutorial.goal(), Goal::IgnoreCase);
+
+        tutorial.advance(|_| true);
+        assert_eq!(tutorial.goal(), Goal::Execute);
+    }
+}
@@ -0,0 +1,201 @@
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+
+/// the directory is listed this often even when file system events are delivered,
+/// network file systems don't report the changes made by other machines
+const RESCAN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// new entries of a directory, found by comparing its listings.
+/// The listings are taken on file system events where they are available,
+/// and periodically where they aren't
+pub struct DirWatcher {
+    dir: PathBuf,
+    /// entries which existed from the start or were reported already
+    known: HashSet<PathBuf>,
+    /// new entries with their size at the previous poll, they are reported
+    /// when the size stops changing, so files being written aren't renamed
+    growing: HashMap<PathBuf, u64>,
+    /// reported entries which weren't renamed, they are reported again when the directory changes
+    retry: HashSet<PathBuf>,
+    /// entries at the previous poll
+    listing: Vec<PathBuf>,
+    /// `None` when the events can't be delivered, the directory is polled then
+    events: Option<(RecommendedWatcher, Receiver<notify::Result<notify::Event>>)>,
+}
+
+impl DirWatcher {
+    /// the existing entries of `dir` are never reported
+    pub fn new(dir: &Path) -> io::Result<Self> {
+        let listing = list_dir(dir)?;
+        Ok(DirWatcher {
+            dir: dir.to_owned(),
+            known: listing.iter().cloned().collect(),
+            growing: HashMap::new(),
+            retry: HashSet::new(),
+            listing,
+            events: watch_events(dir),
+        })
+    }
+
+    /// whether the changes are reported by the file system instead of polling
+    pub fn uses_events(&self) -> bool {
+        self.events.is_some()
+    }
+
+    /// block until the directory may have changed, at most `interval` when polling
+    /// or when new entries are still growing
+    pub fn wait(&mut self, interval: Duration) {
+        let Some((_, events)) = &self.events else {
+            std::thread::sleep(interval);
+            return;
+        };
+        if !self.growing.is_empty() {
+            std::thread::sleep(interval);
+            while events.try_recv().is_ok() {}
+            return;
+        }
+
+        match events.recv_timeout(RESCAN_INTERVAL) {
+            Ok(Ok(_)) | Err(RecvTimeoutError::Timeout) => {
+                // a single change comes as several events
+                while events.try_recv().is_ok() {}
+            }
+            Ok(Err(_)) | Err(RecvTimeoutError::Disconnected) => self.events = None,
+        }
+    }
+
+    /// new entries which haven't changed since the previous poll, in name order,
+    /// with the entries to retry if the directory changed
+    pub fn poll(&mut self) -> io::Result<Vec<PathBuf>> {
+        let entries = list_dir(&self.dir)?;
+        let changed = entries != self.listing;
+
+        // a removed entry is new again if it's created once more
+        let present: HashSet<&PathBuf> = entries.iter().collect();
+        self.known.retain(|path| present.contains(path));
+        self.growing.retain(|path, _| present.contains(path));
+        self.retry.retain(|path| present.contains(path));
+
+        let mut settled = Vec::new();
+        for path in &entries {
+            if self.known.contains(path) {
+                continue;
+            }
+            let Ok(meta) = std::fs::symlink_metadata(path) else {
+                continue;
+            };
+            if self.growing.get(path) == Some(&meta.len()) {
+                self.growing.remove(path);
+                self.known.insert(path.clone());
+                settled.push(path.clone());
+            } else {
+                self.growing.insert(path.clone(), meta.len());
+            }
+        }
+        self.listing = entries;
+
+        // e.g. the existing destination was removed
+        if changed && !self.retry.is_empty() {
+            settled.extend(self.retry.drain());
+            settled.sort();
+        }
+        Ok(settled)
+    }
+
+    /// report these paths again on the next change of the directory, e.g. the files
+    /// whose renames failed or whose destinations exist
+    pub fn retry(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        self.retry.extend(paths);
+    }
+
+    /// never report these paths, e.g. the new names of the renamed files
+    pub fn mark_known(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        for path in paths {
+            self.growing.remove(&path);
+            self.retry.remove(&path);
+            self.known.insert(path);
+        }
+        // the renames themselves aren't changes to retry on
+        if let Ok(entries) = list_dir(&self.dir) {
+            self.listing = entries;
+        }
+    }
+}
+
+/// events of the entries of `dir`, `None` if the platform or the file system can't deliver them
+fn watch_events(
+    dir: &Path,
+) -> Option<(RecommendedWatcher, Receiver<notify::Result<notify::Event>>)> {
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).ok()?;
+    watcher.watch(dir, RecursiveMode::NonRecursive).ok()?;
+    Some((watcher, receiver))
+}
+
+fn list_dir(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut paths = std::fs::read_dir(dir)?
+        .map(|entry| Ok(entry?.path()))
+        .collect::<io::Result<Vec<PathBuf>>>()?;
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settled_new_files_are_reported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+        std::fs::write(path("old"), "").unwrap();
+
+        let mut watcher = DirWatcher::new(dir.path()).unwrap();
+        assert!(watcher.poll().unwrap().is_empty());
+
+        std::fs::write(path("new"), "1").unwrap();
+        // seen for the first time, it may be still written
+        assert!(watcher.poll().unwrap().is_empty());
+        std::fs::write(path("new"), "12").unwrap();
+        assert!(watcher.poll().unwrap().is_empty());
+        assert_eq!(watcher.poll().unwrap(), [path("new")]);
+        assert!(watcher.poll().unwrap().is_empty());
+
+        std::fs::rename(path("new"), path("renamed")).unwrap();
+        watcher.mark_known([path("renamed")]);
+        assert!(watcher.poll().unwrap().is_empty());
+        assert!(watcher.poll().unwrap().is_empty());
+
+        // created again after removing
+        std::fs::remove_file(path("old")).unwrap();
+        assert!(watcher.poll().unwrap().is_empty());
+        std::fs::write(path("old"), "").unwrap();
+        watcher.poll().unwrap();
+        assert_eq!(watcher.poll().unwrap(), [path("old")]);
+    }
+
+    #[test]
+    fn failed_files_are_retried_on_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = |name: &str| dir.path().join(name);
+
+        let mut watcher = DirWatcher::new(dir.path()).unwrap();
+        std::fs::write(path("a"), "").unwrap();
+        watcher.poll().unwrap();
+        assert_eq!(watcher.poll().unwrap(), [path("a")]);
+
+        // e.g. the destination exists
+        watcher.retry([path("a")]);
+        watcher.mark_known([]);
+        assert!(watcher.poll().unwrap().is_empty());
+
+        std::fs::write(path("b"), "").unwrap();
+        assert_eq!(watcher.poll().unwrap(), [path("a")]);
+        assert_eq!(watcher.poll().unwrap(), [path("b")]);
+        assert!(watcher.poll().unwrap().is_empty());
+    }
+}